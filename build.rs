@@ -1,6 +1,10 @@
 use std::error::Error;
 use vergen_git2::{BuildBuilder, Emitter, Git2Builder};
 
+/// The Protocol Buffers schema of the forecast responses.
+#[cfg(feature = "server")]
+const PROTOBUF_SCHEMA: &str = "assets/sinoptik.proto";
+
 fn main() -> Result<(), Box<dyn Error>> {
     // Generate the `cargo:` instructions to fill the appropriate environment variables.
     let build = &BuildBuilder::all_build()?;
@@ -11,5 +15,58 @@ fn main() -> Result<(), Box<dyn Error>> {
         .add_instructions(git2)?
         .emit()?;
 
+    #[cfg(feature = "server")]
+    compile_protobuf_schema()?;
+
+    Ok(())
+}
+
+/// Generates the message types of the Protocol Buffers schema.
+///
+/// The message types can also be deserialized from the JSON representation of the responses: the
+/// fields are renamed to their JSON names and keys that are not a field are rejected.
+#[cfg(feature = "server")]
+fn compile_protobuf_schema() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed={PROTOBUF_SCHEMA}");
+    let file_descriptors = protox::compile([PROTOBUF_SCHEMA], ["."])?;
+
+    let mut config = prost_build::Config::new();
+    config.btree_map(["."]).type_attribute(
+        ".",
+        "#[derive(rocket::serde::Deserialize)]\n\
+         #[serde(crate = \"rocket::serde\", default, deny_unknown_fields)]",
+    );
+    for file in &file_descriptors.file {
+        for message in &file.message_type {
+            for field in &message.field {
+                // The JSON name is only set explicitly if it differs from the default camel case
+                // name, otherwise the JSON key is the name of the field itself.
+                let json_key = match field.json_name() {
+                    json_name if json_name != lower_camel_case(field.name()) => json_name,
+                    _ => field.name(),
+                };
+                let path = format!(".{}.{}.{}", file.package(), message.name(), field.name());
+                config.field_attribute(path, format!("#[serde(rename = \"{json_key}\")]"));
+            }
+        }
+    }
+    config.compile_fds(file_descriptors)?;
+
     Ok(())
 }
+
+/// Converts the name of a field to the default JSON name, e.g. `data_age` to `dataAge`.
+#[cfg(feature = "server")]
+fn lower_camel_case(name: &str) -> String {
+    let mut words = name.split('_');
+    let first = words.next().unwrap_or_default().to_owned();
+
+    words.fold(first, |mut camel_case, word| {
+        let mut chars = word.chars();
+        if let Some(first_char) = chars.next() {
+            camel_case.extend(first_char.to_uppercase());
+            camel_case.push_str(chars.as_str());
+        }
+        camel_case
+    })
+}
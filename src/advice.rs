@@ -0,0 +1,285 @@
+//! Outdoor advice.
+//!
+//! This module is used to combine the PAQI, UV index and precipitation forecast into a simple
+//! scored recommendation per upcoming hour on whether it is a good idea to go outside.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use rocket::serde::Serialize;
+
+use crate::forecast::{Forecast, Metric};
+use crate::{Error, Result};
+
+/// The default number of upcoming hours to give advice for.
+pub(crate) const DEFAULT_ADVICE_HOURS: u32 = 12;
+
+/// The maximum number of upcoming hours to give advice for (three days, beyond which the forecast
+/// has no data for most metrics).
+const MAX_ADVICE_HOURS: u32 = 3 * 24;
+
+/// The metrics the advice is based on.
+pub(crate) const ADVICE_METRICS: [Metric; 3] = [Metric::PAQI, Metric::Precipitation, Metric::UVI];
+
+/// The minimum score for which going outside is recommended.
+const GO_SCORE: u8 = 7;
+
+/// The minimum score for which going outside is recommended with caution.
+const CAUTION_SCORE: u8 = 4;
+
+/// The profiles of the people to give advice for.
+///
+/// The profile determines how much each metric weighs in the score.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, rocket::FromFormField)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub(crate) enum Profile {
+    /// Weighs all metrics equally.
+    #[default]
+    Default,
+    /// Weighs pollen and air quality more, for allergy sufferers.
+    Allergy,
+    /// Weighs the UV index and precipitation more, for people exercising outside.
+    Sport,
+}
+
+impl Profile {
+    /// Returns the weight of the provided metric.
+    fn weight(self, metric: Metric) -> f32 {
+        match (self, metric) {
+            (Profile::Allergy, Metric::PAQI) => 2.0,
+            (Profile::Allergy, _) => 0.75,
+            (Profile::Sport, Metric::PAQI) => 1.0,
+            (Profile::Sport, _) => 1.5,
+            (Profile::Default, _) => 1.0,
+        }
+    }
+}
+
+/// The recommendations that can be given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub(crate) enum Recommendation {
+    /// Going outside is a good idea.
+    Go,
+    /// Going outside is fine, but with caution.
+    Caution,
+    /// Staying inside is better.
+    StayInside,
+}
+
+/// The advice for an hour.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct HourAdvice {
+    /// The start of the hour (in seconds since the UNIX epoch).
+    time: i64,
+
+    /// The score from 0 (worst) to 10 (best).
+    score: u8,
+
+    /// The recommendation based on the score.
+    recommendation: Recommendation,
+
+    /// The metric that lowers the score the most (if any).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limiting_metric: Option<Metric>,
+}
+
+/// The advice for the upcoming hours.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Advice {
+    /// The latitude of the position.
+    lat: f64,
+
+    /// The longitude of the position.
+    lon: f64,
+
+    /// The current time (in seconds since the UNIX epoch).
+    time: i64,
+
+    /// The profile the advice is for.
+    profile: Profile,
+
+    /// The advice per upcoming hour, starting with the current hour.
+    hours: Vec<HourAdvice>,
+
+    /// The errors that occurred for the metrics, which are left out of the scores.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    errors: BTreeMap<Metric, String>,
+}
+
+impl Advice {
+    /// Creates the advice for the provided number of hours from `now` using the forecast.
+    ///
+    /// Returns an error if the number of hours exceeds the maximum.
+    pub(crate) fn new(
+        forecast: &Forecast,
+        profile: Profile,
+        hours: u32,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        let start = now
+            .duration_trunc(Duration::hours(1))
+            .expect("Truncating to an hour always succeeds");
+        if hours > MAX_ADVICE_HOURS
+            || start
+                .checked_add_signed(Duration::hours(i64::from(hours)))
+                .is_none()
+        {
+            return Err(Error::InvalidHours(hours, MAX_ADVICE_HOURS));
+        }
+        let series = ADVICE_METRICS
+            .into_iter()
+            .filter_map(|metric| forecast.series(metric).map(|series| (metric, series)))
+            .collect::<Vec<_>>();
+        let errors = ADVICE_METRICS
+            .into_iter()
+            .filter_map(|metric| forecast.error(metric).map(|error| (metric, error.into())))
+            .collect();
+
+        let hours = (0..i64::from(hours))
+            .map(|hour| {
+                let from = start + Duration::hours(hour);
+                let penalties = series.iter().filter_map(|(metric, series)| {
+                    let value = max_value(series, from, from + Duration::hours(1), *metric)?;
+                    let penalty = penalty(*metric, value) * profile.weight(*metric);
+                    Some((*metric, penalty.min(1.0)))
+                });
+                let limiting = penalties
+                    .filter(|&(_, penalty)| penalty > 0.0)
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b));
+                let score = (10.0 * (1.0 - limiting.map_or(0.0, |(_, penalty)| penalty))).round();
+                let score = score as u8;
+                let recommendation = match score {
+                    GO_SCORE.. => Recommendation::Go,
+                    CAUTION_SCORE.. => Recommendation::Caution,
+                    _ => Recommendation::StayInside,
+                };
+
+                HourAdvice {
+                    time: from.timestamp(),
+                    score,
+                    recommendation,
+                    limiting_metric: limiting.map(|(metric, _)| metric),
+                }
+            })
+            .collect();
+
+        let position = forecast.position();
+        Ok(Self {
+            lat: position.lat,
+            lon: position.lon,
+            time: forecast.time(),
+            profile,
+            hours,
+            errors,
+        })
+    }
+}
+
+/// Returns the penalty (from 0 to 1) of the value of the provided metric.
+fn penalty(metric: Metric, value: f32) -> f32 {
+    let penalty = match metric {
+        // The PAQI goes from 1 to 10.
+        Metric::PAQI => (value - 1.0) / 9.0,
+        // Precipitation of 5 mm/h or more is heavy rain.
+        Metric::Precipitation => value / 5.0,
+        // A UV index of 10 or more is extreme.
+        Metric::UVI => value / 10.0,
+        _ => 0.0,
+    };
+
+    penalty.clamp(0.0, 1.0)
+}
+
+/// Returns the maximum value of the items of the series that are valid during the provided period.
+///
+/// Each item is valid until the next item; the last item is valid for the native cadence of the
+/// metric. Returns [`None`] if no item is valid during the period.
+fn max_value(
+    series: &[(DateTime<Utc>, f32)],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    metric: Metric,
+) -> Option<f32> {
+    series
+        .iter()
+        .enumerate()
+        .filter(|&(index, &(time, _))| {
+            let until = series
+                .get(index + 1)
+                .map_or(time + Duration::seconds(metric.cadence()), |&(next, _)| {
+                    next
+                });
+            time < to && until > from
+        })
+        .map(|(_, &(_, value))| value)
+        .reduce(f32::max)
+}
+
+#[cfg(all(test, feature = "maps"))]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::position::Position;
+    use crate::providers::buienradar::Sample;
+
+    #[test]
+    fn advice() {
+        let t = |hour| Utc.with_ymd_and_hms(2024, 6, 1, hour, 0, 0).unwrap();
+        let samples = Vec::from([Sample::new(t(0), 8)]);
+        let forecast = Forecast::with_uvi(Position::new(51.4, 5.5), t(13).timestamp(), samples);
+
+        // The daily UV index of 8 is valid for all hours.
+        let now = t(13) + Duration::minutes(20);
+        let advice = Advice::new(&forecast, Profile::Default, 2, now).unwrap();
+        assert_eq!(
+            advice.hours,
+            [
+                HourAdvice {
+                    time: t(13).timestamp(),
+                    score: 2,
+                    recommendation: Recommendation::StayInside,
+                    limiting_metric: Some(Metric::UVI),
+                },
+                HourAdvice {
+                    time: t(14).timestamp(),
+                    score: 2,
+                    recommendation: Recommendation::StayInside,
+                    limiting_metric: Some(Metric::UVI),
+                },
+            ]
+        );
+
+        // For allergy sufferers the UV index weighs less.
+        let advice = Advice::new(&forecast, Profile::Allergy, 1, now).unwrap();
+        assert_eq!(advice.hours[0].score, 4);
+        assert_eq!(advice.hours[0].recommendation, Recommendation::Caution);
+
+        // Without items for an hour, going outside is fine.
+        let advice = Advice::new(&forecast, Profile::Sport, 1, t(0) + Duration::days(2)).unwrap();
+        assert_eq!(advice.hours[0].score, 10);
+        assert_eq!(advice.hours[0].limiting_metric, None);
+
+        // The number of hours is limited.
+        let advice = Advice::new(&forecast, Profile::Default, 3 * 24, now).unwrap();
+        assert_eq!(advice.hours.len(), 72);
+        assert!(matches!(
+            Advice::new(&forecast, Profile::Default, 3 * 24 + 1, now),
+            Err(Error::InvalidHours(73, 72))
+        ));
+        assert!(matches!(
+            Advice::new(&forecast, Profile::Default, 4_000_000_000, now),
+            Err(Error::InvalidHours(4_000_000_000, 72))
+        ));
+    }
+
+    #[test]
+    fn penalty() {
+        assert_eq!(super::penalty(Metric::PAQI, 1.0), 0.0);
+        assert_eq!(super::penalty(Metric::Precipitation, 2.5), 0.5);
+        assert_eq!(super::penalty(Metric::UVI, 12.0), 1.0);
+    }
+}
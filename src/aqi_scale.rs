@@ -0,0 +1,113 @@
+//! Air quality index scales.
+//!
+//! Luchtmeetnet provides the Dutch air quality index (LKI), which goes from 1 to 11. This module
+//! converts the pollutant concentrations to the European air quality index (EAQI) of the European
+//! Environment Agency instead, which goes from 1 (good) to 6 (extremely poor), so that non-Dutch
+//! clients get numbers they recognize.
+
+use crate::forecast::Metric;
+use crate::providers::luchtmeetnet::Item;
+
+/// The (exclusive) upper bounds of the concentrations (in µg/m³) of the NO₂ EAQI bands.
+const NO2_BANDS: [f32; 5] = [40.0, 90.0, 120.0, 230.0, 340.0];
+
+/// The (exclusive) upper bounds of the concentrations (in µg/m³) of the O₃ EAQI bands.
+const O3_BANDS: [f32; 5] = [50.0, 100.0, 130.0, 240.0, 380.0];
+
+/// The (exclusive) upper bounds of the concentrations (in µg/m³) of the PM10 EAQI bands.
+const PM10_BANDS: [f32; 5] = [20.0, 40.0, 50.0, 100.0, 150.0];
+
+/// The supported air quality index scales.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, rocket::FromFormField)]
+pub(crate) enum AqiScale {
+    /// The Dutch air quality index (LKI) of the RIVM, from 1 to 11.
+    #[default]
+    Lki,
+    /// The European air quality index (EAQI) of the EEA, from 1 to 6.
+    Eaqi,
+}
+
+/// Returns the EAQI of the provided concentration (in µg/m³) of the provided pollutant.
+///
+/// Returns [`None`] if the EAQI is not determined from the pollutant.
+fn eaqi(pollutant: Metric, concentration: f32) -> Option<f32> {
+    let bands = match pollutant {
+        Metric::NO2 => NO2_BANDS,
+        Metric::O3 => O3_BANDS,
+        Metric::PM10 => PM10_BANDS,
+        _ => return None,
+    };
+    let index = bands
+        .iter()
+        .position(|upper_bound| concentration < *upper_bound)
+        .unwrap_or(bands.len());
+
+    Some(index as f32 + 1.0)
+}
+
+/// Converts the AQI items to the EAQI scale using the provided concentrations per pollutant.
+///
+/// The index of an item is the worst index of the pollutant concentrations at its time. Items
+/// without any concentration at their time are left out.
+pub(crate) fn to_eaqi(items: &mut Vec<Item>, concentrations: &[(Metric, Vec<Item>)]) {
+    items.retain_mut(|item| {
+        let index = concentrations
+            .iter()
+            .flat_map(|(pollutant, concentrations)| {
+                concentrations
+                    .iter()
+                    .filter(|concentration| concentration.time == item.time)
+                    .filter_map(|concentration| eaqi(*pollutant, concentration.value))
+            })
+            .reduce(f32::max);
+        if let Some(index) = index {
+            item.value = index;
+        }
+
+        index.is_some()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn eaqi() {
+        // The bands include their lower bound, the highest band has no upper bound.
+        assert_eq!(super::eaqi(Metric::NO2, 0.0), Some(1.0));
+        assert_eq!(super::eaqi(Metric::NO2, 40.0), Some(2.0));
+        assert_eq!(super::eaqi(Metric::O3, 129.9), Some(3.0));
+        assert_eq!(super::eaqi(Metric::PM10, 99.0), Some(4.0));
+        assert_eq!(super::eaqi(Metric::PM10, 150.0), Some(6.0));
+        assert_eq!(super::eaqi(Metric::AQI, 1.0), None);
+    }
+
+    #[test]
+    fn to_eaqi() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let t_1 = t_0 + Duration::hours(1);
+        let t_2 = t_0 + Duration::hours(2);
+        let mut items = Vec::from([
+            Item::new(t_0, 3.0),
+            Item::new(t_1, 8.0),
+            Item::new(t_2, 4.0),
+        ]);
+        let concentrations = [
+            (
+                Metric::NO2,
+                Vec::from([Item::new(t_0, 35.0), Item::new(t_1, 95.0)]),
+            ),
+            (
+                Metric::PM10,
+                Vec::from([Item::new(t_0, 25.0), Item::new(t_1, 10.0)]),
+            ),
+        ];
+
+        // The worst index of the pollutants is used, items without concentrations are left out.
+        super::to_eaqi(&mut items, &concentrations);
+        assert_eq!(items, [Item::new(t_0, 2.0), Item::new(t_1, 3.0)]);
+    }
+}
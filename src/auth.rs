@@ -0,0 +1,199 @@
+//! API key authentication.
+//!
+//! This module provides a request guard that checks the API key of a request against the
+//! configured API keys and applies the per-key rate limit, if any. If no API keys are configured,
+//! authentication is disabled. It also rejects requests that have been rate limited by the
+//! [`RateLimiter`](crate::rate_limit::RateLimiter) fairing. Additionally, it provides a request
+//! guard for the administration endpoints that requires an API key that is marked as admin key.
+//! Resources that are created with an API key, like webhook subscriptions, are only accessible
+//! with that same API key or an admin key.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+use crate::config::Config;
+use crate::forecast::Metric;
+use crate::{rate_limit, Error};
+
+/// The name of the header that contains the API key.
+const API_KEY_HEADER: &str = "X-API-Key";
+
+/// The name of the query parameter that contains the API key.
+///
+/// This can be used if setting a header is not possible, e.g. for calendar or feed subscriptions.
+const API_KEY_PARAM: &str = "api_key";
+
+/// The duration of the window in which the number of requests per API key are limited.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// The usage of the API keys in the current rate limit window.
+#[derive(Debug, Default)]
+pub(crate) struct ApiKeyUsage(Mutex<HashMap<String, (Instant, u32)>>);
+
+impl ApiKeyUsage {
+    /// Registers a request for the provided API key and checks it against the rate limit.
+    fn register(&self, key: &str, rate_limit: u32) -> Result<(), Error> {
+        let mut usage = self.0.lock().expect("API key usage mutex was poisoned");
+        let now = Instant::now();
+        let (window_start, count) = usage.entry(key.to_owned()).or_insert((now, 0));
+        let elapsed = now.duration_since(*window_start);
+        if elapsed >= RATE_LIMIT_WINDOW {
+            *window_start = now;
+            *count = 0;
+        }
+        if *count >= rate_limit {
+            let retry_after = RATE_LIMIT_WINDOW
+                .saturating_sub(elapsed)
+                .as_secs_f64()
+                .ceil();
+            return Err(Error::RateLimited(retry_after as u64));
+        }
+        *count += 1;
+
+        Ok(())
+    }
+}
+
+/// A request guard for an authenticated request.
+///
+/// It contains the API key used, or [`None`] if authentication is disabled.
+#[derive(Debug)]
+pub(crate) struct ApiKey(Option<String>);
+
+impl ApiKey {
+    /// Returns the personal thresholds configured for the API key.
+    ///
+    /// Returns no thresholds if authentication is disabled.
+    pub(crate) fn thresholds(&self, config: &Config) -> BTreeMap<Metric, f32> {
+        self.0
+            .as_ref()
+            .and_then(|key| config.api_keys.iter().find(|api_key| &api_key.key == key))
+            .map(|api_key| api_key.thresholds.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the API key used, or [`None`] if authentication is disabled.
+    pub(crate) fn key(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// Returns the API key that access to resources is restricted to, i.e. to the resources that
+    /// were created with it.
+    ///
+    /// Returns [`None`] if there is no restriction, because authentication is disabled or the API
+    /// key is an admin key.
+    pub(crate) fn restriction(&self, config: &Config) -> Option<&str> {
+        self.0.as_deref().filter(|&key| {
+            !config
+                .api_keys
+                .iter()
+                .any(|api_key| api_key.key == key && api_key.admin)
+        })
+    }
+}
+
+/// A request guard for a request authenticated with an admin API key.
+///
+/// If authentication is disabled, there are no admin keys, so access is always forbidden.
+#[derive(Debug)]
+pub(crate) struct AdminKey;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Some(retry_after) = rate_limit::retry_after(request) {
+            let error = Error::RateLimited(retry_after);
+            return error.into_outcome(request);
+        }
+
+        let config = request
+            .rocket()
+            .state::<Config>()
+            .expect("Config is managed");
+        if config.api_keys.is_empty() {
+            return Outcome::Success(ApiKey(None));
+        }
+
+        let key = request.headers().get_one(API_KEY_HEADER).or_else(|| {
+            request
+                .query_value::<&str>(API_KEY_PARAM)
+                .and_then(Result::ok)
+        });
+        let Some(api_key_config) =
+            key.and_then(|key| config.api_keys.iter().find(|api_key| api_key.key == key))
+        else {
+            let error = Error::Unauthorized;
+            return error.into_outcome(request);
+        };
+
+        if let Some(rate_limit) = api_key_config.rate_limit {
+            let usage = request
+                .rocket()
+                .state::<ApiKeyUsage>()
+                .expect("API key usage is managed");
+            if let Err(error) = usage.register(&api_key_config.key, rate_limit) {
+                if let Error::RateLimited(retry_after) = error {
+                    rate_limit::set_retry_after(request, retry_after);
+                }
+                return error.into_outcome(request);
+            }
+        }
+
+        Outcome::Success(ApiKey(Some(api_key_config.key.clone())))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminKey {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ApiKey(key) = match request.guard::<ApiKey>().await {
+            Outcome::Success(api_key) => api_key,
+            Outcome::Error(error) => return Outcome::Error(error),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        let config = request
+            .rocket()
+            .state::<Config>()
+            .expect("Config is managed");
+        let is_admin = key.is_some_and(|key| {
+            config
+                .api_keys
+                .iter()
+                .any(|api_key| api_key.key == key && api_key.admin)
+        });
+        if is_admin {
+            Outcome::Success(AdminKey)
+        } else {
+            let error = Error::Forbidden;
+            error.into_outcome(request)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn register() {
+        let usage = ApiKeyUsage::default();
+
+        assert_matches!(usage.register("key1", 2), Ok(()));
+        assert_matches!(usage.register("key1", 2), Ok(()));
+        assert_matches!(usage.register("key1", 2), Err(Error::RateLimited(60)));
+
+        // The usage is per API key.
+        assert_matches!(usage.register("key2", 2), Ok(()));
+    }
+}
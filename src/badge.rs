@@ -0,0 +1,150 @@
+//! Forecast badges.
+//!
+//! This module is used to render a small PNG badge in the style of shields.io that shows the
+//! current value of a metric, color-coded by its classification, for embedding in wikis and
+//! dashboards.
+
+use chrono::{DateTime, Utc};
+use image::{DynamicImage, Rgba};
+
+use crate::forecast::{Forecast, Metric};
+use crate::i18n::Locale;
+use crate::labels;
+use crate::maps::{self, MapFormat};
+use crate::sensor::current_index;
+use crate::Result;
+
+/// The scale of the font used on badges.
+const BADGE_SCALE: u32 = 2;
+
+/// The color of the name part of a badge.
+const NAME_COLOR: [u8; 3] = [0x55, 0x55, 0x55];
+
+/// The color of the value part of a badge if there is no value or it cannot be classified.
+const UNKNOWN_COLOR: [u8; 3] = [0x9f, 0x9f, 0x9f];
+
+/// The colors of the value part of a badge, from the lowest to the highest class.
+const CLASS_COLORS: [[u8; 3]; 4] = [
+    [0x44, 0xcc, 0x11],
+    [0xdf, 0xb3, 0x17],
+    [0xfe, 0x7d, 0x37],
+    [0xe0, 0x5d, 0x44],
+];
+
+/// Renders the badge for the provided metric of the forecast as PNG image.
+///
+/// The badge shows the name of the metric and its current value with its label, see
+/// [`current_index`]. The value part is colored from green to red by its class, or gray if there
+/// is no value.
+pub(crate) fn render(forecast: &Forecast, metric: Metric, now: DateTime<Utc>) -> Result<Vec<u8>> {
+    let name = metric.to_string().replace('_', " ").to_uppercase();
+    let value = forecast
+        .series(metric)
+        .and_then(|series| current_index(&series, now).map(|index| series[index].1));
+    let (text, color) = match value {
+        Some(value) => (value_text(metric, value), class_color(metric, value)),
+        None => (String::from("N/A"), UNKNOWN_COLOR),
+    };
+
+    let (name_width, text_width) = (part_width(&name), part_width(&text));
+    let height = 9 * BADGE_SCALE;
+    let mut image = DynamicImage::new_rgba8(name_width + text_width, height);
+    draw_part(&mut image, 0, name_width, &name, NAME_COLOR);
+    draw_part(&mut image, name_width, text_width, &text, color);
+
+    Ok(MapFormat::Png.encode(image)?)
+}
+
+/// Returns the text showing the value and its (English) label.
+///
+/// The value is rounded to at most one decimal.
+fn value_text(metric: Metric, value: f32) -> String {
+    let value = (value * 10.0).round() / 10.0;
+    match labels::label(metric, value, Locale::En) {
+        Some(label) => format!("{value} {}", label.to_uppercase()),
+        None => value.to_string(),
+    }
+}
+
+/// Returns the color of the value part for the class of the value.
+fn class_color(metric: Metric, value: f32) -> [u8; 3] {
+    let Some((index, count)) = labels::class(metric, value) else {
+        return UNKNOWN_COLOR;
+    };
+    let last = CLASS_COLORS.len() - 1;
+    let color_index = (index * last + (count - 1) / 2) / (count - 1).max(1);
+
+    CLASS_COLORS[color_index.min(last)]
+}
+
+/// Returns the width (in pixels) of a part of the badge with the provided text.
+fn part_width(text: &str) -> u32 {
+    (text.chars().count() as u32 * 4 + 5) * BADGE_SCALE
+}
+
+/// Draws a part of the badge: the text in white on a background of the provided color.
+fn draw_part(image: &mut DynamicImage, x: u32, width: u32, text: &str, [r, g, b]: [u8; 3]) {
+    let height = 9 * BADGE_SCALE;
+    maps::fill_rect(image, (x, 0), (width, height), Rgba::from([r, g, b, 0xff]));
+
+    let white = Rgba::from([0xff, 0xff, 0xff, 0xff]);
+    let origin = (x + 3 * BADGE_SCALE, 2 * BADGE_SCALE);
+    maps::draw_glyphs(image, origin, text, white, BADGE_SCALE);
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+    use image::GenericImageView;
+
+    use super::*;
+    use crate::position::Position;
+    use crate::providers::buienradar::Sample;
+
+    #[test]
+    fn render() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let samples = Vec::from([Sample::new(t_0, 8)]);
+        let forecast = Forecast::with_uvi(Position::new(51.4, 5.5), t_0.timestamp(), samples);
+
+        // The badge shows "UVI" and "8 VERY HIGH" on an orange background.
+        let now = t_0 + Duration::hours(12);
+        let png = super::render(&forecast, Metric::UVI, now).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(
+            image.dimensions(),
+            (part_width("UVI") + part_width("8 VERY HIGH"), 18)
+        );
+        let [r, g, b] = CLASS_COLORS[2];
+        assert_eq!(image.get_pixel(part_width("UVI"), 0), Rgba([r, g, b, 0xff]));
+
+        // Without items, the value is not available.
+        let png = super::render(&forecast, Metric::Pollen, now).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        let [r, g, b] = UNKNOWN_COLOR;
+        assert_eq!(
+            image.get_pixel(part_width("POLLEN"), 0),
+            Rgba([r, g, b, 0xff])
+        );
+    }
+
+    #[test]
+    fn value_text() {
+        assert_eq!(super::value_text(Metric::UVI, 8.0), "8 VERY HIGH");
+        assert_eq!(
+            super::value_text(Metric::Precipitation, 1.25),
+            "1.3 MODERATE RAIN"
+        );
+        assert_eq!(super::value_text(Metric::All, 1.0), "1");
+    }
+
+    #[test]
+    fn class_color() {
+        // The classes are spread over the colors from green to red.
+        assert_eq!(super::class_color(Metric::UVI, 1.0), CLASS_COLORS[0]);
+        assert_eq!(super::class_color(Metric::UVI, 8.0), CLASS_COLORS[2]);
+        assert_eq!(super::class_color(Metric::UVI, 11.0), CLASS_COLORS[3]);
+        assert_eq!(super::class_color(Metric::Pollen, 5.0), CLASS_COLORS[1]);
+        assert_eq!(super::class_color(Metric::All, 1.0), UNKNOWN_COLOR);
+    }
+}
@@ -0,0 +1,103 @@
+//! Binary serialization formats.
+//!
+//! This module is used to serialize responses as [MessagePack](https://msgpack.org/) or
+//! [CBOR](https://www.rfc-editor.org/rfc/rfc8949) instead of JSON, which cuts the payload size and
+//! parse cost for embedded clients. Structs are serialized as maps keyed by their field names, so
+//! that the structure is exactly the same as that of the JSON responses.
+
+use rocket::serde::Serialize;
+
+use crate::Result;
+
+/// Serializes the value as MessagePack.
+pub(crate) fn to_msgpack<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    Ok(rmp_serde::to_vec_named(value)?)
+}
+
+/// Serializes the value as CBOR.
+///
+/// Floats are encoded in the smallest representation that is lossless.
+pub(crate) fn to_cbor<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ciborium::into_writer(value, &mut data)?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::serde::json::json;
+    use rocket::serde::Serialize;
+
+    #[derive(Serialize)]
+    #[serde(crate = "rocket::serde")]
+    struct Item {
+        time: i64,
+        value: f32,
+        label: Option<&'static str>,
+    }
+
+    #[test]
+    fn to_msgpack() {
+        let value = json!({"a": 1, "b": [true, null], "c": -1, "e": "hi"});
+        assert_eq!(
+            super::to_msgpack(&value).unwrap(),
+            [
+                0x84, 0xa1, b'a', 0x01, 0xa1, b'b', 0x92, 0xc3, 0xc0, 0xa1, b'c', 0xff, 0xa1, b'e',
+                0xa2, b'h', b'i'
+            ]
+        );
+
+        // Structs are maps keyed by field name; integers use the smallest representation and
+        // single-precision floats stay single-precision.
+        let item = Item {
+            time: 1_654_524_574,
+            value: 1.5,
+            label: None,
+        };
+        assert_eq!(
+            super::to_msgpack(&item).unwrap(),
+            [
+                0x83, 0xa4, b't', b'i', b'm', b'e', 0xce, 0x62, 0x9e, 0x0a, 0x9e, 0xa5, b'v', b'a',
+                b'l', b'u', b'e', 0xca, 0x3f, 0xc0, 0x00, 0x00, 0xa5, b'l', b'a', b'b', b'e', b'l',
+                0xc0
+            ]
+        );
+        assert_eq!(super::to_msgpack(&-200).unwrap(), [0xd1, 0xff, 0x38]);
+    }
+
+    #[test]
+    fn to_cbor() {
+        // These are examples from RFC 8949, appendix A.
+        assert_eq!(super::to_cbor(&0).unwrap(), [0x00]);
+        assert_eq!(super::to_cbor(&24).unwrap(), [0x18, 0x18]);
+        assert_eq!(
+            super::to_cbor(&1_000_000).unwrap(),
+            [0x1a, 0x00, 0x0f, 0x42, 0x40]
+        );
+        assert_eq!(super::to_cbor(&-1).unwrap(), [0x20]);
+        assert_eq!(super::to_cbor(&-1000).unwrap(), [0x39, 0x03, 0xe7]);
+        assert_eq!(super::to_cbor(&1.5).unwrap(), [0xf9, 0x3e, 0x00]);
+        assert_eq!(
+            super::to_cbor(&100_000.0).unwrap(),
+            [0xfa, 0x47, 0xc3, 0x50, 0x00]
+        );
+        assert_eq!(
+            super::to_cbor(&1.1).unwrap(),
+            [0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]
+        );
+        assert_eq!(super::to_cbor(&json!(null)).unwrap(), [0xf6]);
+        assert_eq!(
+            super::to_cbor(&"IETF").unwrap(),
+            [0x64, b'I', b'E', b'T', b'F']
+        );
+        assert_eq!(
+            super::to_cbor(&json!([1, [2, 3], [4, 5]])).unwrap(),
+            [0x83, 0x01, 0x82, 0x02, 0x03, 0x82, 0x04, 0x05]
+        );
+        assert_eq!(
+            super::to_cbor(&json!({"a": 1, "b": [2, 3]})).unwrap(),
+            [0xa2, 0x61, b'a', 0x01, 0x61, b'b', 0x82, 0x02, 0x03]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Calendar feed generation.
+//!
+//! This module is used to construct an iCalendar feed with events for the periods where the
+//! forecast of a metric exceeds a threshold, see [RFC 5545](https://www.rfc-editor.org/rfc/rfc5545).
+
+use chrono::{DateTime, Utc};
+
+use crate::forecast::Metric;
+use crate::position::Position;
+use crate::thresholds::periods;
+
+/// The date/time format used in iCalendar (in the UTC time zone).
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Returns an iCalendar feed with an event per period where the series exceeds the threshold.
+pub(crate) fn calendar(
+    position: Position,
+    metric: Metric,
+    threshold: f32,
+    series: &[(DateTime<Utc>, f32)],
+) -> String {
+    let now = Utc::now().format(DATE_TIME_FORMAT);
+    let mut lines = Vec::from([
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        format!("PRODID:-//Sinoptik//{}//EN", env!("CARGO_PKG_VERSION")),
+        format!("X-WR-CALNAME:Sinoptik {metric} above {threshold}"),
+    ]);
+
+    for period in periods(series, threshold) {
+        lines.extend([
+            String::from("BEGIN:VEVENT"),
+            format!(
+                "UID:{metric}-{}-{}-{}@sinoptik",
+                period.start.timestamp(),
+                position.lat_as_str(5),
+                position.lon_as_str(5)
+            ),
+            format!("DTSTAMP:{now}"),
+            format!("DTSTART:{}", period.start.format(DATE_TIME_FORMAT)),
+            format!("DTEND:{}", period.end.format(DATE_TIME_FORMAT)),
+            format!("SUMMARY:High {metric} forecast ({})", period.max_value),
+            format!(
+                "DESCRIPTION:The {metric} forecast exceeds {threshold} with a maximum of {}.",
+                period.max_value
+            ),
+            format!("GEO:{};{}", position.lat_as_str(5), position.lon_as_str(5)),
+            String::from("END:VEVENT"),
+        ]);
+    }
+    lines.push(String::from("END:VCALENDAR"));
+
+    // Lines in iCalendar are delimited by CRLF.
+    lines.into_iter().map(|line| line + "\r\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn calendar() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 4, 10, 10, 0, 0).unwrap();
+        let position = Position::new(51.44, 5.47);
+        let ical = super::calendar(position, Metric::Pollen, 7.0, &[(t_0, 8.0)]);
+
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ical.ends_with("END:VCALENDAR\r\n"));
+        assert!(ical.contains("UID:pollen-1712743200-51.44000-5.47000@sinoptik\r\n"));
+        assert!(ical.contains("DTSTART:20240410T100000Z\r\n"));
+        assert!(ical.contains("DTEND:20240410T110000Z\r\n"));
+        assert!(ical.contains("SUMMARY:High pollen forecast (8)\r\n"));
+    }
+}
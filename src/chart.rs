@@ -0,0 +1,404 @@
+//! Forecast charts.
+//!
+//! This module is used to render the forecast of a metric as a simple chart image on the server,
+//! for clients that cannot run a charting library, such as e-ink displays and chat bots. The
+//! precipitation is rendered as a bar chart, all other metrics as a line chart.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use image::{DynamicImage, Rgba};
+
+use crate::forecast::Metric;
+use crate::maps::{self, MapFormat};
+use crate::Result;
+
+/// The width of a chart (in pixels).
+const CHART_WIDTH: u32 = 480;
+
+/// The height of a chart (in pixels).
+const CHART_HEIGHT: u32 = 240;
+
+/// The margins around the plot area of a chart: left, top, right and bottom (in pixels).
+const MARGINS: (u32, u32, u32, u32) = (48, 24, 16, 24);
+
+/// The scale of the font used on PNG charts.
+const CHART_SCALE: u32 = 2;
+
+/// The color of the title, axes and labels of a chart.
+const AXIS_COLOR: [u8; 3] = [0x55, 0x55, 0x55];
+
+/// The color of the bars or line of a chart.
+const DATA_COLOR: [u8; 3] = [0x15, 0x65, 0xc0];
+
+/// The image format of a chart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, rocket::FromFormField)]
+pub(crate) enum ChartFormat {
+    /// The PNG image format.
+    #[default]
+    Png,
+
+    /// The SVG image format.
+    Svg,
+}
+
+/// The layout of a chart: maps the times and values of a series to pixel coordinates.
+#[derive(Debug)]
+struct Layout {
+    /// The time of the first and last item.
+    times: (DateTime<Utc>, DateTime<Utc>),
+
+    /// The lowest and highest value on the vertical axis.
+    values: (f32, f32),
+
+    /// The number of items.
+    count: usize,
+}
+
+impl Layout {
+    /// Determines the layout for the provided series.
+    ///
+    /// The vertical axis always includes zero and spans at least 1 unit, so that e.g. a dry
+    /// precipitation forecast is not stretched.
+    fn new(series: &[(DateTime<Utc>, f32)]) -> Self {
+        let first = series.first().map_or_else(Utc::now, |&(time, _)| time);
+        let last = series.last().map_or(first, |&(time, _)| time);
+        let min = series.iter().map(|&(_, value)| value).fold(0.0, f32::min);
+        let max = series
+            .iter()
+            .map(|&(_, value)| value)
+            .fold(0.0, f32::max)
+            .max(min + 1.0);
+
+        Self {
+            times: (first, last),
+            values: (min, max),
+            count: series.len(),
+        }
+    }
+
+    /// Returns the horizontal pixel coordinate of the provided time.
+    ///
+    /// A series of a single item is centered.
+    fn x(&self, time: DateTime<Utc>) -> f32 {
+        let (left, _, right, _) = MARGINS;
+        let width = (CHART_WIDTH - left - right) as f32;
+        let span = (self.times.1 - self.times.0).num_seconds() as f32;
+        if span == 0.0 {
+            return left as f32 + width / 2.0;
+        }
+
+        left as f32 + width * (time - self.times.0).num_seconds() as f32 / span
+    }
+
+    /// Returns the vertical pixel coordinate of the provided value.
+    fn y(&self, value: f32) -> f32 {
+        let (_, top, _, bottom) = MARGINS;
+        let height = (CHART_HEIGHT - top - bottom) as f32;
+        let (min, max) = self.values;
+
+        (CHART_HEIGHT - bottom) as f32 - height * (value - min) / (max - min)
+    }
+
+    /// Returns the points of the line through the items of the series.
+    fn points(&self, series: &[(DateTime<Utc>, f32)]) -> Vec<(f32, f32)> {
+        series
+            .iter()
+            .map(|&(time, value)| (self.x(time), self.y(value)))
+            .collect()
+    }
+
+    /// Returns the bars (origin and size) for the items of the series.
+    ///
+    /// Each item gets an equal part of the plot area, so the bars are as wide as possible.
+    fn bars(&self, series: &[(DateTime<Utc>, f32)]) -> Vec<(f32, f32, f32, f32)> {
+        let (left, _, right, _) = MARGINS;
+        let slot = (CHART_WIDTH - left - right) as f32 / self.count.max(1) as f32;
+        let baseline = self.y(0.0);
+
+        series
+            .iter()
+            .enumerate()
+            .map(|(index, &(_, value))| {
+                let x = left as f32 + index as f32 * slot + 1.0;
+                let y = self.y(value);
+                (
+                    x,
+                    y.min(baseline),
+                    (slot - 2.0).max(1.0),
+                    (y - baseline).abs(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the labels of the lowest and highest value on the vertical axis.
+    fn value_labels(&self) -> (String, String) {
+        let round = |value: f32| (value * 10.0).round() / 10.0;
+        let (min, max) = self.values;
+
+        (round(min).to_string(), round(max).to_string())
+    }
+
+    /// Returns the labels of the first and last time on the horizontal axis (in UTC).
+    fn time_labels(&self) -> (String, String) {
+        let (first, last) = self.times;
+
+        (
+            first.format("%H:%M").to_string(),
+            last.format("%H:%M UTC").to_string(),
+        )
+    }
+}
+
+/// Returns whether the series of the provided metric is rendered as a bar chart.
+fn is_bar_chart(metric: Metric) -> bool {
+    metric == Metric::Precipitation
+}
+
+/// Returns the title of the chart for the provided metric.
+fn title(metric: Metric) -> String {
+    metric.to_string().replace('_', " ").to_uppercase()
+}
+
+/// Renders the chart of the series of the provided metric as PNG image.
+pub(crate) fn render_png(metric: Metric, series: &[(DateTime<Utc>, f32)]) -> Result<Vec<u8>> {
+    let layout = Layout::new(series);
+    let [r, g, b] = AXIS_COLOR;
+    let axis_color = Rgba::from([r, g, b, 0xff]);
+    let [r, g, b] = DATA_COLOR;
+    let data_color = Rgba::from([r, g, b, 0xff]);
+    let mut image = DynamicImage::new_rgba8(CHART_WIDTH, CHART_HEIGHT);
+    let white = Rgba::from([0xff, 0xff, 0xff, 0xff]);
+    maps::fill_rect(&mut image, (0, 0), (CHART_WIDTH, CHART_HEIGHT), white);
+
+    if is_bar_chart(metric) {
+        for (x, y, width, height) in layout.bars(series) {
+            let origin = (x.round() as u32, y.round() as u32);
+            let size = (width.round() as u32, height.round() as u32);
+            maps::fill_rect(&mut image, origin, size, data_color);
+        }
+    } else {
+        let points = layout.points(series);
+        for (index, &point) in points.iter().enumerate() {
+            let next = points.get(index + 1).copied().unwrap_or(point);
+            draw_line(&mut image, point, next, data_color);
+        }
+    }
+
+    // Draw the axes, the title and the labels.
+    let (left, top, right, bottom) = MARGINS;
+    let baseline = layout.y(0.0).round() as u32;
+    maps::fill_rect(
+        &mut image,
+        (left, top),
+        (1, CHART_HEIGHT - top - bottom),
+        axis_color,
+    );
+    maps::fill_rect(
+        &mut image,
+        (left, baseline),
+        (CHART_WIDTH - left - right, 1),
+        axis_color,
+    );
+    let glyph_height = 5 * CHART_SCALE;
+    let text_width = |text: &str| text.chars().count() as u32 * 4 * CHART_SCALE;
+    maps::draw_glyphs(
+        &mut image,
+        (left, 6),
+        &title(metric),
+        axis_color,
+        CHART_SCALE,
+    );
+    let (min, max) = layout.value_labels();
+    for (text, y) in [
+        (min, layout.y(layout.values.0)),
+        (max, layout.y(layout.values.1)),
+    ] {
+        let x = left.saturating_sub(text_width(&text) + 4);
+        let y = (y.round() as u32).saturating_sub(glyph_height / 2);
+        maps::draw_glyphs(&mut image, (x, y), &text, axis_color, CHART_SCALE);
+    }
+    if !series.is_empty() {
+        let (first, last) = layout.time_labels();
+        let y = CHART_HEIGHT - bottom + 8;
+        maps::draw_glyphs(&mut image, (left, y), &first, axis_color, CHART_SCALE);
+        let x = (CHART_WIDTH - right).saturating_sub(text_width(&last));
+        maps::draw_glyphs(&mut image, (x, y), &last, axis_color, CHART_SCALE);
+    }
+
+    Ok(MapFormat::Png.encode(image)?)
+}
+
+/// Draws a line of 2 pixels wide between the provided points.
+fn draw_line(image: &mut DynamicImage, from: (f32, f32), to: (f32, f32), color: Rgba<u8>) {
+    let steps = (to.0 - from.0)
+        .abs()
+        .max((to.1 - from.1).abs())
+        .ceil()
+        .max(1.0) as u32;
+    for step in 0..=steps {
+        let fraction = step as f32 / steps as f32;
+        let x = from.0 + (to.0 - from.0) * fraction;
+        let y = from.1 + (to.1 - from.1) * fraction;
+        let origin = (x.round().max(1.0) as u32 - 1, y.round().max(1.0) as u32 - 1);
+        maps::fill_rect(image, origin, (2, 2), color);
+    }
+}
+
+/// Renders the chart of the series of the provided metric as SVG image.
+pub(crate) fn render_svg(metric: Metric, series: &[(DateTime<Utc>, f32)]) -> String {
+    let layout = Layout::new(series);
+    let axis_color = hex_color(AXIS_COLOR);
+    let data_color = hex_color(DATA_COLOR);
+    let (left, top, right, bottom) = MARGINS;
+    let baseline = layout.y(0.0);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" \
+         height=\"{CHART_HEIGHT}\" viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\" \
+         font-family=\"sans-serif\" font-size=\"11\" fill=\"{axis_color}\">\n"
+    );
+    svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>\n");
+    if is_bar_chart(metric) {
+        for (x, y, width, height) in layout.bars(series) {
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{x:.1}\" y=\"{y:.1}\" width=\"{width:.1}\" height=\"{height:.1}\" \
+                 fill=\"{data_color}\"/>"
+            );
+        }
+    } else {
+        let points = layout
+            .points(series)
+            .into_iter()
+            .map(|(x, y)| format!("{x:.1},{y:.1}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(
+            svg,
+            "<polyline points=\"{points}\" fill=\"none\" stroke=\"{data_color}\" \
+             stroke-width=\"2\"/>"
+        );
+    }
+
+    // Draw the axes, the title and the labels.
+    let _ = writeln!(
+        svg,
+        "<path d=\"M{left} {top}V{bottom_y}M{left} {baseline:.1}H{right_x}\" \
+         stroke=\"{axis_color}\"/>",
+        bottom_y = CHART_HEIGHT - bottom,
+        right_x = CHART_WIDTH - right,
+    );
+    let _ = writeln!(svg, "<text x=\"{left}\" y=\"16\">{}</text>", title(metric));
+    let (min, max) = layout.value_labels();
+    for (text, y) in [
+        (min, layout.y(layout.values.0)),
+        (max, layout.y(layout.values.1)),
+    ] {
+        let _ = writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{text}</text>",
+            left - 4,
+            y + 4.0
+        );
+    }
+    if !series.is_empty() {
+        let (first, last) = layout.time_labels();
+        let y = CHART_HEIGHT - bottom + 16;
+        let _ = writeln!(svg, "<text x=\"{left}\" y=\"{y}\">{first}</text>");
+        let _ = writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{y}\" text-anchor=\"end\">{last}</text>",
+            CHART_WIDTH - right
+        );
+    }
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// Returns the provided color in hexadecimal notation, e.g. `#1565c0`.
+fn hex_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+    use image::GenericImageView;
+
+    use super::*;
+
+    fn series() -> Vec<(DateTime<Utc>, f32)> {
+        let t_0 = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+
+        (0..4)
+            .map(|hour| (t_0 + Duration::hours(hour), hour as f32 * 2.0))
+            .collect()
+    }
+
+    #[test]
+    fn layout() {
+        let series = series();
+        let layout = Layout::new(&series);
+        let (left, top, right, bottom) = MARGINS;
+        assert_eq!(layout.values, (0.0, 6.0));
+        assert_eq!(layout.x(series[0].0), left as f32);
+        assert_eq!(layout.x(series[3].0), (CHART_WIDTH - right) as f32);
+        assert_eq!(layout.y(0.0), (CHART_HEIGHT - bottom) as f32);
+        assert_eq!(layout.y(6.0), top as f32);
+        assert_eq!(
+            layout.time_labels(),
+            (String::from("12:00"), String::from("15:00 UTC"))
+        );
+
+        // The bars divide the plot area, starting at the baseline.
+        let bars = layout.bars(&series);
+        assert_eq!(bars.len(), 4);
+        assert_eq!(bars[0].3, 0.0);
+        assert_eq!(bars[3].1, top as f32);
+        assert_eq!(bars[3].1 + bars[3].3, layout.y(0.0));
+
+        // The vertical axis includes zero and spans at least 1 unit.
+        let layout = Layout::new(&series[..1]);
+        assert_eq!(layout.values, (0.0, 1.0));
+        let (x, _) = layout.points(&series[..1])[0];
+        assert_eq!(x, (left + (CHART_WIDTH - left - right) / 2) as f32);
+        let layout = Layout::new(&[(series[0].0, -2.5)]);
+        assert_eq!(layout.values, (-2.5, 0.0));
+    }
+
+    #[test]
+    fn render_png() {
+        let png = super::render_png(Metric::Precipitation, &series()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.dimensions(), (CHART_WIDTH, CHART_HEIGHT));
+
+        // The highest bar reaches the top of the plot area.
+        let [r, g, b] = DATA_COLOR;
+        let (_, top, right, _) = MARGINS;
+        assert_eq!(
+            image.get_pixel(CHART_WIDTH - right - 10, top + 1),
+            Rgba([r, g, b, 0xff])
+        );
+
+        // An empty series results in an empty chart.
+        let png = super::render_png(Metric::UVI, &[]).unwrap();
+        assert!(image::load_from_memory(&png).is_ok());
+    }
+
+    #[test]
+    fn render_svg() {
+        let svg = super::render_svg(Metric::Precipitation, &series());
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert_eq!(svg.matches("fill=\"#1565c0\"").count(), 4);
+        assert!(svg.contains(">PRECIPITATION</text>"));
+        assert!(svg.contains(">15:00 UTC</text>"));
+        assert!(svg.ends_with("</svg>\n"));
+
+        let svg = super::render_svg(Metric::UVI, &series());
+        assert!(svg.contains("<polyline points=\"48.0,216.0 186.7,152.0 "));
+        assert!(!svg.contains("<rect x="));
+    }
+}
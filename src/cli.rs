@@ -0,0 +1,436 @@
+//! The command-line interface.
+//!
+//! Without a command, the HTTP server is started. The `forecast` command retrieves a forecast once,
+//! prints it to standard output and exits without starting the server, which is useful for cron
+//! jobs and shell scripts.
+
+use std::process::ExitCode;
+use std::sync::{Arc, RwLock};
+
+use rocket::form::{FromFormField, ValueField};
+use rocket::serde::json::serde_json;
+
+use crate::config::Config;
+use crate::forecast::{forecast, Metric, TimeWindow};
+use crate::geocoder::Geocoder;
+use crate::http::HttpClient;
+use crate::maps::Maps;
+#[cfg(feature = "maps")]
+use crate::maps::{self, MapsType};
+use crate::position::{self, GeocodingCache};
+use crate::providers::{circuit_breaker, combined, mock};
+use crate::server::{setup, Location};
+use crate::{consensus, geocoder, http, logging, nowcast, providers, shared_cache, Result};
+
+/// The usage information of the command-line interface.
+const USAGE: &str = "\
+Usage: sinoptik [forecast OPTIONS]
+
+Without a command, the HTTP server is started.
+
+Commands:
+  forecast  Retrieve the forecast once and print it to standard output
+
+Forecast options:
+  --address <ADDRESS>    The address to retrieve the forecast for
+  --postcode <POSTCODE>  The Dutch postal code to retrieve the forecast for
+  --lat <LAT>            The latitude of the position to retrieve the forecast for
+  --lon <LON>            The longitude of the position to retrieve the forecast for
+  --metrics <METRICS>    Comma-separated list of metrics to retrieve [default: all]
+  --hours <HOURS>        Only include the items of the next number of hours
+  --format <FORMAT>      The output format: json, csv or table [default: json]
+  --strict               Fail if the forecast for any of the metrics could not be retrieved
+  -h, --help             Print this help";
+
+/// The possible command-line argument errors that can occur.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub(crate) enum ArgsError {
+    /// The location is ambiguous, because multiple locations were provided.
+    #[error("Multiple locations provided, use one of --address, --postcode or --lat and --lon")]
+    ConflictingLocations,
+
+    /// Encountered an invalid value for an option.
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+
+    /// No location was provided.
+    #[error("No location provided, use --address, --postcode or --lat and --lon")]
+    MissingLocation,
+
+    /// No value was provided for an option.
+    #[error("Missing value for {0}")]
+    MissingValue(String),
+
+    /// Encountered an unknown command or option.
+    #[error("Unknown argument: {0}")]
+    UnknownArgument(String),
+}
+
+/// The output formats of the forecast command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// The forecast as pretty-printed JSON, like the forecast API endpoint returns.
+    #[default]
+    Json,
+    /// A CSV table with a row per timestamp and a column per metric.
+    Csv,
+    /// A human-readable table with aligned columns.
+    Table,
+}
+
+/// The arguments of the forecast command.
+#[derive(Debug, PartialEq)]
+struct ForecastArgs {
+    /// The location to retrieve the forecast for.
+    location: Location,
+
+    /// The metrics to retrieve.
+    metrics: Vec<Metric>,
+
+    /// The number of hours to restrict the forecast items to (if any).
+    hours: Option<u32>,
+
+    /// The output format.
+    format: OutputFormat,
+
+    /// Whether to fail if the forecast for any of the metrics could not be retrieved.
+    strict: bool,
+}
+
+/// The command to run.
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// Starts the HTTP server.
+    Serve,
+
+    /// Prints the usage information.
+    Help,
+
+    /// Retrieves a forecast once and prints it.
+    Forecast(ForecastArgs),
+}
+
+impl Command {
+    /// Parses the command and its options from the command-line arguments.
+    ///
+    /// The arguments should not include the name of the program.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut args = args.into_iter();
+        match args.next().as_deref() {
+            None => Ok(Command::Serve),
+            Some("-h" | "--help" | "help") => Ok(Command::Help),
+            Some("forecast") => ForecastArgs::parse(args),
+            Some(arg) => Err(ArgsError::UnknownArgument(arg.to_string())),
+        }
+    }
+}
+
+impl ForecastArgs {
+    /// Parses the options of the forecast command.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, ArgsError> {
+        fn parse_value<T: std::str::FromStr>(option: &str, value: String) -> Result<T, ArgsError> {
+            value
+                .parse()
+                .map_err(|_| ArgsError::InvalidValue(option.to_string(), value))
+        }
+
+        let (mut address, mut postcode, mut lat, mut lon) = (None, None, None, None);
+        let mut metrics = Vec::new();
+        let mut hours = None;
+        let mut format = OutputFormat::default();
+        let mut strict = false;
+        while let Some(option) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| ArgsError::MissingValue(option.clone()))
+            };
+            match option.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "--address" => address = Some(value()?),
+                "--postcode" => postcode = Some(value()?),
+                "--lat" => lat = Some(parse_value(&option, value()?)?),
+                "--lon" => lon = Some(parse_value(&option, value()?)?),
+                "--metrics" => {
+                    let value = value()?;
+                    for name in value.split(',').map(str::trim) {
+                        let metric = Metric::from_value(ValueField::from_value(name))
+                            .map_err(|_| ArgsError::InvalidValue(option.clone(), name.into()))?;
+                        metrics.push(metric);
+                    }
+                }
+                "--hours" => hours = Some(parse_value(&option, value()?)?),
+                "--format" => {
+                    format = match value()?.as_str() {
+                        "json" => OutputFormat::Json,
+                        "csv" => OutputFormat::Csv,
+                        "table" => OutputFormat::Table,
+                        other => return Err(ArgsError::InvalidValue(option, other.into())),
+                    }
+                }
+                "--strict" => strict = true,
+                _ => return Err(ArgsError::UnknownArgument(option)),
+            }
+        }
+
+        let location = match (address, postcode, lat, lon) {
+            (Some(address), None, None, None) => Location::Address { address },
+            (None, Some(postcode), None, None) => Location::Postcode { postcode },
+            (None, None, Some(lat), Some(lon)) => Location::Geo { lat, lon },
+            (None, None, None, None) => return Err(ArgsError::MissingLocation),
+            (None, None, Some(_), None) => return Err(ArgsError::MissingValue("--lon".into())),
+            (None, None, None, Some(_)) => return Err(ArgsError::MissingValue("--lat".into())),
+            _ => return Err(ArgsError::ConflictingLocations),
+        };
+        if metrics.is_empty() {
+            metrics.push(Metric::All);
+        }
+
+        Ok(Command::Forecast(ForecastArgs {
+            location,
+            metrics,
+            hours,
+            format,
+            strict,
+        }))
+    }
+}
+
+/// Returns the types of maps that need to be retrieved for the provided metrics.
+#[cfg(feature = "maps")]
+fn required_maps_types(metrics: &[Metric]) -> Vec<MapsType> {
+    let requires = |required: &[Metric]| metrics.iter().any(|metric| required.contains(metric));
+    let mut maps_types = Vec::new();
+    if requires(&[Metric::All, Metric::PAQI, Metric::PAQIDaily, Metric::Pollen]) {
+        maps_types.push(MapsType::Pollen);
+    }
+    if requires(&[Metric::All, Metric::Temperature]) {
+        maps_types.push(MapsType::Temperature);
+    }
+    if requires(&[Metric::All, Metric::UVI]) {
+        maps_types.push(MapsType::UVI);
+    }
+
+    maps_types
+}
+
+/// Sets up the global state using the configuration and retrieves the forecast.
+///
+/// Returns the forecast formatted in the requested output format.
+async fn run_forecast(args: ForecastArgs, config: Config) -> Result<String> {
+    http::set_client(HttpClient::new(
+        &config.http,
+        &config.subscriptions_allowed_hosts,
+    )?);
+    shared_cache::connect(&config).await?;
+    position::set_geocoding_cache(GeocodingCache::load(&config.geocoding_cache));
+    geocoder::set_geocoder(Geocoder::new(&config.geocoder));
+    providers::set_base_urls(config.base_urls);
+    providers::set_cache_capacities(config.cache_capacities);
+    providers::set_cache_ttls(config.cache_ttls);
+    providers::set_position_grid(config.position_grid);
+    mock::set_config(config.mock);
+    nowcast::set_rain_threshold(config.rain_threshold);
+    consensus::set_divergence_threshold(config.consensus_divergence);
+    circuit_breaker::set_config(config.circuit_breaker);
+    combined::set_config(config.paqi_merge);
+
+    let maps_handle = Arc::new(RwLock::new(Maps::new(config.maps)));
+    #[cfg(feature = "maps")]
+    for maps_type in required_maps_types(&args.metrics) {
+        maps::refresh(&maps_handle, maps_type).await;
+    }
+
+    let window = TimeWindow::new(None, None, args.hours)?;
+    let position = args.location.resolve().await?;
+    let mut forecast = forecast(position, args.metrics, &maps_handle).await;
+    if args.strict {
+        forecast.check_complete()?;
+    }
+    forecast.retain_window(window);
+
+    match args.format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(&forecast)? + "\n"),
+        OutputFormat::Csv => forecast.to_csv(),
+        OutputFormat::Table => Ok(forecast.to_table()),
+    }
+}
+
+/// Runs the command provided by the command-line arguments.
+///
+/// The arguments should not include the name of the program. Returns the exit code of the process.
+pub async fn run(args: impl IntoIterator<Item = String>) -> ExitCode {
+    let command = match Command::parse(args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("💥 {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match command {
+        Command::Serve => match setup().launch().await {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("💥 {}", e.pretty_print());
+                ExitCode::FAILURE
+            }
+        },
+        Command::Help => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Command::Forecast(args) => {
+            let config = match rocket::Config::figment().extract::<Config>() {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("💥 Invalid configuration: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            // Log to standard error to keep standard output clean for the forecast.
+            logging::init_with_writer(&config, std::io::stderr);
+
+            match run_forecast(args, config).await {
+                Ok(output) => {
+                    print!("{output}");
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("💥 {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, ArgsError> {
+        Command::parse(args.iter().map(ToString::to_string))
+    }
+
+    #[test]
+    fn command_parse() {
+        assert_eq!(parse(&[]), Ok(Command::Serve));
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["forecast", "-h"]), Ok(Command::Help));
+        assert_eq!(
+            parse(&["serve"]),
+            Err(ArgsError::UnknownArgument(String::from("serve")))
+        );
+
+        // By default, all metrics are retrieved and printed as JSON.
+        assert_eq!(
+            parse(&["forecast", "--address", "Eindhoven"]),
+            Ok(Command::Forecast(ForecastArgs {
+                location: Location::Address {
+                    address: String::from("Eindhoven")
+                },
+                metrics: Vec::from([Metric::All]),
+                hours: None,
+                format: OutputFormat::Json,
+                strict: false,
+            }))
+        );
+        assert_eq!(
+            parse(&[
+                "forecast",
+                "--lat",
+                "51.4",
+                "--lon",
+                "5.5",
+                "--metrics",
+                "pollen,UVI",
+                "--hours",
+                "6",
+                "--format",
+                "table",
+                "--strict"
+            ]),
+            Ok(Command::Forecast(ForecastArgs {
+                location: Location::Geo {
+                    lat: 51.4,
+                    lon: 5.5
+                },
+                metrics: Vec::from([Metric::Pollen, Metric::UVI]),
+                hours: Some(6),
+                format: OutputFormat::Table,
+                strict: true,
+            }))
+        );
+    }
+
+    #[test]
+    fn command_parse_errors() {
+        assert_eq!(parse(&["forecast"]), Err(ArgsError::MissingLocation));
+        assert_eq!(
+            parse(&["forecast", "--address", "Eindhoven", "--postcode", "5611AA"]),
+            Err(ArgsError::ConflictingLocations)
+        );
+        assert_eq!(
+            parse(&["forecast", "--lat", "51.4"]),
+            Err(ArgsError::MissingValue(String::from("--lon")))
+        );
+        assert_eq!(
+            parse(&["forecast", "--address"]),
+            Err(ArgsError::MissingValue(String::from("--address")))
+        );
+        assert_eq!(
+            parse(&["forecast", "--lat", "north"]),
+            Err(ArgsError::InvalidValue(
+                String::from("--lat"),
+                String::from("north")
+            ))
+        );
+        assert_eq!(
+            parse(&[
+                "forecast",
+                "--postcode",
+                "5611AA",
+                "--metrics",
+                "pollen,rain"
+            ]),
+            Err(ArgsError::InvalidValue(
+                String::from("--metrics"),
+                String::from("rain")
+            ))
+        );
+        assert_eq!(
+            parse(&["forecast", "--postcode", "5611AA", "--format", "xml"]),
+            Err(ArgsError::InvalidValue(
+                String::from("--format"),
+                String::from("xml")
+            ))
+        );
+        assert_eq!(
+            parse(&["forecast", "--verbose"]),
+            Err(ArgsError::UnknownArgument(String::from("--verbose")))
+        );
+    }
+
+    #[cfg(feature = "maps")]
+    #[test]
+    fn required_maps_types() {
+        assert_eq!(
+            super::required_maps_types(&[Metric::All]),
+            [MapsType::Pollen, MapsType::Temperature, MapsType::UVI]
+        );
+        assert_eq!(
+            super::required_maps_types(&[Metric::PAQI, Metric::UVI]),
+            [MapsType::Pollen, MapsType::UVI]
+        );
+        assert_eq!(
+            super::required_maps_types(&[Metric::Pollen, Metric::AQI]),
+            [MapsType::Pollen]
+        );
+        assert_eq!(super::required_maps_types(&[Metric::UVI]), [MapsType::UVI]);
+        assert_eq!(
+            super::required_maps_types(&[Metric::Temperature]),
+            [MapsType::Temperature]
+        );
+        assert_eq!(super::required_maps_types(&[Metric::Precipitation]), []);
+    }
+}
@@ -0,0 +1,207 @@
+//! Response compression.
+//!
+//! This module provides a fairing that transparently compresses textual responses (such as JSON
+//! and CSV) using Brotli or gzip, depending on what the client accepts per the `Accept-Encoding`
+//! header. Images and streaming responses are never compressed.
+
+use std::io::{Cursor, Write};
+
+use flate2::write::GzEncoder;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header};
+use rocket::{Request, Response};
+use tracing::warn;
+
+/// The minimum size (in bytes) of a response body for it to be compressed.
+const MIN_COMPRESS_SIZE: usize = 512;
+
+/// The Brotli compression quality (0–11).
+const BROTLI_QUALITY: u32 = 5;
+
+/// The Brotli window size (as a power of two).
+const BROTLI_WINDOW_SIZE: u32 = 22;
+
+/// The supported content encodings, in order of preference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    /// Brotli compression.
+    Brotli,
+
+    /// Gzip compression.
+    Gzip,
+}
+
+impl Encoding {
+    /// Returns the name of the encoding as used in the HTTP headers.
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Negotiates the encoding to use given the value of the `Accept-Encoding` header.
+    ///
+    /// Encodings with a quality value of zero are not acceptable.
+    fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let accepted = accept_encoding
+            .split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let name = params.next()?.to_ascii_lowercase();
+                let quality = params
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(1.0, |q| q.parse::<f32>().unwrap_or(0.0));
+
+                (quality > 0.0).then_some(name)
+            })
+            .collect::<Vec<_>>();
+
+        [Encoding::Brotli, Encoding::Gzip]
+            .into_iter()
+            .find(|encoding| {
+                accepted
+                    .iter()
+                    .any(|name| name == encoding.name() || name == "*")
+            })
+    }
+
+    /// Compresses the provided data.
+    fn compress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut output = Vec::new();
+                let mut encoder = brotli::CompressorWriter::new(
+                    &mut output,
+                    4_096,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW_SIZE,
+                );
+                encoder.write_all(data)?;
+                drop(encoder);
+
+                Ok(output)
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Determines whether responses with the provided content type should be compressed.
+fn is_compressible(content_type: &ContentType) -> bool {
+    content_type.is_json()
+        || content_type.is_csv()
+        || content_type.is_xml()
+        || content_type.top() == "text" && content_type.sub() != "event-stream"
+        || content_type.sub().as_str().ends_with("+xml")
+}
+
+/// Fairing that compresses textual responses if the client accepts it.
+#[derive(Debug, Default)]
+pub(crate) struct Compression;
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Response compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.headers().contains("Content-Encoding")
+            || !response
+                .content_type()
+                .as_ref()
+                .is_some_and(is_compressible)
+        {
+            return;
+        }
+        let Some(encoding) = request
+            .headers()
+            .get_one("Accept-Encoding")
+            .and_then(Encoding::negotiate)
+        else {
+            return;
+        };
+
+        // Empty bodies are left alone, as well as streamed bodies, which have no known size.
+        if response.body().is_none() || response.body().preset_size().is_none() {
+            return;
+        }
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                warn!(error = %e, "💥 Failed to read response body for compression");
+                return;
+            }
+        };
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+        if body.len() < MIN_COMPRESS_SIZE {
+            response.set_sized_body(body.len(), Cursor::new(body));
+            return;
+        }
+
+        match encoding.compress(&body) {
+            Ok(compressed) => {
+                response.set_header(Header::new("Content-Encoding", encoding.name()));
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+            }
+            Err(e) => {
+                warn!(error = %e, "💥 Failed to compress response body");
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(
+            Encoding::negotiate("gzip, deflate, br"),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            Encoding::negotiate("gzip;q=0.8, br;q=0"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(Encoding::negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::negotiate("identity"), None);
+        assert_eq!(Encoding::negotiate(""), None);
+    }
+
+    #[test]
+    fn compress() {
+        let data = "{\"time\":1652187600,\"value\":6.09}".repeat(100);
+
+        let compressed = Encoding::Gzip.compress(data.as_bytes()).unwrap();
+        assert!(compressed.len() < data.len());
+        let mut decompressed = String::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+
+        let compressed = Encoding::Brotli.compress(data.as_bytes()).unwrap();
+        assert!(compressed.len() < data.len());
+        let mut decompressed = String::new();
+        brotli::Decompressor::new(&compressed[..], 4_096)
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
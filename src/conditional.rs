@@ -0,0 +1,175 @@
+//! Conditional requests.
+//!
+//! This module provides a request guard for the conditional request headers (`If-None-Match` and
+//! `If-Modified-Since`) and a responder that adds the validators (`ETag` and `Last-Modified`) to a
+//! response, as well as a `Cache-Control` header with the time the response remains fresh. If the
+//! copy of the client is still valid, it responds with `304 Not Modified` and no body instead.
+
+use std::convert::Infallible;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use chrono::{DateTime, Utc};
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+
+/// The format of HTTP dates as used by the `Last-Modified` and `If-Modified-Since` headers.
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Returns a (weak) entity tag for the provided value.
+///
+/// The tag is weak because responses can differ in details that do not change their meaning,
+/// such as the current time and the age of the data.
+pub(crate) fn etag<T: Hash + ?Sized>(value: &T) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+/// A request guard for the conditional request headers of a request.
+#[derive(Debug, Default)]
+pub(crate) struct Preconditions {
+    /// The entity tags of the `If-None-Match` header, if any.
+    if_none_match: Option<Vec<String>>,
+
+    /// The date/time of the `If-Modified-Since` header, if any and valid.
+    if_modified_since: Option<DateTime<Utc>>,
+}
+
+impl Preconditions {
+    /// Determines whether the copy of the client is still valid given the current validators.
+    ///
+    /// If the `If-None-Match` header is present, the `If-Modified-Since` header is ignored.
+    fn is_fresh(&self, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+        let opaque_tag = |tag: &str| tag.trim_start_matches("W/").to_owned();
+
+        match &self.if_none_match {
+            Some(tags) => tags
+                .iter()
+                .any(|tag| tag == "*" || opaque_tag(tag) == opaque_tag(etag)),
+            None => self
+                .if_modified_since
+                .zip(last_modified)
+                .is_some_and(|(since, modified)| modified.timestamp() <= since.timestamp()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preconditions {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        let if_none_match = headers
+            .get_one("If-None-Match")
+            .map(|value| value.split(',').map(|tag| tag.trim().to_owned()).collect());
+        let if_modified_since = headers
+            .get_one("If-Modified-Since")
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(DateTime::from);
+
+        Outcome::Success(Preconditions {
+            if_none_match,
+            if_modified_since,
+        })
+    }
+}
+
+/// A responder that adds validators to the response or responds with `304 Not Modified`.
+#[derive(Debug)]
+pub(crate) struct Conditional<R> {
+    /// The wrapped response; [`None`] if the copy of the client is still valid.
+    response: Option<R>,
+
+    /// The entity tag of the response.
+    etag: String,
+
+    /// The date/time the data of the response was last modified, if known.
+    last_modified: Option<DateTime<Utc>>,
+
+    /// The number of seconds the response remains fresh, if known.
+    max_age: Option<u64>,
+}
+
+impl<R> Conditional<R> {
+    /// Creates a conditional response for the provided response and its validators.
+    pub(crate) fn new(
+        response: R,
+        etag: String,
+        last_modified: Option<DateTime<Utc>>,
+        preconditions: &Preconditions,
+    ) -> Self {
+        let response = (!preconditions.is_fresh(&etag, last_modified)).then_some(response);
+
+        Self {
+            response,
+            etag,
+            last_modified,
+            max_age: None,
+        }
+    }
+
+    /// Sets the number of seconds the response remains fresh.
+    pub(crate) fn with_max_age(self, max_age: u64) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Conditional<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = match self.response {
+            Some(response) => response.respond_to(request)?,
+            None => Response::build().status(Status::NotModified).finalize(),
+        };
+        response.set_header(Header::new("ETag", self.etag));
+        if let Some(last_modified) = self.last_modified {
+            let last_modified = last_modified.format(HTTP_DATE_FORMAT).to_string();
+            response.set_header(Header::new("Last-Modified", last_modified));
+        }
+        if let Some(max_age) = self.max_age {
+            response.set_header(Header::new("Cache-Control", format!("max-age={max_age}")));
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn is_fresh() {
+        let etag = etag("data");
+        let modified = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+
+        // Without conditional headers, the copy of the client is never valid.
+        let preconditions = Preconditions::default();
+        assert!(!preconditions.is_fresh(&etag, Some(modified)));
+
+        // Entity tags are compared weakly.
+        let preconditions = Preconditions {
+            if_none_match: Some(vec![String::from("\"other\""), etag.replace("W/", "")]),
+            if_modified_since: None,
+        };
+        assert!(preconditions.is_fresh(&etag, Some(modified)));
+        assert!(!preconditions.is_fresh("W/\"other-tag\"", Some(modified)));
+
+        // The modification date is only used if there are no entity tags.
+        let preconditions = Preconditions {
+            if_none_match: None,
+            if_modified_since: Some(modified),
+        };
+        assert!(preconditions.is_fresh(&etag, Some(modified)));
+        assert!(!preconditions.is_fresh(&etag, Some(modified + chrono::Duration::seconds(1))));
+        assert!(!preconditions.is_fresh(&etag, None));
+    }
+}
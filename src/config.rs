@@ -0,0 +1,823 @@
+//! Service configuration.
+//!
+//! The configuration is extracted from the Rocket configuration, i.e. `Rocket.toml` and/or
+//! `ROCKET_*` environment variables, see <https://rocket.rs/v0.5/guide/configuration/>.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::Duration;
+use reqwest::Url;
+use rocket::serde::{Deserialize, Deserializer, Serialize};
+
+use crate::forecast::Metric;
+use crate::geocoder::GeocoderBackend;
+#[cfg(feature = "recording")]
+use crate::http::recording::RecordingMode;
+use crate::logging::{LogFormat, DEFAULT_LOG_FILTER};
+use crate::position::BoundingBox;
+use crate::providers::buienradar::{
+    BUIENRADAR_BASE_URL, POLLEN_BASE_URL, PRECIPITATION_BASE_URL, TEMPERATURE_BASE_URL,
+    UVI_BASE_URL,
+};
+use crate::providers::luchtmeetnet::LUCHTMEETNET_BASE_URL;
+use crate::providers::open_meteo::OPEN_METEO_BASE_URL;
+use crate::shared_cache::CacheBackend;
+
+/// The Sinoptik-specific configuration.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct Config {
+    /// The API keys that are allowed to access the data endpoints.
+    ///
+    /// If empty, authentication is disabled.
+    pub(crate) api_keys: Vec<ApiKeyConfig>,
+
+    /// The base URLs of the upstream APIs.
+    pub(crate) base_urls: BaseUrls,
+
+    /// The cache backend used in addition to the in-memory caches.
+    pub(crate) cache_backend: CacheBackend,
+
+    /// The maximum number of entries of the provider caches.
+    pub(crate) cache_capacities: CacheCapacities,
+
+    /// The times (in seconds) the provider results are cached for.
+    pub(crate) cache_ttls: CacheTtls,
+
+    /// The configuration of the circuit breakers of the providers.
+    pub(crate) circuit_breaker: CircuitBreakerConfig,
+
+    /// The mean difference (in mm/h) between the precipitation providers at or above which they
+    /// are considered to diverge.
+    pub(crate) consensus_divergence: f32,
+
+    /// The configuration of the exporter of forecast values as Prometheus gauges.
+    ///
+    /// If not set, the exporter is disabled.
+    pub(crate) exporter: Option<ExporterConfig>,
+
+    /// The configuration of the geocoder.
+    pub(crate) geocoder: GeocoderConfig,
+
+    /// The configuration of the geocoding cache.
+    pub(crate) geocoding_cache: GeocodingCacheConfig,
+
+    /// The path of the SQLite database that the history of retrieved forecasts is stored in.
+    ///
+    /// If not set, no history is stored.
+    pub(crate) history_file: Option<PathBuf>,
+
+    /// The configuration of the HTTP client used for outbound requests.
+    pub(crate) http: HttpConfig,
+
+    /// The path of the file the named locations are persisted to.
+    ///
+    /// If not set, the named locations are only kept in memory.
+    pub(crate) locations_file: Option<PathBuf>,
+
+    /// The log filter, see the [`EnvFilter`](tracing_subscriber::EnvFilter) directives.
+    pub(crate) log_filter: String,
+
+    /// The log format.
+    pub(crate) log_format: LogFormat,
+
+    /// The configuration of the maps and their refreshing.
+    pub(crate) maps: MapsConfig,
+
+    /// The maximum number of locations of a batch forecast request.
+    pub(crate) max_batch_locations: usize,
+
+    /// The configuration of the mock provider that generates synthetic data for all metrics.
+    ///
+    /// If not set, the data is retrieved from the upstream providers.
+    pub(crate) mock: Option<MockConfig>,
+
+    /// The configuration of the chat notifier.
+    ///
+    /// If not set, no chat notifications are sent.
+    pub(crate) notifier: Option<NotifierConfig>,
+
+    /// The tolerances of merging the pollen and AQI data into the PAQI metric.
+    pub(crate) paqi_merge: PaqiMergeConfig,
+
+    /// The size (in degrees) of the grid that positions are snapped to for provider lookups.
+    ///
+    /// Positions that snap to the same grid point share cached provider results. If zero,
+    /// positions are not snapped.
+    pub(crate) position_grid: f64,
+
+    /// The positions whose forecasts are prefetched, so that the caches are always warm for them.
+    pub(crate) prefetch_positions: Vec<PrefetchedPositionConfig>,
+
+    /// The precipitation (in mm/h) at or above which it is considered to be raining.
+    ///
+    /// This is used for the precipitation nowcast, i.e. when it starts or stops raining.
+    pub(crate) rain_threshold: f32,
+
+    /// The rate limit per client IP address.
+    ///
+    /// If not set, requests are not rate limited per client.
+    pub(crate) rate_limit: Option<RateLimitConfig>,
+
+    /// The URL of the Redis server used by the Redis cache backend.
+    pub(crate) redis_url: Option<String>,
+
+    /// The directory that static files, e.g. of a custom dashboard, are served from.
+    ///
+    /// If not set, no static files are served.
+    pub(crate) static_dir: Option<PathBuf>,
+
+    /// The path of the file the webhook subscriptions are persisted to.
+    ///
+    /// If not set, the subscriptions are only kept in memory.
+    pub(crate) subscriptions_file: Option<PathBuf>,
+
+    /// The hosts of webhook callback URLs that are allowed even if they are not publicly
+    /// reachable, e.g. a home automation server on the private network.
+    pub(crate) subscriptions_allowed_hosts: Vec<String>,
+
+    /// The thresholds per metric above which a warning is published.
+    pub(crate) warning_thresholds: BTreeMap<Metric, f32>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            api_keys: Vec::new(),
+            base_urls: BaseUrls::default(),
+            cache_backend: CacheBackend::default(),
+            cache_capacities: CacheCapacities::default(),
+            cache_ttls: CacheTtls::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            consensus_divergence: 1.0,
+            exporter: None,
+            geocoder: GeocoderConfig::default(),
+            geocoding_cache: GeocodingCacheConfig::default(),
+            history_file: None,
+            http: HttpConfig::default(),
+            locations_file: None,
+            log_filter: String::from(DEFAULT_LOG_FILTER),
+            log_format: LogFormat::default(),
+            maps: MapsConfig::default(),
+            max_batch_locations: 100,
+            mock: None,
+            notifier: None,
+            paqi_merge: PaqiMergeConfig::default(),
+            position_grid: 0.01,
+            prefetch_positions: Vec::new(),
+            rain_threshold: 0.1,
+            rate_limit: None,
+            redis_url: None,
+            static_dir: None,
+            subscriptions_file: None,
+            subscriptions_allowed_hosts: Vec::new(),
+            warning_thresholds: BTreeMap::from([
+                // An AQI above 6 is considered insufficient or worse.
+                (Metric::AQI, 6.0),
+                // Precipitation above 10 mm/h is considered heavy rain.
+                (Metric::Precipitation, 10.0),
+                // An UV index above 5 is considered high or worse.
+                (Metric::UVI, 5.0),
+            ]),
+        }
+    }
+}
+
+/// The configuration of an API key.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct ApiKeyConfig {
+    /// The API key itself.
+    pub(crate) key: String,
+
+    /// The maximum number of requests per minute (if limited).
+    #[serde(default)]
+    pub(crate) rate_limit: Option<u32>,
+
+    /// Whether the API key can be used for the administration endpoints.
+    #[serde(default)]
+    pub(crate) admin: bool,
+
+    /// The personal thresholds per metric to mark the forecast items with.
+    #[serde(default)]
+    pub(crate) thresholds: BTreeMap<Metric, f32>,
+}
+
+/// The configuration of the rate limit per client IP address.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct RateLimitConfig {
+    /// The number of requests per second that is allowed on average.
+    pub(crate) rate: f64,
+
+    /// The number of requests that is allowed in a burst.
+    pub(crate) burst: u32,
+}
+
+impl RateLimitConfig {
+    /// Checks whether the rate limit can be enforced.
+    ///
+    /// The rate must be positive and finite for the buckets to refill, and the burst must be at
+    /// least 1 for any request to be allowed.
+    #[cfg(feature = "server")]
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return Err(format!(
+                "rate must be positive and finite, got {}",
+                self.rate
+            ));
+        }
+        if self.burst == 0 {
+            return Err(String::from("burst must be at least 1"));
+        }
+
+        Ok(())
+    }
+}
+
+/// The maximum number of entries of the provider caches.
+///
+/// If a cache is full, the least recently used entry is evicted.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct CacheCapacities {
+    /// The capacity of the Buienradar precipitation items cache.
+    pub(crate) precipitation: usize,
+
+    /// The capacity of the Buienradar pollen samples cache.
+    pub(crate) pollen: usize,
+
+    /// The capacity of the Buienradar temperature map items cache.
+    pub(crate) temperature: usize,
+
+    /// The capacity of the Buienradar UV index samples cache.
+    pub(crate) uvi: usize,
+
+    /// The capacity of the Luchtmeetnet items cache.
+    pub(crate) luchtmeetnet: usize,
+
+    /// The capacity of the combined items cache.
+    pub(crate) combined: usize,
+
+    /// The capacity of the Open-Meteo precipitation cache.
+    pub(crate) open_meteo: usize,
+}
+
+impl Default for CacheCapacities {
+    fn default() -> Self {
+        Self {
+            precipitation: 10_000,
+            pollen: 10_000,
+            temperature: 10_000,
+            uvi: 10_000,
+            luchtmeetnet: 10_000,
+            combined: 10_000,
+            open_meteo: 10_000,
+        }
+    }
+}
+
+/// The times (in seconds) the provider results are cached for.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct CacheTtls {
+    /// The cache time of the Buienradar precipitation items.
+    pub(crate) precipitation: u64,
+
+    /// The cache time of the Buienradar pollen samples.
+    pub(crate) pollen: u64,
+
+    /// The cache time of the Buienradar temperature map items.
+    pub(crate) temperature: u64,
+
+    /// The cache time of the Buienradar UV index samples.
+    pub(crate) uvi: u64,
+
+    /// The cache time of the Luchtmeetnet items.
+    pub(crate) luchtmeetnet: u64,
+
+    /// The cache time of the combined items.
+    pub(crate) combined: u64,
+
+    /// The cache time of the Open-Meteo precipitation.
+    pub(crate) open_meteo: u64,
+
+    /// The maximum age of stale provider results, as a multiple of their cache time.
+    ///
+    /// If retrieving a fresh result fails, the last known good result is only used instead if it
+    /// was retrieved at most this many cache times ago.
+    pub(crate) max_stale_factor: u32,
+}
+
+impl Default for CacheTtls {
+    fn default() -> Self {
+        Self {
+            precipitation: 300,
+            pollen: 3_600,
+            temperature: 3_600,
+            uvi: 86_400,
+            luchtmeetnet: 1_800,
+            combined: 1_800,
+            open_meteo: 900,
+            max_stale_factor: 4,
+        }
+    }
+}
+
+impl CacheTtls {
+    /// Returns the cache time of the provider results for the provided metric.
+    ///
+    /// For the all metric, the shortest cache time is returned.
+    pub(crate) fn for_metric(&self, metric: Metric) -> u64 {
+        match metric {
+            Metric::All => Metric::all()
+                .into_iter()
+                .map(|metric| self.for_metric(metric))
+                .min()
+                .unwrap_or_default(),
+            Metric::AQI | Metric::NO2 | Metric::O3 | Metric::PM10 => self.luchtmeetnet,
+            Metric::PAQI | Metric::PAQIDaily => self.combined,
+            Metric::Pollen => self.pollen,
+            Metric::Precipitation => self.precipitation,
+            Metric::Temperature => self.temperature,
+            Metric::UVI => self.uvi,
+        }
+    }
+
+    /// Returns the maximum age of stale provider results for the provided metric.
+    pub(crate) fn max_staleness(&self, metric: Metric) -> Duration {
+        let max_staleness = self
+            .for_metric(metric)
+            .saturating_mul(u64::from(self.max_stale_factor));
+
+        i64::try_from(max_staleness)
+            .ok()
+            .and_then(Duration::try_seconds)
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+/// The configuration of the circuit breakers of the providers.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct CircuitBreakerConfig {
+    /// The number of consecutive failures after which calls to a provider are short-circuited.
+    pub(crate) failure_threshold: u32,
+
+    /// The time (in seconds) calls to a provider are short-circuited before a trial call is made.
+    pub(crate) cooldown: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: 60,
+        }
+    }
+}
+
+/// The configuration of the exporter of forecast values as Prometheus gauges.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ExporterConfig {
+    /// The interval (in seconds) between retrievals of the forecasts of the positions.
+    pub(crate) interval: u64,
+
+    /// The positions whose forecasts are exported.
+    pub(crate) positions: Vec<ExportedPositionConfig>,
+}
+
+impl Default for ExporterConfig {
+    fn default() -> Self {
+        Self {
+            interval: 300,
+            positions: Vec::new(),
+        }
+    }
+}
+
+/// The configuration of a position whose forecast is exported.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct ExportedPositionConfig {
+    /// The name of the position, used as the value of the `position` label.
+    pub(crate) name: String,
+
+    /// The latitude of the position.
+    pub(crate) lat: f64,
+
+    /// The longitude of the position.
+    pub(crate) lon: f64,
+
+    /// The metrics that are exported.
+    ///
+    /// If empty, all metrics are exported.
+    #[serde(default)]
+    pub(crate) metrics: Vec<Metric>,
+}
+
+/// The configuration of a position whose forecast is prefetched.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct PrefetchedPositionConfig {
+    /// The latitude of the position.
+    pub(crate) lat: f64,
+
+    /// The longitude of the position.
+    pub(crate) lon: f64,
+
+    /// The metrics that are prefetched.
+    ///
+    /// If empty, all metrics are prefetched.
+    #[serde(default)]
+    pub(crate) metrics: Vec<Metric>,
+}
+
+/// The configuration of the chat notifier.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct NotifierConfig {
+    /// The Matrix room that notifications are posted to (if any).
+    #[serde(default)]
+    pub(crate) matrix: Option<MatrixConfig>,
+
+    /// The Telegram chat that notifications are posted to (if any).
+    #[serde(default)]
+    pub(crate) telegram: Option<TelegramConfig>,
+
+    /// The positions and thresholds that are watched.
+    #[serde(default)]
+    pub(crate) watches: Vec<WatchConfig>,
+}
+
+/// The configuration of a Matrix room to post notifications to.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct MatrixConfig {
+    /// The base URL of the homeserver, e.g. `https://matrix.org`.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) homeserver: Url,
+
+    /// The access token of the (bot) user that posts the notifications.
+    pub(crate) access_token: String,
+
+    /// The ID of the room, e.g. `!abcdef:matrix.org`.
+    pub(crate) room_id: String,
+}
+
+/// The configuration of a Telegram chat to post notifications to.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct TelegramConfig {
+    /// The token of the bot that posts the notifications.
+    pub(crate) bot_token: String,
+
+    /// The ID of the chat, or the username of the channel, e.g. `@sinoptik`.
+    pub(crate) chat_id: String,
+}
+
+/// The configuration of a watched position and threshold.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) struct WatchConfig {
+    /// The name of the position used in the notifications (if any).
+    ///
+    /// If not set, the coordinates are used.
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+
+    /// The latitude of the position.
+    pub(crate) lat: f64,
+
+    /// The longitude of the position.
+    pub(crate) lon: f64,
+
+    /// The metric to evaluate.
+    pub(crate) metric: Metric,
+
+    /// The threshold the metric needs to exceed for a notification.
+    pub(crate) threshold: f32,
+}
+
+/// The tolerances of merging the pollen and AQI data into the PAQI metric.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct PaqiMergeConfig {
+    /// The maximum time (in seconds) a pollen sample can be stamped before the first or after the
+    /// last AQI item for them to be combined.
+    pub(crate) alignment_window: i64,
+
+    /// The maximum age (in seconds) of pollen samples and AQI items for them to be combined.
+    pub(crate) max_age: i64,
+}
+
+impl Default for PaqiMergeConfig {
+    fn default() -> Self {
+        Self {
+            alignment_window: 1_800,
+            max_age: 3_600,
+        }
+    }
+}
+
+/// The configuration of the maps and their refreshing.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct MapsConfig {
+    /// The interval (in seconds) between checks whether streamed forecasts have changed.
+    ///
+    /// The maps themselves are refreshed when they expire instead of at this interval.
+    pub(crate) check_interval: u64,
+
+    /// The maximum (Euclidean) distance in RGB space between the color of a map pixel and a map
+    /// key color for the pixel to be classified as that color.
+    pub(crate) color_tolerance: f32,
+
+    /// The maximum size (in bytes) of a retrieved maps image.
+    ///
+    /// Larger images are rejected without being read completely.
+    pub(crate) max_image_size: u64,
+
+    /// The maximum delay (in seconds) before retrying to retrieve maps after failures.
+    pub(crate) max_retry_delay: u64,
+
+    /// The interval (in seconds) for retrieving pollen maps.
+    pub(crate) pollen_interval: u64,
+
+    /// The number of pollen maps retrieved (one per hour).
+    pub(crate) pollen_map_count: u32,
+
+    /// The interval (in seconds) for retrieving precipitation radar maps.
+    pub(crate) precipitation_interval: u64,
+
+    /// The number of precipitation radar maps retrieved (one per 5 minutes).
+    pub(crate) precipitation_map_count: u32,
+
+    /// The minimum time (in seconds) between retrieving different types of maps.
+    pub(crate) refresh_stagger: u64,
+
+    /// The initial delay (in seconds) before retrying to retrieve maps after a failure.
+    ///
+    /// The delay doubles with every consecutive failure up to the maximum retry delay. It is also
+    /// the minimum time between two retrievals of the same type of maps.
+    pub(crate) retry_delay: u64,
+
+    /// The maximum time (in seconds) after startup that the service is reported as not ready yet
+    /// while the first pollen and UV index maps are being retrieved.
+    ///
+    /// If zero, the service is reported as ready right away.
+    pub(crate) startup_timeout: u64,
+
+    /// The interval (in seconds) for retrieving temperature maps.
+    pub(crate) temperature_interval: u64,
+
+    /// The number of temperature maps retrieved (one per hour).
+    pub(crate) temperature_map_count: u32,
+
+    /// The interval (in seconds) for retrieving UV index maps.
+    pub(crate) uvi_interval: u64,
+
+    /// The number of UV index maps retrieved (one per day).
+    pub(crate) uvi_map_count: u32,
+}
+
+impl Default for MapsConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: 60,
+            color_tolerance: 20.0,
+            max_image_size: 16 * 1024 * 1024,
+            max_retry_delay: 1_800,
+            pollen_interval: 3_600,
+            pollen_map_count: 24,
+            precipitation_interval: 300,
+            precipitation_map_count: 24,
+            refresh_stagger: 10,
+            retry_delay: 30,
+            startup_timeout: 0,
+            temperature_interval: 3_600,
+            temperature_map_count: 24,
+            uvi_interval: 24 * 3_600,
+            uvi_map_count: 5,
+        }
+    }
+}
+
+/// The base URLs of the upstream APIs.
+///
+/// These can be used to point at mirrors or mock servers.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct BaseUrls {
+    /// The base URL of the Buienradar (precipitation) API.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) buienradar: Url,
+
+    /// The base URL of the Luchtmeetnet API.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) luchtmeetnet: Url,
+
+    /// The base URL of the Open-Meteo API.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) open_meteo: Url,
+
+    /// The base URL for retrieving the pollen maps.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) pollen_maps: Url,
+
+    /// The base URL for retrieving the precipitation radar maps.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) precipitation_maps: Url,
+
+    /// The base URL for retrieving the temperature maps.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) temperature_maps: Url,
+
+    /// The base URL for retrieving the UV index maps.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub(crate) uvi_maps: Url,
+}
+
+impl Default for BaseUrls {
+    fn default() -> Self {
+        let parse = |url| Url::parse(url).expect("Default base URL is valid");
+
+        Self {
+            buienradar: parse(BUIENRADAR_BASE_URL),
+            luchtmeetnet: parse(LUCHTMEETNET_BASE_URL),
+            open_meteo: parse(OPEN_METEO_BASE_URL),
+            pollen_maps: parse(POLLEN_BASE_URL),
+            precipitation_maps: parse(PRECIPITATION_BASE_URL),
+            temperature_maps: parse(TEMPERATURE_BASE_URL),
+            uvi_maps: parse(UVI_BASE_URL),
+        }
+    }
+}
+
+/// Deserializes and parses a URL.
+fn deserialize_url<'de, D>(deserializer: D) -> Result<Url, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use rocket::serde::de::Error;
+
+    let url = String::deserialize(deserializer)?;
+
+    Url::parse(&url).map_err(D::Error::custom)
+}
+
+/// Deserializes and parses an optional URL.
+fn deserialize_optional_url<'de, D>(deserializer: D) -> Result<Option<Url>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use rocket::serde::de::Error;
+
+    Option::<String>::deserialize(deserializer)?
+        .map(|url| Url::parse(&url).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// The configuration of the mock provider.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct MockConfig {
+    /// The seed that the synthetic data is derived from.
+    pub(crate) seed: u64,
+}
+
+/// The configuration of the HTTP client used for outbound requests.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct HttpConfig {
+    /// The URL of the proxy that all outbound requests are sent through (if any).
+    ///
+    /// If not set, the proxy is determined by the `HTTP_PROXY`/`HTTPS_PROXY` environment
+    /// variables.
+    pub(crate) proxy: Option<String>,
+
+    /// The timeout (in seconds) of a complete request.
+    pub(crate) timeout: u64,
+
+    /// The timeout (in seconds) for connecting.
+    pub(crate) connect_timeout: u64,
+
+    /// The timeout (in seconds) for each read of the response.
+    pub(crate) read_timeout: u64,
+
+    /// The maximum number of attempts per request.
+    pub(crate) retry_attempts: u32,
+
+    /// The delay (in seconds) before the first retry; it doubles for every retry.
+    pub(crate) retry_delay: u64,
+
+    /// The maximum delay (in seconds) before a retry.
+    pub(crate) max_retry_delay: u64,
+
+    /// The user agent sent along with each request.
+    pub(crate) user_agent: String,
+
+    /// The path of an additional CA certificate (in PEM format) to trust (if any).
+    pub(crate) ca_certificate: Option<PathBuf>,
+
+    /// The configuration for recording or replaying the GET requests (if any).
+    #[cfg(feature = "recording")]
+    pub(crate) recording: Option<RecordingConfig>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            timeout: 30,
+            connect_timeout: 10,
+            read_timeout: 10,
+            retry_attempts: 3,
+            retry_delay: 1,
+            max_retry_delay: 30,
+            user_agent: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            ca_certificate: None,
+            #[cfg(feature = "recording")]
+            recording: None,
+        }
+    }
+}
+
+/// The configuration for recording or replaying the GET requests to the providers and for the
+/// maps.
+#[cfg(feature = "recording")]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct RecordingConfig {
+    /// Whether to record the responses to fixtures or to replay them from fixtures.
+    pub(crate) mode: RecordingMode,
+
+    /// The path of the directory containing the fixtures.
+    pub(crate) path: PathBuf,
+}
+
+/// The configuration of the geocoder.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct GeocoderConfig {
+    /// The geocoder backend.
+    pub(crate) backend: GeocoderBackend,
+
+    /// The URL of the geocoder backend, e.g. of a self-hosted instance.
+    ///
+    /// If not set, the public instance of the backend is used.
+    #[serde(deserialize_with = "deserialize_optional_url")]
+    pub(crate) url: Option<Url>,
+
+    /// The maximum number of requests per second sent to the geocoder backend.
+    ///
+    /// If not set, the default of the backend is used, i.e. one request per second for Nominatim
+    /// and unlimited for the other backends. Set to zero to disable rate limiting.
+    pub(crate) rate: Option<f64>,
+
+    /// The ISO 3166-1 alpha-2 codes of the countries geocoding is restricted to.
+    ///
+    /// If empty, geocoding is not restricted to any countries.
+    pub(crate) country_codes: Vec<String>,
+
+    /// The bounding box geocoded positions are restricted to.
+    ///
+    /// If not set, geocoded positions are not restricted to an area.
+    pub(crate) bounding_box: Option<BoundingBox>,
+}
+
+/// The configuration of the geocoding cache.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct GeocodingCacheConfig {
+    /// The path of the file the geocoding cache is persisted to.
+    ///
+    /// If not set, the geocoding cache is only kept in memory.
+    pub(crate) file: Option<PathBuf>,
+
+    /// The maximum number of cached addresses.
+    pub(crate) size: usize,
+
+    /// The time (in seconds) after which a cached position expires.
+    pub(crate) expiry: u64,
+}
+
+impl Default for GeocodingCacheConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            size: 100,
+            expiry: 30 * 24 * 3_600,
+        }
+    }
+}
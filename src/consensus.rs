@@ -0,0 +1,179 @@
+//! Precipitation consensus between providers.
+//!
+//! This module combines the precipitation of Buienradar with that of a second provider (Open-Meteo)
+//! into a consensus series. If the providers disagree strongly, the forecast is uncertain, which is
+//! indicated by the divergence.
+
+use std::sync::OnceLock;
+
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Duration, Utc};
+use rocket::serde::Serialize;
+
+use crate::config::Config;
+
+/// The mean difference (in mm/h) between the providers at or above which they diverge.
+static DIVERGENCE_THRESHOLD: OnceLock<f32> = OnceLock::new();
+
+/// Sets the mean difference (in mm/h) between the providers at or above which they diverge.
+///
+/// This needs to be done before the first consensus is derived, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_divergence_threshold(divergence_threshold: f32) {
+    let _ = DIVERGENCE_THRESHOLD.set(divergence_threshold);
+}
+
+/// Returns the mean difference (in mm/h) between the providers at or above which they diverge.
+///
+/// If it has not been set, the default threshold is used.
+fn divergence_threshold() -> f32 {
+    *DIVERGENCE_THRESHOLD.get_or_init(|| Config::default().consensus_divergence)
+}
+
+/// The consensus of the precipitation of the providers.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Consensus {
+    /// The consensus items.
+    items: Vec<Item>,
+
+    /// The mean difference between the providers (in mm/h, or in/h for imperial units).
+    divergence: f32,
+
+    /// Whether the providers diverge, i.e. the divergence is at or above the threshold.
+    divergent: bool,
+}
+
+/// A consensus item.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Item {
+    /// The time(stamp) of the forecast.
+    #[serde(serialize_with = "ts_seconds::serialize")]
+    time: DateTime<Utc>,
+
+    /// The mean precipitation of the providers (in mm/h, or in/h for imperial units).
+    value: f32,
+
+    /// The difference between the precipitation of the providers (in mm/h, or in/h for imperial
+    /// units).
+    spread: f32,
+}
+
+impl Consensus {
+    /// Derives the consensus of the primary precipitation series and the secondary series of the
+    /// provided interval (in seconds), using the configured divergence threshold.
+    ///
+    /// The values are expected to be in mm/h. Each item of the primary series is paired with the
+    /// secondary item of which the interval contains it; items without one are left out. Returns
+    /// [`None`] if no items could be paired.
+    pub(crate) fn new(
+        primary: &[(DateTime<Utc>, f32)],
+        secondary: &[(DateTime<Utc>, f32)],
+        interval: i64,
+    ) -> Option<Self> {
+        Self::with_threshold(primary, secondary, interval, divergence_threshold())
+    }
+
+    /// Derives the consensus of the precipitation series using the provided divergence threshold.
+    fn with_threshold(
+        primary: &[(DateTime<Utc>, f32)],
+        secondary: &[(DateTime<Utc>, f32)],
+        interval: i64,
+        threshold: f32,
+    ) -> Option<Self> {
+        let interval = Duration::seconds(interval);
+        let items = primary
+            .iter()
+            .filter_map(|&(time, value)| {
+                let (_, other) = secondary
+                    .iter()
+                    .find(|(start, _)| *start <= time && time < *start + interval)?;
+
+                Some(Item {
+                    time,
+                    value: round((value + other) / 2.0),
+                    spread: round((value - other).abs()),
+                })
+            })
+            .collect::<Vec<_>>();
+        if items.is_empty() {
+            return None;
+        }
+
+        let divergence =
+            round(items.iter().map(|item| item.spread).sum::<f32>() / items.len() as f32);
+
+        Some(Self {
+            items,
+            divergence,
+            divergent: divergence >= threshold,
+        })
+    }
+
+    /// Converts the values and differences using the provided function, e.g. to another system of
+    /// units.
+    pub(crate) fn convert(&mut self, f: impl Fn(f32) -> f32) {
+        for item in &mut self.items {
+            item.value = f(item.value);
+            item.spread = f(item.spread);
+        }
+        self.divergence = f(self.divergence);
+    }
+}
+
+/// Rounds the value to two decimals.
+fn round(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn consensus() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let t = |minutes| t_0 + Duration::minutes(minutes);
+        let primary = [
+            (t(0), 0.0),
+            (t(5), 1.0),
+            (t(10), 2.0),
+            (t(15), 0.4),
+            (t(30), 0.0),
+        ];
+        let secondary = [(t(0), 0.5), (t(15), 0.4)];
+
+        // The items are paired with the interval containing them, the others are left out.
+        let consensus = Consensus::with_threshold(&primary, &secondary, 900, 1.0).unwrap();
+        let items = consensus
+            .items
+            .iter()
+            .map(|item| (item.time, item.value, item.spread))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            [
+                (t(0), 0.25, 0.5),
+                (t(5), 0.75, 0.5),
+                (t(10), 1.25, 1.5),
+                (t(15), 0.4, 0.0)
+            ]
+        );
+        assert_eq!(consensus.divergence, 0.63);
+        assert!(!consensus.divergent);
+
+        // The providers diverge if the mean difference reaches the threshold.
+        let consensus = Consensus::with_threshold(&primary, &secondary, 900, 0.5).unwrap();
+        assert!(consensus.divergent);
+
+        // Without items to pair, there is no consensus.
+        assert_eq!(Consensus::with_threshold(&primary, &[], 900, 1.0), None);
+        assert_eq!(
+            Consensus::with_threshold(&primary, &[(t(60), 1.0)], 900, 1.0),
+            None
+        );
+    }
+}
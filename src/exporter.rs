@@ -0,0 +1,166 @@
+//! Prometheus exporter of forecast values.
+//!
+//! This module provides a task that periodically retrieves the forecast for the configured
+//! positions, and exposes the current values as gauges in the Prometheus text format, e.g.
+//! `sinoptik_uvi{position="home"} 3`. This allows e.g. Grafana alerting to work directly on the
+//! weather and air quality data.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use rocket::tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+use crate::config::ExporterConfig;
+use crate::forecast::{forecast, Metric, Units};
+use crate::maps::MapsHandle;
+use crate::position::Position;
+use crate::sensor::current_index;
+
+/// The time and value of the items of a forecast series.
+type Series = Vec<(DateTime<Utc>, f32)>;
+
+/// A handle to access the exposition.
+pub(crate) type ExporterHandle = Arc<RwLock<Exposition>>;
+
+/// The forecast series per metric and (name of the) position that are exposed.
+#[derive(Debug, Default)]
+pub(crate) struct Exposition {
+    /// The series by metric and position name.
+    series: BTreeMap<(Metric, String), Series>,
+}
+
+impl Exposition {
+    /// Renders the current values of the series as gauges in the Prometheus text format.
+    ///
+    /// Series without a current value, see [`current_index`], are left out.
+    pub(crate) fn render(&self, now: DateTime<Utc>) -> String {
+        let mut text = String::new();
+        let mut last_metric = None;
+        for ((metric, name), series) in &self.series {
+            let Some(index) = current_index(series, now) else {
+                continue;
+            };
+
+            let gauge = gauge_name(*metric);
+            if last_metric != Some(*metric) {
+                let unit = metric
+                    .unit(Units::Metric)
+                    .map(|unit| format!(" (in {unit})"))
+                    .unwrap_or_default();
+                let _ = writeln!(text, "# HELP {gauge} The current {metric} forecast{unit}.");
+                let _ = writeln!(text, "# TYPE {gauge} gauge");
+                last_metric = Some(*metric);
+            }
+            let name = escape_label_value(name);
+            let _ = writeln!(text, "{gauge}{{position=\"{name}\"}} {}", series[index].1);
+        }
+
+        text
+    }
+}
+
+/// Returns the name of the gauge of the provided metric, e.g. `sinoptik_paqi_daily`.
+fn gauge_name(metric: Metric) -> String {
+    format!("sinoptik_{}", metric.to_string().to_lowercase())
+}
+
+/// Escapes the backslashes, double quotes and line feeds of a label value.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Runs a loop that keeps retrieving the forecast for the configured positions and updates the
+/// exposition.
+///
+/// If the forecast for a metric fails, it is no longer exposed for the position until it
+/// succeeds again.
+pub(crate) async fn run(
+    config: ExporterConfig,
+    exporter_handle: ExporterHandle,
+    maps_handle: MapsHandle,
+) {
+    loop {
+        info!(
+            count = config.positions.len(),
+            "📈 Retrieving exported forecasts"
+        );
+        for exported in &config.positions {
+            let metrics = if exported.metrics.is_empty() {
+                Metric::all()
+            } else {
+                exported.metrics.clone()
+            };
+            let position = Position::new(exported.lat, exported.lon);
+            let forecast = forecast(position, metrics.clone(), &maps_handle).await;
+
+            let mut exposition = exporter_handle
+                .write()
+                .expect("Exporter handle lock was poisoned");
+            for metric in metrics {
+                let key = (metric, exported.name.clone());
+                match forecast.series(metric) {
+                    Some(series) => {
+                        exposition.series.insert(key, series);
+                    }
+                    None => {
+                        let error = forecast.error(metric).unwrap_or_default();
+                        warn!(
+                            %metric,
+                            position = exported.name,
+                            error,
+                            "💥 Failed to retrieve exported forecast"
+                        );
+                        exposition.series.remove(&key);
+                    }
+                }
+            }
+        }
+
+        sleep(Duration::from_secs(config.interval)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn render() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let series = Vec::from([(t_0, 1.5), (t_0 + Duration::hours(1), 2.5)]);
+        let mut exposition = Exposition::default();
+        exposition.series.insert(
+            (Metric::Precipitation, String::from("home")),
+            series.clone(),
+        );
+        exposition
+            .series
+            .insert((Metric::Precipitation, String::from("\"work\"")), series);
+        exposition
+            .series
+            .insert((Metric::UVI, String::from("home")), Vec::new());
+
+        let now = t_0 + Duration::minutes(70);
+        assert_eq!(
+            exposition.render(now),
+            "# HELP sinoptik_precipitation The current precipitation forecast (in mm/h).\n\
+             # TYPE sinoptik_precipitation gauge\n\
+             sinoptik_precipitation{position=\"\\\"work\\\"\"} 2.5\n\
+             sinoptik_precipitation{position=\"home\"} 2.5\n"
+        );
+    }
+
+    #[test]
+    fn gauge_name() {
+        assert_eq!(super::gauge_name(Metric::UVI), "sinoptik_uvi");
+        assert_eq!(super::gauge_name(Metric::PAQIDaily), "sinoptik_paqi_daily");
+    }
+}
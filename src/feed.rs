@@ -0,0 +1,75 @@
+//! Atom feed generation.
+//!
+//! This module is used to construct an Atom feed with an entry for each period where the forecast
+//! of a metric exceeds its warning threshold, see
+//! [RFC 4287](https://www.rfc-editor.org/rfc/rfc4287).
+
+use std::collections::BTreeMap;
+
+use chrono::{SecondsFormat, Utc};
+
+use crate::forecast::{Forecast, Metric};
+use crate::position::Position;
+use crate::thresholds::{periods, Period};
+
+/// Returns an Atom feed with an entry per period where a metric exceeds its warning threshold.
+///
+/// Only the metrics that have a threshold and are present in the forecast are considered.
+pub(crate) fn feed(
+    position: Position,
+    thresholds: &BTreeMap<Metric, f32>,
+    forecast: &Forecast,
+) -> String {
+    let lat = position.lat_as_str(5);
+    let lon = position.lon_as_str(5);
+    let mut warnings: Vec<(Metric, f32, Period)> = thresholds
+        .iter()
+        .filter_map(|(&metric, &threshold)| {
+            forecast.series(metric).map(|series| {
+                periods(&series, threshold)
+                    .into_iter()
+                    .map(move |period| (metric, threshold, period))
+            })
+        })
+        .flatten()
+        .collect();
+    warnings.sort_by_key(|(_, _, period)| period.start);
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push('\n');
+    xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    xml.push_str(&format!("<id>urn:sinoptik:warnings:{lat}:{lon}</id>"));
+    xml.push_str(&format!(
+        "<title>Sinoptik warnings for {lat}, {lon}</title>"
+    ));
+    xml.push_str(&format!(
+        "<updated>{}</updated>",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+    ));
+    xml.push_str("<author><name>Sinoptik</name></author>");
+
+    for (metric, threshold, period) in warnings {
+        let start = period.start.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let end = period.end.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        xml.push_str("<entry>");
+        xml.push_str(&format!(
+            "<id>urn:sinoptik:warning:{metric}:{}:{lat}:{lon}</id>",
+            period.start.timestamp()
+        ));
+        xml.push_str(&format!(
+            "<title>High {metric} forecast ({})</title>",
+            period.max_value
+        ));
+        xml.push_str(&format!("<updated>{start}</updated>"));
+        xml.push_str(&format!(
+            "<summary>The {metric} forecast exceeds {threshold} from {start} until {end} \
+             with a maximum of {}.</summary>",
+            period.max_value
+        ));
+        xml.push_str("</entry>");
+    }
+    xml.push_str("</feed>\n");
+
+    xml
+}
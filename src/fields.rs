@@ -0,0 +1,107 @@
+//! Field selection.
+//!
+//! This module is used to trim serialized forecasts to the fields a client asks for, e.g. only the
+//! `time` and `value` of the items, which saves bandwidth for constrained clients. The selection is
+//! applied after serialization, so it works the same for all metric item types.
+
+use std::collections::BTreeSet;
+
+use rocket::serde::json::Value;
+
+use crate::forecast::Metric;
+
+/// A selection of the fields to keep of a forecast and its items.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) struct FieldSelection(BTreeSet<String>);
+
+impl FieldSelection {
+    /// Parses a comma-separated list of field names, e.g. `time,value`.
+    ///
+    /// Surrounding whitespace and empty names are ignored.
+    pub(crate) fn parse(fields: &str) -> Self {
+        let fields = fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(String::from)
+            .collect();
+
+        Self(fields)
+    }
+
+    /// Applies the selection to a serialized forecast.
+    ///
+    /// The selection applies to both the fields of the forecast itself, e.g. `lat` or `freshness`,
+    /// and to those of the items; the fields of the metrics are always kept. Fields that are not
+    /// selected are removed.
+    pub(crate) fn apply(&self, forecast: &mut Value) {
+        let Value::Object(forecast) = forecast else {
+            return;
+        };
+
+        let metrics = Metric::all()
+            .into_iter()
+            .map(|metric| metric.to_string())
+            .collect::<BTreeSet<_>>();
+        forecast.retain(|field, _| metrics.contains(field) || self.0.contains(field));
+        for (_, items) in forecast
+            .iter_mut()
+            .filter(|(field, _)| metrics.contains(*field))
+        {
+            let Value::Array(items) = items else {
+                continue;
+            };
+
+            for item in items {
+                if let Value::Object(item) = item {
+                    item.retain(|field, _| self.0.contains(field));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    use rocket::serde::json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let selection = FieldSelection::parse(" time, value,,");
+        assert_eq!(
+            selection,
+            FieldSelection(BTreeSet::from([
+                String::from("time"),
+                String::from("value")
+            ]))
+        );
+        assert_eq!(FieldSelection::parse(""), FieldSelection::default());
+    }
+
+    #[test]
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    fn apply() {
+        let mut forecast = json!({
+            "lat": 51.4,
+            "lon": 5.5,
+            "time": 1_704_888_000,
+            "UVI": [{"time": 1_704_888_000, "value": 4, "confidence": 1.0}],
+            "PAQI": [{"time": 1_704_888_000, "value": 3.0, "partial": true}],
+            "freshness": {"UVI": {"stale": false, "data_age": 0}},
+        });
+
+        // The metrics are kept, and the selected fields of the forecast and items.
+        FieldSelection::parse("value,freshness").apply(&mut forecast);
+        assert_eq!(
+            forecast,
+            json!({
+                "UVI": [{"value": 4}],
+                "PAQI": [{"value": 3.0}],
+                "freshness": {"UVI": {"stale": false, "data_age": 0}},
+            })
+        );
+    }
+}
@@ -5,28 +5,71 @@
 
 use std::collections::BTreeMap;
 use std::fmt;
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::Instant;
 
-use rocket::serde::Serialize;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio;
+use tracing::{debug, instrument, warn};
 
+use crate::aqi_scale::{self, AqiScale};
+use crate::config::PaqiMergeConfig;
+use crate::consensus::Consensus;
+use crate::i18n::Locale;
+use crate::labels;
+use crate::links::Links;
 use crate::maps::MapsHandle;
+use crate::nowcast::Nowcast;
 use crate::position::Position;
-use crate::providers::buienradar::{Item as BuienradarItem, Sample as BuienradarSample};
-use crate::providers::combined::Item as CombinedItem;
-use crate::providers::luchtmeetnet::Item as LuchtmeetnetItem;
-use crate::{providers, Error};
+use crate::providers::buienradar::{self, Item as BuienradarItem, Sample as BuienradarSample};
+use crate::providers::combined::{self, Item as CombinedItem};
+use crate::providers::luchtmeetnet::{self, Item as LuchtmeetnetItem};
+use crate::providers::open_meteo;
+use crate::providers::{LastKnownGood, Retrieved};
+use crate::summary::Summary;
+use crate::{providers, Error, Result};
+
+/// The step (in seconds) between the items of interpolated map samples.
+const INTERPOLATION_STEP: i64 = 15 * 60;
+
+/// The maximum number of hours of a time window (a year, far beyond the range of any provider).
+const MAX_WINDOW_HOURS: u32 = 366 * 24;
+
+/// The last known good Buienradar precipitation/temperature items.
+static BUIENRADAR_ITEMS: LazyLock<LastKnownGood<Vec<BuienradarItem>>> =
+    LazyLock::new(LastKnownGood::new);
+
+/// The last known good Buienradar pollen/UV index map samples.
+static BUIENRADAR_SAMPLES: LazyLock<LastKnownGood<Vec<BuienradarSample>>> =
+    LazyLock::new(LastKnownGood::new);
+
+/// The last known good combined items.
+static COMBINED_ITEMS: LazyLock<LastKnownGood<Vec<CombinedItem>>> =
+    LazyLock::new(LastKnownGood::new);
+
+/// The last known good Luchtmeetnet items.
+static LUCHTMEETNET_ITEMS: LazyLock<LastKnownGood<Vec<LuchtmeetnetItem>>> =
+    LazyLock::new(LastKnownGood::new);
 
 /// The current forecast for a specific location.
 ///
 /// Only the metrics asked for are included as well as the position and current time.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
 #[serde(crate = "rocket::serde")]
-pub(crate) struct Forecast {
+pub struct Forecast {
     /// The latitude of the position.
     lat: f64,
 
     /// The longitude of the position.
     lon: f64,
 
+    /// The human-readable name of the place of the position (when asked for and found).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+
     /// The current time (in seconds since the UNIX epoch).
     time: i64,
 
@@ -46,6 +89,10 @@ pub(crate) struct Forecast {
     #[serde(rename = "PAQI", skip_serializing_if = "Option::is_none")]
     paqi: Option<Vec<CombinedItem>>,
 
+    /// The daily combination of pollen + air quality index (when asked for).
+    #[serde(rename = "PAQI_daily", skip_serializing_if = "Option::is_none")]
+    paqi_daily: Option<Vec<CombinedItem>>,
+
     /// The particulate matter in the air (when asked for).
     #[serde(rename = "PM10", skip_serializing_if = "Option::is_none")]
     pm10: Option<Vec<LuchtmeetnetItem>>,
@@ -58,13 +105,93 @@ pub(crate) struct Forecast {
     #[serde(skip_serializing_if = "Option::is_none")]
     precipitation: Option<Vec<BuienradarItem>>,
 
+    /// When it starts or stops raining, derived from the precipitation (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    precipitation_nowcast: Option<Nowcast>,
+
+    /// The consensus of the precipitation providers (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    precipitation_consensus: Option<Consensus>,
+
+    /// The temperature (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<Vec<BuienradarItem>>,
+
     /// The UV index (when asked for).
     #[serde(rename = "UVI", skip_serializing_if = "Option::is_none")]
     uvi: Option<Vec<BuienradarSample>>,
 
+    /// The freshness of the data per metric.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    freshness: BTreeMap<Metric, Freshness>,
+
     /// Any errors that occurred.
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     errors: BTreeMap<Metric, String>,
+
+    /// The kinds of the errors that occurred, see [`Error::kind`].
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    error_kinds: BTreeMap<Metric, &'static str>,
+
+    /// The summary of the highlights of the forecast (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<Summary>,
+
+    /// The metadata per metric (when asked for).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    meta: BTreeMap<Metric, MetricMeta>,
+
+    /// The links to the forecast itself and related resources.
+    #[serde(rename = "_links", skip_serializing_if = "Option::is_none")]
+    links: Option<Links>,
+
+    /// The system of units of the values.
+    #[serde(skip)]
+    units: Units,
+
+    /// The scale of the air quality index.
+    #[serde(skip)]
+    aqi_scale: AqiScale,
+}
+
+/// The freshness of the data of a metric.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Freshness {
+    /// Whether the data is stale, i.e. retrieving fresh data failed.
+    stale: bool,
+
+    /// The age of the data since it was last retrieved (in seconds).
+    data_age: i64,
+
+    /// The time the data was first retrieved.
+    #[serde(skip)]
+    retrieved_at: DateTime<Utc>,
+
+    /// The time the data was last retrieved, i.e. confirmed to be current.
+    #[serde(skip)]
+    confirmed_at: DateTime<Utc>,
+}
+
+/// The metadata of a metric.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct MetricMeta {
+    /// The unit of the values, or [`None`] if the values are an index without a unit.
+    unit: Option<&'static str>,
+
+    /// The provider(s) the data is retrieved from.
+    source: &'static str,
+
+    /// The time the data was first retrieved (in seconds since the UNIX epoch).
+    retrieved_at: i64,
+
+    /// The number of seconds between the items.
+    cadence: i64,
+
+    /// The effective tolerances of merging the pollen and AQI data (for the PAQI metric only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    paqi_merge: Option<PaqiMergeConfig>,
 }
 
 impl Forecast {
@@ -72,15 +199,784 @@ impl Forecast {
         Self {
             lat: position.lat,
             lon: position.lon,
-            time: chrono::Utc::now().timestamp(),
+            time: Utc::now().timestamp(),
 
             ..Default::default()
         }
     }
 
+    #[cfg(all(test, feature = "maps", feature = "server"))]
+    pub(crate) fn with_uvi(position: Position, time: i64, uvi: Vec<BuienradarSample>) -> Self {
+        let retrieved_at = Utc.timestamp_opt(time, 0).unwrap();
+        let freshness = Freshness {
+            stale: false,
+            data_age: 0,
+            retrieved_at,
+            confirmed_at: retrieved_at,
+        };
+
+        Self {
+            time,
+            uvi: Some(uvi),
+            freshness: BTreeMap::from([(Metric::UVI, freshness)]),
+            ..Self::new(position)
+        }
+    }
+
+    #[cfg(all(test, feature = "maps", feature = "server"))]
+    pub(crate) fn served_at(self, time: i64) -> Self {
+        Self { time, ..self }
+    }
+
+    /// Sets the human-readable name of the place of the position of the forecast.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn set_location(&mut self, location: String) {
+        self.location = Some(location);
+    }
+
+    /// Returns the position of the forecast.
+    pub fn position(&self) -> Position {
+        Position::new(self.lat, self.lon)
+    }
+
+    /// Returns the time of the forecast (in seconds since the UNIX epoch).
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// Returns the time the most recently retrieved data of the forecast was first retrieved.
+    ///
+    /// Returns [`None`] if the forecast has no data.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn last_modified(&self) -> Option<DateTime<Utc>> {
+        self.freshness
+            .values()
+            .map(|freshness| freshness.retrieved_at)
+            .max()
+    }
+
+    /// Returns the time the data of the provided metric was first retrieved.
+    ///
+    /// Returns [`None`] if the forecast has no data for the metric.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn retrieved_at(&self, metric: Metric) -> Option<DateTime<Utc>> {
+        self.freshness
+            .get(&metric)
+            .map(|freshness| freshness.retrieved_at)
+    }
+
+    /// Returns the number of seconds the forecast remains fresh.
+    ///
+    /// This is the shortest time until the cached data of any of the metrics expires, counting from
+    /// when it was last retrieved. If any of the data is stale or any error occurred, the forecast
+    /// is not fresh at all.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn max_age(&self) -> u64 {
+        if !self.errors.is_empty() {
+            return 0;
+        }
+
+        let now = Utc::now();
+        let ttls = providers::cache_ttls();
+        self.freshness
+            .iter()
+            .map(|(&metric, freshness)| {
+                if freshness.stale {
+                    return 0;
+                }
+
+                let expires_at =
+                    freshness.confirmed_at + Duration::seconds(ttls.for_metric(metric) as i64);
+                expires_at.signed_duration_since(now).num_seconds().max(0) as u64
+            })
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Returns the data of the forecast that determines whether it has changed.
+    ///
+    /// The current time and the age of the data are not included, so this only changes if the data,
+    /// its staleness or the errors change.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn data(&self) -> String {
+        let mut forecast = Self {
+            time: 0,
+            ..self.clone()
+        };
+        for freshness in forecast.freshness.values_mut() {
+            freshness.data_age = 0;
+        }
+
+        serde_json::to_string(&forecast).expect("Forecast can always be serialized")
+    }
+
+    /// Returns whether the data of the provided metric is stale.
+    pub fn is_stale(&self, metric: Metric) -> bool {
+        self.freshness
+            .get(&metric)
+            .is_some_and(|freshness| freshness.stale)
+    }
+
     fn log_error(&mut self, metric: Metric, error: Error) {
-        eprintln!("💥 Encountered error during forecast: {}", error);
+        warn!(%metric, error = %error, "💥 Encountered error during forecast");
         self.errors.insert(metric, error.to_string());
+        self.error_kinds.insert(metric, error.kind());
+    }
+
+    /// Records the freshness of the retrieved data or the error for the provided metric.
+    ///
+    /// Returns the retrieved data, if any.
+    fn record<V>(&mut self, metric: Metric, result: Result<Retrieved<V>>) -> Option<V> {
+        match result {
+            Ok(retrieved) => {
+                let data_age = Utc::now()
+                    .signed_duration_since(retrieved.confirmed_at)
+                    .num_seconds();
+                let freshness = Freshness {
+                    stale: retrieved.stale,
+                    data_age,
+                    retrieved_at: retrieved.retrieved_at,
+                    confirmed_at: retrieved.confirmed_at,
+                };
+                self.freshness.insert(metric, freshness);
+
+                Some(retrieved.value)
+            }
+            Err(err) => {
+                self.log_error(metric, err);
+
+                None
+            }
+        }
+    }
+
+    /// Returns whether the forecast has the same data as the other forecast.
+    ///
+    /// The current time and the freshness of the data of both forecasts are not taken into
+    /// account.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn has_same_data(&self, other: &Self) -> bool {
+        let forecast = Self {
+            time: other.time,
+            freshness: other.freshness.clone(),
+            ..self.clone()
+        };
+
+        forecast == *other
+    }
+
+    /// Retains only the items of all metrics that fall within the provided time window.
+    ///
+    /// The accumulated precipitation is derived again, so that it starts at the window. This should
+    /// be done before converting the units.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn retain_window(&mut self, window: TimeWindow) {
+        fn retain<T: ForecastItem>(items: &mut Option<Vec<T>>, window: TimeWindow) {
+            if let Some(items) = items {
+                items.retain(|item| window.contains(item.time()));
+            }
+        }
+
+        retain(&mut self.aqi, window);
+        retain(&mut self.no2, window);
+        retain(&mut self.o3, window);
+        retain(&mut self.paqi, window);
+        retain(&mut self.paqi_daily, window);
+        retain(&mut self.pm10, window);
+        retain(&mut self.pollen, window);
+        retain(&mut self.precipitation, window);
+        retain(&mut self.temperature, window);
+        retain(&mut self.uvi, window);
+        self.derive_precipitation();
+    }
+
+    /// Derives the intensity class and accumulated precipitation of the precipitation items, see
+    /// [`buienradar::derive_precipitation`].
+    fn derive_precipitation(&mut self) {
+        if let Some(items) = &mut self.precipitation {
+            buienradar::derive_precipitation(items);
+        }
+    }
+
+    /// Retains only a page of the items of all metrics: the items from the provided offset, up to
+    /// the provided limit (if any).
+    ///
+    /// The page is applied to the items of each metric separately.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn retain_page(&mut self, offset: usize, limit: Option<usize>) {
+        fn retain<T>(items: &mut Option<Vec<T>>, offset: usize, limit: Option<usize>) {
+            if let Some(items) = items {
+                items.drain(..offset.min(items.len()));
+                items.truncate(limit.unwrap_or(usize::MAX));
+            }
+        }
+
+        retain(&mut self.aqi, offset, limit);
+        retain(&mut self.no2, offset, limit);
+        retain(&mut self.o3, offset, limit);
+        retain(&mut self.paqi, offset, limit);
+        retain(&mut self.paqi_daily, offset, limit);
+        retain(&mut self.pm10, offset, limit);
+        retain(&mut self.pollen, offset, limit);
+        retain(&mut self.precipitation, offset, limit);
+        retain(&mut self.temperature, offset, limit);
+        retain(&mut self.uvi, offset, limit);
+    }
+
+    /// Converts the air quality index to the provided scale, see [`aqi_scale`].
+    ///
+    /// Only the conversion to the EAQI scale is supported: the NO₂, O₃ and PM10 concentrations are
+    /// retrieved for the position and the AQI items are converted using them. If none of the
+    /// concentrations can be retrieved, the error is recorded for the AQI metric instead.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) async fn convert_aqi_scale(&mut self, scale: AqiScale) {
+        if scale != AqiScale::Eaqi || self.aqi_scale == scale || self.aqi.is_none() {
+            return;
+        }
+
+        let (concentrations, error) = self.resolve_concentrations().await;
+        match (concentrations.is_empty(), error) {
+            (true, Some(err)) => {
+                self.aqi = None;
+                self.freshness.remove(&Metric::AQI);
+                self.log_error(Metric::AQI, err);
+            }
+            _ => {
+                if let Some(items) = &mut self.aqi {
+                    aqi_scale::to_eaqi(items, &concentrations);
+                }
+            }
+        }
+        self.aqi_scale = scale;
+    }
+
+    /// Includes the underlying pollutant concentrations in the items of the indices (AQI and PAQI).
+    ///
+    /// Each item gets the highest concentration per pollutant during its cadence. If retrieving the
+    /// concentrations fails, they are not included.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) async fn include_concentrations(&mut self) {
+        if self.aqi.is_none() && self.paqi.is_none() && self.paqi_daily.is_none() {
+            return;
+        }
+
+        let (concentrations, _) = self.resolve_concentrations().await;
+        let concentrations_at = |time, metric: Metric| {
+            luchtmeetnet::concentrations_at(&concentrations, time, metric.cadence())
+        };
+        for item in self.aqi.iter_mut().flatten() {
+            item.concentrations = concentrations_at(item.time, Metric::AQI);
+        }
+        for (items, metric) in [
+            (&mut self.paqi, Metric::PAQI),
+            (&mut self.paqi_daily, Metric::PAQIDaily),
+        ] {
+            for item in items.iter_mut().flatten() {
+                item.concentrations = concentrations_at(item.time, metric);
+            }
+        }
+    }
+
+    /// Resolves the concentrations of the pollutants of Luchtmeetnet for the position.
+    ///
+    /// The pollutants are retrieved concurrently, falling back to their last known good items.
+    /// Pollutants that could not be retrieved are left out; the first error is returned as well.
+    async fn resolve_concentrations(
+        &self,
+    ) -> (Vec<(Metric, Vec<LuchtmeetnetItem>)>, Option<Error>) {
+        let position = providers::lookup_position(self.position());
+        let resolve = |pollutant| async move {
+            let result = luchtmeetnet::get(position, pollutant).await;
+            let max_staleness = providers::cache_ttls().max_staleness(pollutant);
+            let result = LUCHTMEETNET_ITEMS.resolve(position, pollutant, result, max_staleness);
+
+            (pollutant, result)
+        };
+        let [no2, o3, pm10] = luchtmeetnet::POLLUTANTS;
+        let results = tokio::join!(resolve(no2), resolve(o3), resolve(pm10));
+
+        let mut concentrations = Vec::new();
+        let mut error = None;
+        for (pollutant, result) in [results.0, results.1, results.2] {
+            match result {
+                Ok(retrieved) => concentrations.push((pollutant, retrieved.value)),
+                Err(err) => {
+                    warn!(%pollutant, error = %err, "💥 Could not retrieve the concentrations");
+                    error.get_or_insert(err);
+                }
+            }
+        }
+
+        (concentrations, error)
+    }
+
+    /// Interpolates the items of the metrics that are sampled from the maps (pollen and UV index).
+    ///
+    /// This results in an item every 15 minutes, see [`buienradar::interpolate`].
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn interpolate(&mut self) {
+        let step = Duration::seconds(INTERPOLATION_STEP);
+        for samples in [&mut self.pollen, &mut self.uvi].into_iter().flatten() {
+            *samples = buienradar::interpolate(samples, step);
+        }
+    }
+
+    /// Converts the values of the metrics that have a unit to the provided system of units.
+    ///
+    /// The precipitation is converted to in/h and the temperature to °F for imperial units. The
+    /// other metrics are concentrations or indices, which are the same in both systems.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn convert_units(&mut self, units: Units) {
+        if units == self.units {
+            return;
+        }
+
+        for (items, metric) in [
+            (&mut self.precipitation, Metric::Precipitation),
+            (&mut self.temperature, Metric::Temperature),
+        ] {
+            for item in items.iter_mut().flatten() {
+                item.value = units.convert(metric, self.units.to_metric(metric, item.value));
+                // The accumulated precipitation converts like its rate, e.g. mm like mm/h.
+                item.accumulated = item.accumulated.map(|accumulated| {
+                    units.convert(metric, self.units.to_metric(metric, accumulated))
+                });
+            }
+        }
+        if let Some(consensus) = &mut self.precipitation_consensus {
+            let metric = Metric::Precipitation;
+            consensus.convert(|value| units.convert(metric, self.units.to_metric(metric, value)));
+        }
+        self.units = units;
+    }
+
+    /// Calls the provided function for each item of all metrics with its value in metric units.
+    fn for_each_item(&mut self, mut f: impl FnMut(Metric, f32, &mut dyn ForecastItem)) {
+        fn each<T: ForecastItem>(
+            items: &mut Option<Vec<T>>,
+            metric: Metric,
+            units: Units,
+            f: &mut impl FnMut(Metric, f32, &mut dyn ForecastItem),
+        ) {
+            for item in items.iter_mut().flatten() {
+                let value = units.to_metric(metric, item.value());
+                f(metric, value, item);
+            }
+        }
+
+        let units = self.units;
+        each(&mut self.aqi, Metric::AQI, units, &mut f);
+        each(&mut self.no2, Metric::NO2, units, &mut f);
+        each(&mut self.o3, Metric::O3, units, &mut f);
+        each(&mut self.paqi, Metric::PAQI, units, &mut f);
+        each(&mut self.paqi_daily, Metric::PAQIDaily, units, &mut f);
+        each(&mut self.pm10, Metric::PM10, units, &mut f);
+        each(&mut self.pollen, Metric::Pollen, units, &mut f);
+        each(
+            &mut self.precipitation,
+            Metric::Precipitation,
+            units,
+            &mut f,
+        );
+        each(&mut self.temperature, Metric::Temperature, units, &mut f);
+        each(&mut self.uvi, Metric::UVI, units, &mut f);
+    }
+
+    /// Labels the items of all metrics with their classification in the provided locale.
+    ///
+    /// See [`labels::label`] for the scales that are used, and [`labels::eaqi_label`] for the air
+    /// quality index on the EAQI scale.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn include_labels(&mut self, locale: Locale) {
+        let aqi_scale = self.aqi_scale;
+        self.for_each_item(|metric, value, item| {
+            let label = match (metric, aqi_scale) {
+                (Metric::AQI, AqiScale::Eaqi) => labels::eaqi_label(value, locale),
+                _ => labels::label(metric, value, locale),
+            };
+            item.set_label(label);
+        });
+    }
+
+    /// Marks the items of the metrics that have a threshold with whether their value exceeds it.
+    ///
+    /// The thresholds are in metric units, regardless of the units of the values.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn mark_thresholds(&mut self, thresholds: &BTreeMap<Metric, f32>) {
+        if thresholds.is_empty() {
+            return;
+        }
+
+        self.for_each_item(|metric, value, item| {
+            if let Some(&threshold) = thresholds.get(&metric) {
+                item.set_exceeds(value > threshold);
+            }
+        });
+    }
+
+    /// Includes the consensus of the precipitation of Buienradar and Open-Meteo, see
+    /// [`Consensus`].
+    ///
+    /// The consensus is derived from the precipitation items, so this should be done after
+    /// retaining a time window, but before converting the units. If there are no precipitation
+    /// items or retrieving the precipitation of Open-Meteo fails, no consensus is included.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) async fn include_consensus(&mut self) {
+        let Some(series) = self
+            .series(Metric::Precipitation)
+            .filter(|series| !series.is_empty())
+        else {
+            return;
+        };
+
+        let position = providers::lookup_position(self.position());
+        match open_meteo::get_precipitation(position).await {
+            Ok(secondary) => {
+                self.precipitation_consensus =
+                    Consensus::new(&series, &secondary, open_meteo::PRECIPITATION_INTERVAL);
+            }
+            Err(err) => warn!(error = %err, "💥 Could not retrieve the precipitation consensus"),
+        }
+    }
+
+    /// Includes the summary of the highlights of the forecast, see [`Summary`].
+    ///
+    /// The summary is derived from the items, so this should be done after interpolating,
+    /// retaining a time window and converting the units.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn include_summary(&mut self) {
+        self.summary = Some(Summary::new(self));
+    }
+
+    /// Includes the links to the forecast itself, at `self_href`, and to related resources, see
+    /// [`Links`].
+    ///
+    /// The related resources are those of the metrics asked for, i.e. with items or an error.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn include_links(&mut self, self_href: String, base: &str) {
+        let metrics = Metric::all()
+            .into_iter()
+            .filter(|metric| self.series(*metric).is_some() || self.errors.contains_key(metric));
+        self.links = Some(Links::new(self_href, base, self.position(), metrics));
+    }
+
+    /// Includes the metadata of all metrics with data in the forecast.
+    ///
+    /// The cadence is determined from the items, so this should be done after interpolating
+    /// and retaining a time window. If there are too few items, the native cadence of the metric
+    /// is used, see [`Metric::cadence`].
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn include_meta(&mut self) {
+        for (&metric, freshness) in &self.freshness {
+            let Some(series) = self.series(metric) else {
+                continue;
+            };
+            let cadence = series
+                .windows(2)
+                .map(|pair| pair[1].0.signed_duration_since(pair[0].0).num_seconds())
+                .filter(|&gap| gap > 0)
+                .min()
+                .unwrap_or_else(|| metric.cadence());
+            let meta = MetricMeta {
+                unit: metric.unit(self.units),
+                source: metric.source(),
+                retrieved_at: freshness.retrieved_at.timestamp(),
+                cadence,
+                paqi_merge: (metric == Metric::PAQI).then(|| *combined::config()),
+            };
+            self.meta.insert(metric, meta);
+        }
+    }
+
+    /// Checks whether the forecast for all requested metrics could be retrieved.
+    ///
+    /// Returns [`Error::IncompleteForecast`] with the kinds of the errors otherwise.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn check_complete(&self) -> Result<()> {
+        if self.error_kinds.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::IncompleteForecast(self.error_kinds.clone()))
+        }
+    }
+
+    /// Returns the error that occurred for the provided metric, if any.
+    pub fn error(&self, metric: Metric) -> Option<&str> {
+        self.errors.get(&metric).map(String::as_str)
+    }
+
+    /// Returns the time and value of the items of the provided metric.
+    ///
+    /// Returns [`None`] if the metric is not present in the forecast.
+    pub fn series(&self, metric: Metric) -> Option<Vec<(DateTime<Utc>, f32)>> {
+        fn series<T: ForecastItem>(items: &Option<Vec<T>>) -> Option<Vec<(DateTime<Utc>, f32)>> {
+            items.as_ref().map(|items| {
+                items
+                    .iter()
+                    .map(|item| (item.time(), item.value()))
+                    .collect()
+            })
+        }
+
+        match metric {
+            Metric::All => None,
+            Metric::AQI => series(&self.aqi),
+            Metric::NO2 => series(&self.no2),
+            Metric::O3 => series(&self.o3),
+            Metric::PAQI => series(&self.paqi),
+            Metric::PAQIDaily => series(&self.paqi_daily),
+            Metric::PM10 => series(&self.pm10),
+            Metric::Pollen => series(&self.pollen),
+            Metric::Precipitation => series(&self.precipitation),
+            Metric::Temperature => series(&self.temperature),
+            Metric::UVI => series(&self.uvi),
+        }
+    }
+
+    /// Returns the metrics present in the forecast and their values per timestamp.
+    ///
+    /// The rows contain every timestamp that occurs in any of the metrics.
+    fn table(&self) -> (Vec<Metric>, BTreeMap<i64, BTreeMap<Metric, f32>>) {
+        let mut columns = Vec::new();
+        let mut rows: BTreeMap<i64, BTreeMap<Metric, f32>> = BTreeMap::new();
+        for metric in Metric::all() {
+            if let Some(series) = self.series(metric) {
+                columns.push(metric);
+                for (time, value) in series {
+                    rows.entry(time.timestamp())
+                        .or_default()
+                        .insert(metric, value);
+                }
+            }
+        }
+
+        (columns, rows)
+    }
+
+    /// Returns the forecast as a CSV table.
+    ///
+    /// The table has a row per timestamp that occurs in any of the metrics and a column per metric
+    /// that is present in the forecast. If a metric has no item for a timestamp, the cell is left
+    /// empty.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn to_csv(&self) -> Result<String> {
+        let (columns, rows) = self.table();
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        let header = std::iter::once(String::from("time"))
+            .chain(columns.iter().map(|metric| metric.to_string()));
+        wtr.write_record(header).map_err(Error::CsvWrite)?;
+        for (time, values) in rows {
+            let record = std::iter::once(time.to_string()).chain(columns.iter().map(|metric| {
+                values
+                    .get(metric)
+                    .map(|value| value.to_string())
+                    .unwrap_or_default()
+            }));
+            wtr.write_record(record).map_err(Error::CsvWrite)?;
+        }
+        let data = wtr
+            .into_inner()
+            .map_err(|err| Error::CsvWrite(err.into_error().into()))?;
+
+        Ok(String::from_utf8(data).expect("CSV data is always valid UTF-8"))
+    }
+
+    /// Returns the forecast as a human-readable table with aligned columns.
+    ///
+    /// The table is laid out like the CSV table (see [`Forecast::to_csv`]), but the times are
+    /// formatted in UTC and the errors that occurred for metrics are listed below it.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn to_table(&self) -> String {
+        let (columns, rows) = self.table();
+        let header = std::iter::once(String::from("time (UTC)"))
+            .chain(columns.iter().map(|metric| metric.to_string()))
+            .collect::<Vec<_>>();
+        let lines = rows
+            .into_iter()
+            .map(|(time, values)| {
+                let time = DateTime::from_timestamp(time, 0)
+                    .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| time.to_string());
+                std::iter::once(time)
+                    .chain(columns.iter().map(|metric| {
+                        values
+                            .get(metric)
+                            .map(|value| value.to_string())
+                            .unwrap_or_default()
+                    }))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let widths = header
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                lines
+                    .iter()
+                    .map(|line| line[index].len())
+                    .fold(cell.len(), usize::max)
+            })
+            .collect::<Vec<_>>();
+
+        let mut table = String::new();
+        for line in std::iter::once(&header).chain(&lines) {
+            let cells = line
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>();
+            table.push_str(cells.join("  ").trim_end());
+            table.push('\n');
+        }
+        for (metric, error) in &self.errors {
+            table.push_str(&format!("{metric}: {error}\n"));
+        }
+
+        table
+    }
+}
+
+/// A forecast item that is stamped with a time.
+///
+/// This is used to handle the items of all metrics uniformly.
+pub(crate) trait ForecastItem {
+    /// Returns the time(stamp) of the item.
+    fn time(&self) -> DateTime<Utc>;
+
+    /// Returns the value of the item.
+    fn value(&self) -> f32;
+
+    /// Sets the human-readable classification label of the item.
+    fn set_label(&mut self, label: Option<&'static str>);
+
+    /// Sets whether the value of the item exceeds the threshold of its metric.
+    fn set_exceeds(&mut self, exceeds: bool);
+}
+
+impl ForecastItem for BuienradarItem {
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn value(&self) -> f32 {
+        self.value
+    }
+
+    fn set_label(&mut self, label: Option<&'static str>) {
+        self.label = label;
+    }
+
+    fn set_exceeds(&mut self, exceeds: bool) {
+        self.exceeds = Some(exceeds);
+    }
+}
+
+impl ForecastItem for BuienradarSample {
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn value(&self) -> f32 {
+        self.score
+    }
+
+    fn set_label(&mut self, label: Option<&'static str>) {
+        self.label = label;
+    }
+
+    fn set_exceeds(&mut self, exceeds: bool) {
+        self.exceeds = Some(exceeds);
+    }
+}
+
+impl ForecastItem for CombinedItem {
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn value(&self) -> f32 {
+        self.value
+    }
+
+    fn set_label(&mut self, label: Option<&'static str>) {
+        self.label = label;
+    }
+
+    fn set_exceeds(&mut self, exceeds: bool) {
+        self.exceeds = Some(exceeds);
+    }
+}
+
+impl ForecastItem for LuchtmeetnetItem {
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn value(&self) -> f32 {
+        self.value
+    }
+
+    fn set_label(&mut self, label: Option<&'static str>) {
+        self.label = label;
+    }
+
+    fn set_exceeds(&mut self, exceeds: bool) {
+        self.exceeds = Some(exceeds);
+    }
+}
+
+/// A time window that restricts which forecast items are returned.
+///
+/// Both bounds are inclusive and optional; a missing bound means the window is unbounded on that
+/// side.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct TimeWindow {
+    /// The start of the window.
+    from: Option<DateTime<Utc>>,
+
+    /// The end of the window.
+    to: Option<DateTime<Utc>>,
+}
+
+impl TimeWindow {
+    /// Creates a new time window from UNIX timestamps and/or a number of hours.
+    ///
+    /// If `hours` is provided but `to` is not, the window ends the given number of hours after
+    /// `from`. If `from` is absent as well, the window starts now. The number of hours cannot
+    /// exceed a year.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn new(from: Option<i64>, to: Option<i64>, hours: Option<u32>) -> Result<Self> {
+        let parse = |ts: i64| {
+            Utc.timestamp_opt(ts, 0)
+                .single()
+                .ok_or(Error::InvalidTimeWindow)
+        };
+        if hours.is_some_and(|hours| hours > MAX_WINDOW_HOURS) {
+            return Err(Error::InvalidTimeWindow);
+        }
+        let mut from = from.map(parse).transpose()?;
+        let to = match (to, hours) {
+            (Some(to), _) => Some(parse(to)?),
+            (None, Some(hours)) => {
+                let start = *from.get_or_insert_with(Utc::now);
+                Some(
+                    start
+                        .checked_add_signed(Duration::hours(i64::from(hours)))
+                        .ok_or(Error::InvalidTimeWindow)?,
+                )
+            }
+            (None, None) => None,
+        };
+        if from.zip(to).is_some_and(|(from, to)| from > to) {
+            return Err(Error::InvalidTimeWindow);
+        }
+
+        Ok(Self { from, to })
+    }
+
+    /// Returns whether the provided time lies within the window.
+    pub(crate) fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.from.is_none_or(|from| time >= from) && self.to.is_none_or(|to| time <= to)
     }
 }
 
@@ -89,10 +985,20 @@ impl Forecast {
 /// This is used for selecting which metrics should be calculated & returned.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(
-    Copy, Clone, Debug, Eq, Hash, Ord, PartialOrd, PartialEq, Serialize, rocket::FromFormField,
+    Copy,
+    Clone,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    PartialEq,
+    Serialize,
+    rocket::FromFormField,
 )]
 #[serde(crate = "rocket::serde")]
-pub(crate) enum Metric {
+pub enum Metric {
     /// All metrics.
     #[field(value = "all")]
     All,
@@ -104,24 +1010,104 @@ pub(crate) enum Metric {
     O3,
     /// The combination of pollen + air quality index.
     PAQI,
+    /// The daily combination of pollen + air quality index.
+    #[field(value = "PAQI_daily")]
+    #[serde(rename = "PAQI_daily")]
+    PAQIDaily,
     /// The particulate matter in the air.
     PM10,
     /// The pollen in the air.
-    #[serde(rename(serialize = "pollen"))]
+    #[serde(rename = "pollen")]
     Pollen,
-    #[serde(rename(serialize = "precipitation"))]
+    #[serde(rename = "precipitation")]
     /// The precipitation.
     Precipitation,
+    /// The temperature.
+    #[serde(rename = "temperature")]
+    Temperature,
     /// The UV index.
     UVI,
 }
 
 impl Metric {
     /// Returns all supported metrics.
-    fn all() -> Vec<Metric> {
+    ///
+    /// Metrics that are disabled in this build are not included, see [`Metric::is_enabled`].
+    pub fn all() -> Vec<Metric> {
         use Metric::*;
 
-        Vec::from([AQI, NO2, O3, PAQI, PM10, Pollen, Precipitation, UVI])
+        Vec::from([
+            AQI,
+            NO2,
+            O3,
+            PAQI,
+            PAQIDaily,
+            PM10,
+            Pollen,
+            Precipitation,
+            Temperature,
+            UVI,
+        ])
+        .into_iter()
+        .filter(|metric| metric.is_enabled())
+        .collect()
+    }
+
+    /// Returns whether the metric is enabled in this build.
+    ///
+    /// A metric is disabled if the feature of (one of) its provider(s) is not enabled.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Metric::All => true,
+            Metric::AQI | Metric::NO2 | Metric::O3 | Metric::PM10 => cfg!(feature = "luchtmeetnet"),
+            Metric::PAQI | Metric::PAQIDaily => {
+                cfg!(all(feature = "luchtmeetnet", feature = "maps"))
+            }
+            Metric::Pollen | Metric::Temperature | Metric::UVI => cfg!(feature = "maps"),
+            Metric::Precipitation => cfg!(feature = "buienradar"),
+        }
+    }
+
+    /// Returns the unit of the values of the metric in the provided system of units.
+    ///
+    /// Returns [`None`] if the values are an index without a unit.
+    pub(crate) fn unit(self, units: Units) -> Option<&'static str> {
+        match (self, units) {
+            (Metric::NO2 | Metric::O3 | Metric::PM10, _) => Some("µg/m³"),
+            (Metric::Precipitation, Units::Metric) => Some("mm/h"),
+            (Metric::Precipitation, Units::Imperial) => Some("in/h"),
+            (Metric::Temperature, Units::Metric) => Some("°C"),
+            (Metric::Temperature, Units::Imperial) => Some("°F"),
+            (
+                Metric::All
+                | Metric::AQI
+                | Metric::PAQI
+                | Metric::PAQIDaily
+                | Metric::Pollen
+                | Metric::UVI,
+                _,
+            ) => None,
+        }
+    }
+
+    /// Returns the provider(s) the data of the metric is retrieved from.
+    pub(crate) fn source(self) -> &'static str {
+        match self {
+            Metric::All | Metric::PAQI | Metric::PAQIDaily => "Buienradar, Luchtmeetnet",
+            Metric::AQI | Metric::NO2 | Metric::O3 | Metric::PM10 => "Luchtmeetnet",
+            Metric::Pollen | Metric::Precipitation | Metric::Temperature | Metric::UVI => {
+                "Buienradar"
+            }
+        }
+    }
+
+    /// Returns the native number of seconds between the items of the metric.
+    pub(crate) fn cadence(self) -> i64 {
+        match self {
+            Metric::Precipitation => 5 * 60,
+            Metric::PAQIDaily | Metric::UVI => 24 * 3_600,
+            _ => 3_600,
+        }
     }
 }
 
@@ -133,86 +1119,609 @@ impl fmt::Display for Metric {
             Metric::NO2 => write!(f, "NO2"),
             Metric::O3 => write!(f, "O3"),
             Metric::PAQI => write!(f, "PAQI"),
+            Metric::PAQIDaily => write!(f, "PAQI_daily"),
             Metric::PM10 => write!(f, "PM10"),
             Metric::Pollen => write!(f, "pollen"),
             Metric::Precipitation => write!(f, "precipitation"),
+            Metric::Temperature => write!(f, "temperature"),
             Metric::UVI => write!(f, "UVI"),
         }
     }
 }
 
+/// The supported systems of units of the forecast values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, rocket::FromFormField)]
+pub(crate) enum Units {
+    /// The metric system: precipitation in mm/h and temperature in °C.
+    #[default]
+    Metric,
+    /// The imperial system: precipitation in in/h and temperature in °F.
+    Imperial,
+}
+
+impl Units {
+    /// The number of millimeters per inch.
+    const MM_PER_INCH: f32 = 25.4;
+
+    /// Converts the value of the provided metric from metric units to this system of units.
+    fn convert(self, metric: Metric, value: f32) -> f32 {
+        match (self, metric) {
+            (Units::Imperial, Metric::Precipitation) => value / Self::MM_PER_INCH,
+            (Units::Imperial, Metric::Temperature) => value * 9.0 / 5.0 + 32.0,
+            _ => value,
+        }
+    }
+
+    /// Converts the value of the provided metric from this system of units to metric units.
+    fn to_metric(self, metric: Metric, value: f32) -> f32 {
+        match (self, metric) {
+            (Units::Imperial, Metric::Precipitation) => value * Self::MM_PER_INCH,
+            (Units::Imperial, Metric::Temperature) => (value - 32.0) * 5.0 / 9.0,
+            _ => value,
+        }
+    }
+}
+
+/// Retrieves the data for the provided metric using the provider future, if requested.
+///
+/// The result is resolved using the provided last known good store, see [`LastKnownGood`]. If the
+/// metric is disabled in this build, the provider is not used at all.
+async fn retrieve<V, F>(
+    metric: Metric,
+    metrics: &[Metric],
+    position: Position,
+    last_known_good: &LastKnownGood<V>,
+    provider: F,
+) -> Option<Result<Retrieved<V>>>
+where
+    V: Clone + PartialEq,
+    F: Future<Output = Result<V>>,
+{
+    if !metrics.contains(&metric) {
+        return None;
+    }
+    if !metric.is_enabled() {
+        return Some(Err(Error::MetricDisabled(metric)));
+    }
+
+    let start = Instant::now();
+    let max_staleness = providers::cache_ttls().max_staleness(metric);
+    let result = last_known_good.resolve(position, metric, provider.await, max_staleness);
+    debug!(
+        %metric,
+        duration_ms = start.elapsed().as_millis() as u64,
+        "Retrieved forecast for metric"
+    );
+
+    Some(result)
+}
+
 /// Calculates and returns the forecast.
 ///
 /// The provided list `metrics` determines what will be included in the forecast.
+/// The data for all metrics is retrieved from the providers concurrently.
+#[instrument(skip(maps_handle), fields(lat = position.lat, lon = position.lon))]
 pub(crate) async fn forecast(
     position: Position,
     metrics: Vec<Metric>,
     maps_handle: &MapsHandle,
 ) -> Forecast {
     let mut forecast = Forecast::new(position);
+    // Nearby positions share the data retrieved from the providers.
+    let position = providers::lookup_position(position);
 
-    // Expand the `All` metric if present, deduplicate otherwise.
-    let mut metrics = metrics;
-    if metrics.contains(&Metric::All) {
-        metrics = Metric::all();
+    // Expand the `All` metric if present.
+    let metrics = if metrics.contains(&Metric::All) {
+        Metric::all()
     } else {
-        metrics.dedup()
-    }
+        metrics
+    };
 
-    for metric in metrics {
-        match metric {
-            // This should have been expanded to all the metrics matched below.
-            Metric::All => unreachable!("The all metric should have been expanded"),
-            Metric::AQI => {
-                forecast.aqi = providers::luchtmeetnet::get(position, metric)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-            Metric::NO2 => {
-                forecast.no2 = providers::luchtmeetnet::get(position, metric)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-            Metric::O3 => {
-                forecast.o3 = providers::luchtmeetnet::get(position, metric)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-            Metric::PAQI => {
-                forecast.paqi = providers::combined::get(position, metric, maps_handle)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-            Metric::PM10 => {
-                forecast.pm10 = providers::luchtmeetnet::get(position, metric)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-            Metric::Pollen => {
-                forecast.pollen = providers::buienradar::get_samples(position, metric, maps_handle)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-            Metric::Precipitation => {
-                forecast.precipitation = providers::buienradar::get_items(position, metric)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-            Metric::UVI => {
-                forecast.uvi = providers::buienradar::get_samples(position, metric, maps_handle)
-                    .await
-                    .map_err(|err| forecast.log_error(metric, err))
-                    .ok()
-            }
-        }
+    let (aqi, no2, o3, paqi, paqi_daily, pm10, pollen, precipitation, temperature, uvi) = tokio::join!(
+        retrieve(
+            Metric::AQI,
+            &metrics,
+            position,
+            &LUCHTMEETNET_ITEMS,
+            luchtmeetnet::get(position, Metric::AQI)
+        ),
+        retrieve(
+            Metric::NO2,
+            &metrics,
+            position,
+            &LUCHTMEETNET_ITEMS,
+            luchtmeetnet::get(position, Metric::NO2)
+        ),
+        retrieve(
+            Metric::O3,
+            &metrics,
+            position,
+            &LUCHTMEETNET_ITEMS,
+            luchtmeetnet::get(position, Metric::O3)
+        ),
+        retrieve(
+            Metric::PAQI,
+            &metrics,
+            position,
+            &COMBINED_ITEMS,
+            combined::get(position, Metric::PAQI, maps_handle)
+        ),
+        retrieve(
+            Metric::PAQIDaily,
+            &metrics,
+            position,
+            &COMBINED_ITEMS,
+            combined::get(position, Metric::PAQIDaily, maps_handle)
+        ),
+        retrieve(
+            Metric::PM10,
+            &metrics,
+            position,
+            &LUCHTMEETNET_ITEMS,
+            luchtmeetnet::get(position, Metric::PM10)
+        ),
+        retrieve(
+            Metric::Pollen,
+            &metrics,
+            position,
+            &BUIENRADAR_SAMPLES,
+            buienradar::get_samples(position, Metric::Pollen, maps_handle)
+        ),
+        retrieve(
+            Metric::Precipitation,
+            &metrics,
+            position,
+            &BUIENRADAR_ITEMS,
+            buienradar::get_items(position, Metric::Precipitation)
+        ),
+        retrieve(
+            Metric::Temperature,
+            &metrics,
+            position,
+            &BUIENRADAR_ITEMS,
+            buienradar::get_map_items(position, Metric::Temperature, maps_handle)
+        ),
+        retrieve(
+            Metric::UVI,
+            &metrics,
+            position,
+            &BUIENRADAR_SAMPLES,
+            buienradar::get_samples(position, Metric::UVI, maps_handle)
+        ),
+    );
+
+    if let Some(result) = aqi {
+        forecast.aqi = forecast.record(Metric::AQI, result);
+    }
+    if let Some(result) = no2 {
+        forecast.no2 = forecast.record(Metric::NO2, result);
+    }
+    if let Some(result) = o3 {
+        forecast.o3 = forecast.record(Metric::O3, result);
+    }
+    if let Some(result) = paqi {
+        forecast.paqi = forecast.record(Metric::PAQI, result);
+    }
+    if let Some(result) = paqi_daily {
+        forecast.paqi_daily = forecast.record(Metric::PAQIDaily, result);
+    }
+    if let Some(result) = pm10 {
+        forecast.pm10 = forecast.record(Metric::PM10, result);
+    }
+    if let Some(result) = pollen {
+        forecast.pollen = forecast.record(Metric::Pollen, result);
+    }
+    if let Some(result) = precipitation {
+        forecast.precipitation = forecast.record(Metric::Precipitation, result);
+        forecast.derive_precipitation();
+        forecast.precipitation_nowcast = forecast
+            .series(Metric::Precipitation)
+            .and_then(|series| Nowcast::new(&series, Metric::Precipitation.cadence(), Utc::now()));
+    }
+    if let Some(result) = temperature {
+        forecast.temperature = forecast.record(Metric::Temperature, result);
+    }
+    if let Some(result) = uvi {
+        forecast.uvi = forecast.record(Metric::UVI, result);
     }
 
     forecast
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    fn to_csv() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let t_1 = Utc.with_ymd_and_hms(2024, 1, 10, 13, 0, 0).unwrap();
+
+        let forecast = Forecast {
+            aqi: Some(Vec::from([
+                LuchtmeetnetItem::new(t_0, 2.5),
+                LuchtmeetnetItem::new(t_1, 3.0),
+            ])),
+            uvi: Some(Vec::from([BuienradarSample::new(t_0, 4)])),
+            ..Default::default()
+        };
+        assert_eq!(
+            forecast.to_csv().unwrap(),
+            "time,AQI,UVI\n1704888000,2.5,4\n1704891600,3,\n"
+        );
+
+        // An empty forecast only has a header.
+        assert_eq!(Forecast::default().to_csv().unwrap(), "time\n");
+    }
+
+    #[test]
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    fn to_table() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let t_1 = Utc.with_ymd_and_hms(2024, 1, 10, 13, 0, 0).unwrap();
+
+        let mut forecast = Forecast {
+            aqi: Some(Vec::from([
+                LuchtmeetnetItem::new(t_0, 2.5),
+                LuchtmeetnetItem::new(t_1, 3.0),
+            ])),
+            uvi: Some(Vec::from([BuienradarSample::new(t_0, 4)])),
+            ..Default::default()
+        };
+        forecast.log_error(Metric::Pollen, Error::NoPositionFound);
+        assert_eq!(
+            forecast.to_table(),
+            "time (UTC)        AQI  UVI\n\
+             2024-01-10 12:00  2.5  4\n\
+             2024-01-10 13:00  3\n\
+             pollen: No geocoded position could be found\n"
+        );
+
+        // An empty forecast only has a header.
+        assert_eq!(Forecast::default().to_table(), "time (UTC)\n");
+    }
+
+    #[test]
+    fn record() {
+        let now = Utc::now();
+        let t_0 = now - Duration::hours(2);
+        let mut forecast = Forecast::default();
+        let retrieved = Retrieved {
+            value: 1,
+            retrieved_at: t_0,
+            confirmed_at: now,
+            stale: false,
+        };
+        assert_eq!(forecast.record(Metric::AQI, Ok(retrieved)), Some(1));
+
+        // Data that was first retrieved long ago but just confirmed is as fresh as new data; only
+        // the last modification time is that of when it was first retrieved.
+        assert!(forecast.freshness[&Metric::AQI].data_age <= 1);
+        assert!(forecast.max_age() > 0);
+        assert_eq!(forecast.last_modified(), Some(t_0));
+    }
+
+    #[test]
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    fn include_meta() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let t_1 = Utc.with_ymd_and_hms(2024, 1, 10, 13, 0, 0).unwrap();
+        let freshness = Freshness {
+            stale: false,
+            data_age: 0,
+            retrieved_at: t_0,
+            confirmed_at: t_0,
+        };
+
+        let mut forecast = Forecast {
+            no2: Some(Vec::from([
+                LuchtmeetnetItem::new(t_0, 12.5),
+                LuchtmeetnetItem::new(t_1, 13.0),
+            ])),
+            paqi: Some(Vec::from([CombinedItem::new(t_0, 3.0)])),
+            uvi: Some(Vec::from([BuienradarSample::new(t_0, 4)])),
+            freshness: BTreeMap::from([
+                (Metric::NO2, freshness),
+                (Metric::PAQI, freshness),
+                (Metric::UVI, freshness),
+            ]),
+            ..Default::default()
+        };
+        forecast.include_meta();
+
+        // The cadence is determined from the items, or is the native one for a single item.
+        assert_eq!(
+            forecast.meta,
+            BTreeMap::from([
+                (
+                    Metric::NO2,
+                    MetricMeta {
+                        unit: Some("µg/m³"),
+                        source: "Luchtmeetnet",
+                        retrieved_at: t_0.timestamp(),
+                        cadence: 3_600,
+                        paqi_merge: None,
+                    }
+                ),
+                (
+                    Metric::PAQI,
+                    MetricMeta {
+                        unit: None,
+                        source: "Buienradar, Luchtmeetnet",
+                        retrieved_at: t_0.timestamp(),
+                        cadence: 3_600,
+                        paqi_merge: Some(PaqiMergeConfig::default()),
+                    }
+                ),
+                (
+                    Metric::UVI,
+                    MetricMeta {
+                        unit: None,
+                        source: "Buienradar",
+                        retrieved_at: t_0.timestamp(),
+                        cadence: 86_400,
+                        paqi_merge: None,
+                    }
+                ),
+            ])
+        );
+
+        // Without asking for it, no metadata is included.
+        let json = serde_json::to_string(&Forecast::default()).unwrap();
+        assert!(!json.contains("meta"));
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "buienradar",
+        feature = "luchtmeetnet",
+        feature = "maps",
+        feature = "server"
+    ))]
+    fn serialization_formats() {
+        use rocket::serde::json::Value;
+
+        use crate::binary::{to_cbor, to_msgpack};
+        use crate::protobuf::{messages, to_protobuf};
+
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let freshness = Freshness {
+            stale: true,
+            data_age: 60,
+            retrieved_at: t_0,
+            confirmed_at: t_0,
+        };
+        let concentrations = BTreeMap::from([(Metric::NO2, 12.5), (Metric::PM10, 20.1)]);
+        let item = LuchtmeetnetItem {
+            exceeds: Some(true),
+            concentrations: Some(concentrations.clone()),
+            ..LuchtmeetnetItem::new(t_0, 2.5)
+        };
+        let combined_item = CombinedItem {
+            exceeds: Some(false),
+            partial: true,
+            concentrations: Some(concentrations),
+            ..CombinedItem::new(t_0, 3.0)
+        };
+        let buienradar_item = BuienradarItem {
+            exceeds: Some(false),
+            intensity: Some(buienradar::Intensity::Moderate),
+            accumulated: Some(0.13),
+            ..BuienradarItem::new(t_0, 1.5)
+        };
+        let sample = BuienradarSample {
+            exceeds: Some(true),
+            ..BuienradarSample::new(t_0, 4)
+        };
+
+        // A forecast with all fields set has the same structure in the binary formats as in JSON,
+        // and only has keys that map to fields of the message type.
+        let mut forecast = Forecast {
+            location: Some(String::from("Utrecht")),
+            aqi: Some(Vec::from([item.clone()])),
+            no2: Some(Vec::from([item.clone()])),
+            o3: Some(Vec::from([item.clone()])),
+            paqi: Some(Vec::from([combined_item.clone()])),
+            paqi_daily: Some(Vec::from([combined_item])),
+            pm10: Some(Vec::from([item])),
+            pollen: Some(Vec::from([sample.clone()])),
+            precipitation: Some(Vec::from([buienradar_item.clone()])),
+            precipitation_nowcast: Nowcast::new(
+                &[
+                    (t_0, 0.0),
+                    (t_0 + Duration::minutes(5), 1.5),
+                    (t_0 + Duration::minutes(10), 0.0),
+                ],
+                300,
+                t_0,
+            ),
+            precipitation_consensus: Consensus::new(&[(t_0, 1.5)], &[(t_0, 0.5)], 900),
+            temperature: Some(Vec::from([buienradar_item])),
+            uvi: Some(Vec::from([sample])),
+            freshness: Metric::all()
+                .into_iter()
+                .map(|metric| (metric, freshness))
+                .collect(),
+            ..Default::default()
+        };
+        forecast.log_error(Metric::Pollen, Error::NoPositionFound);
+        forecast.include_labels(Locale::En);
+        forecast.include_summary();
+        forecast.include_meta();
+        forecast.include_links(String::from("/forecast"), "");
+        let value = serde_json::to_value(&forecast).unwrap();
+        let msgpack = to_msgpack(&forecast).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Value>(&msgpack).unwrap(), value);
+        let cbor = to_cbor(&forecast).unwrap();
+        assert_eq!(
+            ciborium::from_reader::<Value, _>(cbor.as_slice()).unwrap(),
+            value
+        );
+        assert!(to_protobuf::<messages::Forecast>(&value).is_ok());
+    }
+
+    #[test]
+    #[cfg(all(feature = "buienradar", feature = "maps"))]
+    fn convert_units() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let mut forecast = Forecast {
+            precipitation: Some(Vec::from([BuienradarItem {
+                accumulated: Some(25.4),
+                ..BuienradarItem::new(t_0, 25.4)
+            }])),
+            temperature: Some(Vec::from([BuienradarItem::new(t_0, 20.0)])),
+            uvi: Some(Vec::from([BuienradarSample::new(t_0, 4)])),
+            ..Default::default()
+        };
+        let original = forecast.clone();
+
+        // Only the precipitation and temperature are converted.
+        forecast.convert_units(Units::Imperial);
+        assert_eq!(
+            forecast.series(Metric::Precipitation),
+            Some(Vec::from([(t_0, 1.0)]))
+        );
+        assert_eq!(
+            forecast.precipitation.as_ref().unwrap()[0].accumulated,
+            Some(1.0)
+        );
+        assert_eq!(
+            forecast.series(Metric::Temperature),
+            Some(Vec::from([(t_0, 68.0)]))
+        );
+        assert_eq!(forecast.series(Metric::UVI), original.series(Metric::UVI));
+        assert_eq!(Metric::Temperature.unit(forecast.units), Some("°F"));
+
+        // Converting to the same system of units does nothing, converting back restores them.
+        forecast.convert_units(Units::Imperial);
+        assert_eq!(
+            forecast.series(Metric::Temperature),
+            Some(Vec::from([(t_0, 68.0)]))
+        );
+        forecast.convert_units(Units::Metric);
+        assert_eq!(forecast, original);
+    }
+
+    #[test]
+    #[cfg(feature = "buienradar")]
+    fn include_labels() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let mut forecast = Forecast {
+            precipitation: Some(Vec::from([BuienradarItem::new(t_0, 5.0)])),
+            ..Default::default()
+        };
+
+        // The labels are based on the metric values, also after converting the units.
+        forecast.convert_units(Units::Imperial);
+        forecast.include_labels(Locale::Nl);
+        let json = serde_json::to_string(&forecast).unwrap();
+        assert!(json.contains(r#""label":"zware regen""#));
+    }
+
+    #[test]
+    #[cfg(feature = "maps")]
+    fn mark_thresholds() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let t_1 = Utc.with_ymd_and_hms(2024, 1, 10, 13, 0, 0).unwrap();
+        let mut forecast = Forecast {
+            pollen: Some(Vec::from([BuienradarSample::new(t_0, 3)])),
+            uvi: Some(Vec::from([
+                BuienradarSample::new(t_0, 6),
+                BuienradarSample::new(t_1, 7),
+            ])),
+            ..Default::default()
+        };
+
+        // Only the items of metrics with a threshold are marked; equal values do not exceed it.
+        forecast.mark_thresholds(&BTreeMap::from([(Metric::UVI, 6.0)]));
+        let exceeds = |samples: &Option<Vec<BuienradarSample>>| {
+            samples
+                .iter()
+                .flatten()
+                .map(|sample| sample.exceeds)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(exceeds(&forecast.uvi), [Some(false), Some(true)]);
+        assert_eq!(exceeds(&forecast.pollen), [None]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    fn retain_page() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let items = (0..4)
+            .map(|hour| LuchtmeetnetItem::new(t_0 + Duration::hours(hour), hour as f32))
+            .collect::<Vec<_>>();
+        let mut forecast = Forecast {
+            aqi: Some(items),
+            uvi: Some(Vec::from([BuienradarSample::new(t_0, 4)])),
+            ..Default::default()
+        };
+
+        // The page is applied to each metric separately.
+        forecast.retain_page(1, Some(2));
+        assert_eq!(
+            forecast.series(Metric::AQI).unwrap(),
+            [
+                (t_0 + Duration::hours(1), 1.0),
+                (t_0 + Duration::hours(2), 2.0)
+            ]
+        );
+        assert_eq!(forecast.series(Metric::UVI).unwrap(), []);
+
+        // An offset beyond the items results in no items; no limit keeps the rest.
+        forecast.retain_page(5, None);
+        assert_eq!(forecast.series(Metric::AQI).unwrap(), []);
+        assert_eq!(forecast.series(Metric::Pollen), None);
+    }
+
+    #[test]
+    fn time_window() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let t_1 = Utc.with_ymd_and_hms(2024, 1, 10, 13, 0, 0).unwrap();
+        let t_2 = Utc.with_ymd_and_hms(2024, 1, 10, 15, 0, 0).unwrap();
+
+        // An unbounded window contains everything.
+        let window = TimeWindow::new(None, None, None).unwrap();
+        assert!(window.contains(t_0));
+        assert!(window.contains(t_2));
+
+        // Both bounds are inclusive.
+        let window = TimeWindow::new(Some(t_0.timestamp()), Some(t_1.timestamp()), None).unwrap();
+        assert!(window.contains(t_0));
+        assert!(window.contains(t_1));
+        assert!(!window.contains(t_2));
+
+        // The number of hours is relative to the start, but `to` takes precedence.
+        let window = TimeWindow::new(Some(t_0.timestamp()), None, Some(1)).unwrap();
+        assert!(window.contains(t_1));
+        assert!(!window.contains(t_2));
+        let window =
+            TimeWindow::new(Some(t_0.timestamp()), Some(t_2.timestamp()), Some(1)).unwrap();
+        assert!(window.contains(t_2));
+
+        // Without a start, the number of hours is relative to now and past items are excluded.
+        let now = Utc::now();
+        let window = TimeWindow::new(None, None, Some(3)).unwrap();
+        assert!(!window.contains(now - Duration::hours(1)));
+        assert!(window.contains(now + Duration::hours(1)));
+        assert!(!window.contains(now + Duration::hours(4)));
+
+        // The start cannot be after the end.
+        assert_matches::assert_matches!(
+            TimeWindow::new(Some(t_2.timestamp()), Some(t_0.timestamp()), None),
+            Err(Error::InvalidTimeWindow)
+        );
+
+        // The number of hours is capped and the end cannot overflow.
+        assert_matches::assert_matches!(
+            TimeWindow::new(None, None, Some(u32::MAX)),
+            Err(Error::InvalidTimeWindow)
+        );
+        assert_matches::assert_matches!(
+            TimeWindow::new(Some(DateTime::<Utc>::MAX_UTC.timestamp()), None, Some(1)),
+            Err(Error::InvalidTimeWindow)
+        );
+    }
+}
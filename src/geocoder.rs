@@ -0,0 +1,355 @@
+//! All supported geocoder backends.
+//!
+//! Addresses are geocoded to positions (forward geocoding) and positions are resolved to place
+//! names (reverse geocoding) using one of the configured backends. Requests to the backend are
+//! throttled to comply with its usage policy.
+
+pub(crate) mod nominatim;
+pub(crate) mod pdok;
+pub(crate) mod photon;
+
+use std::sync::OnceLock;
+
+use reqwest::Url;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::Mutex;
+use rocket::tokio::time::{sleep_until, Duration, Instant};
+
+use crate::config::GeocoderConfig;
+use crate::position::{BoundingBox, Position};
+use crate::{Error, Result};
+
+/// The geocoder used for resolving addresses and place names.
+static GEOCODER: OnceLock<Geocoder> = OnceLock::new();
+
+/// The supported geocoder backends.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub(crate) enum GeocoderBackend {
+    /// Nominatim (OpenStreetMap).
+    #[default]
+    Nominatim,
+    /// PDOK Locatieserver (Dutch addresses only).
+    Pdok,
+    /// Photon (OpenStreetMap).
+    Photon,
+}
+
+impl GeocoderBackend {
+    /// Returns the default URL of the backend.
+    fn default_url(self) -> Url {
+        let url = match self {
+            GeocoderBackend::Nominatim => nominatim::NOMINATIM_URL,
+            GeocoderBackend::Pdok => pdok::PDOK_URL,
+            GeocoderBackend::Photon => photon::PHOTON_URL,
+        };
+
+        Url::parse(url).expect("Default geocoder URL is valid")
+    }
+
+    /// Returns the default maximum number of requests per second, if limited.
+    ///
+    /// The public Nominatim instance allows at most one request per second.
+    fn default_rate(self) -> Option<f64> {
+        match self {
+            GeocoderBackend::Nominatim => Some(1.0),
+            GeocoderBackend::Pdok | GeocoderBackend::Photon => None,
+        }
+    }
+}
+
+/// A geocoding candidate for an address.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Candidate {
+    /// The human-readable name of the candidate.
+    pub(crate) name: String,
+
+    /// The latitude of the candidate.
+    pub(crate) lat: f64,
+
+    /// The longitude of the candidate.
+    pub(crate) lon: f64,
+}
+
+impl Candidate {
+    /// Creates a new geocoding candidate.
+    pub(crate) fn new(name: String, position: Position) -> Self {
+        Self {
+            name,
+            lat: position.lat,
+            lon: position.lon,
+        }
+    }
+
+    /// Returns the geocoded position of the candidate.
+    pub(crate) fn position(&self) -> Position {
+        Position::new(self.lat, self.lon)
+    }
+}
+
+/// The area geocoding is restricted to.
+#[derive(Debug, Default)]
+struct Area {
+    /// The ISO 3166-1 alpha-2 codes of the countries; if empty, any country is included.
+    country_codes: Vec<String>,
+
+    /// The bounding box (if restricted).
+    bounding_box: Option<BoundingBox>,
+}
+
+impl Area {
+    /// Returns whether the country with the provided country code is included in the area.
+    ///
+    /// If geocoding is restricted to countries, an unknown country is not included.
+    fn includes_country(&self, country_code: Option<&str>) -> bool {
+        self.country_codes.is_empty()
+            || country_code.is_some_and(|country_code| {
+                self.country_codes
+                    .iter()
+                    .any(|code| code.eq_ignore_ascii_case(country_code))
+            })
+    }
+
+    /// Returns whether the area contains the provided position.
+    fn contains(&self, position: Position) -> bool {
+        self.bounding_box
+            .is_none_or(|bounding_box| bounding_box.contains(position))
+    }
+}
+
+/// A throttle that spaces requests at a minimum interval.
+#[derive(Debug)]
+struct Throttle {
+    /// The minimum interval between requests (if throttled).
+    interval: Option<Duration>,
+
+    /// The instant at which the next request is allowed.
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    /// Creates a new throttle that allows the provided number of requests per second.
+    ///
+    /// If the rate is not positive, requests are not throttled.
+    fn new(rate: Option<f64>) -> Self {
+        let interval = rate
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| Duration::from_secs_f64(1.0 / rate));
+
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until the next request is allowed.
+    async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        let mut next = self.next.lock().await;
+        sleep_until(*next).await;
+        *next = Instant::now() + interval;
+    }
+}
+
+/// A geocoder using one of the supported backends.
+#[derive(Debug)]
+pub(crate) struct Geocoder {
+    /// The backend.
+    backend: GeocoderBackend,
+
+    /// The URL of the backend.
+    url: Url,
+
+    /// The throttle for the requests to the backend.
+    throttle: Throttle,
+
+    /// The area geocoding is restricted to.
+    area: Area,
+}
+
+impl Geocoder {
+    /// Creates a geocoder using the provided configuration.
+    ///
+    /// The URL and rate limit default to those of the configured backend.
+    pub(crate) fn new(config: &GeocoderConfig) -> Self {
+        let backend = config.backend;
+        let url = config.url.clone().unwrap_or_else(|| backend.default_url());
+        let throttle = Throttle::new(config.rate.or(backend.default_rate()));
+        let area = Area {
+            country_codes: config.country_codes.clone(),
+            bounding_box: config.bounding_box,
+        };
+
+        Self {
+            backend,
+            url,
+            throttle,
+            area,
+        }
+    }
+
+    /// Resolves the geocoded position for the provided address.
+    ///
+    /// This is the position of the first (most plausible) candidate.
+    pub(crate) async fn forward(&self, address: &str) -> Result<Position> {
+        self.search(address, 1, None)
+            .await?
+            .first()
+            .map(Candidate::position)
+            .ok_or(Error::NoPositionFound)
+    }
+
+    /// Searches for at most `limit` geocoding candidates for the provided address.
+    ///
+    /// The candidates are ordered from most to least plausible. Only candidates within the
+    /// configured area are returned; if all found candidates lie outside of it, this fails. If a
+    /// language (code) is provided, the names of the candidates are preferably in that language,
+    /// as far as the backend supports it.
+    pub(crate) async fn search(
+        &self,
+        address: &str,
+        limit: usize,
+        language: Option<&str>,
+    ) -> Result<Vec<Candidate>> {
+        self.throttle.wait().await;
+
+        let (url, area) = (&self.url, &self.area);
+        let found_candidates = match self.backend {
+            GeocoderBackend::Nominatim => {
+                nominatim::search(url, address, limit, language, area).await?
+            }
+            GeocoderBackend::Pdok => pdok::search(url, address, limit, language, area).await?,
+            GeocoderBackend::Photon => photon::search(url, address, limit, language, area).await?,
+        };
+        let found_count = found_candidates.len();
+        let candidates = found_candidates
+            .into_iter()
+            .filter(|candidate| area.contains(candidate.position()))
+            .collect::<Vec<_>>();
+        if found_count > 0 && candidates.is_empty() {
+            return Err(Error::OutsideGeocodingArea);
+        }
+
+        Ok(candidates)
+    }
+
+    /// Resolves the geocoded position for the provided (normalized) Dutch postal code.
+    ///
+    /// Postal codes are always resolved using PDOK Locatieserver; if it is not the configured
+    /// backend, its public instance is used. This fails if the position lies outside of the
+    /// configured area.
+    pub(crate) async fn postcode(&self, postcode: &str) -> Result<Position> {
+        if !self.area.includes_country(Some("nl")) {
+            return Err(Error::OutsideGeocodingArea);
+        }
+
+        let position = if self.backend == GeocoderBackend::Pdok {
+            self.throttle.wait().await;
+
+            pdok::postcode(&self.url, postcode).await?
+        } else {
+            pdok::postcode(&GeocoderBackend::Pdok.default_url(), postcode).await?
+        };
+        if !self.area.contains(position) {
+            return Err(Error::OutsideGeocodingArea);
+        }
+
+        Ok(position)
+    }
+
+    /// Resolves the human-readable place name for the provided position, e.g. "Eindhoven, NL".
+    pub(crate) async fn reverse(&self, position: Position) -> Result<String> {
+        self.throttle.wait().await;
+
+        match self.backend {
+            GeocoderBackend::Nominatim => nominatim::reverse(&self.url, position).await,
+            GeocoderBackend::Pdok => pdok::reverse(&self.url, position).await,
+            GeocoderBackend::Photon => photon::reverse(&self.url, position).await,
+        }
+    }
+}
+
+/// Sets the geocoder.
+///
+/// This needs to be done before the first address is resolved, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_geocoder(geocoder: Geocoder) {
+    let _ = GEOCODER.set(geocoder);
+}
+
+/// Returns the geocoder.
+///
+/// If it has not been set, a geocoder with the default configuration is used.
+pub(crate) fn geocoder() -> &'static Geocoder {
+    GEOCODER.get_or_init(|| Geocoder::new(&GeocoderConfig::default()))
+}
+
+/// Returns the URL of the provided endpoint relative to the URL of a backend.
+fn endpoint_url(url: &Url, endpoint: &str) -> Url {
+    let mut url = url.clone();
+    url.path_segments_mut()
+        .expect("Geocoder URL can be a base")
+        .pop_if_empty()
+        .push(endpoint);
+
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn area() {
+        let area = Area::default();
+        assert!(area.includes_country(Some("fr")));
+        assert!(area.includes_country(None));
+        assert!(area.contains(Position::new(48.86, 2.35)));
+
+        let area = Area {
+            country_codes: vec![String::from("nl"), String::from("be")],
+            bounding_box: Some(BoundingBox {
+                min_lat: 50.7,
+                min_lon: 3.3,
+                max_lat: 53.6,
+                max_lon: 7.3,
+            }),
+        };
+        assert!(area.includes_country(Some("NL")));
+        assert!(!area.includes_country(Some("fr")));
+        assert!(!area.includes_country(None));
+        assert!(area.contains(Position::new(51.44, 5.47)));
+        assert!(!area.contains(Position::new(48.86, 2.35)));
+    }
+
+    #[test]
+    fn endpoint_url() {
+        let url = Url::parse("https://nominatim.openstreetmap.org").unwrap();
+        assert_eq!(
+            super::endpoint_url(&url, "search").as_str(),
+            "https://nominatim.openstreetmap.org/search"
+        );
+
+        let url = Url::parse("https://geo.example.org/nominatim/").unwrap();
+        assert_eq!(
+            super::endpoint_url(&url, "reverse").as_str(),
+            "https://geo.example.org/nominatim/reverse"
+        );
+    }
+
+    #[test]
+    fn throttle() {
+        let throttle = Throttle::new(Some(2.0));
+        assert_eq!(throttle.interval, Some(Duration::from_millis(500)));
+
+        // Requests are not throttled without a (positive) rate.
+        let throttle = Throttle::new(Some(0.0));
+        assert_eq!(throttle.interval, None);
+        let throttle = Throttle::new(None);
+        assert_eq!(throttle.interval, None);
+    }
+}
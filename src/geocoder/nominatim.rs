@@ -0,0 +1,218 @@
+//! The Nominatim geocoder backend.
+//!
+//! For more information about Nominatim and its usage policy, see:
+//! <https://operations.osmfoundation.org/policies/nominatim/>.
+
+use reqwest::Url;
+use rocket::serde::json::serde_json;
+use rocket::serde::Deserialize;
+use tracing::info;
+
+use super::{endpoint_url, Area, Candidate};
+use crate::position::Position;
+use crate::{http, Error, Result};
+
+/// The default URL of the (public) Nominatim API.
+pub(crate) const NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+
+/// The zoom level used for reverse geocoding, which corresponds to the level of cities.
+const REVERSE_ZOOM: u8 = 10;
+
+/// A Nominatim search result.
+///
+/// This is only used temporarily during deserialization.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Place {
+    /// The full name of the place.
+    display_name: String,
+
+    /// The latitude of the place.
+    lat: String,
+
+    /// The longitude of the place.
+    lon: String,
+}
+
+impl TryFrom<Place> for Candidate {
+    type Error = Error;
+
+    fn try_from(place: Place) -> Result<Self> {
+        let parse = |coord: &str| {
+            coord
+                .parse()
+                .map_err(|_| Error::Geocoding(format!("Invalid coordinate: {coord}")))
+        };
+        let position = Position::new(parse(&place.lat)?, parse(&place.lon)?);
+
+        Ok(Candidate::new(place.display_name, position))
+    }
+}
+
+/// The Nominatim reverse geocoding result.
+///
+/// This is only used temporarily during deserialization.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReverseGeocoded {
+    /// The address details of the place, if a place was found.
+    address: Option<PlaceAddress>,
+}
+
+/// The address details of a reverse geocoded place.
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PlaceAddress {
+    /// The name of the city (if any).
+    city: Option<String>,
+
+    /// The name of the town (if any).
+    town: Option<String>,
+
+    /// The name of the village (if any).
+    village: Option<String>,
+
+    /// The name of the municipality (if any).
+    municipality: Option<String>,
+
+    /// The ISO 3166-1 alpha-2 code of the country (if any).
+    country_code: Option<String>,
+}
+
+impl PlaceAddress {
+    /// Returns the human-readable name of the place, e.g. "Eindhoven, NL".
+    ///
+    /// The most specific of the city, town, village or municipality is used, followed by the
+    /// country code if known.
+    fn name(&self) -> Option<String> {
+        let place = self
+            .city
+            .as_ref()
+            .or(self.town.as_ref())
+            .or(self.village.as_ref())
+            .or(self.municipality.as_ref())?;
+
+        match &self.country_code {
+            Some(country_code) => Some(format!("{place}, {}", country_code.to_uppercase())),
+            None => Some(place.clone()),
+        }
+    }
+}
+
+/// Searches for at most `limit` geocoding candidates for the provided address using Nominatim.
+///
+/// The search is restricted to the countries and bounding box of the provided area (if any). The
+/// names are preferably in the provided language.
+pub(super) async fn search(
+    url: &Url,
+    address: &str,
+    limit: usize,
+    language: Option<&str>,
+    area: &Area,
+) -> Result<Vec<Candidate>> {
+    let mut url = endpoint_url(url, "search");
+    url.query_pairs_mut()
+        .append_pair("q", address)
+        .append_pair("format", "jsonv2")
+        .append_pair("limit", &limit.to_string());
+    if let Some(language) = language {
+        url.query_pairs_mut()
+            .append_pair("accept-language", language);
+    }
+    if !area.country_codes.is_empty() {
+        url.query_pairs_mut()
+            .append_pair("countrycodes", &area.country_codes.join(","));
+    }
+    if let Some(bbox) = area.bounding_box {
+        let viewbox = format!(
+            "{},{},{},{}",
+            bbox.min_lon, bbox.max_lat, bbox.max_lon, bbox.min_lat
+        );
+        url.query_pairs_mut()
+            .append_pair("viewbox", &viewbox)
+            .append_pair("bounded", "1");
+    }
+
+    info!(geocoder = "Nominatim", %address, "🌍 Geocoding the position of the address");
+    let output = http::client().get(url).await?.text().await?;
+    let places: Vec<Place> = serde_json::from_str(&output)?;
+
+    places.into_iter().map(Candidate::try_from).collect()
+}
+
+/// Resolves the human-readable place name for the provided position using Nominatim.
+pub(super) async fn reverse(url: &Url, position: Position) -> Result<String> {
+    let mut url = endpoint_url(url, "reverse");
+    url.query_pairs_mut()
+        .append_pair("format", "jsonv2")
+        .append_pair("lat", &position.lat_as_str(5))
+        .append_pair("lon", &position.lon_as_str(5))
+        .append_pair("zoom", &REVERSE_ZOOM.to_string());
+
+    info!(
+        geocoder = "Nominatim",
+        lat = position.lat,
+        lon = position.lon,
+        "🌍 Reverse geocoding the position"
+    );
+    let output = http::client().get(url).await?.text().await?;
+    let reverse_geocoded: ReverseGeocoded = serde_json::from_str(&output)?;
+
+    reverse_geocoded
+        .address
+        .and_then(|address| address.name())
+        .ok_or(Error::NoPlaceNameFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates() {
+        let json = r#"[
+            {"display_name": "Hengelo, Overijssel, Nederland", "lat": "52.2658", "lon": "6.7931"},
+            {"display_name": "Hengelo, Gelderland, Nederland", "lat": "52.0497", "lon": "6.3106"}
+        ]"#;
+        let places: Vec<Place> = serde_json::from_str(json).unwrap();
+        let candidates = places
+            .into_iter()
+            .map(Candidate::try_from)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[1].name, "Hengelo, Gelderland, Nederland");
+        assert_eq!(candidates[1].position(), Position::new(52.0497, 6.3106));
+    }
+
+    #[test]
+    fn place_address_name() {
+        let json = r#"{
+            "place_id": 123,
+            "address": {
+                "city": "Eindhoven",
+                "municipality": "Eindhoven",
+                "state": "Noord-Brabant",
+                "country": "Nederland",
+                "country_code": "nl"
+            }
+        }"#;
+        let reverse_geocoded: ReverseGeocoded = serde_json::from_str(json).unwrap();
+        let address = reverse_geocoded.address.unwrap();
+        assert_eq!(address.name().as_deref(), Some("Eindhoven, NL"));
+
+        // A less specific place is used if there is no city.
+        let address = PlaceAddress {
+            village: Some(String::from("Nuenen")),
+            municipality: Some(String::from("Nuenen, Gerwen en Nederwetten")),
+            ..Default::default()
+        };
+        assert_eq!(address.name().as_deref(), Some("Nuenen"));
+
+        // Positions without a place (e.g. at sea) have no name.
+        let reverse_geocoded: ReverseGeocoded =
+            serde_json::from_str(r#"{"error": "Unable to geocode"}"#).unwrap();
+        assert!(reverse_geocoded.address.is_none());
+        assert_eq!(PlaceAddress::default().name(), None);
+    }
+}
@@ -0,0 +1,183 @@
+//! The PDOK Locatieserver geocoder backend.
+//!
+//! The Locatieserver only covers Dutch addresses, but has better coverage of them.
+//! For more information about PDOK Locatieserver, see:
+//! <https://github.com/PDOK/locatieserver/wiki/API-Locatieserver>.
+
+use reqwest::Url;
+use rocket::serde::json::serde_json;
+use rocket::serde::Deserialize;
+use tracing::info;
+
+use super::{endpoint_url, Area, Candidate};
+use crate::position::Position;
+use crate::{http, Error, Result};
+
+/// The default URL of the PDOK Locatieserver API.
+pub(crate) const PDOK_URL: &str = "https://api.pdok.nl/bzk/locatieserver/search/v3_1";
+
+/// The PDOK Locatieserver API data container.
+///
+/// This is only used temporarily during deserialization.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Container {
+    response: Response,
+}
+
+/// The PDOK Locatieserver API response.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Response {
+    /// The found documents.
+    docs: Vec<Doc>,
+}
+
+/// A PDOK Locatieserver API document.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Doc {
+    /// The centroid of the found object as a WKT point, e.g. `POINT(5.11 52.09)`.
+    centroide_ll: Option<String>,
+
+    /// The display name of the found object.
+    weergavenaam: Option<String>,
+
+    /// The name of the place (woonplaats) of the found object.
+    woonplaatsnaam: Option<String>,
+}
+
+/// Parses a centroid given as a WKT point (longitude, latitude) into a position.
+fn parse_centroid(centroid: &str) -> Result<Position> {
+    let invalid = || Error::Geocoding(format!("Invalid centroid: {centroid}"));
+    let coords = centroid
+        .strip_prefix("POINT(")
+        .and_then(|coords| coords.strip_suffix(')'))
+        .ok_or_else(invalid)?;
+    let (lon, lat) = coords.split_once(' ').ok_or_else(invalid)?;
+    let lon = lon.parse().map_err(|_| invalid())?;
+    let lat = lat.parse().map_err(|_| invalid())?;
+
+    Ok(Position::new(lat, lon))
+}
+
+/// Retrieves the documents from the provided endpoint URL.
+async fn retrieve_docs(url: Url) -> Result<Vec<Doc>> {
+    let output = http::client().get(url).await?.text().await?;
+    let container: Container = serde_json::from_str(&output)?;
+
+    Ok(container.response.docs)
+}
+
+/// Retrieves the position of the first found document from the provided endpoint URL.
+async fn retrieve_position(url: Url) -> Result<Position> {
+    let docs = retrieve_docs(url).await?;
+    let centroid = docs
+        .first()
+        .and_then(|doc| doc.centroide_ll.as_deref())
+        .ok_or(Error::NoPositionFound)?;
+
+    parse_centroid(centroid)
+}
+
+/// Searches for at most `limit` geocoding candidates for the provided address using PDOK
+/// Locatieserver.
+///
+/// Since the Locatieserver only covers the Netherlands, nothing is found if the provided area
+/// does not include it. The names are always in Dutch, so the provided language is ignored.
+pub(super) async fn search(
+    url: &Url,
+    address: &str,
+    limit: usize,
+    _language: Option<&str>,
+    area: &Area,
+) -> Result<Vec<Candidate>> {
+    if !area.includes_country(Some("nl")) {
+        return Ok(Vec::new());
+    }
+
+    let mut url = endpoint_url(url, "free");
+    url.query_pairs_mut()
+        .append_pair("q", address)
+        .append_pair("rows", &limit.to_string())
+        .append_pair("fl", "centroide_ll,weergavenaam");
+
+    info!(geocoder = "PDOK", %address, "🌍 Geocoding the position of the address");
+    let docs = retrieve_docs(url).await?;
+
+    docs.into_iter()
+        .filter_map(|doc| doc.centroide_ll.zip(doc.weergavenaam))
+        .map(|(centroid, name)| Ok(Candidate::new(name, parse_centroid(&centroid)?)))
+        .collect()
+}
+
+/// Resolves the geocoded position for the provided (normalized) Dutch postal code using PDOK
+/// Locatieserver.
+pub(super) async fn postcode(url: &Url, postcode: &str) -> Result<Position> {
+    let mut url = endpoint_url(url, "free");
+    url.query_pairs_mut()
+        .append_pair("q", &format!("postcode:{postcode}"))
+        .append_pair("fq", "type:postcode")
+        .append_pair("rows", "1")
+        .append_pair("fl", "centroide_ll");
+
+    info!(geocoder = "PDOK", %postcode, "🌍 Geocoding the position of the postal code");
+    retrieve_position(url).await
+}
+
+/// Resolves the human-readable place name for the provided position using PDOK Locatieserver.
+pub(super) async fn reverse(url: &Url, position: Position) -> Result<String> {
+    let mut url = endpoint_url(url, "reverse");
+    url.query_pairs_mut()
+        .append_pair("lat", &position.lat_as_str(5))
+        .append_pair("lon", &position.lon_as_str(5))
+        .append_pair("type", "woonplaats")
+        .append_pair("rows", "1")
+        .append_pair("fl", "woonplaatsnaam");
+
+    info!(
+        geocoder = "PDOK",
+        lat = position.lat,
+        lon = position.lon,
+        "🌍 Reverse geocoding the position"
+    );
+    let docs = retrieve_docs(url).await?;
+
+    docs.into_iter()
+        .find_map(|doc| doc.woonplaatsnaam)
+        .map(|place| format!("{place}, NL"))
+        .ok_or(Error::NoPlaceNameFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn parse_centroid() {
+        let json = r#"{
+            "response": {
+                "numFound": 1,
+                "start": 0,
+                "docs": [{"centroide_ll": "POINT(5.47778 51.4393)"}]
+            }
+        }"#;
+        let container: Container = serde_json::from_str(json).unwrap();
+        let centroid = container.response.docs[0].centroide_ll.as_deref().unwrap();
+        assert_eq!(
+            super::parse_centroid(centroid).unwrap(),
+            Position::new(51.4393, 5.47778)
+        );
+
+        assert_matches!(
+            super::parse_centroid("POINT(5.47)"),
+            Err(Error::Geocoding(_))
+        );
+        assert_matches!(
+            super::parse_centroid("5.47 51.43"),
+            Err(Error::Geocoding(_))
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! The Photon geocoder backend.
+//!
+//! For more information about Photon, see: <https://photon.komoot.io/>.
+
+use reqwest::Url;
+use rocket::serde::json::serde_json;
+use rocket::serde::Deserialize;
+use tracing::info;
+
+use super::{endpoint_url, Area, Candidate};
+use crate::position::Position;
+use crate::{http, Error, Result};
+
+/// The default URL of the (public) Photon API.
+pub(crate) const PHOTON_URL: &str = "https://photon.komoot.io";
+
+/// The languages Photon can return the names of places in (besides the local ones).
+const SUPPORTED_LANGUAGES: [&str; 3] = ["de", "en", "fr"];
+
+/// The Photon API (GeoJSON) feature collection.
+///
+/// This is only used temporarily during deserialization.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+/// A Photon API (GeoJSON) feature.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Feature {
+    /// The geometry of the feature.
+    geometry: Geometry,
+
+    /// The properties of the feature.
+    properties: Properties,
+}
+
+/// The geometry of a Photon API feature.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Geometry {
+    /// The coordinates of the feature (longitude, latitude).
+    coordinates: (f64, f64),
+}
+
+/// The properties of a Photon API feature.
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+struct Properties {
+    /// The name of the feature (if any).
+    name: Option<String>,
+
+    /// The type of the feature, e.g. `house`, `street` or `city`.
+    #[serde(rename = "type")]
+    kind: Option<String>,
+
+    /// The name of the city the feature is in (if any).
+    city: Option<String>,
+
+    /// The name of the state (province) the feature is in (if any).
+    state: Option<String>,
+
+    /// The name of the country the feature is in (if any).
+    country: Option<String>,
+
+    /// The ISO 3166-1 alpha-2 code of the country (if any).
+    countrycode: Option<String>,
+}
+
+impl Properties {
+    /// Returns the human-readable name of the place of the feature, e.g. "Eindhoven, NL".
+    ///
+    /// If the feature is a place itself, its name is used, otherwise the city it is in.
+    fn place_name(&self) -> Option<String> {
+        let is_place = matches!(self.kind.as_deref(), Some("city" | "town" | "village"));
+        let place = if is_place {
+            self.name.as_ref()
+        } else {
+            self.city.as_ref()
+        }?;
+
+        match &self.countrycode {
+            Some(countrycode) => Some(format!("{place}, {}", countrycode.to_uppercase())),
+            None => Some(place.clone()),
+        }
+    }
+}
+
+impl From<Feature> for Candidate {
+    fn from(feature: Feature) -> Self {
+        let properties = feature.properties;
+        let mut parts = Vec::<String>::new();
+        for part in [
+            properties.name,
+            properties.city,
+            properties.state,
+            properties.country,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+        let (lon, lat) = feature.geometry.coordinates;
+
+        Candidate::new(parts.join(", "), Position::new(lat, lon))
+    }
+}
+
+/// Retrieves the features from the provided endpoint URL.
+async fn retrieve_features(url: Url) -> Result<Vec<Feature>> {
+    let output = http::client().get(url).await?.text().await?;
+    let collection: FeatureCollection = serde_json::from_str(&output)?;
+
+    Ok(collection.features)
+}
+
+/// Searches for at most `limit` geocoding candidates for the provided address using Photon.
+///
+/// The search is restricted to the bounding box of the provided area (if any) and only
+/// candidates in the countries of the area are kept. The names are in the provided language if
+/// Photon supports it, otherwise in the local language.
+pub(super) async fn search(
+    url: &Url,
+    address: &str,
+    limit: usize,
+    language: Option<&str>,
+    area: &Area,
+) -> Result<Vec<Candidate>> {
+    let mut url = endpoint_url(url, "api");
+    url.query_pairs_mut()
+        .append_pair("q", address)
+        .append_pair("limit", &limit.to_string());
+    if let Some(language) = language.filter(|language| SUPPORTED_LANGUAGES.contains(language)) {
+        url.query_pairs_mut().append_pair("lang", language);
+    }
+    if let Some(bbox) = area.bounding_box {
+        let bbox = format!(
+            "{},{},{},{}",
+            bbox.min_lon, bbox.min_lat, bbox.max_lon, bbox.max_lat
+        );
+        url.query_pairs_mut().append_pair("bbox", &bbox);
+    }
+
+    info!(geocoder = "Photon", %address, "🌍 Geocoding the position of the address");
+    let features = retrieve_features(url).await?;
+
+    Ok(features
+        .into_iter()
+        .filter(|feature| area.includes_country(feature.properties.countrycode.as_deref()))
+        .map(Candidate::from)
+        .collect())
+}
+
+/// Resolves the human-readable place name for the provided position using Photon.
+pub(super) async fn reverse(url: &Url, position: Position) -> Result<String> {
+    let mut url = endpoint_url(url, "reverse");
+    url.query_pairs_mut()
+        .append_pair("lat", &position.lat_as_str(5))
+        .append_pair("lon", &position.lon_as_str(5))
+        .append_pair("limit", "1");
+
+    info!(
+        geocoder = "Photon",
+        lat = position.lat,
+        lon = position.lon,
+        "🌍 Reverse geocoding the position"
+    );
+    let features = retrieve_features(url).await?;
+
+    features
+        .first()
+        .and_then(|feature| feature.properties.place_name())
+        .ok_or(Error::NoPlaceNameFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_name() {
+        let json = r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [5.4779, 51.4393]},
+                "properties": {
+                    "name": "Stratumseind",
+                    "type": "street",
+                    "city": "Eindhoven",
+                    "countrycode": "NL"
+                }
+            }]
+        }"#;
+        let collection: FeatureCollection = serde_json::from_str(json).unwrap();
+        let feature = &collection.features[0];
+        assert_eq!(feature.geometry.coordinates, (5.4779, 51.4393));
+        assert_eq!(
+            feature.properties.place_name().as_deref(),
+            Some("Eindhoven, NL")
+        );
+
+        let candidate = Candidate::from(collection.features.into_iter().next().unwrap());
+        assert_eq!(candidate.name, "Stratumseind, Eindhoven");
+        assert_eq!(candidate.position(), Position::new(51.4393, 5.4779));
+
+        // Places use their own name.
+        let properties = Properties {
+            name: Some(String::from("Nuenen")),
+            kind: Some(String::from("village")),
+            ..Default::default()
+        };
+        assert_eq!(properties.place_name().as_deref(), Some("Nuenen"));
+        assert_eq!(Properties::default().place_name(), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! Grafana JSON datasource.
+//!
+//! This module implements the contract of the Grafana JSON datasource plugin, so that Grafana can
+//! chart the forecasts and the stored history natively, see
+//! <https://grafana.com/grafana/plugins/simpod-json-datasource/>. A query target selects a metric
+//! and a position, e.g. `UVI@52.0905,5.1109`.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::forecast::Metric;
+use crate::history::HistoryItem;
+use crate::position::Position;
+use crate::{Error, Result};
+
+/// A request to search for the available query targets.
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct SearchRequest {
+    /// The (partial) target that is searched for.
+    target: String,
+}
+
+/// A request to query the time series of targets within a time range.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct QueryRequest {
+    /// The time range of the query.
+    pub(crate) range: QueryRange,
+
+    /// The targets that are queried.
+    pub(crate) targets: Vec<QueryTarget>,
+}
+
+/// The time range of a query.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct QueryRange {
+    /// The start of the range.
+    pub(crate) from: DateTime<Utc>,
+
+    /// The end of the range.
+    pub(crate) to: DateTime<Utc>,
+}
+
+/// A target of a query.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct QueryTarget {
+    /// The target itself, e.g. `UVI@52.0905,5.1109`.
+    pub(crate) target: String,
+}
+
+/// The time series of a queried target.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct TimeSeries {
+    /// The queried target.
+    target: String,
+
+    /// The value and timestamp (in milliseconds since the UNIX epoch) of each data point.
+    datapoints: Vec<(f32, i64)>,
+}
+
+/// A parsed query target: the metric and the position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Target {
+    /// The metric of the target.
+    pub(crate) metric: Metric,
+
+    /// The position of the target.
+    pub(crate) position: Position,
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    /// Parses a target of the form `<metric>@<lat>,<lon>`; the metric is case-insensitive.
+    fn from_str(target: &str) -> Result<Self> {
+        let invalid = || Error::InvalidTarget(target.to_owned());
+        let (metric, position) = target.split_once('@').ok_or_else(invalid)?;
+        let (lat, lon) = position.split_once(',').ok_or_else(invalid)?;
+        let metric = Metric::all()
+            .into_iter()
+            .find(|m| m.to_string().eq_ignore_ascii_case(metric.trim()))
+            .ok_or_else(invalid)?;
+        let lat = lat.trim().parse::<f64>().map_err(|_| invalid())?;
+        let lon = lon.trim().parse::<f64>().map_err(|_| invalid())?;
+
+        Ok(Self {
+            metric,
+            position: Position::new(lat, lon),
+        })
+    }
+}
+
+/// Returns the metrics that match the (partial) target of the search request.
+///
+/// The positions cannot be searched for; they need to be appended to the selected metric, see
+/// [`Target`].
+pub(crate) fn search(request: &SearchRequest) -> Vec<String> {
+    let query = request.target.to_lowercase();
+
+    Metric::all()
+        .into_iter()
+        .map(|metric| metric.to_string())
+        .filter(|metric| metric.to_lowercase().contains(&query))
+        .collect()
+}
+
+/// Returns the time series of a target within the time range.
+///
+/// The historical items are combined with the forecasted series; for times present in both, the
+/// forecasted value is used as it is the most recently retrieved.
+pub(crate) fn time_series(
+    target: String,
+    range: QueryRange,
+    history_items: Vec<HistoryItem>,
+    series: Vec<(DateTime<Utc>, f32)>,
+) -> TimeSeries {
+    let mut values = history_items
+        .into_iter()
+        .map(|item| (item.time, item.value))
+        .collect::<BTreeMap<_, _>>();
+    values.extend(
+        series
+            .into_iter()
+            .map(|(time, value)| (time.timestamp(), value)),
+    );
+    let datapoints = values
+        .range(range.from.timestamp()..=range.to.timestamp())
+        .map(|(&time, &value)| (value, time * 1_000))
+        .collect();
+
+    TimeSeries { target, datapoints }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    fn target() {
+        let target = "uvi@52.0905, 5.1109".parse::<Target>().unwrap();
+        assert_eq!(target.metric, Metric::UVI);
+        assert_eq!(target.position, Position::new(52.0905, 5.1109));
+        let target = "PAQI_daily@51.4,5.5".parse::<Target>().unwrap();
+        assert_eq!(target.metric, Metric::PAQIDaily);
+
+        // The metric and position are required.
+        assert!(matches!(
+            "UVI".parse::<Target>(),
+            Err(Error::InvalidTarget(_))
+        ));
+        assert!(matches!(
+            "UVI@52.09".parse::<Target>(),
+            Err(Error::InvalidTarget(_))
+        ));
+        assert!(matches!(
+            "wind@52.09,5.11".parse::<Target>(),
+            Err(Error::InvalidTarget(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(all(feature = "luchtmeetnet", feature = "maps"))]
+    fn search() {
+        let request = SearchRequest {
+            target: String::from("paqi"),
+        };
+        assert_eq!(super::search(&request), ["PAQI", "PAQI_daily"]);
+        assert_eq!(
+            super::search(&SearchRequest::default()).len(),
+            Metric::all().len()
+        );
+    }
+
+    #[test]
+    fn time_series() {
+        let t = |hour| Utc.with_ymd_and_hms(2024, 6, 1, hour, 0, 0).unwrap();
+        let range = QueryRange {
+            from: t(10),
+            to: t(13),
+        };
+        let history_items = Vec::from([
+            HistoryItem {
+                time: t(9).timestamp(),
+                value: 1.0,
+            },
+            HistoryItem {
+                time: t(11).timestamp(),
+                value: 2.0,
+            },
+            HistoryItem {
+                time: t(12).timestamp(),
+                value: 3.0,
+            },
+        ]);
+        let series = Vec::from([(t(12), 4.0), (t(13), 5.0), (t(14), 6.0)]);
+
+        // The forecasted values take precedence, and only the data points in range are included.
+        let time_series =
+            super::time_series(String::from("UVI@51.4,5.5"), range, history_items, series);
+        assert_eq!(
+            time_series,
+            TimeSeries {
+                target: String::from("UVI@51.4,5.5"),
+                datapoints: Vec::from([
+                    (2.0, t(11).timestamp_millis()),
+                    (4.0, t(12).timestamp_millis()),
+                    (5.0, t(13).timestamp_millis())
+                ]),
+            }
+        );
+    }
+}
@@ -0,0 +1,440 @@
+//! Historical data storage.
+//!
+//! This module stores the items of each forecast requested through the API in an SQLite database,
+//! so that past values can be queried later. Requested forecasts are sent to a writer task that
+//! stores them in the background; for each position, metric and time only the most recently
+//! retrieved value is kept.
+//!
+//! Additionally, the forecasted values are kept per lead time, i.e. how far ahead of the time they
+//! were forecasted. Once a value has been observed, i.e. retrieved at or after its time, the
+//! accuracy of the forecasted values can be determined per lead time.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use rocket::serde::Serialize;
+use rocket::tokio;
+use rocket::tokio::sync::mpsc::error::TrySendError;
+use rocket::tokio::sync::mpsc::{channel, Receiver, Sender};
+use rusqlite::{params, Connection};
+use tracing::{debug, error, warn};
+
+use crate::forecast::{Forecast, Metric};
+use crate::position::Position;
+use crate::Result;
+
+/// The schema of the history database.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS history (
+        lat INTEGER NOT NULL,
+        lon INTEGER NOT NULL,
+        metric TEXT NOT NULL,
+        time INTEGER NOT NULL,
+        value REAL NOT NULL,
+        retrieved_at INTEGER NOT NULL,
+        PRIMARY KEY (lat, lon, metric, time)
+    );
+    CREATE TABLE IF NOT EXISTS forecasts (
+        lat INTEGER NOT NULL,
+        lon INTEGER NOT NULL,
+        metric TEXT NOT NULL,
+        time INTEGER NOT NULL,
+        lead_time INTEGER NOT NULL,
+        value REAL NOT NULL,
+        PRIMARY KEY (lat, lon, metric, time, lead_time)
+    );
+";
+
+/// The granularity (in seconds) of the lead times of stored forecasted values.
+const LEAD_TIME_GRANULARITY: i64 = 60;
+
+/// The maximum number of forecasts waiting to be stored by the writer task.
+const QUEUE_SIZE: usize = 256;
+
+/// The sender used to send retrieved forecasts to the writer task (if history is enabled).
+static SENDER: OnceLock<Sender<Forecast>> = OnceLock::new();
+
+/// The number of forecasts that were not stored, because the writer task could not keep up.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// A historical item of a metric.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct HistoryItem {
+    /// The time(stamp) of the item (in seconds since the UNIX epoch).
+    pub(crate) time: i64,
+
+    /// The value of the item.
+    pub(crate) value: f32,
+}
+
+/// The forecast accuracy statistics for a lead time bucket.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct AccuracyStats {
+    /// The start of the lead time bucket (in seconds).
+    pub(crate) lead_time: i64,
+
+    /// The number of forecasted values that have been compared with observed values.
+    pub(crate) count: u64,
+
+    /// The mean absolute error of the forecasted values.
+    pub(crate) mae: f64,
+
+    /// The mean error (forecasted minus observed) of the forecasted values.
+    pub(crate) bias: f64,
+}
+
+/// The history store.
+#[derive(Clone, Debug)]
+pub(crate) struct History {
+    /// The connection to the SQLite database.
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl History {
+    /// Opens the history database at the provided path and creates the schema if necessary.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Stores the items of all metrics of the forecast that are not stale.
+    ///
+    /// The items are stored with the time their data was retrieved from the provider, not the
+    /// time of the forecast, so that data served from a cache is not mistaken for newer data.
+    fn store(&self, forecast: &Forecast) -> Result<()> {
+        let position = forecast.position();
+        let mut connection = self
+            .connection
+            .lock()
+            .expect("History connection mutex was poisoned");
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO history (lat, lon, metric, time, value, retrieved_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (lat, lon, metric, time) DO UPDATE
+                 SET value = excluded.value, retrieved_at = excluded.retrieved_at
+                 WHERE excluded.retrieved_at >= history.retrieved_at",
+            )?;
+            let mut forecast_statement = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO forecasts (lat, lon, metric, time, lead_time, value)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for metric in Metric::all() {
+                if forecast.is_stale(metric) {
+                    continue;
+                }
+                let (Some(series), Some(retrieved_at)) =
+                    (forecast.series(metric), forecast.retrieved_at(metric))
+                else {
+                    continue;
+                };
+                let retrieved_at = retrieved_at.timestamp();
+
+                for (time, value) in series {
+                    statement.execute(params![
+                        position.lat_as_i32(),
+                        position.lon_as_i32(),
+                        metric.to_string(),
+                        time.timestamp(),
+                        value,
+                        retrieved_at
+                    ])?;
+
+                    let lead_time = time.timestamp() - retrieved_at;
+                    if lead_time > 0 {
+                        forecast_statement.execute(params![
+                            position.lat_as_i32(),
+                            position.lon_as_i32(),
+                            metric.to_string(),
+                            time.timestamp(),
+                            lead_time / LEAD_TIME_GRANULARITY * LEAD_TIME_GRANULARITY,
+                            value
+                        ])?;
+                    }
+                }
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Returns the historical items of the metric for the position, optionally restricted to
+    /// the items between the provided (UNIX) timestamps (inclusive).
+    pub(crate) fn query(
+        &self,
+        position: Position,
+        metric: Metric,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Vec<HistoryItem>> {
+        let connection = self
+            .connection
+            .lock()
+            .expect("History connection mutex was poisoned");
+        let mut statement = connection.prepare_cached(
+            "SELECT time, value FROM history
+             WHERE lat = ?1 AND lon = ?2 AND metric = ?3 AND time >= ?4 AND time <= ?5
+             ORDER BY time",
+        )?;
+        let items = statement
+            .query_map(
+                params![
+                    position.lat_as_i32(),
+                    position.lon_as_i32(),
+                    metric.to_string(),
+                    from.unwrap_or(i64::MIN),
+                    to.unwrap_or(i64::MAX)
+                ],
+                |row| {
+                    Ok(HistoryItem {
+                        time: row.get(0)?,
+                        value: row.get(1)?,
+                    })
+                },
+            )?
+            .collect::<Result<_, _>>()?;
+
+        Ok(items)
+    }
+
+    /// Returns the accuracy statistics of the forecasted values of the metric per lead time
+    /// bucket of the provided size (in seconds).
+    ///
+    /// The forecasted values are compared with the observed values, i.e. values that were
+    /// retrieved at or after their time. If a position is provided, only the values for that
+    /// position are taken into account, otherwise those of all positions.
+    pub(crate) fn accuracy(
+        &self,
+        position: Option<Position>,
+        metric: Metric,
+        bucket: i64,
+    ) -> Result<Vec<AccuracyStats>> {
+        let connection = self
+            .connection
+            .lock()
+            .expect("History connection mutex was poisoned");
+        let mut statement = connection.prepare_cached(
+            "SELECT f.lead_time / ?1 * ?1 AS bucket, COUNT(*),
+                    AVG(ABS(f.value - h.value)), AVG(f.value - h.value)
+             FROM forecasts f
+             JOIN history h
+               ON f.lat = h.lat AND f.lon = h.lon AND f.metric = h.metric AND f.time = h.time
+             WHERE f.metric = ?2 AND h.retrieved_at >= h.time
+               AND (?3 IS NULL OR (f.lat = ?3 AND f.lon = ?4))
+             GROUP BY bucket
+             ORDER BY bucket",
+        )?;
+        let stats = statement
+            .query_map(
+                params![
+                    bucket,
+                    metric.to_string(),
+                    position.map(|position| position.lat_as_i32()),
+                    position.map(|position| position.lon_as_i32())
+                ],
+                |row| {
+                    Ok(AccuracyStats {
+                        lead_time: row.get(0)?,
+                        count: row.get(1)?,
+                        mae: row.get(2)?,
+                        bias: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<Result<_, _>>()?;
+
+        Ok(stats)
+    }
+}
+
+/// Sends the retrieved forecast to the writer task, if history is enabled.
+///
+/// This is only called for forecasts requested by clients, so that the forecasts retrieved in the
+/// background (e.g. by prefetching) do not skew the history and accuracy statistics.
+///
+/// If the writer task cannot keep up, e.g. because the database is locked, the queue of forecasts
+/// to store is full and the forecast is dropped instead, which is counted and logged.
+pub(crate) fn record(forecast: &Forecast) {
+    let Some(sender) = SENDER.get() else {
+        return;
+    };
+
+    match sender.try_reserve() {
+        Ok(permit) => permit.send(forecast.clone()),
+        Err(TrySendError::Full(())) => {
+            let dropped = DROPPED.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(dropped, "💥 History queue is full, dropping forecast");
+        }
+        // The writer task only stops when the application shuts down.
+        Err(TrySendError::Closed(())) => {}
+    }
+}
+
+/// Enables recording of retrieved forecasts.
+///
+/// Returns the receiver that needs to be passed to the writer task, see [`run`].
+pub(crate) fn enable() -> Receiver<Forecast> {
+    let (sender, receiver) = channel(QUEUE_SIZE);
+    let _ = SENDER.set(sender);
+
+    receiver
+}
+
+/// Runs the writer task that stores the received forecasts.
+pub(crate) async fn run(history: History, mut receiver: Receiver<Forecast>) {
+    while let Some(forecast) = receiver.recv().await {
+        let history = history.clone();
+        let result = tokio::task::spawn_blocking(move || history.store(&forecast)).await;
+
+        match result {
+            Ok(Ok(())) => debug!("Stored forecast in history"),
+            Ok(Err(e)) => error!(error = %e, "💥 Encountered error during storing of history"),
+            Err(e) => error!(error = %e, "💥 Encountered error during storing of history"),
+        }
+    }
+}
+
+// The tests use UV index forecasts, which require the maps.
+#[cfg(all(test, feature = "maps"))]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::providers::buienradar::Sample;
+
+    #[test]
+    fn history() {
+        let path =
+            std::env::temp_dir().join(format!("sinoptik-test-history-{}.db", std::process::id()));
+        let history = History::open(&path).unwrap();
+        let position = Position::new(51.4, 5.5);
+        let now = Utc::now().timestamp();
+        let samples = Vec::from([
+            Sample::new(Utc.timestamp_opt(now, 0).unwrap(), 3),
+            Sample::new(Utc.timestamp_opt(now + 86_400, 0).unwrap(), 4),
+        ]);
+        let forecast = Forecast::with_uvi(position, now, samples);
+
+        // The UVI items of the forecast are stored and can be queried.
+        history.store(&forecast).unwrap();
+        let items = history.query(position, Metric::UVI, None, None).unwrap();
+        assert_eq!(
+            items,
+            [
+                HistoryItem {
+                    time: now,
+                    value: 3.0
+                },
+                HistoryItem {
+                    time: now + 86_400,
+                    value: 4.0
+                }
+            ]
+        );
+        let items = history
+            .query(position, Metric::UVI, Some(now + 1), None)
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        let items = history.query(position, Metric::AQI, None, None).unwrap();
+        assert!(items.is_empty());
+
+        // Storing the same items again replaces them.
+        history.store(&forecast).unwrap();
+        let items = history.query(position, Metric::UVI, None, None).unwrap();
+        assert_eq!(items.len(), 2);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn accuracy() {
+        let path =
+            std::env::temp_dir().join(format!("sinoptik-test-accuracy-{}.db", std::process::id()));
+        let history = History::open(&path).unwrap();
+        let position = Position::new(51.4, 5.5);
+        let time = |ts: i64| Utc.timestamp_opt(ts, 0).unwrap();
+
+        // Forecast values at 1 and 2 hours ahead, then observe them.
+        let forecast = Forecast::with_uvi(
+            position,
+            0,
+            Vec::from([Sample::new(time(3_600), 4), Sample::new(time(7_200), 6)]),
+        );
+        history.store(&forecast).unwrap();
+        let forecast = Forecast::with_uvi(
+            position,
+            3_600,
+            Vec::from([Sample::new(time(3_600), 3), Sample::new(time(7_200), 7)]),
+        );
+        history.store(&forecast).unwrap();
+        assert_eq!(
+            history.accuracy(None, Metric::UVI, 3_600).unwrap(),
+            [AccuracyStats {
+                lead_time: 3_600,
+                count: 1,
+                mae: 1.0,
+                bias: 1.0
+            }]
+        );
+
+        let forecast =
+            Forecast::with_uvi(position, 7_200, Vec::from([Sample::new(time(7_200), 5)]));
+        history.store(&forecast).unwrap();
+        let stats = history
+            .accuracy(Some(position), Metric::UVI, 3_600)
+            .unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].count, 2);
+        assert_eq!(stats[0].mae, 1.5);
+        assert_eq!(stats[0].bias, 1.5);
+        assert_eq!(stats[1].lead_time, 7_200);
+        assert_eq!(stats[1].bias, 1.0);
+
+        // Data retrieved before the time of its items is not observed, even if it is served
+        // (e.g. from a cache) after that time.
+        let forecast =
+            Forecast::with_uvi(position, 3_600, Vec::from([Sample::new(time(10_800), 4)]));
+        history.store(&forecast).unwrap();
+        let forecast =
+            Forecast::with_uvi(position, 7_200, Vec::from([Sample::new(time(10_800), 2)]))
+                .served_at(14_400);
+        history.store(&forecast).unwrap();
+        let stats = history
+            .accuracy(Some(position), Metric::UVI, 3_600)
+            .unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].count, 2);
+        assert_eq!(stats[1].count, 1);
+        let items = history
+            .query(position, Metric::UVI, Some(10_800), None)
+            .unwrap();
+        assert_eq!(items[0].value, 2.0);
+
+        // Data retrieved earlier does not replace data retrieved later.
+        let forecast = Forecast::with_uvi(position, 0, Vec::from([Sample::new(time(7_200), 9)]))
+            .served_at(14_400);
+        history.store(&forecast).unwrap();
+        let items = history
+            .query(position, Metric::UVI, Some(7_200), Some(7_200))
+            .unwrap();
+        assert_eq!(items[0].value, 5.0);
+
+        // There are no statistics for other positions.
+        let other_position = Position::new(52.1, 5.1);
+        assert!(history
+            .accuracy(Some(other_position), Metric::UVI, 3_600)
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
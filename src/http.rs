@@ -0,0 +1,555 @@
+//! Outbound HTTP requests.
+//!
+//! This module provides the HTTP client that is shared by all outbound requests, i.e. to the
+//! providers, for the maps and to webhooks. Failed idempotent requests are retried with an
+//! exponential backoff.
+//!
+//! Webhooks are only posted to hosts that resolve to globally reachable addresses, unless they
+//! are explicitly allowed, see [`WebhookResolver`].
+//!
+//! With the `recording` feature, the GET requests to the providers and for the maps can be
+//! recorded to fixture files and replayed from them later, see [`recording`].
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::redirect::Policy;
+use reqwest::{Certificate, Client, ClientBuilder, Method, Proxy, RequestBuilder, StatusCode, Url};
+use rocket::tokio;
+use rocket::tokio::time::sleep;
+use tracing::warn;
+
+use crate::config::HttpConfig;
+#[cfg(feature = "recording")]
+use crate::config::RecordingConfig;
+
+#[cfg(feature = "recording")]
+pub(crate) mod recording;
+
+/// The HTTP error type.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    /// Failed to record or replay the fixture for a request.
+    #[cfg(feature = "recording")]
+    #[error("Failed to record or replay the fixture for {0}: {1}")]
+    Fixture(Url, std::io::Error),
+
+    /// A request error occurred.
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+/// Result type that defaults to [`Error`] as the default error type.
+pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The shared HTTP client used for all outbound requests.
+static HTTP_CLIENT: OnceLock<HttpClient> = OnceLock::new();
+
+/// An HTTP client that retries failed requests.
+#[derive(Clone, Debug)]
+pub(crate) struct HttpClient {
+    /// The underlying client.
+    client: Client,
+
+    /// The underlying client that does not follow redirects, used for webhooks.
+    ///
+    /// Following a redirect would allow a webhook to reach a host that was not checked, e.g. on the
+    /// private network. For the same reason, it uses the [`WebhookResolver`].
+    webhook_client: Client,
+
+    /// The maximum number of attempts per request.
+    retry_attempts: u32,
+
+    /// The delay before the first retry; it doubles for every retry.
+    retry_delay: Duration,
+
+    /// The maximum delay before a retry.
+    max_retry_delay: Duration,
+
+    /// The configuration for recording or replaying GET requests (if any).
+    #[cfg(feature = "recording")]
+    recording: Option<RecordingConfig>,
+}
+
+/// A DNS resolver for webhooks that only resolves hosts to globally reachable addresses.
+///
+/// Checking the addresses of the host of a callback URL before posting to it is not enough,
+/// because the host can resolve to other addresses by the time the connection is made (DNS
+/// rebinding). Therefore, the addresses that are connected to are checked as well. Allowed hosts,
+/// which include the hosts of the proxies, are resolved without checking their addresses.
+#[derive(Debug)]
+struct WebhookResolver {
+    /// The hosts that are allowed to resolve to addresses that are not globally reachable.
+    allowed_hosts: Vec<String>,
+}
+
+impl WebhookResolver {
+    /// Creates a new resolver that allows the provided hosts and the hosts of the configured proxy
+    /// and the proxies set by the environment.
+    fn new(config: &HttpConfig, allowed_hosts: &[String]) -> Self {
+        let proxy_urls = ["HTTP_PROXY", "http_proxy", "HTTPS_PROXY", "https_proxy"]
+            .into_iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .chain(config.proxy.clone());
+        let proxy_hosts = proxy_urls.filter_map(|proxy_url| {
+            let proxy_url = Url::parse(&proxy_url).ok()?;
+
+            proxy_url.host_str().map(String::from)
+        });
+        let allowed_hosts = allowed_hosts.iter().cloned().chain(proxy_hosts).collect();
+
+        Self { allowed_hosts }
+    }
+}
+
+impl Resolve for WebhookResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let is_allowed = self
+            .allowed_hosts
+            .iter()
+            .any(|allowed_host| allowed_host.eq_ignore_ascii_case(name.as_str()));
+
+        Box::pin(async move {
+            let addresses = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .collect::<Vec<SocketAddr>>();
+            let all_global =
+                !addresses.is_empty() && addresses.iter().all(|address| is_global(address.ip()));
+            if !is_allowed && !all_global {
+                let message = format!("{} is not publicly reachable", name.as_str());
+                return Err(message.into());
+            }
+            let addresses: Addrs = Box::new(addresses.into_iter());
+
+            Ok(addresses)
+        })
+    }
+}
+
+/// A response to a GET request.
+///
+/// The response is either received from upstream, or, with the `recording` feature, replayed from
+/// a fixture.
+#[derive(Debug)]
+pub(crate) enum Response {
+    /// A response received from upstream.
+    Live(reqwest::Response),
+
+    /// A response replayed from a fixture.
+    #[cfg(feature = "recording")]
+    Replayed(recording::Fixture),
+}
+
+impl Response {
+    /// Returns the status of the response.
+    #[cfg(feature = "maps")]
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            Response::Live(response) => response.status(),
+            #[cfg(feature = "recording")]
+            Response::Replayed(fixture) => fixture.status,
+        }
+    }
+
+    /// Returns the final URL of the response, i.e. after following redirects.
+    #[cfg(feature = "maps")]
+    pub(crate) fn url(&self) -> &Url {
+        match self {
+            Response::Live(response) => response.url(),
+            #[cfg(feature = "recording")]
+            Response::Replayed(fixture) => &fixture.url,
+        }
+    }
+
+    /// Returns the headers of the response.
+    #[cfg(any(feature = "buienradar", feature = "luchtmeetnet"))]
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        match self {
+            Response::Live(response) => response.headers(),
+            #[cfg(feature = "recording")]
+            Response::Replayed(fixture) => &fixture.headers,
+        }
+    }
+
+    /// Returns the length of the body of the response, if known.
+    #[cfg(feature = "maps")]
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        match self {
+            Response::Live(response) => response.content_length(),
+            #[cfg(feature = "recording")]
+            Response::Replayed(fixture) => fixture.body.as_ref().map(|body| body.len() as u64),
+        }
+    }
+
+    /// Returns the body of the response as text.
+    pub(crate) async fn text(self) -> Result<String> {
+        match self {
+            Response::Live(response) => Ok(response.text().await?),
+            #[cfg(feature = "recording")]
+            Response::Replayed(fixture) => {
+                let body = fixture.body.unwrap_or_default();
+
+                Ok(String::from_utf8_lossy(&body).into_owned())
+            }
+        }
+    }
+
+    /// Returns the next chunk of the body of the response, or `None` if it has been read
+    /// completely.
+    #[cfg(feature = "maps")]
+    pub(crate) async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match self {
+            Response::Live(response) => Ok(response.chunk().await?.map(Vec::from)),
+            #[cfg(feature = "recording")]
+            Response::Replayed(fixture) => Ok(fixture.body.take()),
+        }
+    }
+}
+
+impl HttpClient {
+    /// Builds an HTTP client using the provided configuration.
+    ///
+    /// Webhooks can only be posted to hosts that are publicly reachable or in the provided allowed
+    /// hosts. This fails if the proxy URL is invalid or if the CA certificate cannot be read or
+    /// parsed.
+    pub(crate) fn new(
+        config: &HttpConfig,
+        webhook_allowed_hosts: &[String],
+    ) -> crate::Result<Self> {
+        let webhook_resolver = WebhookResolver::new(config, webhook_allowed_hosts);
+        let webhook_client = client_builder(config)?
+            .redirect(Policy::none())
+            .dns_resolver(Arc::new(webhook_resolver))
+            .build()
+            .map_err(Error::from)?;
+
+        Ok(Self {
+            client: client_builder(config)?.build().map_err(Error::from)?,
+            webhook_client,
+            retry_attempts: config.retry_attempts.max(1),
+            retry_delay: Duration::from_secs(config.retry_delay),
+            max_retry_delay: Duration::from_secs(config.max_retry_delay.max(config.retry_delay)),
+            #[cfg(feature = "recording")]
+            recording: config.recording.clone(),
+        })
+    }
+
+    /// Sends a GET request to the provided URL, see [`HttpClient::get_with_headers`].
+    pub(crate) async fn get(&self, url: Url) -> Result<Response> {
+        self.get_with_headers(url, HeaderMap::new()).await
+    }
+
+    /// Sends a GET request with the provided additional headers to the provided URL, see
+    /// [`HttpClient::send`].
+    ///
+    /// If recording is configured, the response is recorded to a fixture, or replayed from a
+    /// fixture instead of sending the request at all.
+    pub(crate) async fn get_with_headers(&self, url: Url, headers: HeaderMap) -> Result<Response> {
+        #[cfg(feature = "recording")]
+        if let Some(config) = &self.recording {
+            return match config.mode {
+                recording::RecordingMode::Record => {
+                    let request = self.client.get(url.clone()).headers(headers);
+                    let response = self.send(request).await?;
+
+                    recording::record(&config.path, url, response).await
+                }
+                recording::RecordingMode::Replay => recording::replay(&config.path, url),
+            };
+        }
+
+        let response = self.send(self.client.get(url).headers(headers)).await?;
+
+        Ok(Response::Live(response))
+    }
+
+    /// Sends a POST request with the provided JSON body to the provided URL, see
+    /// [`HttpClient::send`].
+    ///
+    /// The request is not retried, because POST requests are not idempotent. Redirects are not
+    /// followed, so that the request cannot be sent to another host than the provided one.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) async fn post_json<T: rocket::serde::Serialize + ?Sized>(
+        &self,
+        url: &str,
+        body: &T,
+    ) -> Result<reqwest::Response> {
+        Ok(self.send(self.webhook_client.post(url).json(body)).await?)
+    }
+
+    /// Sends a PUT request with the provided JSON body to the provided URL, authorized using the
+    /// provided bearer token, see [`HttpClient::send`].
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) async fn put_json_authorized<T: rocket::serde::Serialize + ?Sized>(
+        &self,
+        url: &str,
+        token: &str,
+        body: &T,
+    ) -> Result<reqwest::Response> {
+        Ok(self
+            .send(self.client.put(url).bearer_auth(token).json(body))
+            .await?)
+    }
+
+    /// Sends the request and returns the response if it has a success status.
+    ///
+    /// The request is retried with an exponential backoff if sending fails or if the response has
+    /// a server error or "too many requests" status, up until the configured number of attempts.
+    /// Only requests with an idempotent method are retried, so that e.g. a webhook is not posted
+    /// to twice if only its response failed.
+    ///
+    /// The URL of a sensitive request (see [`is_sensitive`]) is left out of its errors, and only
+    /// its origin is logged.
+    async fn send(&self, request: RequestBuilder) -> reqwest::Result<reqwest::Response> {
+        let (idempotent, sensitive, url) = request
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .map(|request| {
+                let sensitive = is_sensitive(&request);
+                let url = if sensitive {
+                    request.url().origin().ascii_serialization()
+                } else {
+                    request.url().to_string()
+                };
+
+                (request.method().is_idempotent(), sensitive, url)
+            })
+            .unwrap_or((false, true, String::new()));
+        let redact = |err: reqwest::Error| if sensitive { err.without_url() } else { err };
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+
+        loop {
+            let result = match request.try_clone() {
+                Some(request) => request.send().await,
+                None => {
+                    return request
+                        .send()
+                        .await
+                        .and_then(reqwest::Response::error_for_status)
+                        .map_err(redact)
+                }
+            }
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(redact);
+            match result {
+                Err(err) if idempotent && attempt < self.retry_attempts && is_retryable(&err) => {
+                    warn!(
+                        %url,
+                        attempt,
+                        attempts = self.retry_attempts,
+                        error = %err,
+                        "💥 Request failed, retrying"
+                    );
+                    sleep(delay).await;
+                    delay = delay.saturating_mul(2).min(self.max_retry_delay);
+                    attempt += 1;
+                }
+                Err(err) => {
+                    warn!(%url, attempt, error = %err, "💥 Request failed, giving up");
+                    return Err(err);
+                }
+                Ok(response) => return Ok(response),
+            }
+        }
+    }
+}
+
+/// Returns whether the URL of the request may contain a secret.
+///
+/// This is the case for authorized requests and for requests that are not GET requests, i.e. to
+/// webhooks and chats: the path of a Telegram bot URL contains its token, for example.
+fn is_sensitive(request: &reqwest::Request) -> bool {
+    request.method() != Method::GET || request.headers().contains_key(AUTHORIZATION)
+}
+
+/// Returns a client builder using the provided configuration.
+///
+/// This fails if the proxy URL is invalid or if the CA certificate cannot be read or parsed.
+fn client_builder(config: &HttpConfig) -> crate::Result<ClientBuilder> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(config.timeout))
+        .connect_timeout(Duration::from_secs(config.connect_timeout))
+        .read_timeout(Duration::from_secs(config.read_timeout))
+        .user_agent(&config.user_agent);
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy).map_err(Error::from)?);
+    }
+    if let Some(path) = &config.ca_certificate {
+        let pem = std::fs::read(path)?;
+        let certificate = Certificate::from_pem(&pem).map_err(Error::from)?;
+        builder = builder.add_root_certificate(certificate);
+    }
+
+    Ok(builder)
+}
+
+/// Returns whether the address is globally reachable.
+///
+/// This excludes the unspecified, loopback, private, shared, link-local, multicast, broadcast,
+/// documentation and reserved addresses, also when IPv4 addresses are mapped to IPv6.
+pub(crate) fn is_global(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, ..] = address.octets();
+
+            !(address.is_unspecified()
+                || address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_multicast()
+                || address.is_broadcast()
+                || address.is_documentation()
+                // "This network" (0.0.0.0/8), shared (100.64.0.0/10) and reserved (240.0.0.0/4).
+                || a == 0
+                || (a == 100 && (b & 0xc0) == 64)
+                || a >= 240)
+        }
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(address) => is_global(IpAddr::V4(address)),
+            None => {
+                let [a, b, ..] = address.segments();
+
+                !(address.is_unspecified()
+                    || address.is_loopback()
+                    || address.is_multicast()
+                    || address.is_unique_local()
+                    || address.is_unicast_link_local()
+                    // Documentation (2001:db8::/32).
+                    || (a == 0x2001 && b == 0x0db8))
+            }
+        },
+    }
+}
+
+/// Determines whether a failed request can be retried.
+///
+/// Requests that failed because of a client error status are not retried, except if the status
+/// indicates a timeout or too many requests.
+pub(crate) fn is_retryable(err: &reqwest::Error) -> bool {
+    match err.status() {
+        Some(StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS) => true,
+        Some(status) => !status.is_client_error(),
+        None => true,
+    }
+}
+
+/// Returns the media type of the response with the provided headers, e.g. `image/png`.
+///
+/// The media type is lowercased and its parameters, e.g. the charset, are left out. If the
+/// response has no (valid) `Content-Type` header, `None` is returned.
+#[cfg(any(feature = "buienradar", feature = "luchtmeetnet"))]
+pub(crate) fn media_type(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(reqwest::header::CONTENT_TYPE)?.to_str().ok()?;
+    let (media_type, _) = content_type.split_once(';').unwrap_or((content_type, ""));
+
+    Some(media_type.trim().to_ascii_lowercase())
+}
+
+/// Sets the shared HTTP client used for all outbound requests.
+///
+/// This needs to be done before the first request is made, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_client(client: HttpClient) {
+    let _ = HTTP_CLIENT.set(client);
+}
+
+/// Returns the shared HTTP client used for all outbound requests.
+///
+/// If it has not been set, a client with the default configuration is used.
+pub(crate) fn client() -> &'static HttpClient {
+    HTTP_CLIENT.get_or_init(|| {
+        HttpClient::new(&HttpConfig::default(), &[]).expect("Default HTTP client can be built")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use rocket::tokio::runtime::Builder;
+
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn new() {
+        let config = HttpConfig::default();
+        assert!(HttpClient::new(&config, &[]).is_ok());
+
+        let config = HttpConfig {
+            proxy: Some(String::from("http://proxy.example.com:3128")),
+            ..Default::default()
+        };
+        assert!(HttpClient::new(&config, &[]).is_ok());
+
+        let config = HttpConfig {
+            ca_certificate: Some("/nonexistent/ca.pem".into()),
+            ..Default::default()
+        };
+        assert_matches!(HttpClient::new(&config, &[]), Err(Error::Io(_)));
+    }
+
+    #[test]
+    fn webhook_resolver() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let resolves = |resolver: &WebhookResolver, host: &str| {
+            runtime
+                .block_on(resolver.resolve(host.parse().unwrap()))
+                .is_ok()
+        };
+
+        // Hosts that resolve to addresses that are not globally reachable are rejected.
+        let config = HttpConfig::default();
+        let resolver = WebhookResolver::new(&config, &[]);
+        assert!(!resolves(&resolver, "localhost"));
+
+        // Unless they are allowed, or are the host of the proxy.
+        let resolver = WebhookResolver::new(&config, &[String::from("LOCALHOST")]);
+        assert!(resolves(&resolver, "localhost"));
+        let config = HttpConfig {
+            proxy: Some(String::from("http://localhost:3128")),
+            ..Default::default()
+        };
+        let resolver = WebhookResolver::new(&config, &[]);
+        assert!(resolves(&resolver, "localhost"));
+    }
+
+    #[test]
+    fn is_sensitive() {
+        let client = Client::new();
+        let url = "https://api.telegram.org/bot123:secret/sendMessage";
+
+        // Plain GET requests, e.g. to the providers, are not sensitive.
+        let request = client.get(url).build().unwrap();
+        assert!(!super::is_sensitive(&request));
+
+        // Authorized requests and requests to webhooks and chats are.
+        let request = client.get(url).bearer_auth("token").build().unwrap();
+        assert!(super::is_sensitive(&request));
+        let request = client.post(url).build().unwrap();
+        assert!(super::is_sensitive(&request));
+        let request = client.put(url).build().unwrap();
+        assert!(super::is_sensitive(&request));
+    }
+
+    #[cfg(any(feature = "buienradar", feature = "luchtmeetnet"))]
+    #[test]
+    fn media_type() {
+        use reqwest::header::{HeaderValue, CONTENT_TYPE};
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(super::media_type(&headers), None);
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        assert_eq!(super::media_type(&headers).as_deref(), Some("image/png"));
+
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("Text/HTML; charset=UTF-8"),
+        );
+        assert_eq!(super::media_type(&headers).as_deref(), Some("text/html"));
+    }
+}
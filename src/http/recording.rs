@@ -0,0 +1,218 @@
+//! Recording and replaying of outbound requests.
+//!
+//! In record mode, the responses to GET requests are written to fixture files in the configured
+//! directory: a JSON file with the URL, status and headers, and a file with the body. In replay
+//! mode, the responses are read from these files instead of sending the requests at all, so that
+//! the forecasts and maps can be retrieved (e.g. in tests or demos) without hitting the live
+//! services.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Url};
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::{Error, Response, Result};
+
+/// The query parameters that are left out of the names of the fixtures.
+///
+/// These are only used to bypass (upstream) caches and change with every request.
+const IGNORED_QUERY_PARAMS: [&str; 1] = ["timestamp"];
+
+/// The recording modes.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub(crate) enum RecordingMode {
+    /// Send the requests and record the responses to fixtures.
+    Record,
+    /// Replay the responses from fixtures instead of sending the requests.
+    Replay,
+}
+
+/// A recorded response.
+#[derive(Debug)]
+pub(crate) struct Fixture {
+    /// The final URL of the response.
+    pub(super) url: Url,
+
+    /// The status of the response.
+    pub(super) status: StatusCode,
+
+    /// The headers of the response.
+    pub(super) headers: HeaderMap,
+
+    /// The body of the response, until it has been read.
+    pub(super) body: Option<Vec<u8>>,
+}
+
+/// The metadata of a recorded response, as stored in the fixture file.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct FixtureMetadata {
+    /// The final URL of the response.
+    url: String,
+
+    /// The status code of the response.
+    status: u16,
+
+    /// The headers of the response (that can be represented as strings).
+    headers: BTreeMap<String, String>,
+}
+
+impl Fixture {
+    /// Writes the fixture for the request to the provided URL to the provided directory.
+    fn write(&self, dir: &Path, url: &Url) -> io::Result<()> {
+        let (metadata_path, body_path) = fixture_paths(dir, url);
+        let metadata = FixtureMetadata {
+            url: self.url.to_string(),
+            status: self.status.as_u16(),
+            headers: self
+                .headers
+                .iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+                .collect(),
+        };
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+
+        std::fs::write(body_path, self.body.as_deref().unwrap_or_default())
+    }
+
+    /// Reads the fixture for the request to the provided URL from the provided directory.
+    fn read(dir: &Path, url: &Url) -> io::Result<Self> {
+        let (metadata_path, body_path) = fixture_paths(dir, url);
+        let metadata: FixtureMetadata =
+            serde_json::from_str(&std::fs::read_to_string(metadata_path)?)?;
+        let headers = metadata
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes()).map_err(io::Error::other)?;
+                let value = HeaderValue::from_str(value).map_err(io::Error::other)?;
+
+                Ok((name, value))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            url: Url::parse(&metadata.url).map_err(io::Error::other)?,
+            status: StatusCode::from_u16(metadata.status).map_err(io::Error::other)?,
+            headers,
+            body: Some(std::fs::read(body_path)?),
+        })
+    }
+}
+
+/// Returns the name of the fixture files for the request to the provided URL.
+///
+/// The name consists of the host, path and query parameters of the URL (except for the ignored
+/// ones), with all characters other than ASCII letters and digits replaced by underscores.
+fn fixture_name(url: &Url) -> String {
+    let mut name = format!("{}{}", url.host_str().unwrap_or_default(), url.path());
+    for (key, value) in url.query_pairs() {
+        if !IGNORED_QUERY_PARAMS.contains(&key.as_ref()) {
+            name.push_str(&format!("_{key}_{value}"));
+        }
+    }
+
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Returns the paths of the metadata and body files of the fixture for the request to the provided
+/// URL in the provided directory.
+fn fixture_paths(dir: &Path, url: &Url) -> (PathBuf, PathBuf) {
+    let name = fixture_name(url);
+
+    (
+        dir.join(format!("{name}.json")),
+        dir.join(format!("{name}.body")),
+    )
+}
+
+/// Records the response to the request to the provided URL to a fixture in the provided directory.
+///
+/// The body is read completely, so that it can be recorded, before the response is returned.
+pub(super) async fn record(dir: &Path, url: Url, response: reqwest::Response) -> Result<Response> {
+    let fixture = Fixture {
+        url: response.url().clone(),
+        status: response.status(),
+        headers: response.headers().clone(),
+        body: Some(response.bytes().await?.to_vec()),
+    };
+    fixture
+        .write(dir, &url)
+        .map_err(|err| Error::Fixture(url.clone(), err))?;
+    info!(%url, "📼 Recorded the response");
+
+    Ok(Response::Replayed(fixture))
+}
+
+/// Replays the response to the request to the provided URL from a fixture in the provided
+/// directory.
+pub(super) fn replay(dir: &Path, url: Url) -> Result<Response> {
+    let fixture = Fixture::read(dir, &url).map_err(|err| Error::Fixture(url, err))?;
+
+    Ok(Response::Replayed(fixture))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::CONTENT_TYPE;
+
+    use super::*;
+
+    #[test]
+    fn fixture_name() {
+        let url = Url::parse(
+            "https://api.luchtmeetnet.nl/open_api/concentrations?formula=lki&latitude=51.4",
+        )
+        .unwrap();
+        assert_eq!(
+            super::fixture_name(&url),
+            "api_luchtmeetnet_nl_open_api_concentrations_formula_lki_latitude_51_4"
+        );
+
+        // The timestamp that is only used to bypass caches is ignored.
+        let url = Url::parse("https://image.buienradar.nl/2.0/image/sprite/WeatherMapPollenRadarHourlyNL?forecast=24&timestamp=2410161200").unwrap();
+        assert_eq!(
+            super::fixture_name(&url),
+            "image_buienradar_nl_2_0_image_sprite_WeatherMapPollenRadarHourlyNL_forecast_24"
+        );
+    }
+
+    #[test]
+    fn write_read() {
+        let dir =
+            std::env::temp_dir().join(format!("sinoptik-test-fixtures-{}", std::process::id()));
+        let url =
+            Url::parse("https://gpsgadget.buienradar.nl/data/raintext?lat=51.4&lon=5.5").unwrap();
+        assert_eq!(
+            Fixture::read(&dir, &url).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let fixture = Fixture {
+            url: url.clone(),
+            status: StatusCode::OK,
+            headers,
+            body: Some(b"000|10:05\n".to_vec()),
+        };
+        fixture.write(&dir, &url).unwrap();
+
+        let replayed_fixture = Fixture::read(&dir, &url).unwrap();
+        assert_eq!(replayed_fixture.url, fixture.url);
+        assert_eq!(replayed_fixture.status, fixture.status);
+        assert_eq!(replayed_fixture.headers, fixture.headers);
+        assert_eq!(replayed_fixture.body, fixture.body);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
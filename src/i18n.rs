@@ -0,0 +1,173 @@
+//! Internationalization.
+//!
+//! This module is used to translate the classification labels and error messages. The messages
+//! are embedded resources per locale that use a subset of the [Fluent](https://projectfluent.org/)
+//! syntax: one `id = message` per line, and comments starting with `#`.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::LazyLock;
+
+use rocket::request::{FromRequest, Outcome, Request};
+
+/// The supported locales.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, rocket::FromFormField)]
+pub(crate) enum Locale {
+    /// English.
+    #[default]
+    En,
+    /// Dutch.
+    Nl,
+}
+
+impl Locale {
+    /// Returns the (ISO 639-1) language code of the locale, e.g. `nl`.
+    pub(crate) fn language_code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Nl => "nl",
+        }
+    }
+
+    /// Determines the most preferred supported locale from the value of an `Accept-Language`
+    /// header.
+    ///
+    /// Only the primary language subtag is considered, so `nl-BE` selects [`Locale::Nl`]. Returns
+    /// [`None`] if none of the accepted languages is supported.
+    pub(crate) fn from_accept_language(value: &str) -> Option<Self> {
+        value
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next()?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |quality| quality.parse::<f32>().ok())?;
+                let locale = match tag.split('-').next()?.to_ascii_lowercase().as_str() {
+                    "en" => Locale::En,
+                    "nl" => Locale::Nl,
+                    _ => return None,
+                };
+
+                (quality > 0.0).then_some((locale, quality))
+            })
+            .fold(
+                None,
+                |best: Option<(Self, f32)>, (locale, quality)| match best {
+                    Some((_, best_quality)) if best_quality >= quality => best,
+                    _ => Some((locale, quality)),
+                },
+            )
+            .map(|(locale, _)| locale)
+    }
+}
+
+/// The locale preferred by the client according to the `Accept-Language` header.
+///
+/// If the header is missing or contains no supported languages, the default locale is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct AcceptLanguage(pub(crate) Locale);
+
+impl AcceptLanguage {
+    /// Determines the preferred locale of the request.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn of(request: &Request<'_>) -> Self {
+        let locale = request
+            .headers()
+            .get_one("Accept-Language")
+            .and_then(Locale::from_accept_language)
+            .unwrap_or_default();
+
+        Self(locale)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptLanguage {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self::of(request))
+    }
+}
+
+/// The embedded messages per locale and message ID.
+static MESSAGES: LazyLock<HashMap<(Locale, &'static str), &'static str>> = LazyLock::new(|| {
+    let resources = [
+        (Locale::En, include_str!("i18n/en.ftl")),
+        (Locale::Nl, include_str!("i18n/nl.ftl")),
+    ];
+
+    resources
+        .into_iter()
+        .flat_map(|(locale, resource)| {
+            parse(resource).map(move |(id, message)| ((locale, id), message))
+        })
+        .collect()
+});
+
+/// Parses the messages of a resource.
+fn parse(resource: &'static str) -> impl Iterator<Item = (&'static str, &'static str)> {
+    resource
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, message)| (id.trim(), message.trim()))
+}
+
+/// Returns the message with the provided ID in the provided locale.
+///
+/// Returns [`None`] if there is no such message in the locale.
+pub(crate) fn message(locale: Locale, id: &str) -> Option<&'static str> {
+    MESSAGES.get(&(locale, id)).copied()
+}
+
+/// Returns the error message for the provided error kind in the provided locale.
+///
+/// There are no English error messages, because the detailed messages of the errors themselves
+/// are used. Returns [`None`] in that case, or if there is no message for the kind.
+#[cfg(feature = "server")]
+pub(crate) fn error_message(locale: Locale, kind: &str) -> Option<&'static str> {
+    message(locale, &format!("error-{kind}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_accept_language() {
+        assert_eq!(Locale::from_accept_language("nl"), Some(Locale::Nl));
+        assert_eq!(
+            Locale::from_accept_language("nl-BE, en;q=0.5"),
+            Some(Locale::Nl)
+        );
+        assert_eq!(
+            Locale::from_accept_language("fr, en-GB;q=0.7, nl;q=0.8"),
+            Some(Locale::Nl)
+        );
+
+        // Languages with a quality of zero or an invalid quality are not accepted.
+        assert_eq!(
+            Locale::from_accept_language("nl;q=0, en;q=0.1"),
+            Some(Locale::En)
+        );
+        assert_eq!(Locale::from_accept_language("nl;q=high"), None);
+        assert_eq!(Locale::from_accept_language("fr, *"), None);
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(message(Locale::Nl, "label-uvi-low"), Some("zwak"));
+        assert_eq!(message(Locale::En, "label-uvi-low"), Some("low"));
+        assert_eq!(message(Locale::En, "error-not_found"), None);
+
+        // All English messages are translated.
+        let missing = MESSAGES
+            .keys()
+            .filter(|(locale, id)| *locale == Locale::En && message(Locale::Nl, id).is_none())
+            .collect::<Vec<_>>();
+        assert!(missing.is_empty(), "Missing Dutch messages: {missing:?}");
+    }
+}
@@ -0,0 +1,52 @@
+# The English messages of Sinoptik.
+#
+# The error messages are not listed here: in English, the detailed messages of the errors
+# themselves are used.
+
+## Air quality (AQI, PAQI, NO₂, O₃ and particulate matter) labels
+
+label-air-quality-good = good
+label-air-quality-moderate = moderate
+label-air-quality-insufficient = insufficient
+label-air-quality-bad = bad
+label-air-quality-very-bad = very bad
+
+## European air quality index (EAQI) labels
+
+label-eaqi-good = good
+label-eaqi-fair = fair
+label-eaqi-moderate = moderate
+label-eaqi-poor = poor
+label-eaqi-very-poor = very poor
+label-eaqi-extremely-poor = extremely poor
+
+## Pollen labels
+
+label-pollen-low = low
+label-pollen-moderate = moderate
+label-pollen-high = high
+label-pollen-very-high = very high
+
+## Precipitation labels
+
+label-precipitation-dry = dry
+label-precipitation-light = light rain
+label-precipitation-moderate = moderate rain
+label-precipitation-heavy = heavy rain
+
+## Temperature labels
+
+label-temperature-freezing = freezing
+label-temperature-cold = cold
+label-temperature-mild = mild
+label-temperature-warm = warm
+label-temperature-summery = summery
+label-temperature-tropical = tropical
+
+## UV index labels
+
+label-uvi-low = low
+label-uvi-moderate = moderate
+label-uvi-high = high
+label-uvi-very-high = very high
+label-uvi-extreme = extreme
@@ -0,0 +1,93 @@
+# The Dutch messages of Sinoptik.
+
+## Air quality (AQI, PAQI, NO₂, O₃ and particulate matter) labels
+
+label-air-quality-good = goed
+label-air-quality-moderate = matig
+label-air-quality-insufficient = onvoldoende
+label-air-quality-bad = slecht
+label-air-quality-very-bad = zeer slecht
+
+## European air quality index (EAQI) labels
+
+label-eaqi-good = goed
+label-eaqi-fair = redelijk
+label-eaqi-moderate = matig
+label-eaqi-poor = slecht
+label-eaqi-very-poor = zeer slecht
+label-eaqi-extremely-poor = extreem slecht
+
+## Pollen labels
+
+label-pollen-low = laag
+label-pollen-moderate = matig
+label-pollen-high = hoog
+label-pollen-very-high = zeer hoog
+
+## Precipitation labels
+
+label-precipitation-dry = droog
+label-precipitation-light = lichte regen
+label-precipitation-moderate = matige regen
+label-precipitation-heavy = zware regen
+
+## Temperature labels
+
+label-temperature-freezing = vorst
+label-temperature-cold = koud
+label-temperature-mild = zacht
+label-temperature-warm = warm
+label-temperature-summery = zomers
+label-temperature-tropical = tropisch
+
+## UV index labels
+
+label-uvi-low = zwak
+label-uvi-moderate = matig
+label-uvi-high = sterk
+label-uvi-very-high = zeer sterk
+label-uvi-extreme = extreem
+
+## Error messages per error kind
+
+error-bad_request = Ongeldig verzoek
+error-candidate_not_found = Geen geocoderingskandidaat gevonden met deze index
+error-circuit_open = De gegevensleverancier faalt herhaaldelijk; probeer het later opnieuw
+error-csv = Fout bij het verwerken van CSV-gegevens
+error-exporter_disabled = De exporter is niet ingeschakeld
+error-forbidden = Geen toegang tot dit endpoint met deze API-sleutel
+error-geocoding = Fout bij het geocoderen van het adres
+error-history_disabled = De opslag van de historie is niet ingeschakeld
+error-incomplete_forecast = De verwachting kon niet voor alle metrieken worden opgehaald
+error-internal = Interne fout
+error-internal_server_error = Interne serverfout
+error-invalid_callback_url = Ongeldige callback-URL
+error-invalid_hours = Ongeldig aantal uren
+error-invalid_location_name = Ongeldige naam van de locatie
+error-invalid_map_options = Ongeldige kaartopties
+error-invalid_postcode = Ongeldige postcode
+error-invalid_target = Ongeldig doel van de query
+error-invalid_threshold = Ongeldige drempelwaarde
+error-invalid_time_window = Ongeldig tijdvenster
+error-location_not_found = Locatie niet gevonden
+error-location_not_owned = De locatie is met een andere API-sleutel opgeslagen
+error-maps = Fout bij het verwerken van de kaarten
+error-metric_disabled = Deze metriek is niet beschikbaar in deze installatie
+error-metric_failed = De gegevens voor de metriek konden niet worden opgehaald
+error-no_maps_yet = Er zijn nog geen kaarten opgehaald
+error-no_place_name_found = Geen plaatsnaam gevonden voor de positie
+error-no_position_found = Geen positie gevonden voor het adres
+error-not_found = Niet gevonden
+error-out_of_bounds = De positie valt buiten de kaart
+error-outside_coverage = De positie valt buiten het gedekte gebied
+error-outside_geocoding_area = Het adres valt buiten het gebied waarin wordt gegeocodeerd
+error-query_too_short = De zoekopdracht is te kort
+error-rate_limited = Te veel verzoeken; probeer het later opnieuw
+error-storage = Fout bij de opslag van gegevens
+error-subscription_not_found = Abonnement niet gevonden
+error-too_many_locations = Te veel locaties in de batch
+error-unauthorized = Geen of een ongeldige API-sleutel opgegeven
+error-unprocessable_entity = Het verzoek kon niet worden verwerkt
+error-unsupported_metric = Deze metriek wordt niet ondersteund
+error-upstream_request = Fout bij het opvragen van gegevens bij de gegevensleverancier
+error-upstream_response = Ongeldig antwoord van de gegevensleverancier
@@ -0,0 +1,187 @@
+//! Human-readable classification labels.
+//!
+//! This module is used to classify forecast values following the scales of the KNMI (UV index,
+//! precipitation and temperature) and RIVM (air quality), so that clients can show e.g. "very high"
+//! instead of a bare UV index of 8.
+
+use crate::forecast::Metric;
+use crate::i18n::{self, Locale};
+
+/// A class of a scale: the (exclusive) upper bound of its values and the message ID of its label.
+type Class = (f32, &'static str);
+
+/// The scale of the air quality index (and PAQI) of the RIVM.
+const AQI_SCALE: &[Class] = &[
+    (4.0, "label-air-quality-good"),
+    (7.0, "label-air-quality-moderate"),
+    (9.0, "label-air-quality-insufficient"),
+    (11.0, "label-air-quality-bad"),
+    (f32::INFINITY, "label-air-quality-very-bad"),
+];
+
+/// The scale of the European air quality index (EAQI) of the EEA.
+const EAQI_SCALE: &[Class] = &[
+    (2.0, "label-eaqi-good"),
+    (3.0, "label-eaqi-fair"),
+    (4.0, "label-eaqi-moderate"),
+    (5.0, "label-eaqi-poor"),
+    (6.0, "label-eaqi-very-poor"),
+    (f32::INFINITY, "label-eaqi-extremely-poor"),
+];
+
+/// The scale of the NO₂ concentration (in µg/m³) of the RIVM.
+const NO2_SCALE: &[Class] = &[
+    (30.0, "label-air-quality-good"),
+    (75.0, "label-air-quality-moderate"),
+    (125.0, "label-air-quality-insufficient"),
+    (200.0, "label-air-quality-bad"),
+    (f32::INFINITY, "label-air-quality-very-bad"),
+];
+
+/// The scale of the O₃ concentration (in µg/m³) of the RIVM.
+const O3_SCALE: &[Class] = &[
+    (60.0, "label-air-quality-good"),
+    (140.0, "label-air-quality-moderate"),
+    (180.0, "label-air-quality-insufficient"),
+    (240.0, "label-air-quality-bad"),
+    (f32::INFINITY, "label-air-quality-very-bad"),
+];
+
+/// The scale of the particulate matter concentration (in µg/m³) of the RIVM.
+const PM10_SCALE: &[Class] = &[
+    (30.0, "label-air-quality-good"),
+    (75.0, "label-air-quality-moderate"),
+    (125.0, "label-air-quality-insufficient"),
+    (200.0, "label-air-quality-bad"),
+    (f32::INFINITY, "label-air-quality-very-bad"),
+];
+
+/// The scale of the pollen score (1 to 10).
+const POLLEN_SCALE: &[Class] = &[
+    (4.0, "label-pollen-low"),
+    (7.0, "label-pollen-moderate"),
+    (9.0, "label-pollen-high"),
+    (f32::INFINITY, "label-pollen-very-high"),
+];
+
+/// The scale of the precipitation (in mm/h) of the KNMI.
+const PRECIPITATION_SCALE: &[Class] = &[
+    (0.1, "label-precipitation-dry"),
+    (1.0, "label-precipitation-light"),
+    (5.0, "label-precipitation-moderate"),
+    (f32::INFINITY, "label-precipitation-heavy"),
+];
+
+/// The scale of the temperature (in °C) of the KNMI.
+const TEMPERATURE_SCALE: &[Class] = &[
+    (0.0, "label-temperature-freezing"),
+    (10.0, "label-temperature-cold"),
+    (20.0, "label-temperature-mild"),
+    (25.0, "label-temperature-warm"),
+    (30.0, "label-temperature-summery"),
+    (f32::INFINITY, "label-temperature-tropical"),
+];
+
+/// The scale of the UV index of the KNMI.
+const UVI_SCALE: &[Class] = &[
+    (3.0, "label-uvi-low"),
+    (5.0, "label-uvi-moderate"),
+    (7.0, "label-uvi-high"),
+    (10.0, "label-uvi-very-high"),
+    (f32::INFINITY, "label-uvi-extreme"),
+];
+
+/// Returns the scale of the provided metric, or [`None`] if it has none.
+fn scale(metric: Metric) -> Option<&'static [Class]> {
+    let scale = match metric {
+        Metric::All => return None,
+        Metric::AQI | Metric::PAQI | Metric::PAQIDaily => AQI_SCALE,
+        Metric::NO2 => NO2_SCALE,
+        Metric::O3 => O3_SCALE,
+        Metric::PM10 => PM10_SCALE,
+        Metric::Pollen => POLLEN_SCALE,
+        Metric::Precipitation => PRECIPITATION_SCALE,
+        Metric::Temperature => TEMPERATURE_SCALE,
+        Metric::UVI => UVI_SCALE,
+    };
+
+    Some(scale)
+}
+
+/// Returns the label of the class the value of the provided metric falls in.
+///
+/// The value is expected to be in metric units. Returns [`None`] if the metric has no scale.
+pub(crate) fn label(metric: Metric, value: f32, locale: Locale) -> Option<&'static str> {
+    label_in(scale(metric)?, value, locale)
+}
+
+/// Returns the label of the class the European air quality index (EAQI) falls in.
+pub(crate) fn eaqi_label(value: f32, locale: Locale) -> Option<&'static str> {
+    label_in(EAQI_SCALE, value, locale)
+}
+
+/// Returns the label of the class of the provided scale the value falls in.
+fn label_in(scale: &[Class], value: f32, locale: Locale) -> Option<&'static str> {
+    scale
+        .iter()
+        .find(|(upper_bound, _)| value < *upper_bound)
+        .and_then(|&(_, id)| i18n::message(locale, id))
+}
+
+/// Returns the index of the class the value of the provided metric falls in and the number of
+/// classes of its scale.
+///
+/// The value is expected to be in metric units. Returns [`None`] if the metric has no scale.
+#[cfg(all(feature = "maps", feature = "server"))]
+pub(crate) fn class(metric: Metric, value: f32) -> Option<(usize, usize)> {
+    let scale = scale(metric)?;
+    let index = scale
+        .iter()
+        .position(|(upper_bound, _)| value < *upper_bound)?;
+
+    Some((index, scale.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label() {
+        assert_eq!(
+            super::label(Metric::UVI, 8.0, Locale::En),
+            Some("very high")
+        );
+        assert_eq!(super::label(Metric::AQI, 9.0, Locale::En), Some("bad"));
+        assert_eq!(
+            super::label(Metric::Precipitation, 5.0, Locale::En),
+            Some("heavy rain")
+        );
+        assert_eq!(
+            super::label(Metric::Precipitation, 0.0, Locale::Nl),
+            Some("droog")
+        );
+        assert_eq!(
+            super::label(Metric::Temperature, -2.5, Locale::Nl),
+            Some("vorst")
+        );
+
+        // Interpolated values are classified too; all is not a metric with values.
+        assert_eq!(super::label(Metric::Pollen, 3.5, Locale::Nl), Some("laag"));
+        assert_eq!(super::label(Metric::All, 1.0, Locale::En), None);
+    }
+
+    #[test]
+    fn eaqi_label() {
+        assert_eq!(super::eaqi_label(2.0, Locale::En), Some("fair"));
+        assert_eq!(super::eaqi_label(6.0, Locale::Nl), Some("extreem slecht"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "maps", feature = "server"))]
+    fn class() {
+        assert_eq!(super::class(Metric::UVI, 8.0), Some((3, 5)));
+        assert_eq!(super::class(Metric::Pollen, 1.0), Some((0, 4)));
+        assert_eq!(super::class(Metric::All, 1.0), None);
+    }
+}
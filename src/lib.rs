@@ -14,397 +14,417 @@
     unused_qualifications
 )]
 #![deny(missing_docs)]
+// Without the server and the library, nothing is exposed and so all code is unused.
+#![cfg_attr(not(any(feature = "server", feature = "library")), allow(dead_code))]
 
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
 
-use rocket::fairing::AdHoc;
+#[cfg(feature = "server")]
 use rocket::http::Status;
-use rocket::response::Responder;
-use rocket::serde::json::Json;
-use rocket::serde::Serialize;
-use rocket::{get, routes, Build, Request, Rocket, State};
-
-use self::forecast::{forecast, Forecast, Metric};
-use self::maps::{mark_map, Error as MapsError, Maps, MapsHandle};
-use self::position::{resolve_address, Position};
 
+use self::forecast::Metric;
+#[cfg(feature = "maps")]
+use self::maps::Error as MapsError;
+
+#[cfg(feature = "server")]
+pub(crate) mod advice;
+pub(crate) mod aqi_scale;
+#[cfg(feature = "server")]
+pub(crate) mod auth;
+#[cfg(all(feature = "maps", feature = "server"))]
+pub(crate) mod badge;
+#[cfg(feature = "server")]
+pub(crate) mod binary;
+#[cfg(feature = "server")]
+pub(crate) mod calendar;
+#[cfg(all(feature = "maps", feature = "server"))]
+pub(crate) mod chart;
+#[cfg(feature = "server")]
+pub(crate) mod cli;
+#[cfg(feature = "server")]
+pub(crate) mod compression;
+#[cfg(feature = "server")]
+pub(crate) mod conditional;
+pub(crate) mod config;
+pub(crate) mod consensus;
+#[cfg(feature = "server")]
+pub(crate) mod exporter;
+#[cfg(feature = "server")]
+pub(crate) mod feed;
+#[cfg(feature = "server")]
+pub(crate) mod fields;
 pub(crate) mod forecast;
+pub(crate) mod geocoder;
+#[cfg(feature = "server")]
+pub(crate) mod grafana;
+#[cfg(feature = "server")]
+pub(crate) mod history;
+pub(crate) mod http;
+pub(crate) mod i18n;
+pub(crate) mod labels;
+#[cfg(feature = "library")]
+pub mod library;
+pub(crate) mod links;
+#[cfg(feature = "server")]
+pub(crate) mod locations;
+pub(crate) mod logging;
+#[cfg_attr(not(feature = "maps"), path = "maps/disabled.rs")]
 pub(crate) mod maps;
+#[cfg(feature = "server")]
+pub(crate) mod notifier;
+pub(crate) mod nowcast;
 pub(crate) mod position;
+#[cfg(feature = "server")]
+pub(crate) mod prefetch;
+#[cfg(feature = "server")]
+pub(crate) mod protobuf;
 pub(crate) mod providers;
+#[cfg(feature = "server")]
+pub(crate) mod rate_limit;
+#[cfg(feature = "server")]
+pub(crate) mod sensor;
+#[cfg(feature = "server")]
+pub(crate) mod server;
+#[cfg_attr(not(feature = "server"), path = "shared_cache/disabled.rs")]
+pub(crate) mod shared_cache;
+pub(crate) mod snapshot;
+#[cfg(feature = "server")]
+pub(crate) mod subscriptions;
+pub(crate) mod summary;
+#[cfg(feature = "server")]
+pub(crate) mod thresholds;
 
 /// The possible provider errors that can occur.
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum Error {
+    /// No geocoding candidate found with the given index.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("No geocoding candidate found with index {0}")]
+    CandidateNotFound(usize),
+
+    /// A CBOR serialization error occurred.
+    #[cfg(feature = "server")]
+    #[error("CBOR serialization error: {0}")]
+    Cbor(#[from] ciborium::ser::Error<std::io::Error>),
+
+    /// Calls to the provider are short-circuited, because it failed repeatedly.
+    #[cfg_attr(
+        not(any(feature = "buienradar", feature = "luchtmeetnet")),
+        allow(dead_code)
+    )]
+    #[error("Calls to {0} are short-circuited, because it failed repeatedly")]
+    CircuitOpen(&'static str),
+
     /// A CSV parse error occurred.
     #[error("CSV parse error: {0}")]
     CsvParse(#[from] csv::Error),
 
+    /// A CSV write error occurred.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("CSV write error: {0}")]
+    CsvWrite(csv::Error),
+
+    /// The exporter is not enabled.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("The exporter is not enabled")]
+    ExporterDisabled,
+
+    /// The API key is not allowed to access the administration endpoints.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("The API key is not allowed to access the administration endpoints")]
+    Forbidden,
+
     /// A geocoding error occurred.
     #[error("Geocoding error: {0}")]
-    Geocoding(#[from] geocoding::GeocodingError),
+    Geocoding(String),
+
+    /// Encountered an invalid callback URL.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Encountered an invalid callback URL: {0}")]
+    InvalidCallbackUrl(String),
+
+    /// History is not enabled.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("History is not enabled")]
+    HistoryDisabled,
 
     /// An HTTP request error occurred.
     #[error("HTTP request error: {0}")]
-    HttpRequest(#[from] reqwest::Error),
+    HttpRequest(#[from] http::Error),
+
+    /// Failed to retrieve the forecast for some metrics (in strict mode).
+    ///
+    /// Contains the kind of the error per failed metric.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error(
+        "Failed to retrieve the forecast for metrics: {}",
+        .0.keys().map(Metric::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    IncompleteForecast(BTreeMap<Metric, &'static str>),
+
+    /// Encountered an invalid threshold for a metric.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Encountered an invalid threshold for {0}: {1}")]
+    InvalidThreshold(Metric, f32),
+
+    /// Encountered an invalid name of a named location.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Encountered an invalid location name: {0}")]
+    InvalidLocationName(String),
+
+    /// The provider responded with invalid data for the given URL.
+    #[cfg_attr(
+        not(any(feature = "buienradar", feature = "luchtmeetnet")),
+        allow(dead_code)
+    )]
+    #[error("{0} responded with invalid data for {1}: {2}")]
+    InvalidResponse(&'static str, String, String),
+
+    /// Encountered an invalid (Dutch) postal code.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Encountered an invalid postal code: {0}")]
+    InvalidPostcode(String),
+
+    /// Encountered an invalid number of hours, exceeding the given maximum.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Encountered an invalid number of hours: {0} (at most {1})")]
+    InvalidHours(u32, u32),
+
+    /// Encountered an invalid Grafana query target.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Encountered an invalid query target: {0}")]
+    InvalidTarget(String),
+
+    /// Encountered an invalid time window.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Encountered an invalid time window")]
+    InvalidTimeWindow,
+
+    /// An I/O error occurred.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 
     /// Failed to join a task.
     #[error("Failed to join a task: {0}")]
     Join(#[from] rocket::tokio::task::JoinError),
 
+    /// A JSON (de)serialization error occurred.
+    #[error("JSON (de)serialization error: {0}")]
+    Json(#[from] rocket::serde::json::serde_json::Error),
+
+    /// No named location found with the given name.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("No location found with name {0}")]
+    LocationNotFound(String),
+
+    /// The named location with the given name was stored with another API key.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("The location with name {0} was stored with another API key")]
+    LocationNotOwned(String),
+
+    /// The metric is disabled, because the feature of its provider is not enabled.
+    #[error("The {0} metric is disabled in this build")]
+    MetricDisabled(Metric),
+
+    /// Failed to retrieve the forecast for a metric.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("Failed to retrieve the forecast for {0}: {1}")]
+    MetricFailed(Metric, String),
+
     /// Failed to merge AQI & pollen items.
     #[error("Failed to merge AQI & pollen items: {0}")]
     Merge(#[from] providers::combined::MergeError),
 
     /// Failed to retrieve or sample the maps.
+    #[cfg(feature = "maps")]
     #[error("Failed to retrieve or sample the maps: {0}")]
     Maps(#[from] maps::Error),
 
+    /// The Redis cache backend is configured, but no Redis URL.
+    #[cfg(feature = "server")]
+    #[error("The Redis cache backend is configured, but no Redis URL")]
+    MissingRedisUrl,
+
+    /// A MessagePack serialization error occurred.
+    #[cfg(feature = "server")]
+    #[error("MessagePack serialization error: {0}")]
+    Msgpack(#[from] rmp_serde::encode::Error),
+
+    /// No place name could be found for a geocoded position.
+    #[error("No place name could be found for the geocoded position")]
+    NoPlaceNameFound,
+
     /// No geocoded position could be found.
     #[error("No geocoded position could be found")]
     NoPositionFound,
 
+    /// The geocoded position lies outside of the configured geocoding area.
+    #[error("The geocoded position lies outside of the supported area")]
+    OutsideGeocodingArea,
+
+    /// The search query is shorter than the given minimum number of characters.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("The search query should be at least {0} characters long")]
+    QueryTooShort(usize),
+
+    /// A Redis error occurred.
+    #[cfg(feature = "server")]
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+
+    /// The rate limit has been exceeded; retry after the given number of seconds.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("The rate limit has been exceeded, retry after {0} seconds")]
+    RateLimited(u64),
+
+    /// An SQLite error occurred.
+    #[cfg(feature = "server")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// No subscription found with the given ID.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("No subscription found with ID {0}")]
+    SubscriptionNotFound(u64),
+
+    /// The batch contains more locations than the given maximum.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("The batch contains {0} locations, but at most {1} are allowed")]
+    TooManyLocations(usize, usize),
+
+    /// No (valid) API key was provided.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    #[error("No (valid) API key was provided")]
+    Unauthorized,
+
+    /// The provider responded with an unexpected content type for the given URL.
+    #[cfg_attr(
+        not(any(feature = "buienradar", feature = "luchtmeetnet")),
+        allow(dead_code)
+    )]
+    #[error("{0} responded with unexpected content type {2} for {1}")]
+    UnexpectedContentType(&'static str, String, String),
+
     /// Encountered an unsupported metric.
     #[error("Encountered an unsupported metric: {0}")]
     UnsupportedMetric(Metric),
 }
 
-impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
-    fn respond_to(self, _request: &'r Request<'_>) -> rocket::response::Result<'o> {
-        eprintln!("💥 Encountered error during request: {}", self);
-
-        let status = match self {
-            Error::NoPositionFound => Status::NotFound,
+impl Error {
+    /// Returns the HTTP status that corresponds to the error.
+    #[cfg(feature = "server")]
+    pub(crate) fn status(&self) -> Status {
+        match self {
+            Error::CandidateNotFound(_) => Status::NotFound,
+            Error::CircuitOpen(_) => Status::ServiceUnavailable,
+            Error::Forbidden => Status::Forbidden,
+            Error::ExporterDisabled => Status::NotFound,
+            Error::HistoryDisabled => Status::NotFound,
+            Error::IncompleteForecast(error_kinds) => {
+                let has_kind = |kind| error_kinds.values().any(|&k| k == kind);
+                if has_kind("metric_disabled") {
+                    Status::NotImplemented
+                } else if has_kind("no_maps_yet") {
+                    Status::ServiceUnavailable
+                } else {
+                    Status::BadGateway
+                }
+            }
+            Error::InvalidCallbackUrl(_) => Status::UnprocessableEntity,
+            Error::InvalidHours(_, _) => Status::UnprocessableEntity,
+            Error::InvalidLocationName(_) => Status::BadRequest,
+            Error::InvalidPostcode(_) => Status::BadRequest,
+            Error::InvalidResponse(_, _, _) => Status::BadGateway,
+            Error::InvalidThreshold(_, _) => Status::BadRequest,
+            Error::InvalidTarget(_) => Status::BadRequest,
+            Error::InvalidTimeWindow => Status::BadRequest,
+            Error::LocationNotFound(_) => Status::NotFound,
+            Error::LocationNotOwned(_) => Status::Forbidden,
+            Error::MetricDisabled(_) => Status::NotImplemented,
+            Error::NoPlaceNameFound | Error::NoPositionFound => Status::NotFound,
+            Error::OutsideGeocodingArea => Status::NotFound,
+            Error::QueryTooShort(_) => Status::BadRequest,
+            #[cfg(feature = "maps")]
+            Error::Maps(MapsError::InvalidOptions(_)) => Status::BadRequest,
+            #[cfg(feature = "maps")]
             Error::Maps(MapsError::NoMapsYet) => Status::ServiceUnavailable,
+            #[cfg(feature = "maps")]
             Error::Maps(MapsError::OutOfBoundCoords(_, _)) => Status::NotFound,
+            #[cfg(feature = "maps")]
             Error::Maps(MapsError::OutOfBoundOffset(_)) => Status::NotFound,
+            #[cfg(feature = "maps")]
+            Error::Maps(MapsError::OutsideCoverage(_, _)) => Status::NotFound,
+            Error::RateLimited(_) => Status::TooManyRequests,
+            Error::SubscriptionNotFound(_) => Status::NotFound,
+            Error::TooManyLocations(_, _) => Status::UnprocessableEntity,
+            Error::Unauthorized => Status::Unauthorized,
+            Error::UnexpectedContentType(_, _, _) => Status::BadGateway,
+            Error::UnsupportedMetric(_) => Status::BadRequest,
             _ => Status::InternalServerError,
-        };
-
-        Err(status)
+        }
     }
-}
-
-#[derive(Responder)]
-#[response(content_type = "image/png")]
-struct PngImageData(Vec<u8>);
-
-/// Result type that defaults to [`Error`] as the default error type.
-pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 
-/// The version information as JSON response.
-#[derive(Debug, Serialize)]
-#[serde(crate = "rocket::serde")]
-struct VersionInfo {
-    /// The version of the build.
-    version: String,
-
-    /// The timestamp of the build.
-    timestamp: String,
-
-    /// The (most recent) git SHA used for the build.
-    git_sha: String,
-
-    /// The timestamp of the last git commit used for the build.
-    git_timestamp: String,
-}
-impl VersionInfo {
-    /// Retrieves the version information from the environment variables.
-    fn new() -> Self {
-        Self {
-            version: String::from(env!("CARGO_PKG_VERSION")),
-            timestamp: String::from(env!("VERGEN_BUILD_TIMESTAMP")),
-            git_sha: String::from(&env!("VERGEN_GIT_SHA")[0..7]),
-            git_timestamp: String::from(env!("VERGEN_GIT_COMMIT_TIMESTAMP")),
+    /// Returns the kind of the error, e.g. `no_position_found`.
+    ///
+    /// This allows API clients to distinguish failure causes programmatically.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Error::CandidateNotFound(_) => "candidate_not_found",
+            Error::CircuitOpen(_) => "circuit_open",
+            Error::CsvParse(_) | Error::CsvWrite(_) => "csv",
+            Error::Forbidden => "forbidden",
+            Error::Geocoding(_) => "geocoding",
+            Error::InvalidCallbackUrl(_) => "invalid_callback_url",
+            Error::ExporterDisabled => "exporter_disabled",
+            Error::HistoryDisabled => "history_disabled",
+            Error::HttpRequest(_) => "upstream_request",
+            Error::IncompleteForecast(_) => "incomplete_forecast",
+            Error::InvalidHours(_, _) => "invalid_hours",
+            Error::InvalidLocationName(_) => "invalid_location_name",
+            Error::InvalidPostcode(_) => "invalid_postcode",
+            Error::InvalidResponse(_, _, _) => "upstream_response",
+            Error::InvalidThreshold(_, _) => "invalid_threshold",
+            Error::InvalidTarget(_) => "invalid_target",
+            Error::InvalidTimeWindow => "invalid_time_window",
+            Error::Io(_) | Error::Join(_) | Error::Json(_) => "internal",
+            #[cfg(feature = "server")]
+            Error::Cbor(_) | Error::Msgpack(_) => "internal",
+            Error::LocationNotFound(_) => "location_not_found",
+            Error::LocationNotOwned(_) => "location_not_owned",
+            Error::MetricDisabled(_) => "metric_disabled",
+            Error::MetricFailed(_, _) | Error::Merge(_) => "metric_failed",
+            #[cfg(feature = "maps")]
+            Error::Maps(MapsError::InvalidOptions(_)) => "invalid_map_options",
+            #[cfg(feature = "maps")]
+            Error::Maps(MapsError::NoMapsYet) => "no_maps_yet",
+            #[cfg(feature = "maps")]
+            Error::Maps(MapsError::OutsideCoverage(_, _)) => "outside_coverage",
+            #[cfg(feature = "maps")]
+            Error::Maps(MapsError::OutOfBoundCoords(_, _) | MapsError::OutOfBoundOffset(_)) => {
+                "out_of_bounds"
+            }
+            #[cfg(feature = "maps")]
+            Error::Maps(_) => "maps",
+            #[cfg(feature = "server")]
+            Error::MissingRedisUrl | Error::Redis(_) | Error::Sqlite(_) => "storage",
+            Error::NoPlaceNameFound => "no_place_name_found",
+            Error::NoPositionFound => "no_position_found",
+            Error::OutsideGeocodingArea => "outside_geocoding_area",
+            Error::QueryTooShort(_) => "query_too_short",
+            Error::RateLimited(_) => "rate_limited",
+            Error::SubscriptionNotFound(_) => "subscription_not_found",
+            Error::TooManyLocations(_, _) => "too_many_locations",
+            Error::Unauthorized => "unauthorized",
+            Error::UnexpectedContentType(_, _, _) => "upstream_response",
+            Error::UnsupportedMetric(_) => "unsupported_metric",
         }
     }
 }
 
-/// Handler for retrieving the forecast for an address.
-#[get("/forecast?<address>&<metrics>")]
-async fn forecast_address(
-    address: String,
-    metrics: Vec<Metric>,
-    maps_handle: &State<MapsHandle>,
-) -> Result<Json<Forecast>> {
-    let position = resolve_address(address).await?;
-    let forecast = forecast(position, metrics, maps_handle).await;
-
-    Ok(Json(forecast))
-}
-
-/// Handler for retrieving the forecast for a geocoded position.
-#[get("/forecast?<lat>&<lon>&<metrics>", rank = 2)]
-async fn forecast_geo(
-    lat: f64,
-    lon: f64,
-    metrics: Vec<Metric>,
-    maps_handle: &State<MapsHandle>,
-) -> Json<Forecast> {
-    let position = Position::new(lat, lon);
-    let forecast = forecast(position, metrics, maps_handle).await;
-
-    Json(forecast)
-}
-
-/// Handler for showing the current map with the geocoded position of an address for a specific
-/// metric.
-///
-/// Note: This handler is mosly used for debugging purposes!
-#[get("/map?<address>&<metric>")]
-async fn map_address(
-    address: String,
-    metric: Metric,
-    maps_handle: &State<MapsHandle>,
-) -> Result<PngImageData> {
-    let position = resolve_address(address).await?;
-    let image_data = mark_map(position, metric, maps_handle).await;
-
-    image_data.map(PngImageData)
-}
-
-/// Handler for showing the current map with the geocoded position for a specific metric.
-///
-/// Note: This handler is mosly used for debugging purposes!
-#[get("/map?<lat>&<lon>&<metric>", rank = 2)]
-async fn map_geo(
-    lat: f64,
-    lon: f64,
-    metric: Metric,
-    maps_handle: &State<MapsHandle>,
-) -> Result<PngImageData> {
-    let position = Position::new(lat, lon);
-    let image_data = mark_map(position, metric, maps_handle).await;
-
-    image_data.map(PngImageData)
-}
-
-/// Returns the version information.
-#[get("/version", format = "application/json")]
-async fn version() -> Result<Json<VersionInfo>> {
-    Ok(Json(VersionInfo::new()))
-}
-
-/// Sets up Rocket without fairings.
-fn rocket_core(maps_handle: MapsHandle) -> Rocket<Build> {
-    rocket::build()
-        .mount(
-            "/",
-            routes![
-                forecast_address,
-                forecast_geo,
-                map_address,
-                map_geo,
-                version
-            ],
-        )
-        .manage(maps_handle)
-}
-
-/// Sets up Rocket.
-fn rocket(maps_handle: MapsHandle) -> Rocket<Build> {
-    let rocket = rocket_core(Arc::clone(&maps_handle));
-    let maps_refresher = maps::run(maps_handle);
-
-    rocket
-        .attach(AdHoc::on_liftoff("Maps refresher", |_| {
-            Box::pin(async move {
-                // We don't care about the join handle nor error results?
-                let _refresher = rocket::tokio::spawn(maps_refresher);
-            })
-        }))
-        .attach(AdHoc::on_liftoff("Version", |_| {
-            Box::pin(async move {
-                let name = env!("CARGO_PKG_NAME");
-                let version = env!("CARGO_PKG_VERSION");
-                let git_sha = &env!("VERGEN_GIT_SHA")[0..7];
-
-                println!("🌁 Started {name} v{version} (git @{git_sha})");
-            })
-        }))
-}
-
-/// Sets up Rocket and the maps cache refresher task.
-pub fn setup() -> Rocket<Build> {
-    let maps = Maps::new();
-    let maps_handle = Arc::new(Mutex::new(maps));
-
-    rocket(maps_handle)
-}
-
-#[cfg(test)]
-mod tests {
-    use assert_float_eq::*;
-    use assert_matches::assert_matches;
-    use image::{DynamicImage, Rgba, RgbaImage};
-    use rocket::http::{ContentType, Status};
-    use rocket::local::blocking::Client;
-    use rocket::serde::json::Value as JsonValue;
-
-    use super::maps::RetrievedMaps;
-    use super::*;
-
-    fn maps_stub(map_count: u32) -> RetrievedMaps {
-        let map_color = Rgba::from([73, 218, 33, 255]); // First color from map key.
-        let image =
-            DynamicImage::ImageRgba8(RgbaImage::from_pixel(820 * map_count, 988, map_color));
-
-        RetrievedMaps::new(image)
-    }
-
-    fn maps_handle_stub() -> MapsHandle {
-        let mut maps = Maps::new();
-        maps.pollen = Some(maps_stub(24));
-        maps.uvi = Some(maps_stub(5));
-
-        Arc::new(Mutex::new(maps))
-    }
-
-    #[test]
-    fn forecast_address() {
-        let maps_handle = maps_handle_stub();
-        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
-
-        // Get an empty forecast for the provided address.
-        let response = client.get("/forecast?address=eindhoven").dispatch();
-        assert_eq!(response.status(), Status::Ok);
-        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
-        assert_float_absolute_eq!(json["lat"].as_f64().unwrap(), 51.448557, 1e-1);
-        assert_float_absolute_eq!(json["lon"].as_f64().unwrap(), 5.450123, 1e-1);
-        assert_matches!(json["time"], JsonValue::Number(_));
-        assert_matches!(json.get("AQI"), None);
-        assert_matches!(json.get("NO2"), None);
-        assert_matches!(json.get("O3"), None);
-        assert_matches!(json.get("PAQI"), None);
-        assert_matches!(json.get("PM10"), None);
-        assert_matches!(json.get("pollen"), None);
-        assert_matches!(json.get("precipitation"), None);
-        assert_matches!(json.get("UVI"), None);
-
-        // Get a forecast with all metrics for the provided address.
-        let response = client
-            .get("/forecast?address=eindhoven&metrics=all")
-            .dispatch();
-        assert_eq!(response.status(), Status::Ok);
-        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
-        assert_float_absolute_eq!(json["lat"].as_f64().unwrap(), 51.448557, 1e-1);
-        assert_float_absolute_eq!(json["lon"].as_f64().unwrap(), 5.450123, 1e-1);
-        assert_matches!(json["time"], JsonValue::Number(_));
-        assert_matches!(json.get("AQI"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("NO2"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("O3"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("PAQI"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("PM10"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("pollen"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("precipitation"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("UVI"), Some(JsonValue::Array(_)));
-    }
-
-    #[test]
-    fn forecast_geo() {
-        let maps_handle = maps_handle_stub();
-        let client = Client::tracked(rocket(maps_handle)).expect("valid Rocket instance");
-
-        // Get an empty forecast for the geocoded location.
-        let response = client.get("/forecast?lat=51.4&lon=5.5").dispatch();
-        assert_eq!(response.status(), Status::Ok);
-        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
-        assert_f64_near!(json["lat"].as_f64().unwrap(), 51.4);
-        assert_f64_near!(json["lon"].as_f64().unwrap(), 5.5);
-        assert_matches!(json["time"], JsonValue::Number(_));
-        assert_matches!(json.get("AQI"), None);
-        assert_matches!(json.get("NO2"), None);
-        assert_matches!(json.get("O3"), None);
-        assert_matches!(json.get("PAQI"), None);
-        assert_matches!(json.get("PM10"), None);
-        assert_matches!(json.get("pollen"), None);
-        assert_matches!(json.get("precipitation"), None);
-        assert_matches!(json.get("UVI"), None);
-
-        // Get a forecast with all metrics for the geocoded location.
-        let response = client
-            .get("/forecast?lat=51.4&lon=5.5&metrics=all")
-            .dispatch();
-        assert_eq!(response.status(), Status::Ok);
-        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
-        assert_f64_near!(json["lat"].as_f64().unwrap(), 51.4);
-        assert_f64_near!(json["lon"].as_f64().unwrap(), 5.5);
-        assert_matches!(json["time"], JsonValue::Number(_));
-        assert_matches!(json.get("AQI"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("NO2"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("O3"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("PAQI"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("PM10"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("pollen"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("precipitation"), Some(JsonValue::Array(_)));
-        assert_matches!(json.get("UVI"), Some(JsonValue::Array(_)));
-    }
-
-    #[test]
-    fn map_address() {
-        let maps_handle = Arc::new(Mutex::new(Maps::new()));
-        let maps_handle_clone = Arc::clone(&maps_handle);
-        let client =
-            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
-
-        // No maps available yet.
-        let response = client
-            .get("/map?address=eindhoven&metric=pollen")
-            .dispatch();
-        assert_eq!(response.status(), Status::ServiceUnavailable);
-
-        // Load some dummy map.
-        let mut maps = maps_handle_clone
-            .lock()
-            .expect("Maps handle mutex was poisoned");
-        maps.pollen = Some(maps_stub(24));
-        drop(maps);
-
-        // There should be a map now.
-        let response = client
-            .get("/map?address=eindhoven&metric=pollen")
-            .dispatch();
-        assert_eq!(response.status(), Status::Ok);
-        assert_eq!(response.content_type(), Some(ContentType::PNG));
-
-        // ... but not if it is out of bounds.
-        let response = client.get("/map?address=berlin&metric=pollen").dispatch();
-        assert_eq!(response.status(), Status::NotFound);
-
-        // No metric selected, don't know which map to show?
-        let response = client.get("/map?address=eindhoven").dispatch();
-        assert_eq!(response.status(), Status::UnprocessableEntity);
-    }
+/// Result type that defaults to [`Error`] as the default error type.
+pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 
-    #[test]
-    fn map_geo() {
-        let maps_handle = Arc::new(Mutex::new(Maps::new()));
-        let maps_handle_clone = Arc::clone(&maps_handle);
-        let client =
-            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
-
-        // No maps available yet.
-        let response = client.get("/map?lat=51.4&lon=5.5&metric=pollen").dispatch();
-        assert_eq!(response.status(), Status::ServiceUnavailable);
-
-        // Load some dummy map.
-        let mut maps = maps_handle_clone
-            .lock()
-            .expect("Maps handle mutex was poisoned");
-        maps.pollen = Some(maps_stub(24));
-        drop(maps);
-
-        // There should be a map now.
-        let response = client.get("/map?lat=51.4&lon=5.5&metric=pollen").dispatch();
-        assert_eq!(response.status(), Status::Ok);
-        assert_eq!(response.content_type(), Some(ContentType::PNG));
-
-        // ... but not if it is out of bounds.
-        let response = client.get("/map?lat=0.0&lon=0.0&metric=pollen").dispatch();
-        assert_eq!(response.status(), Status::NotFound);
-
-        // No metric passed, don't know which map to show?
-        let response = client.get("/map?lat=51.4&lon=5.5").dispatch();
-        assert_eq!(response.status(), Status::UnprocessableEntity);
-    }
-}
+#[cfg(feature = "server")]
+pub use self::cli::run;
+#[cfg(feature = "server")]
+pub use self::server::setup;
@@ -0,0 +1,63 @@
+//! The public library API.
+//!
+//! This allows other Rust projects to embed Sinoptik and retrieve forecasts without running the
+//! HTTP server. It is only available if the `library` feature is enabled.
+
+pub use crate::forecast::{Forecast, Metric};
+pub use crate::position::Position;
+
+use crate::forecast::forecast;
+use crate::maps::MapsHandle;
+#[cfg(feature = "maps")]
+use crate::maps::{self, MapsType};
+
+/// A cache of the pollen, temperature and UV index maps.
+///
+/// The maps are required for the pollen, temperature, UV index and PAQI metrics. The cache starts
+/// out empty and needs to be refreshed (periodically) to retrieve the current maps. It can be
+/// cloned cheaply to share it.
+#[derive(Clone, Debug, Default)]
+pub struct MapsCache {
+    /// The handle to the maps.
+    maps_handle: MapsHandle,
+}
+
+impl MapsCache {
+    /// Creates a new, empty maps cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refreshes the maps by retrieving the current pollen, temperature and UV index maps.
+    ///
+    /// Returns whether all maps were retrieved successfully; errors are logged. If retrieving a
+    /// map fails, the previously retrieved one is kept until it becomes stale.
+    ///
+    /// This is only available if the `maps` feature is enabled.
+    #[cfg(feature = "maps")]
+    pub async fn refresh(&self) -> bool {
+        let mut outcomes = maps::refresh(&self.maps_handle, MapsType::Pollen).await;
+        outcomes.extend(maps::refresh(&self.maps_handle, MapsType::Temperature).await);
+        outcomes.extend(maps::refresh(&self.maps_handle, MapsType::UVI).await);
+
+        outcomes.iter().all(|outcome| outcome.is_ok())
+    }
+
+    /// Retrieves the forecast for the provided position and metrics using the cached maps.
+    ///
+    /// See [`fetch_forecast`] for more information.
+    pub async fn fetch_forecast(&self, position: Position, metrics: &[Metric]) -> Forecast {
+        forecast(position, metrics.to_vec(), &self.maps_handle).await
+    }
+}
+
+/// Retrieves the forecast for the provided position and metrics.
+///
+/// The data for all metrics is retrieved from the providers concurrently. If retrieving the data
+/// for a metric fails, the error is recorded in the forecast instead, see [`Forecast::error`].
+///
+/// No maps are available, so the pollen, temperature, UV index and PAQI metrics will fail. Use
+/// [`MapsCache::fetch_forecast`] to retrieve those.
+pub async fn fetch_forecast(position: Position, metrics: &[Metric]) -> Forecast {
+    MapsCache::new().fetch_forecast(position, metrics).await
+}
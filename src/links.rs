@@ -0,0 +1,126 @@
+//! Hypermedia links.
+//!
+//! This module is used to link a forecast to related resources in the style of
+//! [HAL](https://datatracker.ietf.org/doc/html/draft-kelly-json-hal), so that generic hypermedia
+//! clients can discover them, e.g. go from a PAQI forecast straight to the pollen map for the same
+//! position.
+
+use rocket::serde::Serialize;
+
+use crate::forecast::Metric;
+use crate::position::Position;
+
+/// A link to a resource.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Link {
+    /// The (relative) URI of the resource.
+    href: String,
+
+    /// The name that distinguishes the link from others of the same relation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+/// The links of a forecast by relation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Links {
+    /// The link to the forecast itself.
+    #[serde(rename = "self")]
+    self_link: Link,
+
+    /// The links to the maps of the metrics of the forecast (for the same position).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    map: Vec<Link>,
+}
+
+impl Links {
+    /// Creates the links of a forecast for the position and metrics.
+    ///
+    /// The links to other resources are prefixed with `base`, the path prefix of the API version
+    /// the forecast was requested with. There are no map links if the maps are not supported.
+    pub(crate) fn new(
+        self_href: String,
+        base: &str,
+        position: Position,
+        metrics: impl IntoIterator<Item = Metric>,
+    ) -> Self {
+        let mut map_metrics = metrics
+            .into_iter()
+            .filter_map(map_metric)
+            .filter(|_| cfg!(feature = "maps"))
+            .collect::<Vec<_>>();
+        map_metrics.sort();
+        map_metrics.dedup();
+        let map = map_metrics
+            .into_iter()
+            .map(|metric| Link {
+                href: format!(
+                    "{base}/map?lat={}&lon={}&metric={metric}",
+                    position.lat, position.lon
+                ),
+                name: Some(metric.to_string()),
+            })
+            .collect();
+
+        Self {
+            self_link: Link {
+                href: self_href,
+                name: None,
+            },
+            map,
+        }
+    }
+}
+
+/// Returns the metric of the map that shows the provided metric, if any.
+///
+/// The maps of the combined metrics are those of pollen, as there are no AQI maps.
+fn map_metric(metric: Metric) -> Option<Metric> {
+    match metric {
+        Metric::PAQI | Metric::PAQIDaily | Metric::Pollen => Some(Metric::Pollen),
+        Metric::Precipitation | Metric::Temperature | Metric::UVI => Some(metric),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "maps"))]
+mod tests {
+    use rocket::serde::json::{json, serde_json};
+
+    use super::*;
+
+    #[test]
+    fn new() {
+        let links = Links::new(
+            String::from("/v1/forecast?lat=51.4&lon=5.5&metrics=PAQI&metrics=pollen&metrics=AQI"),
+            "/v1",
+            Position::new(51.4, 5.5),
+            [Metric::AQI, Metric::PAQI, Metric::Pollen],
+        );
+        assert_eq!(
+            serde_json::to_value(links).unwrap(),
+            json!({
+                "self": {
+                    "href": "/v1/forecast?lat=51.4&lon=5.5&metrics=PAQI&metrics=pollen&metrics=AQI"
+                },
+                "map": [
+                    {"href": "/v1/map?lat=51.4&lon=5.5&metric=pollen", "name": "pollen"}
+                ]
+            })
+        );
+
+        // Without metrics that have maps, there are no map links.
+        let links = Links::new(
+            String::from("/forecast?lat=51.4&lon=5.5"),
+            "",
+            Position::new(51.4, 5.5),
+            [Metric::NO2],
+        );
+        assert_eq!(
+            serde_json::to_value(links).unwrap(),
+            json!({ "self": { "href": "/forecast?lat=51.4&lon=5.5" } })
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Named locations.
+//!
+//! This module keeps track of named locations, e.g. "home" or "office", that are stored
+//! server-side so that clients sharing an instance can retrieve forecasts by name instead of
+//! having to provide (and geocode) the same address over and over again.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::position::Position;
+use crate::snapshot::{Snapshot, SnapshotVersions};
+use crate::{Error, Result};
+
+/// The maximum length of the name of a location.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// A handle to access the named locations.
+pub(crate) type NamedLocationsHandle = Arc<Mutex<NamedLocations>>;
+
+/// A named location.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct NamedLocation {
+    /// The unique name of the location.
+    pub(crate) name: String,
+
+    /// The latitude of the position.
+    lat: f64,
+
+    /// The longitude of the position.
+    lon: f64,
+
+    /// The API key the location was stored with (if authentication is enabled).
+    ///
+    /// It is only persisted, see [`StoredLocation`], and never exposed.
+    #[serde(skip)]
+    owner: Option<String>,
+}
+
+impl NamedLocation {
+    /// Returns the geocoded position of the location.
+    pub(crate) fn position(&self) -> Position {
+        Position::new(self.lat, self.lon)
+    }
+
+    /// Returns whether the location can be changed with the API key that access is restricted to,
+    /// see [`ApiKey::restriction`](crate::auth::ApiKey::restriction).
+    fn is_changeable(&self, restriction: Option<&str>) -> bool {
+        restriction.is_none_or(|key| self.owner.as_deref() == Some(key))
+    }
+}
+
+/// A location as it is persisted, including the API key it was stored with.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct StoredLocation<L> {
+    /// The location.
+    #[serde(flatten)]
+    location: L,
+
+    /// The API key the location was stored with (if authentication is enabled).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+}
+
+/// The collection of all named locations.
+///
+/// If a file path is set, the locations are persisted to it after every change, see
+/// [`Snapshot`].
+#[derive(Debug, Default)]
+pub(crate) struct NamedLocations {
+    /// The path of the file the locations are persisted to (if any).
+    path: Option<PathBuf>,
+
+    /// The versions of the snapshots of the locations.
+    versions: SnapshotVersions,
+
+    /// The locations by name.
+    locations: BTreeMap<String, NamedLocation>,
+}
+
+impl NamedLocations {
+    /// Loads the named locations from the file at the provided path, if any.
+    ///
+    /// If the file does not exist yet, there are no named locations.
+    pub(crate) fn load(path: Option<PathBuf>) -> Result<Self> {
+        let locations = match &path {
+            Some(path) if path.exists() => {
+                let data = std::fs::read_to_string(path)?;
+                let locations: Vec<StoredLocation<NamedLocation>> = serde_json::from_str(&data)?;
+
+                locations
+                    .into_iter()
+                    .map(|stored| {
+                        let location = NamedLocation {
+                            owner: stored.owner,
+                            ..stored.location
+                        };
+                        (location.name.clone(), location)
+                    })
+                    .collect()
+            }
+            _ => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            path,
+            locations,
+            ..Default::default()
+        })
+    }
+
+    /// Returns a snapshot of the changed locations to persist to the file, if any.
+    fn snapshot(&mut self) -> Result<Snapshot> {
+        let locations = self
+            .locations
+            .values()
+            .map(|location| StoredLocation {
+                location,
+                owner: location.owner.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        self.versions.snapshot(self.path.as_deref(), &locations)
+    }
+
+    /// Returns all named locations, ordered by name.
+    pub(crate) fn all(&self) -> Vec<NamedLocation> {
+        self.locations.values().cloned().collect()
+    }
+
+    /// Returns the location with the provided name.
+    pub(crate) fn get(&self, name: &str) -> Result<NamedLocation> {
+        self.locations
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::LocationNotFound(String::from(name)))
+    }
+
+    /// Stores the position under the provided name with the provided API key (if any) and returns
+    /// the resulting location, together with the snapshot to persist.
+    ///
+    /// A location with the same name is replaced, but only if it was stored with the API key that
+    /// access is restricted to (if any). The name is validated, see [`validate_name`].
+    pub(crate) fn set(
+        &mut self,
+        name: &str,
+        position: Position,
+        owner: Option<&str>,
+        restriction: Option<&str>,
+    ) -> Result<(NamedLocation, Snapshot)> {
+        validate_name(name)?;
+        self.check_changeable(name, restriction)?;
+
+        let location = NamedLocation {
+            name: String::from(name),
+            lat: position.lat,
+            lon: position.lon,
+            owner: owner.map(String::from),
+        };
+        self.locations
+            .insert(location.name.clone(), location.clone());
+
+        Ok((location, self.snapshot()?))
+    }
+
+    /// Removes the location with the provided name and returns the snapshot to persist.
+    ///
+    /// The location is only removed if it was stored with the API key that access is restricted
+    /// to (if any).
+    pub(crate) fn remove(&mut self, name: &str, restriction: Option<&str>) -> Result<Snapshot> {
+        self.get(name)?;
+        self.check_changeable(name, restriction)?;
+        self.locations.remove(name);
+
+        self.snapshot()
+    }
+
+    /// Checks whether the location with the provided name, if any, can be changed with the API
+    /// key that access is restricted to.
+    pub(crate) fn check_changeable(&self, name: &str, restriction: Option<&str>) -> Result<()> {
+        match self.locations.get(name) {
+            Some(location) if !location.is_changeable(restriction) => {
+                Err(Error::LocationNotOwned(String::from(name)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks whether the name of a location is valid.
+///
+/// The name should consist of at most 64 ASCII letters, digits, dashes and underscores.
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    let valid_name = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(Error::InvalidLocationName(String::from(name)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::tokio::runtime::Builder;
+
+    use super::*;
+
+    #[test]
+    fn named_locations() {
+        let path = std::env::temp_dir().join(format!(
+            "sinoptik-test-locations-{}.json",
+            std::process::id()
+        ));
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let mut locations = NamedLocations::load(Some(path.clone())).unwrap();
+        assert!(locations.all().is_empty());
+
+        // Set locations; a location with the same name is replaced.
+        let (_, snapshot) = locations
+            .set("home", Position::new(51.0, 5.0), None, None)
+            .unwrap();
+        runtime.block_on(snapshot.write()).unwrap();
+        let (_, snapshot) = locations
+            .set("office", Position::new(52.0, 4.9), None, None)
+            .unwrap();
+        runtime.block_on(snapshot.write()).unwrap();
+        let (location, snapshot) = locations
+            .set("home", Position::new(51.4, 5.5), None, None)
+            .unwrap();
+        runtime.block_on(snapshot.write()).unwrap();
+        assert_eq!(location.position(), Position::new(51.4, 5.5));
+        assert_eq!(locations.all().len(), 2);
+
+        // Invalid names are rejected.
+        for name in ["", "my home", "thuis!", &"x".repeat(65)] {
+            assert!(matches!(
+                validate_name(name),
+                Err(Error::InvalidLocationName(_))
+            ));
+            assert!(matches!(
+                locations.set(name, Position::new(51.4, 5.5), None, None),
+                Err(Error::InvalidLocationName(_))
+            ));
+        }
+
+        // Removals are persisted, even if the snapshots are written out of order.
+        let (_, set_snapshot) = locations
+            .set("school", Position::new(52.1, 5.1), None, None)
+            .unwrap();
+        let removed_snapshot = locations.remove("office", None).unwrap();
+        runtime.block_on(removed_snapshot.write()).unwrap();
+        runtime.block_on(set_snapshot.write()).unwrap();
+        assert!(matches!(
+            locations.remove("office", None),
+            Err(Error::LocationNotFound(_))
+        ));
+        let loaded_locations = NamedLocations::load(Some(path.clone())).unwrap();
+        assert_eq!(loaded_locations.all(), locations.all());
+        assert_eq!(
+            loaded_locations.get("home").unwrap().position(),
+            Position::new(51.4, 5.5)
+        );
+
+        // Locations can only be changed with the API key they were stored with (if restricted).
+        let (_, snapshot) = locations
+            .set(
+                "work",
+                Position::new(52.0, 4.9),
+                Some("secret1"),
+                Some("secret1"),
+            )
+            .unwrap();
+        runtime.block_on(snapshot.write()).unwrap();
+        assert!(matches!(
+            locations.set(
+                "work",
+                Position::new(51.4, 5.5),
+                Some("secret2"),
+                Some("secret2")
+            ),
+            Err(Error::LocationNotOwned(_))
+        ));
+        assert!(matches!(
+            locations.remove("work", Some("secret2")),
+            Err(Error::LocationNotOwned(_))
+        ));
+        let loaded_locations = NamedLocations::load(Some(path.clone())).unwrap();
+        assert!(matches!(
+            loaded_locations.check_changeable("work", Some("secret2")),
+            Err(Error::LocationNotOwned(_))
+        ));
+        assert!(loaded_locations
+            .check_changeable("work", Some("secret1"))
+            .is_ok());
+        let _snapshot = locations.remove("work", None).unwrap();
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
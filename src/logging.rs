@@ -0,0 +1,139 @@
+//! Structured logging.
+//!
+//! This module sets up the [`tracing`] subscriber using the configured filter and format, and
+//! provides a fairing that opens a span for and logs every handled request including its duration.
+
+use std::convert::Infallible;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::Deserialize;
+use rocket::{Data, Request, Response};
+use tracing::{info, info_span, Span};
+#[cfg(feature = "server")]
+use tracing_subscriber::fmt::MakeWriter;
+#[cfg(feature = "server")]
+use tracing_subscriber::EnvFilter;
+
+#[cfg(feature = "server")]
+use crate::config::Config;
+
+/// The default log filter.
+pub(crate) const DEFAULT_LOG_FILTER: &str = "info";
+
+/// The supported log formats.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// JSON objects, one per line.
+    Json,
+}
+
+/// Sets up the global tracing subscriber using the log filter and format from the configuration.
+///
+/// If the log filter is invalid, the default log filter is used instead.
+#[cfg(feature = "server")]
+pub(crate) fn init(config: &Config) {
+    init_with_writer(config, std::io::stdout);
+}
+
+/// Sets up the global tracing subscriber like [`init`], but writes the log using the provided
+/// writer.
+#[cfg(feature = "server")]
+pub(crate) fn init_with_writer<W>(config: &Config, writer: W)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_new(&config.log_filter).unwrap_or_else(|e| {
+        eprintln!(
+            "💥 Invalid log filter {:?}, using the default: {e}",
+            config.log_filter
+        );
+        EnvFilter::new(DEFAULT_LOG_FILTER)
+    });
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    let result = match config.log_format {
+        LogFormat::Text => subscriber.try_init(),
+        LogFormat::Json => subscriber.json().try_init(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("💥 Failed to set up logging: {e}");
+    }
+}
+
+/// The instant the handling of a request started.
+///
+/// This is stored in the request-local cache.
+#[derive(Debug)]
+struct RequestStart(Instant);
+
+/// The span of a request.
+///
+/// The span is opened by the [`RequestLogger`] fairing and stored in the request-local cache. It
+/// can be used as a request guard so that handlers can carry it through their work.
+#[derive(Clone, Debug)]
+pub(crate) struct RequestSpan(pub(crate) Span);
+
+impl RequestSpan {
+    /// Opens the span for the request.
+    ///
+    /// Only the path of the URI is recorded, because the query may contain an API key.
+    fn open(request: &Request<'_>) -> Self {
+        Self(info_span!(
+            "request",
+            method = %request.method(),
+            path = %request.uri().path(),
+        ))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestSpan {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(request.local_cache(|| Self::open(request)).clone())
+    }
+}
+
+/// The fairing that opens a span for and logs every handled request including its status and
+/// duration.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request logger",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+        request.local_cache(|| RequestSpan::open(request));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let RequestStart(start) = request.local_cache(|| RequestStart(Instant::now()));
+        let RequestSpan(span) = request.local_cache(|| RequestSpan::open(request));
+
+        span.in_scope(|| {
+            info!(
+                client_ip = ?request.client_ip(),
+                status = response.status().code,
+                duration_ms = start.elapsed().as_millis() as u64,
+                "📨 Handled request"
+            )
+        });
+    }
+}
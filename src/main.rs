@@ -15,8 +15,14 @@
 )]
 #![deny(missing_docs)]
 
-/// Starts the main maps refresh task and sets up and launches Rocket.
-#[rocket::launch]
-async fn rocket() -> _ {
-    sinoptik::setup()
+use std::process::ExitCode;
+
+/// Runs the command provided by the command-line arguments.
+///
+/// Without a command, this starts the main maps refresh task and sets up and launches Rocket.
+#[rocket::main]
+async fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    sinoptik::run(args).await
 }
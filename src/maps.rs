@@ -1,24 +1,39 @@
 //! Maps retrieval and caching.
 //!
 //! This module provides a task that keeps maps up-to-date using a maps-specific refresh interval.
-//! It stores all the maps as [`DynamicImage`]s in memory.
+//! It stores all the maps as [`DynamicImage`]s in memory, together with a pre-computed grid of
+//! scores used for sampling.
 
 use std::collections::HashMap;
 use std::f64::consts::PI;
-use std::sync::{Arc, Mutex};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
 
-use chrono::serde::ts_seconds;
+use chrono::serde::ts_seconds_option;
 use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
 use image::{
-    DynamicImage, GenericImage, GenericImageView, ImageError, ImageFormat, Pixel, Rgb, Rgba,
+    DynamicImage, GenericImage, GenericImageView, ImageError, ImageFormat, ImageReader, Limits,
+    Pixel, Rgba,
 };
-use reqwest::Url;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{StatusCode, Url};
+use rocket::http::{Accept, MediaType};
 use rocket::serde::Serialize;
 use rocket::tokio;
+use rocket::tokio::sync::Semaphore;
 use rocket::tokio::time::sleep;
+use tracing::{error, info, warn};
 
+use crate::config::MapsConfig;
 use crate::forecast::Metric;
-use crate::position::Position;
+use crate::position::{BoundingBox, Position};
+#[cfg(doc)]
+use crate::providers::buienradar;
+use crate::providers::buienradar::{Item, Sample};
+use crate::{http, providers};
 
 /// The possible maps errors that can occur.
 #[derive(Debug, thiserror::Error)]
@@ -29,7 +44,7 @@ pub(crate) enum Error {
 
     /// A HTTP request error occurred.
     #[error("HTTP request error: {0}")]
-    HttpRequest(#[from] reqwest::Error),
+    HttpRequest(#[from] http::Error),
 
     /// Failed to represent HTTP header as a string.
     #[error("Failed to represent HTTP header as a string")]
@@ -39,6 +54,10 @@ pub(crate) enum Error {
     #[error("Image error: {0}")]
     Image(#[from] ImageError),
 
+    /// The image is larger than the maximum size (in bytes).
+    #[error("The image is larger than the maximum size of {0} bytes")]
+    ImageTooLarge(u64),
+
     /// Encountered an invalid image file path.
     #[error("Invalid image file path: {0}")]
     InvalidImagePath(String),
@@ -47,10 +66,27 @@ pub(crate) enum Error {
     #[error("Failed to join a task: {0}")]
     Join(#[from] tokio::task::JoinError),
 
+    /// The image does not consist of whole maps, i.e. it has invalid dimensions.
+    #[error("The image does not consist of whole maps: its size is {0}×{1} pixels")]
+    InvalidMapDimensions(u32, u32),
+
+    /// Encountered invalid map options.
+    #[error("Invalid map options: {0}")]
+    InvalidOptions(String),
+
     /// Did not find any known (map key) colors in samples.
     #[error("Did not find any known colors in samples")]
     NoKnownColorsInSamples,
 
+    /// The map key no longer matches the colors of the maps.
+    #[error(
+        "The map key no longer matches the maps: only {:.0}% of the colored pixels match \
+         (most common unknown colors: {})",
+        .0 * 100.0,
+        .1.join(", ")
+    )]
+    MapKeyMismatch(f32, Vec<String>),
+
     /// No maps found (yet).
     #[error("No maps found (yet)")]
     NoMapsYet,
@@ -59,19 +95,38 @@ pub(crate) enum Error {
     #[error("Got out of bound coordinates for a map: ({0}, {1})")]
     OutOfBoundCoords(u32, u32),
 
+    /// A PNG encoding error occurred.
+    #[error("PNG encoding error: {0}")]
+    PngEncoding(#[from] png::EncodingError),
+
     /// Got out of bound offset for a map.
     #[error("Got out of bound offset for a map: {0}")]
-    OutOfBoundOffset(u32),
+    OutOfBoundOffset(i64),
+
+    /// The position lies outside of the area covered by the map.
+    #[error(
+        "The position ({}, {}) lies outside of the supported coverage area",
+        .0.lat,
+        .0.lon
+    )]
+    OutsideCoverage(Position, BoundingBox),
+
+    /// The maps image has an unexpected content type, e.g. when an error page is returned.
+    #[error("Buienradar responded with unexpected content type {1} for {0}")]
+    UnexpectedContentType(String, String),
 }
 
 /// Result type that defaults to [`Error`] as the default error type.
 pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 
-/// A handle to access the in-memory cached maps.
-pub(crate) type MapsHandle = Arc<Mutex<Maps>>;
+/// The maximum number of image processing tasks on the maps that can run concurrently.
+const MAX_BLOCKING_TASKS: usize = 4;
+
+/// The semaphore that bounds the number of concurrent image processing tasks.
+static BLOCKING_TASKS: Semaphore = Semaphore::const_new(MAX_BLOCKING_TASKS);
 
-/// A histogram mapping map key colors to occurences/counts.
-type MapKeyHistogram = HashMap<Rgb<u8>, u32>;
+/// A handle to access the in-memory cached maps.
+pub(crate) type MapsHandle = Arc<RwLock<Maps>>;
 
 /// The Buienradar map key used for determining the score of a coordinate by mapping its color.
 ///
@@ -90,27 +145,73 @@ const MAP_KEY: [[u8; 3]; 10] = [
     [0xB3, 0x30, 0xA1], // #B330A1
 ];
 
+/// The labels of the scores of the Buienradar map key.
+const MAP_KEY_LABELS: [&str; 10] = ["1", "2", "3", "4", "5", "6", "7", "8", "9", "10"];
+
+/// The legend of the Buienradar map key (used by the pollen and UV index maps).
+const MAP_LEGEND: Legend = Legend {
+    colors: &MAP_KEY,
+    labels: &MAP_KEY_LABELS,
+};
+
+/// The Buienradar temperature map key used for determining the temperature of a coordinate by
+/// mapping its color.
+///
+/// The temperature of each color is given by [`TEMPERATURE_MAP_VALUES`] at the same index.
+#[rustfmt::skip]
+const TEMPERATURE_MAP_KEY: [[u8; 3]; 10] = [
+    [0x64, 0x00, 0xC8], // #6400C8
+    [0x00, 0x32, 0xFF], // #0032FF
+    [0x00, 0x96, 0xFF], // #0096FF
+    [0x00, 0xC8, 0xC8], // #00C8C8
+    [0x00, 0xC8, 0x64], // #00C864
+    [0x96, 0xDC, 0x00], // #96DC00
+    [0xFF, 0xE6, 0x00], // #FFE600
+    [0xFF, 0xA0, 0x00], // #FFA000
+    [0xFF, 0x50, 0x00], // #FF5000
+    [0xC8, 0x00, 0x00], // #C80000
+];
+
+/// The temperatures (in °C) of the colors of the Buienradar temperature map key.
+///
+/// Each color covers the 5 °C starting from its temperature.
+const TEMPERATURE_MAP_VALUES: [f32; 10] =
+    [-10.0, -5.0, 0.0, 5.0, 10.0, 15.0, 20.0, 25.0, 30.0, 35.0];
+
+/// The labels of the colors of the Buienradar temperature map key.
+const TEMPERATURE_MAP_KEY_LABELS: [&str; 10] =
+    ["-10", "-5", "0", "5", "10", "15", "20", "25", "30", "35"];
+
+/// The legend of the Buienradar temperature map key.
+const TEMPERATURE_MAP_LEGEND: Legend = Legend {
+    colors: &TEMPERATURE_MAP_KEY,
+    labels: &TEMPERATURE_MAP_KEY_LABELS,
+};
+
+/// The width (in pixels) of a single Buienradar map.
+const MAP_WIDTH: u32 = 820;
+
+/// The height (in pixels) of a single Buienradar map.
+const MAP_HEIGHT: u32 = 988;
+
 /// The Buienradar map sample size.
 ///
 /// Determines the number of pixels in width/height that is sampled around the sampling coordinate.
 const MAP_SAMPLE_SIZE: [u32; 2] = [31, 31];
 
-/// The interval between map refreshes (in seconds).
-const REFRESH_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(60);
+/// The minimum fraction of the colored pixels of the maps that needs to have a map key color.
+///
+/// If fewer pixels match, the map key is considered to no longer match the maps, e.g. because the
+/// palette of the maps has changed.
+const MIN_MAP_KEY_COVERAGE: f32 = 0.5;
 
-/// The base URL for retrieving the pollen maps from Buienradar.
-const POLLEN_BASE_URL: &str =
-    "https://image.buienradar.nl/2.0/image/sprite/WeatherMapPollenRadarHourlyNL\
-        ?width=820&height=988&extension=png&renderBackground=False&renderBranding=False\
-        &renderText=False&history=0&forecast=24&skip=0";
+/// The maximum number of unknown colors that is reported if the map key does not match the maps.
+const MAX_REPORTED_UNKNOWN_COLORS: usize = 5;
 
-/// The interval for retrieving pollen maps.
+/// The size of the cells of the pre-computed sample grid.
 ///
-/// The endpoint provides a map for every hour, 24 in total.
-const POLLEN_INTERVAL: i64 = 3_600;
-
-/// The number of pollen maps retained.
-const POLLEN_MAP_COUNT: u32 = 24;
+/// Determines the number of pixels in width/height of a cell that share the same score.
+const MAP_GRID_CELL_SIZE: u32 = 6;
 
 /// The number of seconds each pollen map is for.
 const POLLEN_MAP_INTERVAL: i64 = 3_600;
@@ -125,18 +226,17 @@ const POLLEN_MAP_REF_POINTS: [(Position, (u32, u32)); 2] = [
     (Position::new(53.40, 6.90), (111, 694)), // Lauwersoog (lat/y) and Enschede (lon/x)
 ];
 
-/// The base URL for retrieving the UV index maps from Buienradar.
-const UVI_BASE_URL: &str = "https://image.buienradar.nl/2.0/image/sprite/WeatherMapUVIndexNL\
-        ?width=820&height=988&extension=png&&renderBackground=False&renderBranding=False\
-        &renderText=False&history=0&forecast=5&skip=0";
+/// The number of seconds each precipitation radar map is for.
+const PRECIPITATION_MAP_INTERVAL: i64 = 300;
 
-/// The interval for retrieving UV index maps.
-///
-/// The endpoint provides a map for every day, 5 in total.
-const UVI_INTERVAL: i64 = 24 * 3_600;
+/// The position reference points for the precipitation radar map.
+const PRECIPITATION_MAP_REF_POINTS: [(Position, (u32, u32)); 2] = POLLEN_MAP_REF_POINTS;
 
-/// The number of UV index maps retained.
-const UVI_MAP_COUNT: u32 = 5;
+/// The number of seconds each temperature map is for.
+const TEMPERATURE_MAP_INTERVAL: i64 = 3_600;
+
+/// The reference points used for the temperature maps.
+const TEMPERATURE_MAP_REF_POINTS: [(Position, (u32, u32)); 2] = POLLEN_MAP_REF_POINTS;
 
 /// The number of seconds each UV index map is for.
 const UVI_MAP_INTERVAL: i64 = 24 * 3_600;
@@ -144,26 +244,78 @@ const UVI_MAP_INTERVAL: i64 = 24 * 3_600;
 /// The position reference points for the UV index map.
 const UVI_MAP_REF_POINTS: [(Position, (u32, u32)); 2] = POLLEN_MAP_REF_POINTS;
 
+/// The mean radius of the Earth (in kilometers).
+const EARTH_RADIUS_KM: f64 = 6_371.0;
+
+/// The delay between the frames of an animated map (in milliseconds).
+const ANIMATION_FRAME_DELAY: u16 = 500;
+
+/// The default color of the marker of the position on a map.
+const DEFAULT_MARKER_COLOR: [u8; 4] = [0x00, 0x00, 0x00, 0xff];
+
+/// The default size of the marker of the position on a map (in pixels).
+const DEFAULT_MARKER_SIZE: u32 = 10;
+
+/// The maximum size of the marker of the position on a map (in pixels).
+const MAX_MARKER_SIZE: u32 = 100;
+
+/// The thickness of the lines of the marker of the position on a map (in pixels).
+const MARKER_THICKNESS: i64 = 2;
+
+/// The scale of the font used to overlay the time and the legend on a map.
+const FONT_SCALE: u32 = 2;
+
 /// The `MapsRefresh` trait is used to reduce the time a lock needs to be held when updating maps.
 ///
 /// When refreshing maps, the lock only needs to be held when checking whether a refresh is
 /// necessary and when the new maps have been retrieved and can be updated.
 trait MapsRefresh {
-    /// Determines whether the pollen maps need to be refreshed.
-    fn needs_pollen_refresh(&self) -> bool;
+    /// Returns when the pollen maps need to be refreshed, or `None` if there are none yet.
+    fn pollen_refresh_at(&self) -> Option<DateTime<Utc>>;
 
-    /// Determines whether the UV index maps need to be refreshed.
-    fn needs_uvi_refresh(&self) -> bool;
+    /// Returns when the precipitation radar maps need to be refreshed, or `None` if there are none
+    /// yet.
+    fn precipitation_refresh_at(&self) -> Option<DateTime<Utc>>;
+
+    /// Returns when the temperature maps need to be refreshed, or `None` if there are none yet.
+    fn temperature_refresh_at(&self) -> Option<DateTime<Utc>>;
+
+    /// Returns when the UV index maps need to be refreshed, or `None` if there are none yet.
+    fn uvi_refresh_at(&self) -> Option<DateTime<Utc>>;
 
     /// Determines whether the pollen maps are stale.
     fn is_pollen_stale(&self) -> bool;
 
+    /// Determines whether the precipitation radar maps are stale.
+    fn is_precipitation_stale(&self) -> bool;
+
+    /// Determines whether the temperature maps are stale.
+    fn is_temperature_stale(&self) -> bool;
+
     /// Determines whether the UV index maps are stale.
     fn is_uvi_stale(&self) -> bool;
 
+    /// Returns the validators of the current pollen maps (if any).
+    fn pollen_validators(&self) -> Validators;
+
+    /// Returns the validators of the current precipitation radar maps (if any).
+    fn precipitation_validators(&self) -> Validators;
+
+    /// Returns the validators of the current temperature maps (if any).
+    fn temperature_validators(&self) -> Validators;
+
+    /// Returns the validators of the current UV index maps (if any).
+    fn uvi_validators(&self) -> Validators;
+
     /// Updates the pollen maps.
     fn set_pollen(&self, result: Result<RetrievedMaps>);
 
+    /// Updates the precipitation radar maps.
+    fn set_precipitation(&self, result: Result<RetrievedMaps>);
+
+    /// Updates the temperature maps.
+    fn set_temperature(&self, result: Result<RetrievedMaps>);
+
     /// Updates the UV index maps.
     fn set_uvi(&self, result: Result<RetrievedMaps>);
 }
@@ -174,8 +326,19 @@ pub(crate) struct Maps {
     /// The pollen maps (from Buienradar).
     pub(crate) pollen: Option<RetrievedMaps>,
 
+    /// The precipitation radar maps (from Buienradar).
+    ///
+    /// These are only used for showing maps, not for sampling.
+    pub(crate) precipitation: Option<RetrievedMaps>,
+
+    /// The temperature maps (from Buienradar).
+    pub(crate) temperature: Option<RetrievedMaps>,
+
     /// The UV index maps (from Buienradar).
     pub(crate) uvi: Option<RetrievedMaps>,
+
+    /// The configuration of the maps and their refreshing.
+    config: MapsConfig,
 }
 
 impl Maps {
@@ -183,431 +346,2847 @@ impl Maps {
     ///
     /// It contains an [`DynamicImage`] per maps type, if downloaded, and the timestamp of the last
     /// update.
-    pub(crate) fn new() -> Self {
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn new(config: MapsConfig) -> Self {
         Self {
             pollen: None,
+            precipitation: None,
+            temperature: None,
             uvi: None,
+            config,
         }
     }
 
-    /// Returns a current pollen map that marks the provided position.
-    pub(crate) fn pollen_mark(&self, position: Position) -> Result<DynamicImage> {
+    /// Returns an unmodified pollen map.
+    ///
+    /// The map is selected using the provided options; by default the current map is selected. It
+    /// also returns the validity of the selected map.
+    pub(crate) fn pollen_raw(&self, options: &MapOptions) -> Result<(DynamicImage, Validity)> {
         let maps = self.pollen.as_ref().ok_or(Error::NoMapsYet)?;
-        let image = &maps.image;
-        let stamp = maps.timestamp_base;
-        let marked_image = map_at(
-            image,
-            stamp,
+        let map_count = maps.grid.count;
+        let instant = options.instant(POLLEN_MAP_INTERVAL, map_count, Utc::now())?;
+        let image = map_at(
+            &maps.image,
+            maps.timestamp_base,
+            POLLEN_MAP_INTERVAL,
+            map_count,
+            instant,
+        )?;
+        let validity = maps.validity(POLLEN_MAP_INTERVAL, self.config.pollen_interval, instant);
+
+        Ok((image, validity))
+    }
+
+    /// Returns a pollen map that marks the provided position.
+    ///
+    /// The map is selected and rendered using the provided options; by default the current map is
+    /// selected. It also returns the validity of the selected map.
+    pub(crate) fn pollen_mark(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(DynamicImage, Validity)> {
+        let (image, validity) = self.pollen_raw(options)?;
+        let coords = project(&image, POLLEN_MAP_REF_POINTS, position)?;
+        let marked_image = mark(image, coords, options.marker()?);
+        let mut image = options.crop(marked_image, coords, POLLEN_MAP_REF_POINTS, position);
+        if options.legend {
+            draw_legend(&mut image, &MAP_LEGEND);
+        }
+
+        Ok((image, validity))
+    }
+
+    /// Returns the frames of all pollen maps that mark the provided position.
+    ///
+    /// The time of each map is overlaid on its frame. It also returns the validity of the frames.
+    pub(crate) fn pollen_frames(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(Vec<DynamicImage>, Validity)> {
+        let maps = self.pollen.as_ref().ok_or(Error::NoMapsYet)?;
+        let map_count = maps.grid.count;
+        let frames = frames(
+            maps,
             POLLEN_MAP_INTERVAL,
-            POLLEN_MAP_COUNT,
-            Utc::now(),
+            map_count,
+            POLLEN_MAP_REF_POINTS,
+            position,
+            options,
+            options.legend.then_some(&MAP_LEGEND),
         )?;
-        let coords = project(&marked_image, POLLEN_MAP_REF_POINTS, position)?;
+        let validity = maps.frames_validity(self.config.pollen_interval);
 
-        Ok(mark(marked_image, coords))
+        Ok((frames, validity))
     }
 
     /// Samples the pollen maps for the given position.
     pub(crate) fn pollen_samples(&self, position: Position) -> Result<Vec<Sample>> {
         let maps = self.pollen.as_ref().ok_or(Error::NoMapsYet)?;
         let image = &maps.image;
-        let map = image.view(0, 0, image.width() / UVI_MAP_COUNT, image.height());
+        let map = image.view(0, 0, image.width() / maps.grid.count, image.height());
         let coords = project(&*map, POLLEN_MAP_REF_POINTS, position)?;
         let stamp = maps.timestamp_base;
 
-        sample(image, stamp, POLLEN_MAP_INTERVAL, POLLEN_MAP_COUNT, coords)
+        maps.grid.samples(stamp, POLLEN_MAP_INTERVAL, coords)
     }
 
-    /// Returns a current UV index map that marks the provided position.
-    pub(crate) fn uvi_mark(&self, position: Position) -> Result<DynamicImage> {
-        let maps = self.uvi.as_ref().ok_or(Error::NoMapsYet)?;
+    /// Returns an unmodified precipitation radar map.
+    ///
+    /// The map is selected using the provided options; by default the current map is selected. It
+    /// also returns the validity of the selected map.
+    pub(crate) fn precipitation_raw(
+        &self,
+        options: &MapOptions,
+    ) -> Result<(DynamicImage, Validity)> {
+        let maps = self.precipitation.as_ref().ok_or(Error::NoMapsYet)?;
+        let map_count = maps.grid.count;
+        let instant = options.instant(PRECIPITATION_MAP_INTERVAL, map_count, Utc::now())?;
+        let image = map_at(
+            &maps.image,
+            maps.timestamp_base,
+            PRECIPITATION_MAP_INTERVAL,
+            map_count,
+            instant,
+        )?;
+        let validity = maps.validity(
+            PRECIPITATION_MAP_INTERVAL,
+            self.config.precipitation_interval,
+            instant,
+        );
+
+        Ok((image, validity))
+    }
+
+    /// Returns a precipitation radar map that marks the provided position.
+    ///
+    /// The map is selected and rendered using the provided options; by default the current map is
+    /// selected. No legend is available for this map. It also returns the validity of the selected
+    /// map.
+    pub(crate) fn precipitation_mark(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(DynamicImage, Validity)> {
+        if options.legend {
+            return Err(Error::InvalidOptions(String::from(
+                "no legend is available for the precipitation maps",
+            )));
+        }
+        let (image, validity) = self.precipitation_raw(options)?;
+        let coords = project(&image, PRECIPITATION_MAP_REF_POINTS, position)?;
+        let marked_image = mark(image, coords, options.marker()?);
+        let image = options.crop(marked_image, coords, PRECIPITATION_MAP_REF_POINTS, position);
+
+        Ok((image, validity))
+    }
+
+    /// Returns the frames of all precipitation radar maps that mark the provided position.
+    ///
+    /// The time of each map is overlaid on its frame. No legend is available for these maps. It
+    /// also returns the validity of the frames.
+    pub(crate) fn precipitation_frames(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(Vec<DynamicImage>, Validity)> {
+        if options.legend {
+            return Err(Error::InvalidOptions(String::from(
+                "no legend is available for the precipitation maps",
+            )));
+        }
+        let maps = self.precipitation.as_ref().ok_or(Error::NoMapsYet)?;
+        let map_count = maps.grid.count;
+        let frames = frames(
+            maps,
+            PRECIPITATION_MAP_INTERVAL,
+            map_count,
+            PRECIPITATION_MAP_REF_POINTS,
+            position,
+            options,
+            None,
+        )?;
+        let validity = maps.frames_validity(self.config.precipitation_interval);
+
+        Ok((frames, validity))
+    }
+
+    /// Returns an unmodified temperature map.
+    ///
+    /// The map is selected using the provided options; by default the current map is selected. It
+    /// also returns the validity of the selected map.
+    pub(crate) fn temperature_raw(&self, options: &MapOptions) -> Result<(DynamicImage, Validity)> {
+        let maps = self.temperature.as_ref().ok_or(Error::NoMapsYet)?;
+        let map_count = maps.grid.count;
+        let instant = options.instant(TEMPERATURE_MAP_INTERVAL, map_count, Utc::now())?;
+        let image = map_at(
+            &maps.image,
+            maps.timestamp_base,
+            TEMPERATURE_MAP_INTERVAL,
+            map_count,
+            instant,
+        )?;
+        let validity = maps.validity(
+            TEMPERATURE_MAP_INTERVAL,
+            self.config.temperature_interval,
+            instant,
+        );
+
+        Ok((image, validity))
+    }
+
+    /// Returns a temperature map that marks the provided position.
+    ///
+    /// The map is selected and rendered using the provided options; by default the current map is
+    /// selected. It also returns the validity of the selected map.
+    pub(crate) fn temperature_mark(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(DynamicImage, Validity)> {
+        let (image, validity) = self.temperature_raw(options)?;
+        let coords = project(&image, TEMPERATURE_MAP_REF_POINTS, position)?;
+        let marked_image = mark(image, coords, options.marker()?);
+        let mut image = options.crop(marked_image, coords, TEMPERATURE_MAP_REF_POINTS, position);
+        if options.legend {
+            draw_legend(&mut image, &TEMPERATURE_MAP_LEGEND);
+        }
+
+        Ok((image, validity))
+    }
+
+    /// Returns the frames of all temperature maps that mark the provided position.
+    ///
+    /// The time of each map is overlaid on its frame. It also returns the validity of the frames.
+    pub(crate) fn temperature_frames(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(Vec<DynamicImage>, Validity)> {
+        let maps = self.temperature.as_ref().ok_or(Error::NoMapsYet)?;
+        let map_count = maps.grid.count;
+        let frames = frames(
+            maps,
+            TEMPERATURE_MAP_INTERVAL,
+            map_count,
+            TEMPERATURE_MAP_REF_POINTS,
+            position,
+            options,
+            options.legend.then_some(&TEMPERATURE_MAP_LEGEND),
+        )?;
+        let validity = maps.frames_validity(self.config.temperature_interval);
+
+        Ok((frames, validity))
+    }
+
+    /// Samples the temperature maps for the given position.
+    ///
+    /// The score of each sample is converted to the temperature of its color in the temperature
+    /// map key, see [`TEMPERATURE_MAP_VALUES`].
+    pub(crate) fn temperature_items(&self, position: Position) -> Result<Vec<Item>> {
+        let maps = self.temperature.as_ref().ok_or(Error::NoMapsYet)?;
         let image = &maps.image;
+        let map = image.view(0, 0, image.width() / maps.grid.count, image.height());
+        let coords = project(&*map, TEMPERATURE_MAP_REF_POINTS, position)?;
         let stamp = maps.timestamp_base;
-        let marked_image = map_at(image, stamp, UVI_MAP_INTERVAL, UVI_MAP_COUNT, Utc::now())?;
-        let coords = project(&marked_image, POLLEN_MAP_REF_POINTS, position)?;
+        let samples = maps.grid.samples(stamp, TEMPERATURE_MAP_INTERVAL, coords)?;
+
+        Ok(samples
+            .into_iter()
+            .map(|sample| Item {
+                time: sample.time,
+                // Scores go from 1..=10, not 0..=9!
+                value: TEMPERATURE_MAP_VALUES[sample.score as usize - 1],
+                label: None,
+                exceeds: None,
+                intensity: None,
+                accumulated: None,
+            })
+            .collect())
+    }
+
+    /// Returns an unmodified UV index map.
+    ///
+    /// The map is selected using the provided options; by default the current map is selected. It
+    /// also returns the validity of the selected map.
+    pub(crate) fn uvi_raw(&self, options: &MapOptions) -> Result<(DynamicImage, Validity)> {
+        let maps = self.uvi.as_ref().ok_or(Error::NoMapsYet)?;
+        let map_count = maps.grid.count;
+        let instant = options.instant(UVI_MAP_INTERVAL, map_count, Utc::now())?;
+        let image = map_at(
+            &maps.image,
+            maps.timestamp_base,
+            UVI_MAP_INTERVAL,
+            map_count,
+            instant,
+        )?;
+        let validity = maps.validity(UVI_MAP_INTERVAL, self.config.uvi_interval, instant);
+
+        Ok((image, validity))
+    }
+
+    /// Returns a UV index map that marks the provided position.
+    ///
+    /// The map is selected and rendered using the provided options; by default the current map is
+    /// selected. It also returns the validity of the selected map.
+    pub(crate) fn uvi_mark(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(DynamicImage, Validity)> {
+        let (image, validity) = self.uvi_raw(options)?;
+        let coords = project(&image, UVI_MAP_REF_POINTS, position)?;
+        let marked_image = mark(image, coords, options.marker()?);
+        let mut image = options.crop(marked_image, coords, UVI_MAP_REF_POINTS, position);
+        if options.legend {
+            draw_legend(&mut image, &MAP_LEGEND);
+        }
+
+        Ok((image, validity))
+    }
+
+    /// Returns the frames of all UV index maps that mark the provided position.
+    ///
+    /// The time of each map is overlaid on its frame. It also returns the validity of the frames.
+    pub(crate) fn uvi_frames(
+        &self,
+        position: Position,
+        options: &MapOptions,
+    ) -> Result<(Vec<DynamicImage>, Validity)> {
+        let maps = self.uvi.as_ref().ok_or(Error::NoMapsYet)?;
+        let map_count = maps.grid.count;
+        let frames = frames(
+            maps,
+            UVI_MAP_INTERVAL,
+            map_count,
+            UVI_MAP_REF_POINTS,
+            position,
+            options,
+            options.legend.then_some(&MAP_LEGEND),
+        )?;
+        let validity = maps.frames_validity(self.config.uvi_interval);
 
-        Ok(mark(marked_image, coords))
+        Ok((frames, validity))
     }
 
     /// Samples the UV index maps for the given position.
     pub(crate) fn uvi_samples(&self, position: Position) -> Result<Vec<Sample>> {
         let maps = self.uvi.as_ref().ok_or(Error::NoMapsYet)?;
         let image = &maps.image;
-        let map = image.view(0, 0, image.width() / UVI_MAP_COUNT, image.height());
+        let map = image.view(0, 0, image.width() / maps.grid.count, image.height());
         let coords = project(&*map, UVI_MAP_REF_POINTS, position)?;
         let stamp = maps.timestamp_base;
 
-        sample(image, stamp, UVI_MAP_INTERVAL, UVI_MAP_COUNT, coords)
+        maps.grid.samples(stamp, UVI_MAP_INTERVAL, coords)
     }
 }
 
 impl MapsRefresh for MapsHandle {
     fn is_pollen_stale(&self) -> bool {
-        let maps = self.lock().expect("Maps handle mutex was poisoned");
+        let maps = self.read().expect("Maps handle lock was poisoned");
 
         match &maps.pollen {
             Some(pollen_maps) => {
                 Utc::now().signed_duration_since(pollen_maps.mtime)
-                    > Duration::seconds(POLLEN_MAP_COUNT as i64 * POLLEN_MAP_INTERVAL)
+                    > Duration::seconds(maps.config.pollen_map_count as i64 * POLLEN_MAP_INTERVAL)
             }
             None => false,
         }
     }
 
-    fn is_uvi_stale(&self) -> bool {
-        let maps = self.lock().expect("Maps handle mutex was poisoned");
+    fn is_precipitation_stale(&self) -> bool {
+        let maps = self.read().expect("Maps handle lock was poisoned");
 
-        match &maps.uvi {
-            Some(uvi_maps) => {
-                Utc::now().signed_duration_since(uvi_maps.mtime)
-                    > Duration::seconds(UVI_MAP_COUNT as i64 * UVI_MAP_INTERVAL)
+        match &maps.precipitation {
+            Some(precipitation_maps) => {
+                Utc::now().signed_duration_since(precipitation_maps.mtime)
+                    > Duration::seconds(
+                        maps.config.precipitation_map_count as i64 * PRECIPITATION_MAP_INTERVAL,
+                    )
             }
             None => false,
         }
     }
 
-    fn needs_pollen_refresh(&self) -> bool {
-        let maps = self.lock().expect("Maps handle mutex was poisoned");
+    fn is_temperature_stale(&self) -> bool {
+        let maps = self.read().expect("Maps handle lock was poisoned");
 
-        match &maps.pollen {
-            Some(pollen_maps) => {
-                Utc::now()
-                    .signed_duration_since(pollen_maps.mtime)
-                    .num_seconds()
-                    > POLLEN_INTERVAL
+        match &maps.temperature {
+            Some(temperature_maps) => {
+                Utc::now().signed_duration_since(temperature_maps.mtime)
+                    > Duration::seconds(
+                        maps.config.temperature_map_count as i64 * TEMPERATURE_MAP_INTERVAL,
+                    )
             }
-            None => true,
+            None => false,
         }
     }
 
-    fn needs_uvi_refresh(&self) -> bool {
-        let maps = self.lock().expect("Maps handle mutex was poisoned");
+    fn is_uvi_stale(&self) -> bool {
+        let maps = self.read().expect("Maps handle lock was poisoned");
 
         match &maps.uvi {
             Some(uvi_maps) => {
-                Utc::now()
-                    .signed_duration_since(uvi_maps.mtime)
-                    .num_seconds()
-                    > UVI_INTERVAL
+                Utc::now().signed_duration_since(uvi_maps.mtime)
+                    > Duration::seconds(maps.config.uvi_map_count as i64 * UVI_MAP_INTERVAL)
             }
-            None => true,
+            None => false,
         }
     }
 
+    fn pollen_refresh_at(&self) -> Option<DateTime<Utc>> {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+        let interval = Duration::seconds(maps.config.pollen_interval as i64);
+
+        maps.pollen
+            .as_ref()
+            .map(|pollen_maps| pollen_maps.mtime + interval)
+    }
+
+    fn precipitation_refresh_at(&self) -> Option<DateTime<Utc>> {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+        let interval = Duration::seconds(maps.config.precipitation_interval as i64);
+
+        maps.precipitation
+            .as_ref()
+            .map(|precipitation_maps| precipitation_maps.mtime + interval)
+    }
+
+    fn temperature_refresh_at(&self) -> Option<DateTime<Utc>> {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+        let interval = Duration::seconds(maps.config.temperature_interval as i64);
+
+        maps.temperature
+            .as_ref()
+            .map(|temperature_maps| temperature_maps.mtime + interval)
+    }
+
+    fn uvi_refresh_at(&self) -> Option<DateTime<Utc>> {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+        let interval = Duration::seconds(maps.config.uvi_interval as i64);
+
+        maps.uvi.as_ref().map(|uvi_maps| uvi_maps.mtime + interval)
+    }
+
+    fn pollen_validators(&self) -> Validators {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+
+        maps.pollen
+            .as_ref()
+            .map(|pollen_maps| pollen_maps.validators.clone())
+            .unwrap_or_default()
+    }
+
+    fn precipitation_validators(&self) -> Validators {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+
+        maps.precipitation
+            .as_ref()
+            .map(|precipitation_maps| precipitation_maps.validators.clone())
+            .unwrap_or_default()
+    }
+
+    fn temperature_validators(&self) -> Validators {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+
+        maps.temperature
+            .as_ref()
+            .map(|temperature_maps| temperature_maps.validators.clone())
+            .unwrap_or_default()
+    }
+
+    fn uvi_validators(&self) -> Validators {
+        let maps = self.read().expect("Maps handle lock was poisoned");
+
+        maps.uvi
+            .as_ref()
+            .map(|uvi_maps| uvi_maps.validators.clone())
+            .unwrap_or_default()
+    }
+
     fn set_pollen(&self, retrieved_maps: Result<RetrievedMaps>) {
         if retrieved_maps.is_ok() || self.is_pollen_stale() {
-            let mut maps = self.lock().expect("Maps handle mutex was poisoned");
+            let mut maps = self.write().expect("Maps handle lock was poisoned");
             maps.pollen = retrieved_maps.ok();
         }
     }
 
+    fn set_precipitation(&self, retrieved_maps: Result<RetrievedMaps>) {
+        if retrieved_maps.is_ok() || self.is_precipitation_stale() {
+            let mut maps = self.write().expect("Maps handle lock was poisoned");
+            maps.precipitation = retrieved_maps.ok();
+        }
+    }
+
+    fn set_temperature(&self, retrieved_maps: Result<RetrievedMaps>) {
+        if retrieved_maps.is_ok() || self.is_temperature_stale() {
+            let mut maps = self.write().expect("Maps handle lock was poisoned");
+            maps.temperature = retrieved_maps.ok();
+        }
+    }
+
     fn set_uvi(&self, retrieved_maps: Result<RetrievedMaps>) {
         if retrieved_maps.is_ok() || self.is_uvi_stale() {
-            let mut maps = self.lock().expect("Maps handle mutex was poisoned");
+            let mut maps = self.write().expect("Maps handle lock was poisoned");
             maps.uvi = retrieved_maps.ok();
         }
     }
 }
 
-/// A Buienradar map sample.
+/// A grid of pre-computed scores for a set of maps.
 ///
-/// This represents a value at a given time.
-#[derive(Clone, Debug, PartialEq, Serialize)]
-#[serde(crate = "rocket::serde")]
-pub(crate) struct Sample {
-    /// The time(stamp) of the forecast.
-    #[serde(serialize_with = "ts_seconds::serialize")]
-    pub(crate) time: DateTime<Utc>,
+/// Each map is divided into cells of [`MAP_GRID_CELL_SIZE`] pixels. The score of a cell is
+/// determined by the map key color that occurs most within the cells that cover the sample area
+/// (see [`MAP_SAMPLE_SIZE`]) around it. Its confidence is the fraction of all pixels in the sample
+/// area that have that color, so it is low near color boundaries and where most pixels have no
+/// known color, e.g. near the sea. This way, sampling a position only requires a lookup.
+#[derive(Debug)]
+pub(crate) struct SampleGrid {
+    /// The number of maps (time slices).
+    count: u32,
 
-    /// The forecasted score.
-    ///
-    /// A value in the range `1..=10`.
-    #[serde(rename(serialize = "value"))]
-    pub(crate) score: u8,
-}
+    /// The number of cells per row.
+    columns: u32,
 
-impl Sample {
-    #[cfg(test)]
-    pub(crate) fn new(time: DateTime<Utc>, score: u8) -> Self {
-        Self { time, score }
-    }
-}
+    /// The number of cells per column.
+    rows: u32,
 
-/// Builds a scoring histogram for the map key.
-fn map_key_histogram() -> MapKeyHistogram {
-    MAP_KEY
-        .into_iter()
-        .fold(HashMap::new(), |mut hm, channels| {
-            hm.insert(Rgb::from(channels), 0);
-            hm
-        })
+    /// The scores and their confidence per map, row and column; `None` if no known colors were
+    /// found.
+    scores: Vec<Option<(u8, f32)>>,
 }
 
-/// Samples the provided maps at the given (map-relative) coordinates and starting timestamp.
-/// It assumes the provided coordinates are within bounds of at least one map.
-/// The interval is the number of seconds the timestamp is bumped for each map.
-fn sample<I: GenericImageView<Pixel = Rgba<u8>>>(
-    image: &I,
-    stamp: DateTime<Utc>,
-    interval: i64,
-    count: u32,
-    coords: (u32, u32),
-) -> Result<Vec<Sample>> {
-    let (x, y) = coords;
-    let width = image.width() / count;
-    let height = image.height();
-    if x > width || y > height {
-        return Err(Error::OutOfBoundCoords(x, y));
-    }
-    let max_sample_width = (width - x).min(MAP_SAMPLE_SIZE[0]);
-    let max_sample_height = (height - y).min(MAP_SAMPLE_SIZE[1]);
-    let mut samples = Vec::with_capacity(count as usize);
-    let mut time = stamp;
-    let mut offset = 0;
-
-    while offset < image.width() {
-        let map = image.view(
-            x.saturating_sub(MAP_SAMPLE_SIZE[0] / 2) + offset,
-            y.saturating_sub(MAP_SAMPLE_SIZE[1] / 2),
-            max_sample_width,
-            max_sample_height,
-        );
-        let histogram = map
-            .pixels()
-            .fold(map_key_histogram(), |mut h, (_px, _py, color)| {
-                h.entry(color.to_rgb()).and_modify(|count| *count += 1);
-                h
-            });
-        let (max_color, &count) = histogram
-            .iter()
-            .max_by_key(|(_color, count)| *count)
-            .expect("Map key is never empty");
-        if count == 0 {
-            return Err(Error::NoKnownColorsInSamples);
+impl SampleGrid {
+    /// Computes the sample grid for the provided image consisting of `count` maps.
+    ///
+    /// The scores are determined using the colors of the provided map key; pixel colors within the
+    /// provided tolerance of a key color are classified as that color (see [`classify`]).
+    fn compute<const N: usize>(
+        image: &DynamicImage,
+        count: u32,
+        key: &[[u8; 3]; N],
+        tolerance: f32,
+    ) -> Self {
+        let converted_image;
+        let image = match image.as_rgba8() {
+            Some(image) => image,
+            None => {
+                converted_image = image.to_rgba8();
+                &converted_image
+            }
+        };
+        let (width, height) = (image.width() / count, image.height());
+        let columns = width.div_ceil(MAP_GRID_CELL_SIZE) as usize;
+        let rows = height.div_ceil(MAP_GRID_CELL_SIZE) as usize;
+        let cells = columns * rows;
+
+        // Count the occurrences of the map key colors per cell.
+        // Neighbouring pixels often have the same color, so remember the last classification.
+        let mut histograms = vec![[0u32; N]; count as usize * cells];
+        let mut last_classified = None;
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let index = x / width;
+            if index >= count {
+                continue;
+            }
+            let color = pixel.to_rgb();
+            let score = match last_classified {
+                Some((last_color, score)) if last_color == color => score,
+                _ => {
+                    let score = classify(color.0, key, tolerance);
+                    last_classified = Some((color, score));
+                    score
+                }
+            };
+            if let Some(score) = score {
+                let column = ((x % width) / MAP_GRID_CELL_SIZE) as usize;
+                let row = (y / MAP_GRID_CELL_SIZE) as usize;
+                histograms[index as usize * cells + row * columns + column][score] += 1;
+            }
         }
 
-        let score = MAP_KEY
-            .iter()
-            .position(|&color| &Rgb::from(color) == max_color)
-            .map(|score| score + 1) // Scores go from 1..=10, not 0..=9!
-            .expect("Maximum color is always a map key color") as u8;
+        // Score each cell using the histograms of the cells within the sample area, using a
+        // summed-area table of the histograms per map.
+        let radius_x = (MAP_SAMPLE_SIZE[0] / MAP_GRID_CELL_SIZE / 2) as usize;
+        let radius_y = (MAP_SAMPLE_SIZE[1] / MAP_GRID_CELL_SIZE / 2) as usize;
+        let stride = columns + 1;
+        let mut table = vec![[0u32; N]; stride * (rows + 1)];
+        let mut scores = Vec::with_capacity(count as usize * cells);
+        for histograms in histograms.chunks_exact(cells) {
+            for row in 0..rows {
+                let mut row_counts = [0u32; N];
+                for column in 0..columns {
+                    let above = table[row * stride + column + 1];
+                    let entry = &mut table[(row + 1) * stride + column + 1];
+                    for (score, count) in histograms[row * columns + column].iter().enumerate() {
+                        row_counts[score] += count;
+                        entry[score] = above[score] + row_counts[score];
+                    }
+                }
+            }
+
+            for row in 0..rows {
+                let (row0, row1) = (row.saturating_sub(radius_y), (row + radius_y + 1).min(rows));
+                for column in 0..columns {
+                    let column0 = column.saturating_sub(radius_x);
+                    let column1 = (column + radius_x + 1).min(columns);
+                    let counts: [u32; N] = std::array::from_fn(|score| {
+                        table[row1 * stride + column1][score]
+                            + table[row0 * stride + column0][score]
+                            - table[row0 * stride + column1][score]
+                            - table[row1 * stride + column0][score]
+                    });
+                    let (score, max_count) =
+                        counts.into_iter().enumerate().fold((0, 0), |max, entry| {
+                            if entry.1 > max.1 {
+                                entry
+                            } else {
+                                max
+                            }
+                        });
+
+                    // The sample area is clipped to the map, and includes pixels without a
+                    // known color (e.g. the background).
+                    let pixels = |cell0: usize, cell1: usize, size: u32| {
+                        (cell1 as u32 * MAP_GRID_CELL_SIZE).min(size)
+                            - cell0 as u32 * MAP_GRID_CELL_SIZE
+                    };
+                    let area = pixels(column0, column1, width) * pixels(row0, row1, height);
+                    let confidence = max_count as f32 / area as f32;
+
+                    // Scores go from 1..=10, not 0..=9!
+                    scores.push((max_count > 0).then_some((score as u8 + 1, confidence)));
+                }
+            }
+        }
 
-        samples.push(Sample { time, score });
-        time += Duration::seconds(interval);
-        offset += width;
+        Self {
+            count,
+            columns: columns as u32,
+            rows: rows as u32,
+            scores,
+        }
     }
 
-    Ok(samples)
+    /// Looks up the samples at the given (map-relative) coordinates and starting timestamp.
+    ///
+    /// The interval is the number of seconds the timestamp is bumped for each map.
+    fn samples(
+        &self,
+        stamp: DateTime<Utc>,
+        interval: i64,
+        coords: (u32, u32),
+    ) -> Result<Vec<Sample>> {
+        let (x, y) = coords;
+        let column = x / MAP_GRID_CELL_SIZE;
+        let row = y / MAP_GRID_CELL_SIZE;
+        if column >= self.columns || row >= self.rows {
+            return Err(Error::OutOfBoundCoords(x, y));
+        }
+
+        (0..self.count)
+            .map(|index| {
+                let cell = (index * self.rows + row) * self.columns + column;
+                let (score, confidence) =
+                    self.scores[cell as usize].ok_or(Error::NoKnownColorsInSamples)?;
+                let time = stamp + Duration::seconds(index as i64 * interval);
+
+                Ok(Sample {
+                    time,
+                    score: f32::from(score),
+                    confidence,
+                    label: None,
+                    exceeds: None,
+                })
+            })
+            .collect()
+    }
 }
 
-/// A retrieved image with some metadata.
-#[derive(Debug)]
-pub(crate) struct RetrievedMaps {
-    /// The image data.
-    pub(crate) image: DynamicImage,
+/// The projection of a position on a map.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Projection {
+    /// The x-coordinate (in pixels) of the position on the map.
+    x: i64,
 
-    /// The date/time the image was last modified.
-    pub(crate) mtime: DateTime<Utc>,
+    /// The y-coordinate (in pixels) of the position on the map.
+    y: i64,
 
-    /// The starting date/time the image corresponds with.
-    pub(crate) timestamp_base: DateTime<Utc>,
+    /// The width (in pixels) of the map.
+    width: u32,
+
+    /// The height (in pixels) of the map.
+    height: u32,
+
+    /// Whether the coordinates lie within the map.
+    in_bounds: bool,
 }
 
-impl RetrievedMaps {
-    #[cfg(test)]
-    pub(crate) fn new(image: DynamicImage) -> Self {
-        let mtime = Utc::now();
-        let timestamp_base = Utc::now();
+/// The validity of a (marked) map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Validity {
+    /// The date/time the map was last modified.
+    pub(crate) last_modified: DateTime<Utc>,
 
-        Self {
-            image,
-            mtime,
-            timestamp_base,
-        }
-    }
+    /// The date/time the map expires, i.e. is replaced by the next or a refreshed map.
+    pub(crate) expires_at: DateTime<Utc>,
 }
 
-/// Retrieves an image from the provided URL.
-async fn retrieve_image(url: Url) -> Result<RetrievedMaps> {
-    let response = reqwest::get(url).await?;
-    let mtime = match response.headers().get(reqwest::header::LAST_MODIFIED) {
-        Some(mtime_header) => {
-            let mtime_headr_str = mtime_header.to_str()?;
+/// The image format of a (single) map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, rocket::FromFormField)]
+pub(crate) enum MapFormat {
+    /// The PNG image format.
+    #[default]
+    Png,
 
-            DateTime::from(DateTime::parse_from_rfc2822(mtime_headr_str)?)
-        }
-        None => Utc::now(),
-    };
+    /// The JPEG image format; it does not support transparency.
+    Jpeg,
 
-    let timestamp_base = {
-        let path = response.url().path();
-        let (_, filename) = path
-            .rsplit_once('/')
-            .ok_or_else(|| Error::InvalidImagePath(path.to_owned()))?;
-        let (timestamp_str, _) = filename
-            .split_once("__")
-            .ok_or_else(|| Error::InvalidImagePath(path.to_owned()))?;
-        let timestamp = NaiveDateTime::parse_from_str(timestamp_str, "%Y%m%d%H%M")?;
+    /// The (lossless) WebP image format.
+    Webp,
+}
 
-        Utc.from_utc_datetime(&timestamp)
-    };
-    let bytes = response.bytes().await?;
+impl MapFormat {
+    /// Negotiates the format given the media types the client accepts per the `Accept` header.
+    ///
+    /// The supported media type with the highest quality value is selected; media types with a
+    /// quality value of zero are not acceptable. Returns [`None`] if no supported media type is
+    /// accepted explicitly (wildcards are not considered).
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn negotiate(accept: &Accept) -> Option<Self> {
+        let mut formats = accept
+            .iter()
+            .filter_map(|media_type| {
+                let format = [MapFormat::Png, MapFormat::Jpeg, MapFormat::Webp]
+                    .into_iter()
+                    .find(|format| media_type.media_type() == &format.media_type())?;
+                let quality = media_type.weight_or(1.0);
 
-    tokio::task::spawn_blocking(move || {
-        image::load_from_memory_with_format(&bytes, ImageFormat::Png)
-            .map(|image| RetrievedMaps {
-                image,
-                mtime,
-                timestamp_base,
+                (quality > 0.0).then_some((format, quality))
             })
-            .map_err(Error::from)
-    })
-    .await?
-}
+            .collect::<Vec<_>>();
+        // Sort stably so that the first format listed wins if the quality values are equal.
+        formats.sort_by(|(_, q1), (_, q2)| q2.total_cmp(q1));
 
-/// Retrieves the pollen maps from Buienradar.
-///
-/// See [`POLLEN_BASE_URL`] for the base URL and [`retrieve_image`] for the retrieval function.
-async fn retrieve_pollen_maps() -> Result<RetrievedMaps> {
-    let timestamp = format!("{}", chrono::Local::now().format("%y%m%d%H%M"));
-    let mut url = Url::parse(POLLEN_BASE_URL).unwrap();
-    url.query_pairs_mut().append_pair("timestamp", &timestamp);
+        formats.first().map(|(format, _)| *format)
+    }
 
-    println!("🗺️  Refreshing pollen maps from: {}", url);
-    retrieve_image(url).await
-}
+    /// Returns the media type of the format.
+    pub(crate) fn media_type(self) -> MediaType {
+        match self {
+            MapFormat::Png => MediaType::PNG,
+            MapFormat::Jpeg => MediaType::JPEG,
+            MapFormat::Webp => MediaType::WEBP,
+        }
+    }
 
-/// Retrieves the UV index maps from Buienradar.
-///
-/// See [`UVI_BASE_URL`] for the base URL and [`retrieve_image`] for the retrieval function.
-async fn retrieve_uvi_maps() -> Result<RetrievedMaps> {
-    let timestamp = format!("{}", chrono::Local::now().format("%y%m%d%H%M"));
-    let mut url = Url::parse(UVI_BASE_URL).unwrap();
-    url.query_pairs_mut().append_pair("timestamp", &timestamp);
+    /// Encodes the image in the format.
+    pub(crate) fn encode(self, image: DynamicImage) -> Result<Vec<u8>> {
+        use std::io::Cursor;
 
-    println!("🗺️  Refreshing UV index maps from: {}", url);
-    retrieve_image(url).await
-}
+        let (image, image_format) = match self {
+            MapFormat::Png => (image, ImageFormat::Png),
+            // JPEG does not support an alpha channel.
+            MapFormat::Jpeg => (DynamicImage::ImageRgb8(image.to_rgb8()), ImageFormat::Jpeg),
+            MapFormat::Webp => (image, ImageFormat::WebP),
+        };
+        let mut image_data = Cursor::new(Vec::new());
+        image.write_to(&mut image_data, image_format)?;
 
-/// Returns the map for the given instant.
-fn map_at(
-    image: &DynamicImage,
-    stamp: DateTime<Utc>,
-    interval: i64,
-    count: u32,
-    instant: DateTime<Utc>,
-) -> Result<DynamicImage> {
-    let duration = instant.signed_duration_since(stamp);
-    let offset = (duration.num_seconds() / interval) as u32;
-    // Check if out of bounds.
-    if offset >= count {
-        return Err(Error::OutOfBoundOffset(offset));
+        Ok(image_data.into_inner())
     }
-    let width = image.width() / count;
-
-    Ok(image.crop_imm(offset * width, 0, width, image.height()))
 }
 
-/// Marks the provided coordinates on the map using a horizontal and vertical line.
-fn mark(mut image: DynamicImage, coords: (u32, u32)) -> DynamicImage {
-    let (x, y) = coords;
+/// The style of the marker of the position on a map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, rocket::FromFormField)]
+pub(crate) enum MarkerStyle {
+    /// A crosshair centered on the position.
+    #[default]
+    Crosshair,
 
-    for py in 0..image.height() {
-        image.put_pixel(x, py, Rgba::from([0x00, 0x00, 0x00, 0x70]));
+    /// A circle around the position.
+    Circle,
+
+    /// A pin with its point on the position.
+    Pin,
+}
+
+/// The legend of a map key.
+#[derive(Clone, Copy, Debug)]
+struct Legend {
+    /// The colors of the map key, from the lowest to the highest value.
+    colors: &'static [[u8; 3]],
+
+    /// The labels of the colors.
+    labels: &'static [&'static str],
+}
+
+/// The marker of the position on a map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Marker {
+    /// The style of the marker.
+    style: MarkerStyle,
+
+    /// The color of the marker.
+    color: Rgba<u8>,
+
+    /// The size of the marker (in pixels).
+    ///
+    /// This is the length of the arms of the crosshair, the radius of the circle or the height of
+    /// the pin.
+    size: u32,
+}
+
+/// The options for rendering a map.
+#[derive(Clone, Debug, Default, PartialEq, rocket::FromForm)]
+pub(crate) struct MapOptions {
+    /// The zoom factor; crops the map to the given fraction of its size around the position.
+    pub(crate) zoom: Option<f64>,
+
+    /// The radius (in kilometers) of the area around the position the map is cropped to.
+    pub(crate) radius_km: Option<f64>,
+
+    /// The time (in seconds since the UNIX epoch) to select the map for; defaults to now.
+    pub(crate) time: Option<i64>,
+
+    /// The number of maps after the current one to select.
+    pub(crate) offset: Option<u32>,
+
+    /// Whether to overlay the legend of the map key.
+    pub(crate) legend: bool,
+
+    /// The style of the marker of the position; defaults to a crosshair.
+    pub(crate) marker: Option<MarkerStyle>,
+
+    /// The color of the marker as hexadecimal RGB(A) value, e.g. `ff0000` or `#ff000080`.
+    pub(crate) marker_color: Option<String>,
+
+    /// The size of the marker (in pixels).
+    pub(crate) marker_size: Option<u32>,
+}
+
+impl MapOptions {
+    /// Validates the options.
+    fn validate(&self) -> Result<()> {
+        self.marker()?;
+        if self.time.is_some() && self.offset.is_some() {
+            return Err(Error::InvalidOptions(String::from(
+                "only one of time and offset can be provided",
+            )));
+        }
+
+        match (self.zoom, self.radius_km) {
+            (Some(_), Some(_)) => Err(Error::InvalidOptions(String::from(
+                "only one of zoom and radius_km can be provided",
+            ))),
+            (Some(zoom), None) if !(zoom.is_finite() && zoom >= 1.0) => Err(Error::InvalidOptions(
+                String::from("zoom must be at least 1"),
+            )),
+            (None, Some(radius_km)) if !(radius_km.is_finite() && radius_km > 0.0) => Err(
+                Error::InvalidOptions(String::from("radius_km must be positive")),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the marker of the position using the marker style, color and size options.
+    fn marker(&self) -> Result<Marker> {
+        let color = match &self.marker_color {
+            Some(color) => parse_color(color)
+                .ok_or_else(|| Error::InvalidOptions(format!("invalid marker color: {color}")))?,
+            None => Rgba::from(DEFAULT_MARKER_COLOR),
+        };
+        let size = self.marker_size.unwrap_or(DEFAULT_MARKER_SIZE);
+        if !(1..=MAX_MARKER_SIZE).contains(&size) {
+            return Err(Error::InvalidOptions(format!(
+                "marker_size must be between 1 and {MAX_MARKER_SIZE}"
+            )));
+        }
+
+        Ok(Marker {
+            style: self.marker.unwrap_or_default(),
+            color,
+            size,
+        })
+    }
+
+    /// Returns the instant to select the map for given the interval (in seconds) and the number of
+    /// the maps.
+    ///
+    /// This is the provided time, the time of the map the provided offset after the current one, or
+    /// otherwise the current time. The offset has to be less than the number of maps.
+    fn instant(&self, interval: i64, count: u32, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        match (self.time, self.offset) {
+            (Some(time), _) => DateTime::from_timestamp(time, 0)
+                .ok_or_else(|| Error::InvalidOptions(format!("invalid time: {time}"))),
+            (None, Some(offset)) if offset >= count => Err(Error::InvalidOptions(format!(
+                "offset must be less than {count}"
+            ))),
+            (None, Some(offset)) => now
+                .checked_add_signed(Duration::seconds(i64::from(offset) * interval))
+                .ok_or_else(|| Error::InvalidOptions(format!("invalid offset: {offset}"))),
+            (None, None) => Ok(now),
+        }
+    }
+
+    /// Returns the size (width and height in pixels) of the map cropped to the zoom or radius.
+    ///
+    /// Returns [`None`] if the map should not be cropped.
+    fn crop_size(
+        &self,
+        (width, height): (u32, u32),
+        ref_points: [(Position, (u32, u32)); 2],
+        position: Position,
+    ) -> Option<(u32, u32)> {
+        let size = if let Some(zoom) = self.zoom {
+            (width as f64 / zoom, height as f64 / zoom)
+        } else {
+            let radius_km = self.radius_km?;
+            let radius = radius_km * pixels_per_km(ref_points, position);
+            (2.0 * radius, 2.0 * radius)
+        };
+        let clamp = |size: f64, max: u32| (size.round() as u32).clamp(1, max);
+
+        Some((clamp(size.0, width), clamp(size.1, height)))
+    }
+
+    /// Crops the map around the provided coordinates according to the zoom or radius (if any).
+    ///
+    /// The cropped area is centered on the coordinates, but shifted where necessary to stay within
+    /// the map.
+    fn crop(
+        &self,
+        image: DynamicImage,
+        coords: (u32, u32),
+        ref_points: [(Position, (u32, u32)); 2],
+        position: Position,
+    ) -> DynamicImage {
+        let (width, height) = image.dimensions();
+        let Some((crop_width, crop_height)) = self.crop_size((width, height), ref_points, position)
+        else {
+            return image;
+        };
+        let (x, y) = coords;
+        let left = x.saturating_sub(crop_width / 2).min(width - crop_width);
+        let top = y.saturating_sub(crop_height / 2).min(height - crop_height);
+
+        image.crop_imm(left, top, crop_width, crop_height)
+    }
+}
+
+/// A retrieved image with some metadata.
+#[derive(Debug)]
+pub(crate) struct RetrievedMaps {
+    /// The image data.
+    pub(crate) image: DynamicImage,
+
+    /// The date/time the image was last modified.
+    pub(crate) mtime: DateTime<Utc>,
+
+    /// The starting date/time the image corresponds with.
+    pub(crate) timestamp_base: DateTime<Utc>,
+
+    /// The pre-computed sample grid of the image.
+    pub(crate) grid: SampleGrid,
+
+    /// The validators of the image, used to only retrieve it again if it has changed.
+    validators: Validators,
+}
+
+impl RetrievedMaps {
+    /// Returns the validity of the map for the given instant.
+    ///
+    /// The map was last modified at the latest of the date/time the image was last modified and
+    /// the date/time the map for the given instant starts. It expires at the earliest of the
+    /// date/time the next map starts and the date/time the image is due for a refresh.
+    /// The interval is the number of seconds each map is for and the refresh interval the number
+    /// of seconds after which the image is refreshed.
+    fn validity(&self, interval: i64, refresh_interval: u64, instant: DateTime<Utc>) -> Validity {
+        let duration = instant.signed_duration_since(self.timestamp_base);
+        let offset = duration.num_seconds().div_euclid(interval);
+        let start = self.timestamp_base + Duration::seconds(offset * interval);
+        let end = start + Duration::seconds(interval);
+        let refresh_at = self.mtime + Duration::seconds(refresh_interval as i64);
+
+        Validity {
+            last_modified: start.max(self.mtime),
+            expires_at: end.min(refresh_at),
+        }
+    }
+
+    /// Returns the validity of the frames of all maps.
+    ///
+    /// The frames were last modified when the image was last modified and expire when the image
+    /// is due for a refresh.
+    fn frames_validity(&self, refresh_interval: u64) -> Validity {
+        Validity {
+            last_modified: self.mtime,
+            expires_at: self.mtime + Duration::seconds(refresh_interval as i64),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new(image: DynamicImage, map_count: u32) -> Self {
+        let mtime = Utc::now();
+        let timestamp_base = Utc::now();
+        let tolerance = MapsConfig::default().color_tolerance;
+        let grid = SampleGrid::compute(&image, map_count, &MAP_KEY, tolerance);
+
+        Self {
+            image,
+            mtime,
+            timestamp_base,
+            grid,
+            validators: Validators::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new_temperature(image: DynamicImage, map_count: u32) -> Self {
+        let tolerance = MapsConfig::default().color_tolerance;
+        let grid = SampleGrid::compute(&image, map_count, &TEMPERATURE_MAP_KEY, tolerance);
+
+        Self {
+            grid,
+            ..Self::new(image, map_count)
+        }
+    }
+}
+
+/// The validators of a retrieved image.
+///
+/// These are the `ETag` and `Last-Modified` header values of the response, which are sent back when
+/// retrieving the image again so that the server can reply that it has not been modified instead.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Validators {
+    /// The entity tag of the image (if provided).
+    etag: Option<HeaderValue>,
+
+    /// The date/time the image was last modified, as provided (if at all).
+    last_modified: Option<HeaderValue>,
+}
+
+impl Validators {
+    /// Extracts the validators from the headers of a response.
+    fn from_response(headers: &HeaderMap) -> Self {
+        Self {
+            etag: headers.get(ETAG).cloned(),
+            last_modified: headers.get(LAST_MODIFIED).cloned(),
+        }
+    }
+
+    /// Returns the headers of a conditional request using the validators.
+    fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = &self.etag {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+
+        headers
     }
-    for px in 0..image.width() {
-        image.put_pixel(px, y, Rgba::from([0x00, 0x00, 0x00, 0x70]));
+}
+
+/// Retrieves an image consisting of the provided number of maps from the provided URL.
+///
+/// The image is retrieved conditionally using the validators of the current image (if any). If
+/// the server replies that the image has not been modified, [`None`] is returned and the image is
+/// neither downloaded nor decoded again.
+///
+/// After retrieval, the image is validated against the provided map key and its sample grid is
+/// computed (see [`SampleGrid`]). Colors within the provided tolerance of a key color are
+/// classified as that color.
+async fn retrieve_image<const N: usize>(
+    url: Url,
+    map_count: u32,
+    key: &'static [[u8; 3]; N],
+    config: &MapsConfig,
+    validators: Validators,
+) -> Result<Option<RetrievedMaps>> {
+    let mut response = http::client()
+        .get_with_headers(url, validators.conditional_headers())
+        .await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        info!(url = %response.url(), "🗺️  Maps have not been modified");
+        return Ok(None);
+    }
+
+    match http::media_type(response.headers()) {
+        Some(media_type) if media_type != "image/png" => {
+            return Err(Error::UnexpectedContentType(
+                response.url().to_string(),
+                media_type,
+            ));
+        }
+        _ => {}
+    }
+
+    let validators = Validators::from_response(response.headers());
+    let mtime = match response.headers().get(LAST_MODIFIED) {
+        Some(mtime_header) => {
+            let mtime_headr_str = mtime_header.to_str()?;
+
+            DateTime::from(DateTime::parse_from_rfc2822(mtime_headr_str)?)
+        }
+        None => Utc::now(),
+    };
+
+    let timestamp_base = {
+        let path = response.url().path();
+        let (_, filename) = path
+            .rsplit_once('/')
+            .ok_or_else(|| Error::InvalidImagePath(path.to_owned()))?;
+        let (timestamp_str, _) = filename
+            .split_once("__")
+            .ok_or_else(|| Error::InvalidImagePath(path.to_owned()))?;
+        let timestamp = NaiveDateTime::parse_from_str(timestamp_str, "%Y%m%d%H%M")?;
+
+        Utc.from_utc_datetime(&timestamp)
+    };
+    let bytes = read_body(&mut response, config.max_image_size).await?;
+
+    let color_tolerance = config.color_tolerance;
+    tokio::task::spawn_blocking(move || {
+        let image = decode_image(&bytes, map_count)?;
+        let map_count = count_maps(&image, map_count)?;
+        validate_map_key(&image, key, color_tolerance)?;
+        let grid = SampleGrid::compute(&image, map_count, key, color_tolerance);
+
+        Ok(Some(RetrievedMaps {
+            image,
+            mtime,
+            timestamp_base,
+            grid,
+            validators,
+        }))
+    })
+    .await?
+}
+
+/// Reads the body of the response chunk by chunk, up until the provided maximum size (in bytes).
+///
+/// If the response announces or turns out to have a larger body, reading is stopped and an error
+/// is returned, so that a misbehaving upstream cannot exhaust the memory.
+async fn read_body(response: &mut http::Response, max_size: u64) -> Result<Vec<u8>> {
+    let content_length = response.content_length().unwrap_or_default();
+    if content_length > max_size {
+        return Err(Error::ImageTooLarge(max_size));
+    }
+
+    let mut bytes = Vec::with_capacity(content_length as usize);
+    while let Some(chunk) = response.chunk().await? {
+        if (bytes.len() + chunk.len()) as u64 > max_size {
+            return Err(Error::ImageTooLarge(max_size));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+/// Decodes the provided PNG image that should consist of the provided number of maps.
+///
+/// The dimensions are checked as soon as the header is decoded, before any pixel data is
+/// allocated: the image should be [`MAP_HEIGHT`] pixels high and at most twice as wide as the
+/// expected number of maps.
+fn decode_image(bytes: &[u8], map_count: u32) -> Result<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAP_WIDTH * map_count.max(1) * 2);
+    limits.max_image_height = Some(MAP_HEIGHT);
+    let mut reader = ImageReader::with_format(std::io::Cursor::new(bytes), ImageFormat::Png);
+    reader.limits(limits);
+
+    Ok(reader.decode()?)
+}
+
+/// Returns the number of maps that the provided image consists of.
+///
+/// The image should consist of the expected number of maps of [`MAP_WIDTH`]×[`MAP_HEIGHT`] pixels
+/// side by side. If it consists of another number of maps, a warning is logged and the actual
+/// number of maps is used. If it does not consist of whole maps, an error is returned.
+fn count_maps(image: &DynamicImage, expected_count: u32) -> Result<u32> {
+    let (width, height) = image.dimensions();
+    if width == 0 || width % MAP_WIDTH != 0 || height != MAP_HEIGHT {
+        return Err(Error::InvalidMapDimensions(width, height));
+    }
+
+    let count = width / MAP_WIDTH;
+    if count != expected_count {
+        warn!(
+            count,
+            expected_count, "🗺️  Retrieved an unexpected number of maps"
+        );
+    }
+
+    Ok(count)
+}
+
+/// Classifies the provided color as the index of the nearest color of the provided map key.
+///
+/// The color is only classified if its (Euclidean) distance in RGB space to the nearest key color
+/// is at most the provided tolerance; a tolerance of 0 only classifies exact matches.
+fn classify<const N: usize>(color: [u8; 3], key: &[[u8; 3]; N], tolerance: f32) -> Option<usize> {
+    let distance_squared = |key_color: &[u8; 3]| {
+        color
+            .iter()
+            .zip(key_color)
+            .map(|(&c, &k)| (i32::from(c) - i32::from(k)).pow(2))
+            .sum::<i32>()
+    };
+    let (index, distance_squared) = key
+        .iter()
+        .map(distance_squared)
+        .enumerate()
+        .min_by_key(|&(_, distance_squared)| distance_squared)?;
+
+    (distance_squared as f32 <= tolerance * tolerance).then_some(index)
+}
+
+/// Validates the provided map key against the colors of the provided image.
+///
+/// The maps are retrieved without a legend, so the key colors cannot be read from the image.
+/// Instead, the fraction of the colored (i.e. not fully transparent) pixels that have a map key
+/// color (within the provided tolerance, see [`classify`]) is determined. If it is less than
+/// [`MIN_MAP_KEY_COVERAGE`], the palette of the maps has likely changed and an error is returned
+/// that reports the most common unknown colors.
+///
+/// An empty map key (for maps that are not sampled) or an image without colored pixels always
+/// passes.
+fn validate_map_key<const N: usize>(
+    image: &DynamicImage,
+    key: &[[u8; 3]; N],
+    tolerance: f32,
+) -> Result<()> {
+    if N == 0 {
+        return Ok(());
+    }
+
+    let converted_image;
+    let image = match image.as_rgba8() {
+        Some(image) => image,
+        None => {
+            converted_image = image.to_rgba8();
+            &converted_image
+        }
+    };
+    // Neighbouring pixels often have the same color, so remember the last classification.
+    let mut colored = 0u64;
+    let mut unknown_colors = HashMap::<[u8; 3], u64>::new();
+    let mut last_classified = None;
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a == 0 {
+            continue;
+        }
+        colored += 1;
+        let color = [r, g, b];
+        let known = match last_classified {
+            Some((last_color, known)) if last_color == color => known,
+            _ => {
+                let known = classify(color, key, tolerance).is_some();
+                last_classified = Some((color, known));
+                known
+            }
+        };
+        if !known {
+            *unknown_colors.entry(color).or_default() += 1;
+        }
+    }
+    if colored == 0 {
+        return Ok(());
+    }
+
+    let unknown = unknown_colors.values().sum::<u64>();
+    let coverage = (colored - unknown) as f32 / colored as f32;
+    if coverage >= MIN_MAP_KEY_COVERAGE {
+        return Ok(());
+    }
+
+    let mut unknown_colors = unknown_colors.into_iter().collect::<Vec<_>>();
+    unknown_colors
+        .sort_by(|(color1, count1), (color2, count2)| count2.cmp(count1).then(color1.cmp(color2)));
+    let colors = unknown_colors
+        .into_iter()
+        .take(MAX_REPORTED_UNKNOWN_COLORS)
+        .map(|([r, g, b], _)| format!("#{r:02X}{g:02X}{b:02X}"))
+        .collect();
+
+    Err(Error::MapKeyMismatch(coverage, colors))
+}
+
+/// Retrieves the provided number of pollen maps from Buienradar.
+///
+/// The configured base URL is used, which defaults to [`buienradar::POLLEN_BASE_URL`]. See
+/// [`retrieve_image`] for the retrieval function.
+async fn retrieve_pollen_maps(
+    map_count: u32,
+    config: &MapsConfig,
+    validators: Validators,
+) -> Result<Option<RetrievedMaps>> {
+    let timestamp = format!("{}", chrono::Local::now().format("%y%m%d%H%M"));
+    let mut url = providers::base_urls().pollen_maps.clone();
+    url.query_pairs_mut()
+        .append_pair("forecast", &map_count.to_string())
+        .append_pair("timestamp", &timestamp);
+
+    info!(maps = "pollen", %url, "🗺️  Refreshing maps");
+    retrieve_image(url, map_count, &MAP_KEY, config, validators).await
+}
+
+/// Retrieves the provided number of precipitation radar maps from Buienradar.
+///
+/// The configured base URL is used, which defaults to [`buienradar::PRECIPITATION_BASE_URL`]. See
+/// [`retrieve_image`] for the retrieval function.
+async fn retrieve_precipitation_maps(
+    map_count: u32,
+    config: &MapsConfig,
+    validators: Validators,
+) -> Result<Option<RetrievedMaps>> {
+    let timestamp = format!("{}", chrono::Local::now().format("%y%m%d%H%M"));
+    let mut url = providers::base_urls().precipitation_maps.clone();
+    url.query_pairs_mut()
+        .append_pair("forecast", &map_count.to_string())
+        .append_pair("timestamp", &timestamp);
+
+    info!(maps = "precipitation", %url, "🗺️  Refreshing maps");
+    // The precipitation radar maps are not sampled, so no map key is used.
+    retrieve_image(url, map_count, &[], config, validators).await
+}
+
+/// Retrieves the provided number of temperature maps from Buienradar.
+///
+/// The configured base URL is used, which defaults to [`buienradar::TEMPERATURE_BASE_URL`]. See
+/// [`retrieve_image`] for the retrieval function.
+async fn retrieve_temperature_maps(
+    map_count: u32,
+    config: &MapsConfig,
+    validators: Validators,
+) -> Result<Option<RetrievedMaps>> {
+    let timestamp = format!("{}", chrono::Local::now().format("%y%m%d%H%M"));
+    let mut url = providers::base_urls().temperature_maps.clone();
+    url.query_pairs_mut()
+        .append_pair("forecast", &map_count.to_string())
+        .append_pair("timestamp", &timestamp);
+
+    info!(maps = "temperature", %url, "🗺️  Refreshing maps");
+    retrieve_image(url, map_count, &TEMPERATURE_MAP_KEY, config, validators).await
+}
+
+/// Retrieves the provided number of UV index maps from Buienradar.
+///
+/// The configured base URL is used, which defaults to [`buienradar::UVI_BASE_URL`]. See
+/// [`retrieve_image`] for the retrieval function.
+async fn retrieve_uvi_maps(
+    map_count: u32,
+    config: &MapsConfig,
+    validators: Validators,
+) -> Result<Option<RetrievedMaps>> {
+    let timestamp = format!("{}", chrono::Local::now().format("%y%m%d%H%M"));
+    let mut url = providers::base_urls().uvi_maps.clone();
+    url.query_pairs_mut()
+        .append_pair("forecast", &map_count.to_string())
+        .append_pair("timestamp", &timestamp);
+
+    info!(maps = "UVI", %url, "🗺️  Refreshing maps");
+    retrieve_image(url, map_count, &MAP_KEY, config, validators).await
+}
+
+/// Returns the map for the given instant.
+fn map_at(
+    image: &DynamicImage,
+    stamp: DateTime<Utc>,
+    interval: i64,
+    count: u32,
+    instant: DateTime<Utc>,
+) -> Result<DynamicImage> {
+    let duration = instant.signed_duration_since(stamp);
+    let offset = duration.num_seconds().div_euclid(interval);
+    // Check if out of bounds.
+    if !(0..i64::from(count)).contains(&offset) {
+        return Err(Error::OutOfBoundOffset(offset));
+    }
+    let width = image.width() / count;
+
+    Ok(image.crop_imm(offset as u32 * width, 0, width, image.height()))
+}
+
+/// Marks the provided coordinates on the map using the provided marker.
+///
+/// The part of the marker that lies outside of the map is clipped.
+fn mark(mut image: DynamicImage, coords: (u32, u32), marker: Marker) -> DynamicImage {
+    let (x, y) = (i64::from(coords.0), i64::from(coords.1));
+    let size = i64::from(marker.size);
+    let (width, height) = (i64::from(image.width()), i64::from(image.height()));
+    let mut put_pixel = |px: i64, py: i64| {
+        if (0..width).contains(&px) && (0..height).contains(&py) {
+            image.put_pixel(px as u32, py as u32, marker.color);
+        }
+    };
+    let half = MARKER_THICKNESS / 2;
+
+    match marker.style {
+        MarkerStyle::Crosshair => {
+            for d in -size..=size {
+                for t in -half..MARKER_THICKNESS - half {
+                    put_pixel(x + d, y + t);
+                    put_pixel(x + t, y + d);
+                }
+            }
+        }
+        MarkerStyle::Circle => {
+            let inner = (size - MARKER_THICKNESS).max(0);
+            for dy in -size..=size {
+                for dx in -size..=size {
+                    let distance_sq = dx * dx + dy * dy;
+                    if distance_sq <= size * size && distance_sq > inner * inner {
+                        put_pixel(x + dx, y + dy);
+                    }
+                }
+            }
+        }
+        MarkerStyle::Pin => {
+            // A round head on top of a needle with its point on the position.
+            let radius = (size / 3).max(1);
+            let head_y = y - size + radius;
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    if dx * dx + dy * dy <= radius * radius {
+                        put_pixel(x + dx, head_y + dy);
+                    }
+                }
+            }
+            for py in head_y..=y {
+                for t in -half..MARKER_THICKNESS - half {
+                    put_pixel(x + t, py);
+                }
+            }
+        }
     }
 
     image
 }
 
+/// Parses a color from a hexadecimal RGB or RGBA value, optionally prefixed by `#`.
+///
+/// Returns [`None`] if the value is not a valid color.
+fn parse_color(value: &str) -> Option<Rgba<u8>> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut color = [0xff; 4];
+    for (index, component) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *component = u8::from_str_radix(&hex[2 * index..2 * index + 2], 16).ok()?;
+    }
+
+    Some(Rgba::from(color))
+}
+
+/// Returns the frames of all maps with the provided position marked and the time of each map
+/// overlaid.
+///
+/// The frames are rendered using the provided options and the provided legend is overlaid (if
+/// any).
+fn frames(
+    maps: &RetrievedMaps,
+    interval: i64,
+    count: u32,
+    ref_points: [(Position, (u32, u32)); 2],
+    position: Position,
+    options: &MapOptions,
+    legend: Option<&Legend>,
+) -> Result<Vec<DynamicImage>> {
+    let image = &maps.image;
+    let width = image.width() / count;
+    let map = image.view(0, 0, width, image.height());
+    let coords = project(&*map, ref_points, position)?;
+    let marker = options.marker()?;
+
+    Ok((0..count)
+        .map(|offset| {
+            let time = maps.timestamp_base + Duration::seconds(i64::from(offset) * interval);
+            let frame = mark(
+                image.crop_imm(offset * width, 0, width, image.height()),
+                coords,
+                marker,
+            );
+            let mut frame = options.crop(frame, coords, ref_points, position);
+            let text = time.format("%Y-%m-%d %H:%M UTC").to_string();
+            draw_text(&mut frame, (0, 0), &text);
+            if let Some(legend) = legend {
+                draw_legend(&mut frame, legend);
+            }
+
+            frame
+        })
+        .collect())
+}
+
+/// Returns the glyph of the provided character in a font that is 3 pixels wide and 5 high.
+///
+/// Each row is a bit pattern where the most significant of the 3 bits is the leftmost pixel.
+/// Only digits, uppercase letters and the punctuation needed for timestamps and values are
+/// supported; others are rendered as a space.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => [0b000; 5],
+    }
+}
+
+/// Fills the provided rectangle (origin and size in pixels) of the image with a color.
+///
+/// The part of the rectangle that lies outside of the image is skipped.
+pub(crate) fn fill_rect(
+    image: &mut DynamicImage,
+    (x, y): (u32, u32),
+    (w, h): (u32, u32),
+    color: Rgba<u8>,
+) {
+    let (width, height) = image.dimensions();
+    for py in y..(y + h).min(height) {
+        for px in x..(x + w).min(width) {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// Draws the provided text in black on a white background with its top-left corner at the
+/// provided origin.
+fn draw_text(image: &mut DynamicImage, (x, y): (u32, u32), text: &str) {
+    let box_width = text.chars().count() as u32 * 4 * FONT_SCALE + FONT_SCALE;
+    let white = Rgba::from([0xff, 0xff, 0xff, 0xff]);
+    fill_rect(image, (x, y), (box_width, 7 * FONT_SCALE), white);
+
+    let black = Rgba::from([0x00, 0x00, 0x00, 0xff]);
+    draw_glyphs(
+        image,
+        (x + FONT_SCALE, y + FONT_SCALE),
+        text,
+        black,
+        FONT_SCALE,
+    );
+}
+
+/// Draws the glyphs of the provided text in a color and scale with the top-left corner of the
+/// first glyph at the provided origin.
+///
+/// Each glyph is 3 by 5 pixels at scale 1, followed by a pixel of spacing, see [`glyph`].
+pub(crate) fn draw_glyphs(
+    image: &mut DynamicImage,
+    (x, y): (u32, u32),
+    text: &str,
+    color: Rgba<u8>,
+    scale: u32,
+) {
+    for (index, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                let px = x + index as u32 * 4 * scale + col * scale;
+                let py = y + row as u32 * scale;
+                fill_rect(image, (px, py), (scale, scale), color);
+            }
+        }
+    }
+}
+
+/// Draws the provided legend of a map key in the bottom-left corner of the image.
+///
+/// The legend lists each color with its label, the highest value at the top.
+fn draw_legend(image: &mut DynamicImage, legend: &Legend) {
+    let row_height = 7 * FONT_SCALE;
+    let top = image
+        .height()
+        .saturating_sub(legend.colors.len() as u32 * row_height);
+    let label_width = legend.labels.iter().map(|label| label.len()).max();
+    let label_width = label_width.unwrap_or_default();
+
+    let entries = legend.colors.iter().zip(legend.labels);
+    for (index, (color, label)) in entries.rev().enumerate() {
+        let y = top + index as u32 * row_height;
+        let [r, g, b] = *color;
+        fill_rect(
+            image,
+            (0, y),
+            (row_height, row_height),
+            Rgba::from([r, g, b, 0xff]),
+        );
+        draw_text(image, (row_height, y), &format!("{label:>label_width$}"));
+    }
+}
+
+/// Encodes the provided frames as an animated PNG (APNG) image that loops indefinitely.
+fn encode_apng(frames: &[DynamicImage]) -> Result<Vec<u8>> {
+    let (width, height) = frames
+        .first()
+        .map(GenericImageView::dimensions)
+        .ok_or(Error::NoMapsYet)?;
+    let mut image_data = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut image_data, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_frame_delay(ANIMATION_FRAME_DELAY, 1000)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(frame.to_rgba8().as_raw())?;
+    }
+    writer.finish()?;
+
+    Ok(image_data)
+}
+
 /// Projects the provided geocoded position to a coordinate on a map.
 ///
-/// This uses two reference points and a Mercator projection on the y-coordinates of those points
-/// to calculate how the map scales with respect to the provided position.
+/// See [`project_unbounded`] for how the position is projected. If the position lies outside of
+/// the map, the error contains the area covered by the map.
 fn project<I: GenericImageView>(
     image: &I,
     ref_points: [(Position, (u32, u32)); 2],
     pos: Position,
 ) -> Result<(u32, u32)> {
+    let (x, y) = project_unbounded(ref_points, pos);
+
+    let (width, height) = image.dimensions();
+    if (0..i64::from(width)).contains(&x) && (0..i64::from(height)).contains(&y) {
+        Ok((x as u32, y as u32))
+    } else {
+        Err(Error::OutsideCoverage(
+            pos,
+            coverage(width, height, ref_points),
+        ))
+    }
+}
+
+/// Projects the provided geocoded position to a coordinate on a map that may lie outside of it.
+///
+/// This uses two reference points and a Mercator projection on the y-coordinates of those points
+/// to calculate how the map scales with respect to the provided position.
+fn project_unbounded(ref_points: [(Position, (u32, u32)); 2], pos: Position) -> (i64, i64) {
     // Get the data from the reference points.
     let (ref1, (ref1_y, ref1_x)) = ref_points[0];
     let (ref2, (ref2_y, ref2_x)) = ref_points[1];
 
     // For the x-coordinate, use a linear scale.
     let scale_x = ((ref2_x - ref1_x) as f64) / (ref2.lon_as_rad() - ref1.lon_as_rad());
-    let x = ((pos.lon_as_rad() - ref1.lon_as_rad()) * scale_x + ref1_x as f64).round() as u32;
+    let x = ((pos.lon_as_rad() - ref1.lon_as_rad()) * scale_x + ref1_x as f64).round();
 
     // For the y-coordinate,  use a Mercator-projected scale.
-    let mercator_y = |lat: f64| (lat / 2.0 + PI / 4.0).tan().ln();
     let ref1_merc_y = mercator_y(ref1.lat_as_rad());
     let ref2_merc_y = mercator_y(ref2.lat_as_rad());
     let scale_y = ((ref1_y - ref2_y) as f64) / (ref2_merc_y - ref1_merc_y);
-    let y = ((ref2_merc_y - mercator_y(pos.lat_as_rad())) * scale_y + ref2_y as f64).round() as u32;
+    let y = ((ref2_merc_y - mercator_y(pos.lat_as_rad())) * scale_y + ref2_y as f64).round();
 
-    if image.in_bounds(x, y) {
-        Ok((x, y))
-    } else {
-        Err(Error::OutOfBoundCoords(x, y))
+    (x as i64, y as i64)
+}
+
+/// Returns the number of pixels per kilometer on a map at the provided position.
+///
+/// This uses the horizontal scale of the map (see [`project`]), corrected for the latitude.
+fn pixels_per_km(ref_points: [(Position, (u32, u32)); 2], position: Position) -> f64 {
+    let (ref1, (_, ref1_x)) = ref_points[0];
+    let (ref2, (_, ref2_x)) = ref_points[1];
+    let scale_x = ((ref2_x - ref1_x) as f64) / (ref2.lon_as_rad() - ref1.lon_as_rad());
+
+    scale_x / (EARTH_RADIUS_KM * position.lat_as_rad().cos())
+}
+
+/// Returns the Mercator-projected y-coordinate for the provided latitude (in radians).
+fn mercator_y(lat: f64) -> f64 {
+    (lat / 2.0 + PI / 4.0).tan().ln()
+}
+
+/// Returns the area covered by a map of the provided size.
+///
+/// This is the inverse of [`project`] applied to the corners of the map.
+fn coverage(width: u32, height: u32, ref_points: [(Position, (u32, u32)); 2]) -> BoundingBox {
+    let (ref1, (ref1_y, ref1_x)) = ref_points[0];
+    let (ref2, (ref2_y, ref2_x)) = ref_points[1];
+
+    let scale_x = ((ref2_x - ref1_x) as f64) / (ref2.lon_as_rad() - ref1.lon_as_rad());
+    let lon = |x: u32| {
+        let lon_rad = (x as f64 - ref1_x as f64) / scale_x + ref1.lon_as_rad();
+        lon_rad.to_degrees()
+    };
+
+    let ref1_merc_y = mercator_y(ref1.lat_as_rad());
+    let ref2_merc_y = mercator_y(ref2.lat_as_rad());
+    let scale_y = ((ref1_y - ref2_y) as f64) / (ref2_merc_y - ref1_merc_y);
+    let lat = |y: u32| {
+        let merc_y = ref2_merc_y - (y as f64 - ref2_y as f64) / scale_y;
+        (2.0 * merc_y.exp().atan() - PI / 2.0).to_degrees()
+    };
+
+    BoundingBox {
+        min_lat: lat(height - 1),
+        min_lon: lon(0),
+        max_lat: lat(0),
+        max_lon: lon(width - 1),
     }
 }
 
-/// Returns the data of a map with a crosshair drawn on it for the given position.
+/// Runs the provided (blocking) image processing function on the maps.
+///
+/// The function is run on a blocking thread so that it cannot starve the async runtime. At most
+/// [`MAX_BLOCKING_TASKS`] functions run concurrently; other calls wait for their turn.
+pub(crate) async fn process<F, T, E>(maps_handle: &MapsHandle, f: F) -> std::result::Result<T, E>
+where
+    F: FnOnce(&Maps) -> std::result::Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: From<Error> + Send + 'static,
+{
+    let _permit = BLOCKING_TASKS
+        .acquire()
+        .await
+        .expect("Blocking tasks semaphore is never closed");
+    let maps_handle = Arc::clone(maps_handle);
+
+    tokio::task::spawn_blocking(move || {
+        let maps = maps_handle.read().expect("Maps handle lock was poisoned");
+        f(&maps)
+    })
+    .await
+    .map_err(|err| E::from(Error::from(err)))?
+}
+
+/// Returns the data of a map with the given position marked on it.
 ///
-/// The map that is used is determined by the provided metric.
+/// The map that is used is determined by the provided metric and it is encoded in the provided
+/// format. It also returns the validity of the map.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
 pub(crate) async fn mark_map(
     position: Position,
     metric: Metric,
+    options: MapOptions,
+    format: MapFormat,
     maps_handle: &MapsHandle,
-) -> crate::Result<Vec<u8>> {
-    use std::io::Cursor;
+) -> crate::Result<(Vec<u8>, Validity)> {
+    options.validate()?;
+    process(maps_handle, move |maps| {
+        let (image, validity) = match metric {
+            Metric::Pollen => maps.pollen_mark(position, &options),
+            Metric::Precipitation => maps.precipitation_mark(position, &options),
+            Metric::Temperature => maps.temperature_mark(position, &options),
+            Metric::UVI => maps.uvi_mark(position, &options),
+            _ => return Err(crate::Error::UnsupportedMetric(metric)),
+        }?;
 
-    let maps_handle = Arc::clone(maps_handle);
-    tokio::task::spawn_blocking(move || {
-        let maps = maps_handle.lock().expect("Maps handle lock was poisoned");
-        let image = match metric {
-            Metric::Pollen => maps.pollen_mark(position),
-            Metric::UVI => maps.uvi_mark(position),
+        let image_data = format.encode(image)?;
+
+        Ok((image_data, validity))
+    })
+    .await
+}
+
+/// Returns the projection of the given position on the current map.
+///
+/// The map that is used is determined by the provided metric. The coordinates are also returned if
+/// they lie outside of the map.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn projection(
+    position: Position,
+    metric: Metric,
+    maps_handle: &MapsHandle,
+) -> crate::Result<Projection> {
+    let maps = maps_handle.read().expect("Maps handle lock was poisoned");
+    let (retrieved_maps, ref_points) = match metric {
+        Metric::Pollen => (&maps.pollen, POLLEN_MAP_REF_POINTS),
+        Metric::Precipitation => (&maps.precipitation, PRECIPITATION_MAP_REF_POINTS),
+        Metric::Temperature => (&maps.temperature, TEMPERATURE_MAP_REF_POINTS),
+        Metric::UVI => (&maps.uvi, UVI_MAP_REF_POINTS),
+        _ => return Err(crate::Error::UnsupportedMetric(metric)),
+    };
+    let retrieved_maps = retrieved_maps.as_ref().ok_or(Error::NoMapsYet)?;
+    let width = retrieved_maps.image.width() / retrieved_maps.grid.count;
+    let height = retrieved_maps.image.height();
+    let (x, y) = project_unbounded(ref_points, position);
+    let in_bounds = (0..i64::from(width)).contains(&x) && (0..i64::from(height)).contains(&y);
+
+    Ok(Projection {
+        x,
+        y,
+        width,
+        height,
+        in_bounds,
+    })
+}
+
+/// Returns the data of an unmodified map.
+///
+/// The map that is used is determined by the provided metric and selected using the provided
+/// options; the options for rendering are ignored. It is encoded in the provided format. It also
+/// returns the validity of the map.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn raw_map(
+    metric: Metric,
+    options: MapOptions,
+    format: MapFormat,
+    maps_handle: &MapsHandle,
+) -> crate::Result<(Vec<u8>, Validity)> {
+    options.validate()?;
+    process(maps_handle, move |maps| {
+        let (image, validity) = match metric {
+            Metric::Pollen => maps.pollen_raw(&options),
+            Metric::Precipitation => maps.precipitation_raw(&options),
+            Metric::Temperature => maps.temperature_raw(&options),
+            Metric::UVI => maps.uvi_raw(&options),
             _ => return Err(crate::Error::UnsupportedMetric(metric)),
         }?;
-        drop(maps);
+        let image_data = format.encode(image)?;
 
-        // Encode the image as PNG image data.
-        let mut image_data = Cursor::new(Vec::new());
-        match image.write_to(&mut image_data, ImageFormat::Png) {
-            Ok(()) => Ok(image_data.into_inner()),
-            Err(err) => Err(crate::Error::from(Error::from(err))),
-        }
+        Ok((image_data, validity))
+    })
+    .await
+}
+
+/// Returns the data of an animation of all maps with the given position marked on them.
+///
+/// The maps that are used are determined by the provided metric. The animation is encoded as an
+/// animated PNG (APNG) image with the time of each map overlaid. It also returns the validity of
+/// the animation.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn animate_map(
+    position: Position,
+    metric: Metric,
+    options: MapOptions,
+    maps_handle: &MapsHandle,
+) -> crate::Result<(Vec<u8>, Validity)> {
+    options.validate()?;
+    process(maps_handle, move |maps| {
+        let (frames, validity) = match metric {
+            Metric::Pollen => maps.pollen_frames(position, &options),
+            Metric::Precipitation => maps.precipitation_frames(position, &options),
+            Metric::Temperature => maps.temperature_frames(position, &options),
+            Metric::UVI => maps.uvi_frames(position, &options),
+            _ => return Err(crate::Error::UnsupportedMetric(metric)),
+        }?;
+        let image_data = encode_apng(&frames)?;
+
+        Ok((image_data, validity))
     })
     .await
-    .map_err(Error::from)?
+}
+
+/// The types of maps.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, rocket::FromFormField)]
+pub(crate) enum MapsType {
+    /// All types of maps.
+    All,
+
+    /// The pollen maps.
+    Pollen,
+
+    /// The precipitation radar maps.
+    Precipitation,
+
+    /// The temperature maps.
+    Temperature,
+
+    /// The UV index maps.
+    UVI,
+}
+
+/// The outcome of a maps refresh.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct RefreshOutcome {
+    /// The type of the refreshed maps.
+    maps: &'static str,
+
+    /// The date/time the retrieved maps were last modified, if successful.
+    #[serde(
+        serialize_with = "ts_seconds_option::serialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    mtime: Option<DateTime<Utc>>,
+
+    /// Whether the maps have not been modified since they were last retrieved.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    not_modified: bool,
+
+    /// The error that occurred, if unsuccessful.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl RefreshOutcome {
+    /// Creates the outcome of refreshing the provided maps type from the retrieval result.
+    ///
+    /// The result is [`None`] if the maps have not been modified.
+    fn new(maps: &'static str, retrieved_maps: &Result<Option<RetrievedMaps>>) -> Self {
+        match retrieved_maps {
+            Ok(retrieved_maps) => Self {
+                maps,
+                mtime: retrieved_maps.as_ref().map(|maps| maps.mtime),
+                not_modified: retrieved_maps.is_none(),
+                error: None,
+            },
+            Err(e) => {
+                error!(maps, error = %e, "💥 Encountered error during maps refresh");
+
+                Self {
+                    maps,
+                    mtime: None,
+                    not_modified: false,
+                    error: Some(e.to_string()),
+                }
+            }
+        }
+    }
+
+    /// Returns whether the refresh was successful.
+    pub(crate) fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Retrieves the pollen maps and updates them using the maps handle.
+async fn refresh_pollen(maps_handle: &MapsHandle, config: &MapsConfig) -> RefreshOutcome {
+    let map_count = config.pollen_map_count;
+    let validators = maps_handle.pollen_validators();
+    let retrieved_maps = retrieve_pollen_maps(map_count, config, validators).await;
+    let outcome = RefreshOutcome::new("pollen", &retrieved_maps);
+    if let Some(retrieved_maps) = retrieved_maps.transpose() {
+        maps_handle.set_pollen(retrieved_maps);
+    }
+
+    outcome
+}
+
+/// Retrieves the precipitation radar maps and updates them using the maps handle.
+async fn refresh_precipitation(maps_handle: &MapsHandle, config: &MapsConfig) -> RefreshOutcome {
+    let map_count = config.precipitation_map_count;
+    let validators = maps_handle.precipitation_validators();
+    let retrieved_maps = retrieve_precipitation_maps(map_count, config, validators).await;
+    let outcome = RefreshOutcome::new("precipitation", &retrieved_maps);
+    if let Some(retrieved_maps) = retrieved_maps.transpose() {
+        maps_handle.set_precipitation(retrieved_maps);
+    }
+
+    outcome
+}
+
+/// Retrieves the temperature maps and updates them using the maps handle.
+async fn refresh_temperature(maps_handle: &MapsHandle, config: &MapsConfig) -> RefreshOutcome {
+    let map_count = config.temperature_map_count;
+    let validators = maps_handle.temperature_validators();
+    let retrieved_maps = retrieve_temperature_maps(map_count, config, validators).await;
+    let outcome = RefreshOutcome::new("temperature", &retrieved_maps);
+    if let Some(retrieved_maps) = retrieved_maps.transpose() {
+        maps_handle.set_temperature(retrieved_maps);
+    }
+
+    outcome
+}
+
+/// Retrieves the UV index maps and updates them using the maps handle.
+async fn refresh_uvi(maps_handle: &MapsHandle, config: &MapsConfig) -> RefreshOutcome {
+    let map_count = config.uvi_map_count;
+    let validators = maps_handle.uvi_validators();
+    let retrieved_maps = retrieve_uvi_maps(map_count, config, validators).await;
+    let outcome = RefreshOutcome::new("UVI", &retrieved_maps);
+    if let Some(retrieved_maps) = retrieved_maps.transpose() {
+        maps_handle.set_uvi(retrieved_maps);
+    }
+
+    outcome
+}
+
+/// Refreshes the maps of the provided type immediately, regardless of whether it is necessary.
+///
+/// Returns the outcome per type of maps.
+pub(crate) async fn refresh(maps_handle: &MapsHandle, maps_type: MapsType) -> Vec<RefreshOutcome> {
+    let config = maps_handle
+        .read()
+        .expect("Maps handle lock was poisoned")
+        .config
+        .clone();
+    let mut outcomes = Vec::new();
+
+    info!(?maps_type, "🗺️  Forcing a maps refresh");
+    if matches!(maps_type, MapsType::All | MapsType::Pollen) {
+        outcomes.push(refresh_pollen(maps_handle, &config).await);
+    }
+    if matches!(maps_type, MapsType::All | MapsType::Precipitation) {
+        outcomes.push(refresh_precipitation(maps_handle, &config).await);
+    }
+    if matches!(maps_type, MapsType::All | MapsType::Temperature) {
+        outcomes.push(refresh_temperature(maps_handle, &config).await);
+    }
+    if matches!(maps_type, MapsType::All | MapsType::UVI) {
+        outcomes.push(refresh_uvi(maps_handle, &config).await);
+    }
+
+    outcomes
+}
+
+/// Refreshes the maps of the provided (single) type.
+async fn refresh_maps_type(
+    maps_handle: &MapsHandle,
+    config: &MapsConfig,
+    maps_type: MapsType,
+) -> RefreshOutcome {
+    match maps_type {
+        MapsType::All => unreachable!("Maps are refreshed per type"),
+        MapsType::Pollen => refresh_pollen(maps_handle, config).await,
+        MapsType::Precipitation => refresh_precipitation(maps_handle, config).await,
+        MapsType::Temperature => refresh_temperature(maps_handle, config).await,
+        MapsType::UVI => refresh_uvi(maps_handle, config).await,
+    }
+}
+
+/// Returns when the maps of the provided (single) type need to be refreshed.
+fn refresh_at(maps_handle: &MapsHandle, maps_type: MapsType) -> Option<DateTime<Utc>> {
+    match maps_type {
+        MapsType::All => unreachable!("Maps are refreshed per type"),
+        MapsType::Pollen => maps_handle.pollen_refresh_at(),
+        MapsType::Precipitation => maps_handle.precipitation_refresh_at(),
+        MapsType::Temperature => maps_handle.temperature_refresh_at(),
+        MapsType::UVI => maps_handle.uvi_refresh_at(),
+    }
+}
+
+/// The refresh schedule of a type of maps.
+#[derive(Clone, Copy, Debug)]
+struct RefreshSchedule {
+    /// The type of maps.
+    maps_type: MapsType,
+
+    /// When the next attempt to refresh the maps is made.
+    next_attempt: DateTime<Utc>,
+
+    /// The number of consecutive failed attempts.
+    failures: u32,
+}
+
+/// Returns a random number, used for jitter.
+fn random() -> u64 {
+    RandomState::new().hash_one(Utc::now().timestamp_nanos_opt())
+}
+
+/// Determines the delay before retrying after the provided number of consecutive failures.
+///
+/// The delay grows exponentially from the retry delay up to the maximum retry delay. To avoid
+/// synchronized retries, a random jitter of up to half the delay is subtracted using `random`.
+fn retry_delay(failures: u32, config: &MapsConfig, random: u64) -> Duration {
+    let max_delay = config.max_retry_delay.max(config.retry_delay) * 1000;
+    let delay = (config.retry_delay * 1000)
+        .saturating_mul(1 << failures.saturating_sub(1).min(32))
+        .min(max_delay);
+    let jitter = random % (delay / 2 + 1);
+
+    Duration::milliseconds((delay - jitter) as i64)
 }
 
 /// Runs a loop that keeps refreshing the maps when necessary.
 ///
-/// Use [`MapsRefresh`] trait methods on `maps_handle` to check whether each maps type needs to be
-/// refreshed and uses its retrieval function to update it if necessary.
+/// Each type of maps is scheduled to be refreshed when it expires, i.e. when the refresh interval
+/// has passed since its modification time. If refreshing fails, it is retried with an exponential
+/// backoff with jitter. Retrievals of different types of maps are staggered to avoid bursts of
+/// requests to the provider.
+///
+/// The intervals, delays and the number of maps to retrieve are determined by the maps
+/// configuration.
 pub(crate) async fn run(maps_handle: MapsHandle) {
+    let config = maps_handle
+        .read()
+        .expect("Maps handle lock was poisoned")
+        .config
+        .clone();
+    let stagger = Duration::seconds(config.refresh_stagger as i64);
+    let start = Utc::now();
+    // The pollen and UV index maps are retrieved first, because the service is only ready once
+    // these are available.
+    let mut schedules = [
+        MapsType::Pollen,
+        MapsType::UVI,
+        MapsType::Precipitation,
+        MapsType::Temperature,
+    ]
+    .into_iter()
+    .zip(0..)
+    .map(|(maps_type, index)| RefreshSchedule {
+        maps_type,
+        next_attempt: start + stagger * index,
+        failures: 0,
+    })
+    .collect::<Vec<_>>();
+
     loop {
-        println!("🕔 Refreshing the maps (if necessary)...");
+        let now = Utc::now();
+        let schedule = schedules
+            .iter_mut()
+            .min_by_key(|schedule| schedule.next_attempt)
+            .expect("There are refresh schedules");
+        if schedule.next_attempt > now {
+            let wait = (schedule.next_attempt - now).to_std().unwrap_or_default();
+            sleep(wait).await;
+            continue;
+        }
 
-        if maps_handle.needs_pollen_refresh() {
-            let retrieved_maps = retrieve_pollen_maps().await;
-            if let Err(e) = retrieved_maps.as_ref() {
-                eprintln!("💥 Encountered error during pollen maps refresh: {}", e);
-            }
-            maps_handle.set_pollen(retrieved_maps);
+        // The maps may have been refreshed in the meantime, e.g. via the administration endpoint.
+        let maps_type = schedule.maps_type;
+        if let Some(refresh_at) = refresh_at(&maps_handle, maps_type).filter(|&at| at > now) {
+            schedule.next_attempt = refresh_at;
+            continue;
+        }
+
+        info!(?maps_type, "🕔 Refreshing the maps...");
+        let outcome = refresh_maps_type(&maps_handle, &config, maps_type).await;
+        let now = Utc::now();
+        if outcome.is_ok() {
+            // Upstream may not have newer maps yet, so do not retry right away.
+            let min_next_attempt = now + Duration::seconds(config.retry_delay as i64);
+            let refresh_at = refresh_at(&maps_handle, maps_type).unwrap_or(min_next_attempt);
+            schedule.failures = 0;
+            schedule.next_attempt = refresh_at.max(min_next_attempt);
+        } else {
+            schedule.failures += 1;
+            schedule.next_attempt = now + retry_delay(schedule.failures, &config, random());
+        }
+        info!(?maps_type, next_attempt = %schedule.next_attempt, "🕔 Scheduled the next maps refresh");
+
+        // Stagger the retrieval of the other types of maps.
+        for schedule in &mut schedules {
+            schedule.next_attempt = schedule.next_attempt.max(now + stagger);
         }
+    }
+}
+
+/// A handle to access the status of the maps refresher.
+pub(crate) type RefresherStatusHandle = Arc<RefresherStatus>;
+
+/// The status of the maps refresher task.
+#[derive(Debug, Default, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct RefresherStatus {
+    /// Whether the refresher is running.
+    running: AtomicBool,
+
+    /// The number of times the refresher has been restarted after it terminated.
+    restarts: AtomicU32,
+
+    /// The time the refresher was first started.
+    #[serde(skip)]
+    started_at: OnceLock<DateTime<Utc>>,
+}
+
+impl RefresherStatus {
+    /// Returns whether the refresher is healthy, i.e. running.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn is_healthy(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the refresher is still starting up at the provided time.
+    ///
+    /// This is the case as long as the first pollen and UV index maps have not been retrieved,
+    /// until the configured startup timeout has passed since the refresher was first started.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn is_starting(&self, maps: &Maps, now: DateTime<Utc>) -> bool {
+        let startup_timeout = Duration::seconds(maps.config.startup_timeout as i64);
+        let has_first_maps = maps.pollen.is_some() && maps.uvi.is_some();
+
+        !has_first_maps
+            && self
+                .started_at
+                .get()
+                .is_some_and(|started_at| now - *started_at < startup_timeout)
+    }
+}
 
-        if maps_handle.needs_uvi_refresh() {
-            let retrieved_maps = retrieve_uvi_maps().await;
-            if let Err(e) = retrieved_maps.as_ref() {
-                eprintln!("💥 Encountered error during UVI maps refresh: {}", e);
+/// Runs the maps refresher task and restarts it if it terminates, e.g. because it panicked.
+///
+/// The refresher is restarted with an exponential backoff with jitter using the configured retry
+/// delays. The backoff is reset if the refresher ran for longer than the maximum retry delay. The
+/// status of the refresher is updated via `status`.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn supervise(maps_handle: MapsHandle, status: RefresherStatusHandle) {
+    let config = maps_handle
+        .read()
+        .expect("Maps handle lock was poisoned")
+        .config
+        .clone();
+    let max_retry_delay = Duration::seconds(config.max_retry_delay as i64);
+    let mut failures = 0;
+
+    loop {
+        let started_at = Utc::now();
+        status.started_at.get_or_init(|| started_at);
+        status.running.store(true, Ordering::Relaxed);
+        let result = tokio::spawn(run(Arc::clone(&maps_handle))).await;
+        status.running.store(false, Ordering::Relaxed);
+        match result {
+            Ok(()) => error!("💥 The maps refresher stopped unexpectedly"),
+            Err(e) => error!(error = %e, "💥 The maps refresher terminated"),
+        }
+
+        if Utc::now() - started_at > max_retry_delay {
+            failures = 0;
+        }
+        failures += 1;
+        let delay = retry_delay(failures, &config, random());
+        sleep(delay.to_std().unwrap_or_default()).await;
+
+        // A panic while holding the lock poisons it, but the maps are only replaced as a whole.
+        maps_handle.clear_poison();
+        let restarts = status.restarts.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(restarts, "🔁 Restarting the maps refresher");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use image::RgbaImage;
+
+    use super::*;
+
+    #[test]
+    fn project() {
+        let image = RgbaImage::new(820, 988);
+
+        // Positions on the map are projected to their coordinates.
+        let vlissingen = Position::new(51.44, 3.57);
+        let coords = super::project(&image, POLLEN_MAP_REF_POINTS, vlissingen).unwrap();
+        assert_eq!(coords, (84, 745));
+        let eindhoven = Position::new(51.44, 5.47);
+        assert!(super::project(&image, POLLEN_MAP_REF_POINTS, eindhoven).is_ok());
+
+        // Positions outside of the map report the area covered by the map.
+        let paris = Position::new(48.86, 2.35);
+        let Err(Error::OutsideCoverage(position, coverage)) =
+            super::project(&image, POLLEN_MAP_REF_POINTS, paris)
+        else {
+            panic!("Paris should lie outside of the map");
+        };
+        assert_eq!(position, paris);
+        assert!(coverage.contains(vlissingen));
+        assert!(coverage.contains(eindhoven));
+        assert!(!coverage.contains(paris));
+        let north = Position::new(coverage.max_lat + 0.1, 5.0);
+        assert!(super::project(&image, POLLEN_MAP_REF_POINTS, north).is_err());
+
+        // The projection is also available for positions outside of the map.
+        let maps = RetrievedMaps::new(DynamicImage::ImageRgba8(RgbaImage::new(820 * 2, 988)), 2);
+        let maps_handle = Arc::new(RwLock::new(Maps {
+            pollen: Some(maps),
+            ..Default::default()
+        }));
+        assert_eq!(
+            projection(vlissingen, Metric::Pollen, &maps_handle).unwrap(),
+            Projection {
+                x: 84,
+                y: 745,
+                width: 820,
+                height: 988,
+                in_bounds: true
             }
-            maps_handle.set_uvi(retrieved_maps);
+        );
+        let paris_projection = projection(paris, Metric::Pollen, &maps_handle).unwrap();
+        assert!(paris_projection.x < 0 && paris_projection.y >= 988);
+        assert!(!paris_projection.in_bounds);
+        assert!(matches!(
+            projection(paris, Metric::UVI, &maps_handle),
+            Err(crate::Error::Maps(Error::NoMapsYet))
+        ));
+    }
+
+    #[test]
+    fn sample_grid() {
+        // Two maps of 120×60 pixels: the first map has the first key color on its left half and
+        // the third key color on its right half, the second map has no known colors.
+        let image = RgbaImage::from_fn(240, 60, |x, _y| match x {
+            0..60 => Rgba::from([0x49, 0xDA, 0x21, 0xFF]),
+            60..120 => Rgba::from([0xFF, 0xF8, 0x8B, 0xFF]),
+            _ => Rgba::from([0x00, 0x00, 0x00, 0xFF]),
+        });
+        let grid = SampleGrid::compute(&DynamicImage::ImageRgba8(image), 2, &MAP_KEY, 0.0);
+        let stamp = Utc::now();
+
+        assert!(matches!(
+            grid.samples(stamp, 3_600, (10, 30)),
+            Err(Error::NoKnownColorsInSamples)
+        ));
+        assert!(matches!(
+            grid.samples(stamp, 3_600, (120, 30)),
+            Err(Error::OutOfBoundCoords(120, 30))
+        ));
+
+        let grid = SampleGrid { count: 1, ..grid };
+        let samples = grid.samples(stamp, 3_600, (10, 30)).unwrap();
+        assert_eq!(samples, [Sample::new(stamp, 1)]);
+        let samples = grid.samples(stamp, 3_600, (110, 30)).unwrap();
+        assert_eq!(samples, [Sample::new(stamp, 3)]);
+
+        // The confidence is lower where the sample area covers multiple key colors.
+        let samples = grid.samples(stamp, 3_600, (58, 30)).unwrap();
+        assert_eq!(samples[0].score, 1.0);
+        assert_eq!(samples[0].confidence, 0.6);
+
+        // The confidence is also lower where the sample area covers pixels without known colors.
+        let image = RgbaImage::from_fn(120, 60, |x, _y| match x {
+            0..100 => Rgba::from([0x00, 0x00, 0x00, 0x00]),
+            _ => Rgba::from([0x49, 0xDA, 0x21, 0xFF]),
+        });
+        let grid = SampleGrid::compute(&DynamicImage::ImageRgba8(image), 1, &MAP_KEY, 0.0);
+        let samples = grid.samples(stamp, 3_600, (110, 30)).unwrap();
+        assert_eq!(samples[0].score, 1.0);
+        assert_eq!(samples[0].confidence, 20.0 / 24.0);
+    }
+
+    #[test]
+    fn count_maps() {
+        // Images that consist of the expected number of maps are accepted.
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(MAP_WIDTH * 3, MAP_HEIGHT));
+        assert_eq!(super::count_maps(&image, 3).unwrap(), 3);
+
+        // The actual number of maps is used if it differs.
+        assert_eq!(super::count_maps(&image, 24).unwrap(), 3);
+
+        // Images that do not consist of whole maps are rejected.
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(MAP_WIDTH * 3 - 10, MAP_HEIGHT));
+        assert!(matches!(
+            super::count_maps(&image, 3),
+            Err(Error::InvalidMapDimensions(2450, 988))
+        ));
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(MAP_WIDTH, 500));
+        assert!(super::count_maps(&image, 1).is_err());
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(0, MAP_HEIGHT));
+        assert!(super::count_maps(&image, 1).is_err());
+    }
+
+    #[test]
+    fn decode_image() {
+        let encode = |width, height| {
+            let image = DynamicImage::ImageRgba8(RgbaImage::new(width, height));
+            let mut image_data = std::io::Cursor::new(Vec::new());
+            image.write_to(&mut image_data, ImageFormat::Png).unwrap();
+
+            image_data.into_inner()
+        };
+
+        // Images with the expected dimensions are decoded.
+        let image = super::decode_image(&encode(MAP_WIDTH * 3, MAP_HEIGHT), 3).unwrap();
+        assert_eq!(image.dimensions(), (MAP_WIDTH * 3, MAP_HEIGHT));
+
+        // Images that are too high or much wider are rejected before decoding the pixels.
+        assert!(matches!(
+            super::decode_image(&encode(MAP_WIDTH, MAP_HEIGHT * 2), 1),
+            Err(Error::Image(ImageError::Limits(_)))
+        ));
+        assert!(matches!(
+            super::decode_image(&encode(MAP_WIDTH * 7, MAP_HEIGHT), 3),
+            Err(Error::Image(ImageError::Limits(_)))
+        ));
+
+        // Invalid image data is rejected.
+        assert!(super::decode_image(b"<html></html>", 1).is_err());
+    }
+
+    #[test]
+    fn classify() {
+        // Exact matches are always classified.
+        assert_eq!(super::classify([0x49, 0xDA, 0x21], &MAP_KEY, 0.0), Some(0));
+
+        // Colors close to a key color are only classified within the tolerance.
+        let drifted = [0x4C, 0xD8, 0x24];
+        assert_eq!(super::classify(drifted, &MAP_KEY, 0.0), None);
+        assert_eq!(super::classify(drifted, &MAP_KEY, 5.0), Some(0));
+
+        // The nearest key color is used.
+        assert_eq!(super::classify([0x35, 0xD2, 0x05], &MAP_KEY, 20.0), Some(1));
+        assert_eq!(super::classify([0x12, 0x34, 0x56], &MAP_KEY, 20.0), None);
+        assert_eq!(super::classify([0x12, 0x34, 0x56], &[], 20.0), None);
+    }
+
+    #[test]
+    fn temperature_items() {
+        // Two temperature maps of which the position lies in an area of 15 °C and 20 °C.
+        let image = RgbaImage::from_fn(820 * 2, 988, |x, _y| {
+            let [r, g, b] = TEMPERATURE_MAP_KEY[5 + (x / 820) as usize];
+            Rgba::from([r, g, b, 0xFF])
+        });
+        let mut maps = Maps {
+            temperature: Some(RetrievedMaps::new_temperature(
+                DynamicImage::ImageRgba8(image),
+                2,
+            )),
+            ..Default::default()
+        };
+        let temperature_maps = maps.temperature.as_ref().unwrap();
+        let stamp = temperature_maps.timestamp_base;
+
+        let items = maps.temperature_items(Position::new(51.4, 5.5)).unwrap();
+        assert_eq!(
+            items,
+            [
+                Item::new(stamp, 15.0),
+                Item::new(stamp + Duration::hours(1), 20.0)
+            ]
+        );
+
+        // Maps without known temperature colors cannot be sampled.
+        let image = RgbaImage::from_pixel(820, 988, Rgba::from([0x49, 0xDA, 0x21, 0xFF]));
+        maps.temperature = Some(RetrievedMaps::new_temperature(
+            DynamicImage::ImageRgba8(image),
+            1,
+        ));
+        assert!(matches!(
+            maps.temperature_items(Position::new(51.4, 5.5)),
+            Err(Error::NoKnownColorsInSamples)
+        ));
+    }
+
+    #[test]
+    fn validate_map_key() {
+        // Maps of which most colored pixels have a map key color match the key.
+        let image = RgbaImage::from_fn(100, 100, |x, _y| match x {
+            0..60 => Rgba::from([0x49, 0xDA, 0x21, 0xFF]),
+            60..80 => Rgba::from([0x12, 0x34, 0x56, 0xFF]),
+            _ => Rgba::from([0x00, 0x00, 0x00, 0x00]),
+        });
+        let image = DynamicImage::ImageRgba8(image);
+        assert!(super::validate_map_key(&image, &MAP_KEY, 0.0).is_ok());
+
+        // Maps with a changed palette do not match the key; the unknown colors are reported.
+        let result = super::validate_map_key(&image, &TEMPERATURE_MAP_KEY, 0.0);
+        let Err(Error::MapKeyMismatch(coverage, colors)) = result else {
+            panic!("Temperature map key should not match the pollen map colors");
+        };
+        assert_eq!(coverage, 0.0);
+        assert_eq!(colors, ["#49DA21", "#123456"]);
+
+        // An empty map key and transparent maps always match.
+        assert!(super::validate_map_key(&image, &[], 0.0).is_ok());
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(100, 100));
+        assert!(super::validate_map_key(&image, &MAP_KEY, 0.0).is_ok());
+    }
+
+    #[test]
+    fn map_options() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(820, 988));
+        let position = Position::new(51.4, 5.5);
+        let coords = super::project(&image, POLLEN_MAP_REF_POINTS, position).unwrap();
+
+        // Without zoom or radius, the map is not cropped.
+        let options = MapOptions::default();
+        assert!(options.validate().is_ok());
+        let cropped = options.crop(image.clone(), coords, POLLEN_MAP_REF_POINTS, position);
+        assert_eq!(cropped.dimensions(), (820, 988));
+
+        // The map is cropped to a fraction of its size using the zoom...
+        let options = MapOptions {
+            zoom: Some(4.0),
+            ..Default::default()
+        };
+        assert!(options.validate().is_ok());
+        let cropped = options.crop(image.clone(), coords, POLLEN_MAP_REF_POINTS, position);
+        assert_eq!(cropped.dimensions(), (205, 247));
+
+        // ... or to the area around the position using the radius (about 2.6 pixels per km).
+        let options = MapOptions {
+            radius_km: Some(20.0),
+            ..Default::default()
+        };
+        assert!(options.validate().is_ok());
+        let cropped = options.crop(image.clone(), coords, POLLEN_MAP_REF_POINTS, position);
+        assert_eq!(cropped.dimensions(), (106, 106));
+
+        // The cropped area is shifted to stay within the map near the borders, and the size is
+        // limited to the map.
+        let options = MapOptions {
+            radius_km: Some(1_000.0),
+            ..Default::default()
+        };
+        let cropped = options.crop(image.clone(), (0, 0), POLLEN_MAP_REF_POINTS, position);
+        assert_eq!(cropped.dimensions(), (820, 988));
+        let options = MapOptions {
+            zoom: Some(2.0),
+            ..Default::default()
+        };
+        let cropped = options.crop(image, (819, 987), POLLEN_MAP_REF_POINTS, position);
+        assert_eq!(cropped.dimensions(), (410, 494));
+
+        // Invalid options are rejected.
+        for options in [
+            MapOptions {
+                zoom: Some(0.5),
+                ..Default::default()
+            },
+            MapOptions {
+                radius_km: Some(-1.0),
+                ..Default::default()
+            },
+            MapOptions {
+                zoom: Some(2.0),
+                radius_km: Some(10.0),
+                ..Default::default()
+            },
+            MapOptions {
+                time: Some(0),
+                offset: Some(1),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(options.validate(), Err(Error::InvalidOptions(_))));
         }
+    }
+
+    #[test]
+    fn map_selection() {
+        let image = RgbaImage::new(820 * 3, 988);
+        let image = DynamicImage::ImageRgba8(image);
+        let stamp = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let now = stamp + Duration::minutes(90);
+
+        // By default, the current map is selected.
+        let options = MapOptions::default();
+        assert_eq!(options.instant(3_600, 3, now).unwrap(), now);
+
+        // A map can be selected by offset from the current one or by time.
+        let options = MapOptions {
+            offset: Some(1),
+            ..Default::default()
+        };
+        let instant = options.instant(3_600, 3, now).unwrap();
+        assert_eq!(instant, stamp + Duration::minutes(150));
+        assert!(map_at(&image, stamp, 3_600, 3, instant).is_ok());
+        let options = MapOptions {
+            time: Some(stamp.timestamp() + 2 * 3_600),
+            ..Default::default()
+        };
+        let instant = options.instant(3_600, 3, now).unwrap();
+        assert_eq!(instant, stamp + Duration::hours(2));
+        assert!(map_at(&image, stamp, 3_600, 3, instant).is_ok());
+
+        // Offsets beyond the number of maps are rejected, and cannot overflow.
+        for offset in [3, u32::MAX] {
+            let options = MapOptions {
+                offset: Some(offset),
+                ..Default::default()
+            };
+            assert!(matches!(
+                options.instant(3_600, 3, now),
+                Err(Error::InvalidOptions(_))
+            ));
+        }
+
+        // Maps before the first or after the last one are not available.
+        let instant = stamp + Duration::hours(3);
+        assert!(matches!(
+            map_at(&image, stamp, 3_600, 3, instant),
+            Err(Error::OutOfBoundOffset(3))
+        ));
+        let instant = stamp - Duration::minutes(30);
+        assert!(matches!(
+            map_at(&image, stamp, 3_600, 3, instant),
+            Err(Error::OutOfBoundOffset(-1))
+        ));
+    }
+
+    #[test]
+    fn marker() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(100, 100));
+        let red = Rgba::from([0xff, 0x00, 0x00, 0xff]);
+        let transparent = Rgba::from([0x00, 0x00, 0x00, 0x00]);
+        let marker = |style| Marker {
+            style,
+            color: red,
+            size: 10,
+        };
+
+        // The crosshair has arms of the marker size around the position.
+        let marked = mark(image.clone(), (50, 50), marker(MarkerStyle::Crosshair));
+        assert_eq!(marked.get_pixel(50, 50), red);
+        assert_eq!(marked.get_pixel(60, 50), red);
+        assert_eq!(marked.get_pixel(50, 40), red);
+        assert_eq!(marked.get_pixel(61, 50), transparent);
+        assert_eq!(marked.get_pixel(55, 55), transparent);
+
+        // The circle has the marker size as radius and leaves the position visible.
+        let marked = mark(image.clone(), (50, 50), marker(MarkerStyle::Circle));
+        assert_eq!(marked.get_pixel(60, 50), red);
+        assert_eq!(marked.get_pixel(50, 41), red);
+        assert_eq!(marked.get_pixel(50, 50), transparent);
+
+        // The pin has its point on the position and its head the marker size above it.
+        let marked = mark(image.clone(), (50, 50), marker(MarkerStyle::Pin));
+        assert_eq!(marked.get_pixel(50, 50), red);
+        assert_eq!(marked.get_pixel(50, 40), red);
+        assert_eq!(marked.get_pixel(50, 39), transparent);
+        assert_eq!(marked.get_pixel(50, 51), transparent);
+
+        // Markers are clipped at the borders of the map.
+        let marked = mark(image, (0, 99), marker(MarkerStyle::Circle));
+        assert_eq!(marked.get_pixel(10, 99), red);
+
+        // The marker options are validated.
+        let options = MapOptions {
+            marker: Some(MarkerStyle::Pin),
+            marker_color: Some(String::from("#ff000080")),
+            marker_size: Some(20),
+            ..Default::default()
+        };
+        let marker = options.marker().unwrap();
+        assert_eq!(marker.style, MarkerStyle::Pin);
+        assert_eq!(marker.color, Rgba::from([0xff, 0x00, 0x00, 0x80]));
+        assert_eq!(marker.size, 20);
+        assert_eq!(
+            parse_color("00ff00"),
+            Some(Rgba::from([0x00, 0xff, 0x00, 0xff]))
+        );
+        for options in [
+            MapOptions {
+                marker_color: Some(String::from("red")),
+                ..Default::default()
+            },
+            MapOptions {
+                marker_size: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(options.validate(), Err(Error::InvalidOptions(_))));
+        }
+    }
+
+    #[test]
+    fn map_format() {
+        use std::str::FromStr;
+
+        let accept = |value| Accept::from_str(value).unwrap();
+
+        // The accepted format with the highest quality is negotiated, the first one on a tie.
+        let format = MapFormat::negotiate(&accept("image/webp, image/png;q=0.9"));
+        assert_eq!(format, Some(MapFormat::Webp));
+        let format = MapFormat::negotiate(&accept("image/png;q=0.5, image/jpeg"));
+        assert_eq!(format, Some(MapFormat::Jpeg));
+        let format = MapFormat::negotiate(&accept("image/png, image/jpeg"));
+        assert_eq!(format, Some(MapFormat::Png));
+        // Unsupported media types, wildcards and unacceptable formats are not negotiated.
+        assert_eq!(MapFormat::negotiate(&accept("image/gif, */*")), None);
+        assert_eq!(MapFormat::negotiate(&accept("image/webp;q=0")), None);
+
+        // The image is encoded in the format.
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(10, 10));
+        let image_data = MapFormat::Png.encode(image.clone()).unwrap();
+        assert!(image_data.starts_with(b"\x89PNG"));
+        let image_data = MapFormat::Jpeg.encode(image.clone()).unwrap();
+        assert!(image_data.starts_with(&[0xFF, 0xD8]));
+        let image_data = MapFormat::Webp.encode(image).unwrap();
+        assert!(image_data.starts_with(b"RIFF"));
+        assert_eq!(&image_data[8..12], b"WEBP");
+    }
+
+    #[test]
+    fn legend() {
+        let mut image = DynamicImage::ImageRgba8(RgbaImage::new(820, 988));
+        draw_legend(&mut image, &MAP_LEGEND);
+
+        // The colors of the highest and lowest score are at the top and bottom of the legend.
+        let [r, g, b] = MAP_KEY[9];
+        assert_eq!(image.get_pixel(0, 848), Rgba::from([r, g, b, 0xff]));
+        let [r, g, b] = MAP_KEY[0];
+        assert_eq!(image.get_pixel(13, 987), Rgba::from([r, g, b, 0xff]));
+        // The labels are drawn on a white background next to them.
+        assert_eq!(
+            image.get_pixel(14, 848),
+            Rgba::from([0xff, 0xff, 0xff, 0xff])
+        );
+        assert_eq!(
+            image.get_pixel(40, 848),
+            Rgba::from([0x00, 0x00, 0x00, 0x00])
+        );
+
+        // The legend is clipped on maps that are too small.
+        let mut image = DynamicImage::ImageRgba8(RgbaImage::new(20, 20));
+        draw_legend(&mut image, &MAP_LEGEND);
+        let [r, g, b] = MAP_KEY[9];
+        assert_eq!(image.get_pixel(0, 0), Rgba::from([r, g, b, 0xff]));
+
+        // Legends with wider labels get a wider label box.
+        let mut image = DynamicImage::ImageRgba8(RgbaImage::new(820, 988));
+        draw_legend(&mut image, &TEMPERATURE_MAP_LEGEND);
+        let [r, g, b] = TEMPERATURE_MAP_KEY[9];
+        assert_eq!(image.get_pixel(0, 848), Rgba::from([r, g, b, 0xff]));
+        assert_eq!(
+            image.get_pixel(32, 848),
+            Rgba::from([0xff, 0xff, 0xff, 0xff])
+        );
+    }
+
+    #[test]
+    fn animation() {
+        let image = RgbaImage::from_pixel(820 * 3, 988, Rgba::from([0x49, 0xDA, 0x21, 0xFF]));
+        let maps = RetrievedMaps::new(DynamicImage::ImageRgba8(image), 3);
+        let frames = frames(
+            &maps,
+            3_600,
+            3,
+            POLLEN_MAP_REF_POINTS,
+            Position::new(51.4, 5.5),
+            &MapOptions::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(frames.len(), 3);
+
+        // The time is overlaid in black on white in the top-left corner.
+        let frame = frames[0].to_rgba8();
+        assert_eq!(frame.dimensions(), (820, 988));
+        assert_eq!(frame.get_pixel(0, 0), &Rgba::from([0xFF, 0xFF, 0xFF, 0xFF]));
+        assert_eq!(
+            frame.get_pixel(FONT_SCALE, FONT_SCALE),
+            &Rgba::from([0x00, 0x00, 0x00, 0xFF])
+        );
+
+        // The frames are encoded as an animated PNG image.
+        let image_data = encode_apng(&frames).unwrap();
+        let decoder = png::Decoder::new(std::io::Cursor::new(image_data));
+        let reader = decoder.read_info().unwrap();
+        let animation_control = reader.info().animation_control.unwrap();
+        assert_eq!(animation_control.num_frames, 3);
+        assert_eq!(animation_control.num_plays, 0);
+    }
+
+    #[test]
+    fn retry_delay() {
+        let config = MapsConfig {
+            retry_delay: 30,
+            max_retry_delay: 300,
+            ..Default::default()
+        };
+
+        // Without jitter, the delay doubles with every failure up to the maximum.
+        let delays = (1..=6)
+            .map(|failures| super::retry_delay(failures, &config, 0).num_seconds())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [30, 60, 120, 240, 300, 300]);
+
+        // The jitter subtracts up to half of the delay.
+        assert_eq!(super::retry_delay(1, &config, 15_000).num_seconds(), 15);
+        assert_eq!(super::retry_delay(1, &config, 15_001).num_seconds(), 30);
+        for random in [1, 1_234, 98_765_432, u64::MAX] {
+            let delay = super::retry_delay(3, &config, random).num_seconds();
+            assert!((60..=120).contains(&delay));
+        }
+
+        // A huge number of failures does not overflow.
+        assert_eq!(super::retry_delay(u32::MAX, &config, 0).num_seconds(), 300);
+    }
+
+    #[test]
+    fn validators() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"5f3c\""));
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Mon, 06 Jun 2022 14:09:34 GMT"),
+        );
+        let validators = Validators::from_response(&headers);
+
+        // The validators are sent back as the conditional request headers.
+        let conditional_headers = validators.conditional_headers();
+        assert_eq!(conditional_headers.len(), 2);
+        assert_eq!(conditional_headers[IF_NONE_MATCH], "\"5f3c\"");
+        assert_eq!(
+            conditional_headers[IF_MODIFIED_SINCE],
+            "Mon, 06 Jun 2022 14:09:34 GMT"
+        );
+
+        // Without validators, the request is unconditional.
+        let validators = Validators::from_response(&HeaderMap::new());
+        assert_eq!(validators, Validators::default());
+        assert!(validators.conditional_headers().is_empty());
+
+        // Not modified maps are reported as such.
+        let outcome = RefreshOutcome::new("pollen", &Ok(None));
+        assert!(outcome.is_ok());
+        assert!(outcome.not_modified);
+        assert_eq!(outcome.mtime, None);
+    }
 
-        sleep(REFRESH_INTERVAL).await;
+    #[test]
+    fn refresher_status_starting() {
+        let config = MapsConfig {
+            startup_timeout: 60,
+            ..Default::default()
+        };
+        let mut maps = Maps::new(config);
+        let status = RefresherStatus::default();
+        let now = Utc::now();
+
+        // The refresher is not starting before it has been started.
+        assert!(!status.is_starting(&maps, now));
+
+        // It is starting until the first pollen and UV index maps are retrieved...
+        status.started_at.set(now).unwrap();
+        assert!(status.is_starting(&maps, now + Duration::seconds(30)));
+        maps.pollen = Some(RetrievedMaps::new(
+            DynamicImage::ImageRgba8(RgbaImage::new(820, 988)),
+            1,
+        ));
+        assert!(status.is_starting(&maps, now + Duration::seconds(30)));
+
+        // ... or the startup timeout has passed.
+        assert!(!status.is_starting(&maps, now + Duration::seconds(60)));
+        maps.uvi = Some(RetrievedMaps::new(
+            DynamicImage::ImageRgba8(RgbaImage::new(820, 988)),
+            1,
+        ));
+        assert!(!status.is_starting(&maps, now + Duration::seconds(30)));
+
+        // Without a startup timeout, it is never starting.
+        let maps = Maps::default();
+        assert!(!status.is_starting(&maps, now));
     }
 }
@@ -0,0 +1,51 @@
+//! Stand-in for the maps if the `maps` feature is disabled.
+//!
+//! No maps are retrieved, so the metrics that require them (pollen, temperature, UV index and
+//! PAQI) are not available.
+
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use rocket::serde::Serialize;
+
+use crate::config::MapsConfig;
+
+/// A handle to access the (absent) maps.
+pub(crate) type MapsHandle = Arc<RwLock<Maps>>;
+
+/// Container type for the maps, which is always empty.
+#[derive(Debug, Default)]
+pub(crate) struct Maps;
+
+impl Maps {
+    /// Creates a new (empty) maps cache.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn new(_config: MapsConfig) -> Self {
+        Self
+    }
+}
+
+/// A handle to access the status of the (absent) maps refresher.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) type RefresherStatusHandle = Arc<RefresherStatus>;
+
+/// The status of the maps refresher, which never runs.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+#[derive(Debug, Default, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct RefresherStatus;
+
+impl RefresherStatus {
+    /// Returns whether the refresher is healthy, which is always the case because there is none.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn is_healthy(&self) -> bool {
+        true
+    }
+
+    /// Returns whether the refresher is still starting up, which is never the case because there
+    /// is none.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn is_starting(&self, _maps: &Maps, _now: DateTime<Utc>) -> bool {
+        false
+    }
+}
@@ -0,0 +1,267 @@
+//! Chat notifications.
+//!
+//! This module provides a task that periodically evaluates the forecasts for the configured
+//! watches, i.e. positions with a metric and threshold. Whenever the forecast exceeds the
+//! threshold, e.g. because rain is imminent or the pollen count is very high, a message is posted
+//! once per period to the configured Matrix room and/or Telegram chat.
+
+use std::collections::BTreeSet;
+
+use chrono::Utc;
+use rocket::serde::json::json;
+use rocket::tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+use crate::config::{MatrixConfig, NotifierConfig, TelegramConfig, WatchConfig};
+use crate::forecast::{forecast, Metric, Units};
+use crate::i18n::Locale;
+use crate::labels;
+use crate::maps::MapsHandle;
+use crate::position::Position;
+use crate::thresholds::{periods, Period};
+use crate::{http, Error, Result};
+
+/// The interval between evaluations of the watches.
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The base URL of the Telegram Bot API.
+const TELEGRAM_BASE_URL: &str = "https://api.telegram.org";
+
+/// Returns the message for a period during which the forecast of a watch exceeds its threshold.
+fn message(watch: &WatchConfig, period: &Period) -> String {
+    let place = watch
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("{:.4}, {:.4}", watch.lat, watch.lon));
+    let unit = |metric: Metric| {
+        metric
+            .unit(Units::Metric)
+            .map(|unit| format!(" {unit}"))
+            .unwrap_or_default()
+    };
+    let label = labels::label(watch.metric, period.max_value, Locale::En)
+        .map(|label| format!(" ({label})"))
+        .unwrap_or_default();
+
+    format!(
+        "The {metric} forecast for {place} exceeds {threshold}{unit} from {start} until {end} UTC \
+         with a maximum of {max_value}{unit}{label}.",
+        metric = watch.metric,
+        threshold = watch.threshold,
+        unit = unit(watch.metric),
+        start = period.start.format("%H:%M"),
+        end = period.end.format("%H:%M"),
+        max_value = period.max_value,
+    )
+}
+
+/// Returns the URL to send a message with the provided transaction ID to a Matrix room.
+fn matrix_url(config: &MatrixConfig, transaction_id: &str) -> String {
+    format!(
+        "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+        config.homeserver.as_str().trim_end_matches('/'),
+        encode_path_segment(&config.room_id),
+        encode_path_segment(transaction_id)
+    )
+}
+
+/// Percent-encodes all but the unreserved characters of a URL path segment.
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Posts the message to the Matrix room.
+async fn post_matrix(config: &MatrixConfig, text: &str) -> Result<()> {
+    // The transaction ID makes retries of the same message idempotent.
+    let transaction_id = format!("sinoptik-{}", Utc::now().timestamp_micros());
+    let url = matrix_url(config, &transaction_id);
+    let body = json!({ "msgtype": "m.text", "body": text });
+    http::client()
+        .put_json_authorized(&url, &config.access_token, &body)
+        .await?;
+
+    Ok(())
+}
+
+/// Posts the message to the Telegram chat.
+async fn post_telegram(config: &TelegramConfig, text: &str) -> Result<()> {
+    let url = format!("{TELEGRAM_BASE_URL}/bot{}/sendMessage", config.bot_token);
+    let body = json!({ "chat_id": config.chat_id, "text": text });
+    http::client().post_json(&url, &body).await?;
+
+    Ok(())
+}
+
+/// A chat that messages are posted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Chat {
+    /// The Matrix room.
+    Matrix,
+    /// The Telegram chat.
+    Telegram,
+}
+
+impl Chat {
+    /// Returns the chats that are configured.
+    fn configured(config: &NotifierConfig) -> Vec<Self> {
+        [
+            (Self::Matrix, config.matrix.is_some()),
+            (Self::Telegram, config.telegram.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(chat, configured)| configured.then_some(chat))
+        .collect()
+    }
+}
+
+/// Posts the message to the chat (if configured).
+///
+/// Posting to Matrix is retried with an exponential backoff, see
+/// [`HttpClient`](crate::http::HttpClient); posting to Telegram is not retried as it is not
+/// idempotent.
+async fn post(config: &NotifierConfig, chat: Chat, text: &str) -> Result<()> {
+    match (chat, &config.matrix, &config.telegram) {
+        (Chat::Matrix, Some(matrix), _) => post_matrix(matrix, text).await,
+        (Chat::Telegram, _, Some(telegram)) => post_telegram(telegram, text).await,
+        _ => Ok(()),
+    }
+}
+
+/// Evaluates the forecast for a watch and posts messages for new periods that exceed the
+/// threshold.
+///
+/// The chats and starts of the periods that have been notified are kept track of in
+/// `notified_periods`. If posting to a chat fails, it is logged and the period is posted to that
+/// chat again at the next evaluation, without posting it to the other chats again.
+async fn evaluate(
+    config: &NotifierConfig,
+    watch: &WatchConfig,
+    notified_periods: &mut BTreeSet<(Chat, i64)>,
+    maps_handle: &MapsHandle,
+) -> Result<()> {
+    let metric = watch.metric;
+    let position = Position::new(watch.lat, watch.lon);
+    let forecast = forecast(position, Vec::from([metric]), maps_handle).await;
+    let series = forecast.series(metric).ok_or_else(|| {
+        let error = forecast.error(metric).unwrap_or_default();
+        Error::MetricFailed(metric, error.to_owned())
+    })?;
+
+    let chats = Chat::configured(config);
+    for period in periods(&series, watch.threshold) {
+        let start = period.start.timestamp();
+        let text = message(watch, &period);
+        for &chat in &chats {
+            if notified_periods.contains(&(chat, start)) {
+                continue;
+            }
+
+            info!(%metric, ?chat, lat = watch.lat, lon = watch.lon, "💬 Posting chat notification");
+            match post(config, chat, &text).await {
+                Ok(()) => {
+                    notified_periods.insert((chat, start));
+                }
+                Err(e) => error!(
+                    %metric,
+                    ?chat,
+                    error = %e,
+                    "💥 Encountered error while posting chat notification"
+                ),
+            }
+        }
+    }
+
+    // Notified periods that have started more than a day ago are forgotten.
+    let too_old = Utc::now().timestamp() - 24 * 3_600;
+    notified_periods.retain(|&(_, start)| start > too_old);
+
+    Ok(())
+}
+
+/// Runs a loop that keeps evaluating the watches and posts messages when necessary.
+pub(crate) async fn run(config: NotifierConfig, maps_handle: MapsHandle) {
+    if config.matrix.is_none() && config.telegram.is_none() {
+        error!("💥 No Matrix room or Telegram chat configured for the notifier");
+        return;
+    }
+
+    let mut notified_periods = vec![BTreeSet::new(); config.watches.len()];
+    loop {
+        for (watch, notified_periods) in config.watches.iter().zip(&mut notified_periods) {
+            if let Err(e) = evaluate(&config, watch, notified_periods, &maps_handle).await {
+                error!(
+                    metric = %watch.metric,
+                    error = %e,
+                    "💥 Encountered error during chat notification"
+                );
+            }
+        }
+
+        sleep(EVALUATION_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use reqwest::Url;
+
+    use super::*;
+
+    #[test]
+    fn message() {
+        let mut watch = WatchConfig {
+            name: Some(String::from("Utrecht")),
+            lat: 52.0905,
+            lon: 5.1109,
+            metric: Metric::Precipitation,
+            threshold: 0.1,
+        };
+        let period = Period {
+            start: Utc.with_ymd_and_hms(2024, 6, 1, 14, 5, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 6, 1, 14, 40, 0).unwrap(),
+            max_value: 2.5,
+        };
+        assert_eq!(
+            super::message(&watch, &period),
+            "The precipitation forecast for Utrecht exceeds 0.1 mm/h from 14:05 until 14:40 UTC \
+             with a maximum of 2.5 mm/h (moderate rain)."
+        );
+
+        // Without a name, the coordinates are used; indices have no unit.
+        watch.name = None;
+        watch.metric = Metric::Pollen;
+        watch.threshold = 7.0;
+        let period = Period {
+            max_value: 9.0,
+            ..period
+        };
+        assert_eq!(
+            super::message(&watch, &period),
+            "The pollen forecast for 52.0905, 5.1109 exceeds 7 from 14:05 until 14:40 UTC \
+             with a maximum of 9 (very high)."
+        );
+    }
+
+    #[test]
+    fn matrix_url() {
+        let config = MatrixConfig {
+            homeserver: Url::parse("https://matrix.example.org/").unwrap(),
+            access_token: String::from("token"),
+            room_id: String::from("!room:example.org"),
+        };
+        assert_eq!(
+            super::matrix_url(&config, "sinoptik-1"),
+            "https://matrix.example.org/_matrix/client/v3/rooms/%21room%3Aexample.org/send/\
+             m.room.message/sinoptik-1"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Precipitation nowcasts.
+//!
+//! This module derives when it starts or stops raining from the precipitation items, which is what
+//! most "do I need to leave now" clients are after. Whether it rains is determined by comparing the
+//! precipitation with a configurable threshold.
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Duration, Utc};
+use rocket::serde::Serialize;
+
+use crate::config::Config;
+
+/// The precipitation (in mm/h) at or above which it is considered to be raining.
+static RAIN_THRESHOLD: OnceLock<f32> = OnceLock::new();
+
+/// Sets the precipitation (in mm/h) at or above which it is considered to be raining.
+///
+/// This needs to be done before the first nowcast is derived, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_rain_threshold(rain_threshold: f32) {
+    let _ = RAIN_THRESHOLD.set(rain_threshold);
+}
+
+/// Returns the precipitation (in mm/h) at or above which it is considered to be raining.
+///
+/// If it has not been set, the default threshold is used.
+fn rain_threshold() -> f32 {
+    *RAIN_THRESHOLD.get_or_init(|| Config::default().rain_threshold)
+}
+
+/// When it starts or stops raining.
+///
+/// The times are only present if the precipitation crosses the rain threshold within the series.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Nowcast {
+    /// Whether it is raining now.
+    raining: bool,
+
+    /// The time it starts raining, if it is dry now (in seconds since the UNIX epoch).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rain_starts_at: Option<i64>,
+
+    /// The time it stops raining (in seconds since the UNIX epoch).
+    ///
+    /// If it is dry now, this is when the first upcoming rain stops.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rain_stops_at: Option<i64>,
+
+    /// The time it stays dry until, if it is dry now (in seconds since the UNIX epoch).
+    ///
+    /// This is when it starts raining, or the end of the series if it stays dry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dry_until: Option<i64>,
+}
+
+impl Nowcast {
+    /// Derives the nowcast from the series of precipitation items at the provided time, using the
+    /// configured rain threshold.
+    ///
+    /// Items that have ended before now are ignored; each item is assumed to last for the provided
+    /// cadence (in seconds). Returns [`None`] if there are no current or upcoming items.
+    pub(crate) fn new(
+        series: &[(DateTime<Utc>, f32)],
+        cadence: i64,
+        now: DateTime<Utc>,
+    ) -> Option<Self> {
+        Self::with_threshold(series, cadence, now, rain_threshold())
+    }
+
+    /// Derives the nowcast from the series of precipitation items using the provided threshold.
+    fn with_threshold(
+        series: &[(DateTime<Utc>, f32)],
+        cadence: i64,
+        now: DateTime<Utc>,
+        threshold: f32,
+    ) -> Option<Self> {
+        let cadence = Duration::seconds(cadence);
+        let series = series
+            .iter()
+            .filter(|(time, _)| *time + cadence > now)
+            .collect::<Vec<_>>();
+        let (_, first_value) = series.first()?;
+        let raining = *first_value >= threshold;
+        let is_raining = |value: f32| value >= threshold;
+        let crossing = |from: usize, raining: bool| {
+            series
+                .iter()
+                .skip(from)
+                .position(|(_, value)| is_raining(*value) == raining)
+                .map(|index| from + index)
+        };
+
+        let mut nowcast = Nowcast {
+            raining,
+            ..Default::default()
+        };
+        if raining {
+            nowcast.rain_stops_at = crossing(0, false).map(|index| series[index].0.timestamp());
+        } else {
+            let starts = crossing(0, true);
+            nowcast.rain_starts_at = starts.map(|index| series[index].0.timestamp());
+            nowcast.rain_stops_at = starts
+                .and_then(|starts| crossing(starts, false))
+                .map(|index| series[index].0.timestamp());
+            nowcast.dry_until = nowcast
+                .rain_starts_at
+                .or_else(|| series.last().map(|(time, _)| (*time + cadence).timestamp()));
+        }
+
+        Some(nowcast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn nowcast() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 4, 10, 12, 0, 0).unwrap();
+        let t = |minutes| t_0 + Duration::minutes(minutes);
+        let nowcast = |values: &[f32], now| {
+            let series = values
+                .iter()
+                .enumerate()
+                .map(|(index, &value)| (t(5 * index as i64), value))
+                .collect::<Vec<_>>();
+
+            Nowcast::with_threshold(&series, 300, now, 0.1)
+        };
+
+        // It is dry now, starts raining in 10 minutes and stops 10 minutes later.
+        assert_eq!(
+            nowcast(&[0.0, 0.0, 0.5, 1.2, 0.0, 0.0], t(0)),
+            Some(Nowcast {
+                raining: false,
+                rain_starts_at: Some(t(10).timestamp()),
+                rain_stops_at: Some(t(20).timestamp()),
+                dry_until: Some(t(10).timestamp()),
+            })
+        );
+
+        // It is raining now and stops in 10 minutes; items that have ended are ignored.
+        assert_eq!(
+            nowcast(&[0.0, 0.3, 0.2, 0.0, 0.8], t(6)),
+            Some(Nowcast {
+                raining: true,
+                rain_starts_at: None,
+                rain_stops_at: Some(t(15).timestamp()),
+                dry_until: None,
+            })
+        );
+
+        // It stays dry (below the threshold) until the end of the series.
+        assert_eq!(
+            nowcast(&[0.0, 0.05, 0.0], t(0)),
+            Some(Nowcast {
+                raining: false,
+                rain_starts_at: None,
+                rain_stops_at: None,
+                dry_until: Some(t(15).timestamp()),
+            })
+        );
+
+        // Without current or upcoming items, there is no nowcast.
+        assert_eq!(nowcast(&[0.0, 0.0], t(10)), None);
+        assert_eq!(nowcast(&[], t(0)), None);
+    }
+}
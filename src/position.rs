@@ -2,14 +2,38 @@
 //!
 //! This module contains everything related to geographic coordinate system functionality.
 
+use std::collections::BTreeMap;
+#[cfg(feature = "maps")]
 use std::f64::consts::PI;
+use std::future::Future;
 use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 
 use cached::proc_macro::cached;
-use geocoding::{Forward, Openstreetmap, Point};
-use rocket::tokio;
+use cached::TimedSizedCache;
+use chrono::Utc;
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+use tracing::warn;
 
-use crate::{Error, Result};
+use crate::config::GeocodingCacheConfig;
+use crate::geocoder::{geocoder, Candidate};
+use crate::i18n::Locale;
+use crate::snapshot::{Snapshot, SnapshotVersions};
+use crate::{shared_cache, Error, Result};
+
+/// The maximum number of geocoding candidates resolved for an address.
+const MAX_GEOCODING_CANDIDATES: usize = 10;
+
+/// The maximum number of suggestions resolved for a search query.
+pub(crate) const MAX_SEARCH_SUGGESTIONS: usize = 10;
+
+/// The minimum number of characters of a search query.
+const MIN_SEARCH_QUERY_LENGTH: usize = 3;
+
+/// The cache of geocoded positions of addresses.
+static GEOCODING_CACHE: OnceLock<Mutex<GeocodingCache>> = OnceLock::new();
 
 /// A (geocoded) position.
 ///
@@ -22,17 +46,17 @@ use crate::{Error, Result};
 /// up to the 5th decimal, we consider them the same (see [`Position::lat_as_i32`] and
 /// [`Position::lon_as_i32`]).
 #[derive(Clone, Copy, Debug, Default)]
-pub(crate) struct Position {
+pub struct Position {
     /// The latitude of the position.
-    pub(crate) lat: f64,
+    pub lat: f64,
 
     /// The longitude of the position.
-    pub(crate) lon: f64,
+    pub lon: f64,
 }
 
 impl Position {
     /// Creates a new (geocoded) position.
-    pub(crate) const fn new(lat: f64, lon: f64) -> Self {
+    pub const fn new(lat: f64, lon: f64) -> Self {
         Self { lat, lon }
     }
 
@@ -40,7 +64,7 @@ impl Position {
     ///
     /// This is achieved by multiplying it by `10_000` and rounding it.  Thus, this gives a
     /// precision of 5 decimals.
-    fn lat_as_i32(&self) -> i32 {
+    pub(crate) fn lat_as_i32(&self) -> i32 {
         (self.lat * 10_000.0).round() as i32
     }
 
@@ -48,20 +72,34 @@ impl Position {
     ///
     /// This is achieved by multiplying it by `10_000` and rounding it.  Thus, this gives a
     /// precision of 5 decimals.
-    fn lon_as_i32(&self) -> i32 {
+    pub(crate) fn lon_as_i32(&self) -> i32 {
         (self.lon * 10_000.0).round() as i32
     }
 
     /// Returns the latitude in radians.
+    #[cfg(feature = "maps")]
     pub(crate) fn lat_as_rad(&self) -> f64 {
         self.lat * PI / 180.0
     }
 
     /// Returns the longitude in radians.
+    #[cfg(feature = "maps")]
     pub(crate) fn lon_as_rad(&self) -> f64 {
         self.lon * PI / 180.0
     }
 
+    /// Returns the position snapped to the nearest point of a grid of the given size (in degrees).
+    ///
+    /// If the size is not positive, the position is returned as is.
+    pub(crate) fn snap(&self, grid: f64) -> Self {
+        if grid <= 0.0 {
+            return *self;
+        }
+        let snap = |coord: f64| (coord / grid).round() * grid;
+
+        Self::new(snap(self.lat), snap(self.lon))
+    }
+
     /// Returns the latitude as a string with the given precision.
     pub(crate) fn lat_as_str(&self, precision: usize) -> String {
         format!("{:.*}", precision, self.lat)
@@ -73,13 +111,6 @@ impl Position {
     }
 }
 
-impl From<&Point<f64>> for Position {
-    fn from(point: &Point<f64>) -> Self {
-        // The `geocoding` API always returns (longitude, latitude) as (x, y).
-        Position::new(point.y(), point.x())
-    }
-}
-
 impl Hash for Position {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         // Floats cannot be hashed. Use the 5-decimal precision integer representation of the
@@ -97,21 +128,482 @@ impl PartialEq for Position {
 
 impl Eq for Position {}
 
+/// A bounding box of geocoded positions.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct BoundingBox {
+    /// The minimum latitude (south).
+    pub(crate) min_lat: f64,
+
+    /// The minimum longitude (west).
+    pub(crate) min_lon: f64,
+
+    /// The maximum latitude (north).
+    pub(crate) max_lat: f64,
+
+    /// The maximum longitude (east).
+    pub(crate) max_lon: f64,
+}
+
+impl BoundingBox {
+    /// Returns whether the bounding box contains the provided position.
+    pub(crate) fn contains(&self, position: Position) -> bool {
+        (self.min_lat..=self.max_lat).contains(&position.lat)
+            && (self.min_lon..=self.max_lon).contains(&position.lon)
+    }
+}
+
+/// A geocoded position of an address in the geocoding cache.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct CachedPosition {
+    /// The latitude of the position.
+    lat: f64,
+
+    /// The longitude of the position.
+    lon: f64,
+
+    /// The (UNIX) timestamp of when the address was resolved.
+    resolved_at: i64,
+
+    /// The (UNIX) timestamp of when the position was last used.
+    last_used: i64,
+}
+
+/// The cache of geocoded positions of addresses.
+///
+/// Only the configured number of least recently used addresses are kept and positions expire after
+/// the configured time. If a file path is set, the cache is persisted to it after every change, see
+/// [`Snapshot`].
+#[derive(Debug, Default)]
+pub(crate) struct GeocodingCache {
+    /// The path of the file the cache is persisted to (if any).
+    path: Option<PathBuf>,
+
+    /// The versions of the snapshots of the cache.
+    versions: SnapshotVersions,
+
+    /// The maximum number of cached addresses.
+    size: usize,
+
+    /// The time (in seconds) after which a cached position expires.
+    expiry: i64,
+
+    /// The cached positions by address.
+    positions: BTreeMap<String, CachedPosition>,
+}
+
+impl GeocodingCache {
+    /// Loads the geocoding cache from the configured file, if any.
+    ///
+    /// If the file does not exist yet, the cache is empty. If the file cannot be read, e.g.
+    /// because it is corrupt, this is logged and the cache is empty too, because it only saves
+    /// geocoding requests. Expired positions are dropped.
+    pub(crate) fn load(config: &GeocodingCacheConfig) -> Self {
+        let positions = match &config.file {
+            Some(path) if path.exists() => Self::read(path).unwrap_or_else(|e| {
+                warn!(
+                    path = %path.display(),
+                    error = %e,
+                    "💥 Failed to load the geocoding cache, starting with an empty cache"
+                );
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        let mut cache = Self {
+            path: config.file.clone(),
+            size: config.size,
+            expiry: config.expiry as i64,
+            positions,
+            ..Default::default()
+        };
+        let now = Utc::now().timestamp();
+        cache
+            .positions
+            .retain(|_, position| now - position.resolved_at < cache.expiry);
+
+        cache
+    }
+
+    /// Reads the cached positions from the file at the provided path.
+    fn read(path: &Path) -> Result<BTreeMap<String, CachedPosition>> {
+        let data = std::fs::read_to_string(path)?;
+
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Returns a snapshot of the changed cache to persist to the file, if any.
+    fn snapshot(&mut self) -> Result<Snapshot> {
+        self.versions
+            .snapshot(self.path.as_deref(), &self.positions)
+    }
+
+    /// Returns the cached position for the provided address, if any and not expired.
+    fn get(&mut self, address: &str) -> Option<Position> {
+        let now = Utc::now().timestamp();
+        let cached_position = self.positions.get_mut(address)?;
+        if now - cached_position.resolved_at >= self.expiry {
+            return None;
+        }
+        cached_position.last_used = now;
+
+        Some(Position::new(cached_position.lat, cached_position.lon))
+    }
+
+    /// Inserts the resolved position for the provided address and returns the snapshot to persist.
+    ///
+    /// If the cache is full, expired positions and then the least recently used positions are
+    /// evicted.
+    fn insert(&mut self, address: String, position: Position) -> Result<Snapshot> {
+        let now = Utc::now().timestamp();
+        let cached_position = CachedPosition {
+            lat: position.lat,
+            lon: position.lon,
+            resolved_at: now,
+            last_used: now,
+        };
+        self.positions.insert(address, cached_position);
+        self.positions
+            .retain(|_, position| now - position.resolved_at < self.expiry);
+        while self.positions.len() > self.size {
+            let least_recently_used = self
+                .positions
+                .iter()
+                .min_by_key(|(_, position)| position.last_used)
+                .map(|(address, _)| address.clone())
+                .expect("Cache is not empty");
+            self.positions.remove(&least_recently_used);
+        }
+
+        self.snapshot()
+    }
+}
+
+/// Sets the geocoding cache.
+///
+/// This needs to be done before the first address is resolved, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_geocoding_cache(cache: GeocodingCache) {
+    let _ = GEOCODING_CACHE.set(Mutex::new(cache));
+}
+
+/// Returns the geocoding cache.
+///
+/// If it has not been set, an in-memory cache with the default configuration is used.
+fn geocoding_cache() -> &'static Mutex<GeocodingCache> {
+    GEOCODING_CACHE.get_or_init(|| {
+        let config = GeocodingCacheConfig::default();
+
+        Mutex::new(GeocodingCache::load(&config))
+    })
+}
+
+/// Resolves the geocoded position for the provided geocoding cache key.
+///
+/// If the position is not in the geocoding cache, it is retrieved using `retrieve`. If the result
+/// is [`Ok`], it will be cached in the geocoding cache.
+async fn resolve_cached<F, Fut>(key: String, retrieve: F) -> Result<Position>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Position>>,
+{
+    let cached_position = geocoding_cache()
+        .lock()
+        .expect("Geocoding cache mutex was poisoned")
+        .get(&key);
+    if let Some(position) = cached_position {
+        return Ok(position);
+    }
+
+    let shared_key = format!("geocoding:{key}");
+    let ttl = geocoding_cache()
+        .lock()
+        .expect("Geocoding cache mutex was poisoned")
+        .expiry as u64;
+    let output = shared_cache::get_or_retrieve(&shared_key, ttl, || async {
+        let position = retrieve().await?;
+
+        Ok(serde_json::to_string(&(position.lat, position.lon))?)
+    })
+    .await?;
+    let (lat, lon) = serde_json::from_str(&output)?;
+    let position = Position::new(lat, lon);
+
+    // The cache is persisted after the lock has been released.
+    let snapshot = geocoding_cache()
+        .lock()
+        .expect("Geocoding cache mutex was poisoned")
+        .insert(key, position);
+    let result = match snapshot {
+        Ok(snapshot) => snapshot.write().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!(error = %e, "💥 Encountered error during persisting of the geocoding cache");
+    }
+
+    Ok(position)
+}
+
 /// Resolves the geocoded position for a given address.
 ///
-/// If the result is [`Ok`], it will be cached.
-/// Note that only the 100 least recently used addresses will be cached.
-#[cached(size = 100, result = true)]
+/// If the result is [`Ok`], it will be cached in the geocoding cache.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
 pub(crate) async fn resolve_address(address: String) -> Result<Position> {
-    println!("🌍 Geocoding the position of the address: {}", address);
-    tokio::task::spawn_blocking(move || {
-        let osm = Openstreetmap::new();
-        let points: Vec<Point<f64>> = osm.forward(&address)?;
-
-        points
-            .first()
-            .ok_or(Error::NoPositionFound)
-            .map(Position::from)
+    resolve_cached(address.clone(), || geocoder().forward(&address)).await
+}
+
+/// Resolves the geocoding candidates for a given address.
+///
+/// The candidates are ordered from most to least plausible. If the result is [`Ok`], it will be
+/// cached for the given address using the same size and expiry as the geocoding cache.
+#[cached(
+    ty = "TimedSizedCache<String, Vec<Candidate>>",
+    create = "{
+        let cache = geocoding_cache().lock().expect(\"Geocoding cache mutex was poisoned\");
+        TimedSizedCache::with_size_and_lifespan(cache.size.max(1), cache.expiry as u64)
+    }",
+    result = true
+)]
+pub(crate) async fn resolve_candidates(address: String) -> Result<Vec<Candidate>> {
+    let key = format!("geocoding-candidates:{address}");
+    let ttl = geocoding_cache()
+        .lock()
+        .expect("Geocoding cache mutex was poisoned")
+        .expiry as u64;
+    let output = shared_cache::get_or_retrieve(&key, ttl, || async {
+        let candidates = geocoder()
+            .search(&address, MAX_GEOCODING_CANDIDATES, None)
+            .await?;
+
+        Ok(serde_json::to_string(&candidates)?)
     })
-    .await?
+    .await?;
+    let candidates: Vec<Candidate> = serde_json::from_str(&output)?;
+    if candidates.is_empty() {
+        return Err(Error::NoPositionFound);
+    }
+
+    Ok(candidates)
+}
+
+/// Resolves the place suggestions for a (partial) search query, e.g. "eind".
+///
+/// The query is normalized first, so that differently cased variants share the cached result.
+/// The names of the suggestions are preferably in the language of the given locale. Unlike for
+/// geocoding candidates, it is not an error if nothing is found; this fails if the query is too
+/// short to give meaningful suggestions though.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn resolve_suggestions(query: &str, locale: Locale) -> Result<Vec<Candidate>> {
+    let query = query.trim().to_lowercase();
+    if query.chars().count() < MIN_SEARCH_QUERY_LENGTH {
+        return Err(Error::QueryTooShort(MIN_SEARCH_QUERY_LENGTH));
+    }
+
+    search_suggestions(query, locale).await
+}
+
+/// Searches for the place suggestions for a normalized search query.
+///
+/// If the result is [`Ok`], it will be cached for the given query and locale using the same size
+/// and expiry as the geocoding cache.
+#[cached(
+    ty = "TimedSizedCache<(String, Locale), Vec<Candidate>>",
+    create = "{
+        let cache = geocoding_cache().lock().expect(\"Geocoding cache mutex was poisoned\");
+        TimedSizedCache::with_size_and_lifespan(cache.size.max(1), cache.expiry as u64)
+    }",
+    result = true
+)]
+async fn search_suggestions(query: String, locale: Locale) -> Result<Vec<Candidate>> {
+    let language = locale.language_code();
+    let key = format!("geocoding-suggestions:{language}:{query}");
+    let ttl = geocoding_cache()
+        .lock()
+        .expect("Geocoding cache mutex was poisoned")
+        .expiry as u64;
+    let output = shared_cache::get_or_retrieve(&key, ttl, || async {
+        let suggestions = match geocoder()
+            .search(&query, MAX_SEARCH_SUGGESTIONS, Some(language))
+            .await
+        {
+            Err(Error::OutsideGeocodingArea) => Vec::new(),
+            result => result?,
+        };
+
+        Ok(serde_json::to_string(&suggestions)?)
+    })
+    .await?;
+
+    Ok(serde_json::from_str(&output)?)
+}
+
+/// Resolves the geocoded position of the geocoding candidate with the given index for an address.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn resolve_candidate(address: String, index: usize) -> Result<Position> {
+    resolve_candidates(address)
+        .await?
+        .get(index)
+        .map(Candidate::position)
+        .ok_or(Error::CandidateNotFound(index))
+}
+
+/// Normalizes a Dutch postal code, e.g. "5611 ab" becomes "5611AB".
+///
+/// Returns [`None`] if it is not a valid Dutch postal code, i.e. four digits not starting with
+/// zero followed by two letters.
+fn normalize_postcode(postcode: &str) -> Option<String> {
+    let postcode = postcode
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let (digits, letters) = postcode.split_at_checked(4)?;
+    let is_valid = !digits.starts_with('0')
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && letters.len() == 2
+        && letters.chars().all(|c| c.is_ascii_uppercase());
+
+    is_valid.then_some(postcode)
+}
+
+/// Resolves the geocoded position for a given Dutch postal code.
+///
+/// Postal codes are always resolved using PDOK Locatieserver, regardless of the configured
+/// geocoder backend. If the result is [`Ok`], it will be cached in the geocoding cache.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn resolve_postcode(postcode: String) -> Result<Position> {
+    let postcode = normalize_postcode(&postcode).ok_or(Error::InvalidPostcode(postcode))?;
+    let key = format!("postcode:{postcode}");
+
+    resolve_cached(key, || geocoder().postcode(&postcode)).await
+}
+
+/// Resolves the human-readable place name for a given geocoded position.
+///
+/// If the result is [`Ok`], it will be cached for the given position using the same size and
+/// expiry as the geocoding cache.
+#[cached(
+    ty = "TimedSizedCache<Position, String>",
+    create = "{
+        let cache = geocoding_cache().lock().expect(\"Geocoding cache mutex was poisoned\");
+        TimedSizedCache::with_size_and_lifespan(cache.size.max(1), cache.expiry as u64)
+    }",
+    result = true
+)]
+pub(crate) async fn resolve_place_name(position: Position) -> Result<String> {
+    let key = format!(
+        "reverse-geocoding:{},{}",
+        position.lat_as_str(5),
+        position.lon_as_str(5)
+    );
+    let ttl = geocoding_cache()
+        .lock()
+        .expect("Geocoding cache mutex was poisoned")
+        .expiry as u64;
+
+    shared_cache::get_or_retrieve(&key, ttl, || geocoder().reverse(position)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::tokio::runtime::Builder;
+
+    use super::*;
+
+    #[test]
+    fn bounding_box() {
+        let bounding_box = BoundingBox {
+            min_lat: 50.7,
+            min_lon: 3.3,
+            max_lat: 53.6,
+            max_lon: 7.3,
+        };
+        assert!(bounding_box.contains(Position::new(51.44, 5.47))); // Eindhoven
+        assert!(bounding_box.contains(Position::new(50.7, 7.3)));
+        assert!(!bounding_box.contains(Position::new(48.86, 2.35))); // Paris, France
+        assert!(!bounding_box.contains(Position::new(52.0, 8.0)));
+    }
+
+    #[test]
+    fn snap() {
+        let position = Position::new(52.09074, 5.12142);
+
+        // Nearby positions snap to the same grid point.
+        let snapped = position.snap(0.01);
+        assert_eq!(snapped, Position::new(52.09, 5.12));
+        assert_eq!(Position::new(52.0851, 5.1151).snap(0.01), snapped);
+        assert_ne!(Position::new(52.0849, 5.1151).snap(0.01), snapped);
+        assert_eq!(position.snap(0.05), Position::new(52.1, 5.1));
+
+        // Without a grid, the position is not snapped.
+        assert_eq!(position.snap(0.0), position);
+    }
+
+    #[test]
+    fn geocoding_cache() {
+        let path = std::env::temp_dir().join(format!(
+            "sinoptik-test-geocoding-{}.json",
+            std::process::id()
+        ));
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let config = GeocodingCacheConfig {
+            file: Some(path.clone()),
+            size: 2,
+            ..Default::default()
+        };
+        let mut cache = GeocodingCache::load(&config);
+        assert_eq!(cache.get("Utrecht"), None);
+
+        // Inserted positions are cached and persisted.
+        let utrecht = Position::new(52.09, 5.11);
+        let snapshot = cache.insert(String::from("Utrecht"), utrecht).unwrap();
+        runtime.block_on(snapshot.write()).unwrap();
+        assert_eq!(cache.get("Utrecht"), Some(utrecht));
+        let loaded_cache = GeocodingCache::load(&config);
+        assert_eq!(loaded_cache.positions, cache.positions);
+
+        // The least recently used address is evicted if the cache is full.
+        let eindhoven = Position::new(51.44, 5.47);
+        let _snapshot = cache.insert(String::from("Eindhoven"), eindhoven).unwrap();
+        cache.positions.get_mut("Eindhoven").unwrap().last_used -= 10;
+        let _snapshot = cache
+            .insert(String::from("Amsterdam"), Position::new(52.37, 4.9))
+            .unwrap();
+        assert_eq!(cache.positions.len(), 2);
+        assert_eq!(cache.get("Eindhoven"), None);
+        assert_eq!(cache.get("Utrecht"), Some(utrecht));
+
+        // Expired positions are not returned nor loaded.
+        cache.positions.get_mut("Utrecht").unwrap().resolved_at -= cache.expiry;
+        assert_eq!(cache.get("Utrecht"), None);
+        runtime.block_on(cache.snapshot().unwrap().write()).unwrap();
+        let loaded_cache = GeocodingCache::load(&config);
+        assert_eq!(loaded_cache.positions.len(), 1);
+
+        // A corrupt file results in an empty cache.
+        std::fs::write(&path, "{\"Utrecht\": {").unwrap();
+        let loaded_cache = GeocodingCache::load(&config);
+        assert!(loaded_cache.positions.is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn normalize_postcode() {
+        assert_eq!(
+            super::normalize_postcode("5611AB").as_deref(),
+            Some("5611AB")
+        );
+        assert_eq!(
+            super::normalize_postcode(" 5611 ab").as_deref(),
+            Some("5611AB")
+        );
+        assert_eq!(super::normalize_postcode("0611AB"), None);
+        assert_eq!(super::normalize_postcode("5611A"), None);
+        assert_eq!(super::normalize_postcode("5611ABC"), None);
+        assert_eq!(super::normalize_postcode("56111A"), None);
+        assert_eq!(super::normalize_postcode("5611ÅB"), None);
+    }
 }
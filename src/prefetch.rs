@@ -0,0 +1,167 @@
+//! Scheduled prefetching of forecasts.
+//!
+//! This module provides a task that proactively retrieves the forecasts for the configured
+//! positions, so that the provider caches are always warm for them and forecast requests for these
+//! positions are answered straight from the caches. The cached results of each metric are replaced
+//! shortly before they would expire, according to the cache time of the metric.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use rocket::tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+use crate::config::PrefetchedPositionConfig;
+use crate::forecast::{forecast, Metric};
+use crate::maps::MapsHandle;
+use crate::position::Position;
+use crate::{providers, shared_cache};
+
+/// The minimum interval between refreshes of the cached results of a metric.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Returns the interval between refreshes of the cached results with the provided cache time.
+///
+/// The results are refreshed once 90% of the cache time has passed, so that they are replaced
+/// before they expire, but at most once per minute.
+fn refresh_interval(cache_ttl: u64) -> Duration {
+    Duration::from_secs(cache_ttl - cache_ttl / 10).max(MIN_REFRESH_INTERVAL)
+}
+
+/// The times the cached results are due to be refreshed per (index of the) position and metric.
+#[derive(Debug, Default)]
+struct Schedule(BTreeMap<(usize, Metric), Instant>);
+
+impl Schedule {
+    /// Creates a schedule for the provided positions, with all metrics due at the provided time.
+    ///
+    /// If no metrics are configured for a position, all metrics are prefetched.
+    fn new(positions: &[PrefetchedPositionConfig], now: Instant) -> Self {
+        let due_times = positions
+            .iter()
+            .enumerate()
+            .flat_map(|(index, prefetched)| {
+                let metrics =
+                    if prefetched.metrics.is_empty() || prefetched.metrics.contains(&Metric::All) {
+                        Metric::all()
+                    } else {
+                        prefetched.metrics.clone()
+                    };
+
+                metrics
+                    .into_iter()
+                    .map(move |metric| ((index, metric), now))
+            })
+            .collect();
+
+        Self(due_times)
+    }
+
+    /// Returns the metrics that are due at the provided time per (index of the) position.
+    fn due(&self, now: Instant) -> BTreeMap<usize, Vec<Metric>> {
+        let mut due = BTreeMap::<usize, Vec<Metric>>::new();
+        for (&(index, metric), _) in self.0.iter().filter(|(_, due_time)| **due_time <= now) {
+            due.entry(index).or_default().push(metric);
+        }
+
+        due
+    }
+
+    /// Schedules the next refresh of a metric of a position at the provided time.
+    fn reschedule(&mut self, index: usize, metric: Metric, due_time: Instant) {
+        self.0.insert((index, metric), due_time);
+    }
+
+    /// Returns the time the next refresh is due, if any.
+    fn next(&self) -> Option<Instant> {
+        self.0.values().min().copied()
+    }
+}
+
+/// Runs a loop that keeps the cached forecasts for the configured positions warm.
+///
+/// When the cached results of a metric for a position are due to be refreshed, they are purged and
+/// retrieved again from the upstream APIs right away, bypassing the shared cache, instead of
+/// waiting for them to expire and be retrieved during a request.
+pub(crate) async fn run(positions: Vec<PrefetchedPositionConfig>, maps_handle: MapsHandle) {
+    let cache_ttls = providers::cache_ttls();
+    let mut schedule = Schedule::new(&positions, Instant::now());
+    while let Some(next) = schedule.next() {
+        sleep(next.saturating_duration_since(Instant::now())).await;
+
+        let now = Instant::now();
+        for (index, metrics) in schedule.due(now) {
+            let position = Position::new(positions[index].lat, positions[index].lon);
+            info!(
+                lat = position.lat,
+                lon = position.lon,
+                ?metrics,
+                "🔥 Prefetching the forecast"
+            );
+            let lookup_position = providers::lookup_position(position);
+            for &metric in &metrics {
+                providers::purge_caches(None, Some(metric), Some(lookup_position)).await;
+            }
+
+            // The shared cache may still hold the results that are about to expire.
+            let forecast =
+                shared_cache::bypass(forecast(position, metrics.clone(), &maps_handle)).await;
+            for metric in metrics {
+                if let Some(error) = forecast.error(metric) {
+                    warn!(%metric, error, "💥 Failed to prefetch the forecast");
+                }
+                let interval = refresh_interval(cache_ttls.for_metric(metric));
+                schedule.reschedule(index, metric, now + interval);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_interval() {
+        assert_eq!(super::refresh_interval(3_600), Duration::from_secs(3_240));
+        assert_eq!(super::refresh_interval(300), Duration::from_secs(270));
+        assert_eq!(super::refresh_interval(30), MIN_REFRESH_INTERVAL);
+    }
+
+    #[test]
+    #[cfg(feature = "maps")]
+    fn schedule() {
+        let positions = [
+            PrefetchedPositionConfig {
+                lat: 51.4,
+                lon: 5.5,
+                metrics: vec![Metric::Pollen, Metric::UVI],
+            },
+            PrefetchedPositionConfig {
+                lat: 52.0905,
+                lon: 5.1109,
+                metrics: Vec::new(),
+            },
+        ];
+        let now = Instant::now();
+        let mut schedule = Schedule::new(&positions, now);
+        assert_eq!(schedule.next(), Some(now));
+
+        // Initially, all metrics are due; no metrics means all metrics.
+        let due = schedule.due(now);
+        assert_eq!(due[&0], vec![Metric::Pollen, Metric::UVI]);
+        assert_eq!(due[&1], Metric::all());
+
+        // Rescheduled metrics are no longer due until their next refresh.
+        let later = now + Duration::from_secs(300);
+        schedule.reschedule(0, Metric::Pollen, later);
+        assert_eq!(schedule.due(now)[&0], vec![Metric::UVI]);
+        assert_eq!(schedule.due(later)[&0], vec![Metric::Pollen, Metric::UVI]);
+        schedule.reschedule(0, Metric::UVI, later);
+        for metric in Metric::all() {
+            schedule.reschedule(1, metric, later);
+        }
+        assert!(schedule.due(now).is_empty());
+        assert_eq!(schedule.next(), Some(later));
+    }
+}
@@ -0,0 +1,78 @@
+//! Protocol Buffers serialization.
+//!
+//! This module is used to serialize responses as [Protocol Buffers](https://protobuf.dev/)
+//! messages, following the schema in `assets/sinoptik.proto`, so that strongly-typed clients can
+//! generate their models from it. The message types are generated from the schema by the build
+//! script. Like for the [binary formats](crate::binary), the response is serialized to a JSON
+//! value first, which is then converted to the message type using the JSON names of its fields.
+
+use rocket::serde::json::Value;
+use rocket::serde::Deserialize;
+
+use crate::Result;
+
+/// The message types of the schema.
+pub(crate) mod messages {
+    include!(concat!(env!("OUT_DIR"), "/sinoptik.v1.rs"));
+}
+
+/// The schema (in the Protocol Buffers language) that is published.
+pub(crate) const SCHEMA: &str = include_str!("../assets/sinoptik.proto");
+
+/// Serializes the JSON value as message of the provided message type.
+///
+/// This fails if the value has keys that are not a field of the message type (or of the nested
+/// message types), so that they cannot be left out silently; null values are left out.
+pub(crate) fn to_protobuf<'a, M>(value: &'a Value) -> Result<Vec<u8>>
+where
+    M: prost::Message + Deserialize<'a>,
+{
+    let message = M::deserialize(value)?;
+
+    Ok(message.encode_to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use rocket::serde::json::json;
+
+    use super::messages::{Forecast, Item, Sample};
+
+    #[test]
+    fn to_protobuf() {
+        let value = json!({"time": 1, "value": 1.5, "label": "x", "exceeds": true});
+        assert_eq!(
+            super::to_protobuf::<Item>(&value).unwrap(),
+            [0x08, 0x01, 0x15, 0x00, 0x00, 0xc0, 0x3f, 0x1a, 0x01, b'x', 0x20, 0x01]
+        );
+
+        // Fields are mapped by their JSON names and null values are left out.
+        let value = json!({
+            "location": null,
+            "time": -1,
+            "UVI": [{"time": 300, "value": 2, "confidence": 1}],
+            "errors": {"AQI": "e"},
+        });
+        let data = super::to_protobuf::<Forecast>(&value).unwrap();
+        let forecast = Forecast::decode(data.as_slice()).unwrap();
+        assert_eq!(forecast.location, None);
+        assert_eq!(forecast.time, -1);
+        assert_eq!(
+            forecast.uvi,
+            [Sample {
+                time: 300,
+                value: 2.0,
+                confidence: 1.0,
+                ..Default::default()
+            }]
+        );
+        assert_eq!(forecast.errors["AQI"], "e");
+
+        // Keys that are not a field are rejected, also in nested messages.
+        let value = json!({"time": 1, "extra": 1});
+        assert!(super::to_protobuf::<Item>(&value).is_err());
+        let value = json!({"lat": 1.0, "UVI": [{"time": 1, "extra": 2}]});
+        assert!(super::to_protobuf::<Forecast>(&value).is_err());
+    }
+}
@@ -3,5 +3,465 @@
 //! Data is either provided via a direct (JSON) API or via looking up values on maps.
 
 pub(crate) mod buienradar;
+// Without the providers that call upstream APIs, the circuit breakers are only reported.
+#[cfg_attr(
+    not(any(feature = "buienradar", feature = "luchtmeetnet")),
+    allow(dead_code)
+)]
+pub(crate) mod circuit_breaker;
 pub(crate) mod combined;
 pub(crate) mod luchtmeetnet;
+pub(crate) mod mock;
+pub(crate) mod open_meteo;
+#[cfg(any(feature = "buienradar", feature = "luchtmeetnet"))]
+pub(crate) mod single_flight;
+
+use std::hash::Hash;
+use std::sync::{Mutex, OnceLock};
+
+use cached::{Cached, SizedCache, TimedSizedCache};
+use chrono::{DateTime, Duration, Utc};
+use rocket::serde::Serialize;
+use tracing::{info, warn};
+
+use self::circuit_breaker::CircuitBreakerStatus;
+use crate::config::{BaseUrls, CacheCapacities, CacheTtls, Config};
+use crate::forecast::Metric;
+use crate::position::Position;
+use crate::Result;
+
+/// The maximum number of last known good results that are kept per provider.
+const LAST_KNOWN_GOOD_SIZE: usize = 1_000;
+
+/// The base URLs of the upstream APIs.
+static BASE_URLS: OnceLock<BaseUrls> = OnceLock::new();
+
+/// The size (in degrees) of the grid that positions are snapped to for provider lookups.
+static POSITION_GRID: OnceLock<f64> = OnceLock::new();
+
+/// The maximum number of entries of the provider caches.
+static CACHE_CAPACITIES: OnceLock<CacheCapacities> = OnceLock::new();
+
+/// The times (in seconds) the provider results are cached for.
+static CACHE_TTLS: OnceLock<CacheTtls> = OnceLock::new();
+
+/// Sets the size (in degrees) of the grid that positions are snapped to for provider lookups.
+///
+/// This needs to be done before the first data is retrieved, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_position_grid(position_grid: f64) {
+    let _ = POSITION_GRID.set(position_grid);
+}
+
+/// Returns the position to use for provider lookups for the provided position.
+///
+/// The position is snapped to the configured grid, so that nearby positions share cached results
+/// and upstream requests. If the grid has not been set, the default grid is used.
+pub(crate) fn lookup_position(position: Position) -> Position {
+    let grid = POSITION_GRID.get_or_init(|| Config::default().position_grid);
+
+    position.snap(*grid)
+}
+
+/// Sets the maximum number of entries of the provider caches.
+///
+/// This needs to be done before the first results are cached, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_cache_capacities(cache_capacities: CacheCapacities) {
+    let _ = CACHE_CAPACITIES.set(cache_capacities);
+}
+
+/// Returns the maximum number of entries of the provider caches.
+///
+/// If these have not been set, the defaults are used.
+pub(crate) fn cache_capacities() -> &'static CacheCapacities {
+    CACHE_CAPACITIES.get_or_init(CacheCapacities::default)
+}
+
+/// Sets the times the provider results are cached for.
+///
+/// This needs to be done before the first results are cached, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_cache_ttls(cache_ttls: CacheTtls) {
+    let _ = CACHE_TTLS.set(cache_ttls);
+}
+
+/// Returns the times the provider results are cached for.
+///
+/// If these have not been set, the defaults are used.
+pub(crate) fn cache_ttls() -> &'static CacheTtls {
+    CACHE_TTLS.get_or_init(CacheTtls::default)
+}
+
+/// Sets the base URLs of the upstream APIs.
+///
+/// This needs to be done before the first data is retrieved, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_base_urls(base_urls: BaseUrls) {
+    let _ = BASE_URLS.set(base_urls);
+}
+
+/// Returns the base URLs of the upstream APIs.
+///
+/// If these have not been set, the defaults are used.
+#[cfg(any(feature = "buienradar", feature = "luchtmeetnet"))]
+pub(crate) fn base_urls() -> &'static BaseUrls {
+    BASE_URLS.get_or_init(BaseUrls::default)
+}
+
+/// The supported metric data providers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, rocket::FromFormField)]
+pub(crate) enum Provider {
+    /// Buienradar (precipitation, pollen and UV index).
+    Buienradar,
+
+    /// The combination of Buienradar and Luchtmeetnet (PAQI).
+    Combined,
+
+    /// Luchtmeetnet (air quality).
+    Luchtmeetnet,
+
+    /// Open-Meteo (precipitation consensus).
+    #[field(value = "open_meteo")]
+    OpenMeteo,
+}
+
+/// The statistics of a provider cache.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct CacheStats {
+    /// The name of the cache.
+    name: &'static str,
+
+    /// The number of entries in the cache.
+    size: usize,
+
+    /// The maximum number of entries in the cache.
+    capacity: usize,
+
+    /// The ratio of the number of entries to the capacity of the cache.
+    occupancy: f64,
+
+    /// The number of cache hits.
+    hits: u64,
+
+    /// The number of cache misses.
+    misses: u64,
+
+    /// The ratio of cache hits to all lookups (if there have been any).
+    hit_rate: Option<f64>,
+}
+
+impl CacheStats {
+    /// Collects the statistics of the provided cache.
+    fn new<K: Clone + Hash + Eq, V>(name: &'static str, cache: &TimedSizedCache<K, V>) -> Self {
+        let hits = cache.cache_hits().unwrap_or_default();
+        let misses = cache.cache_misses().unwrap_or_default();
+        let lookups = hits + misses;
+        let size = cache.cache_size();
+        let capacity = cache.cache_capacity().unwrap_or(size).max(1);
+
+        Self {
+            name,
+            size,
+            capacity,
+            occupancy: size as f64 / capacity as f64,
+            hits,
+            misses,
+            hit_rate: (lookups > 0).then(|| hits as f64 / lookups as f64),
+        }
+    }
+}
+
+/// Removes the entries of which the key matches from the provided cache.
+///
+/// Returns the number of removed entries.
+fn purge<K, V, F>(cache: &mut TimedSizedCache<K, V>, matches: F) -> usize
+where
+    K: Clone + Hash + Eq,
+    F: Fn(&K) -> bool,
+{
+    let keys = cache
+        .key_order()
+        .filter(|key| matches(key))
+        .cloned()
+        .collect::<Vec<_>>();
+    for key in &keys {
+        cache.cache_remove(key);
+    }
+
+    keys.len()
+}
+
+/// Returns the statistics of the caches of all providers.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn cache_stats() -> Vec<CacheStats> {
+    let mut stats = Vec::new();
+    #[cfg(feature = "buienradar")]
+    stats.extend(buienradar::cache_stats().await);
+    stats.push(combined::cache_stats().await);
+    #[cfg(feature = "luchtmeetnet")]
+    stats.push(luchtmeetnet::cache_stats().await);
+    #[cfg(feature = "buienradar")]
+    stats.push(open_meteo::cache_stats().await);
+
+    stats
+}
+
+/// Purges the cached results of the providers.
+///
+/// The purge can be restricted to a specific provider, metric and/or position.
+/// Returns the number of purged entries.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) async fn purge_caches(
+    provider: Option<Provider>,
+    metric: Option<Metric>,
+    position: Option<Position>,
+) -> usize {
+    let selected = |p: Provider| provider.is_none_or(|provider| provider == p);
+    let mut count = 0;
+    #[cfg(feature = "buienradar")]
+    if selected(Provider::Buienradar) {
+        count += buienradar::purge_cache(metric, position).await;
+    }
+    if selected(Provider::Combined) {
+        count += combined::purge_cache(metric, position).await;
+    }
+    #[cfg(feature = "luchtmeetnet")]
+    if selected(Provider::Luchtmeetnet) {
+        count += luchtmeetnet::purge_cache(metric, position).await;
+    }
+    #[cfg(feature = "buienradar")]
+    if selected(Provider::OpenMeteo) {
+        count += open_meteo::purge_cache(metric, position).await;
+    }
+    info!(
+        ?provider,
+        ?metric,
+        ?position,
+        count,
+        "🧹 Purged provider caches"
+    );
+
+    count
+}
+
+/// Returns the status of the circuit breakers of all providers that call upstream APIs.
+#[allow(clippy::vec_init_then_push)]
+#[cfg_attr(
+    not(any(feature = "buienradar", feature = "luchtmeetnet")),
+    allow(unused_mut)
+)]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn circuit_breakers() -> Vec<CircuitBreakerStatus> {
+    let mut statuses = Vec::new();
+    #[cfg(feature = "buienradar")]
+    statuses.push(buienradar::circuit_breaker_status());
+    #[cfg(feature = "luchtmeetnet")]
+    statuses.push(luchtmeetnet::circuit_breaker_status());
+    #[cfg(feature = "buienradar")]
+    statuses.push(open_meteo::circuit_breaker_status());
+
+    statuses
+}
+
+/// The last known good values per position and metric.
+type LastKnownGoodCache<V> = SizedCache<(Position, Metric), LastKnownGoodEntry<V>>;
+
+/// A last known good value, along with the times it was first and last retrieved.
+#[derive(Clone, Debug)]
+struct LastKnownGoodEntry<V> {
+    /// The value.
+    value: V,
+
+    /// The time the value was first retrieved.
+    retrieved_at: DateTime<Utc>,
+
+    /// The time the value was last retrieved, i.e. confirmed to be current.
+    confirmed_at: DateTime<Utc>,
+}
+
+/// A provider result along with its freshness.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Retrieved<V> {
+    /// The retrieved value.
+    pub(crate) value: V,
+
+    /// The time the value was first retrieved.
+    pub(crate) retrieved_at: DateTime<Utc>,
+
+    /// The time the value was last retrieved, i.e. confirmed to be current.
+    pub(crate) confirmed_at: DateTime<Utc>,
+
+    /// Whether the value is stale, i.e. retrieving a fresh value failed.
+    pub(crate) stale: bool,
+}
+
+/// The last known good results of a provider per position and metric.
+///
+/// When retrieving a fresh result fails, the last known good result is used instead (if any and
+/// not too old).
+#[derive(Debug)]
+pub(crate) struct LastKnownGood<V> {
+    /// The last known good values and the times they were retrieved.
+    results: Mutex<LastKnownGoodCache<V>>,
+}
+
+impl<V: Clone + PartialEq> LastKnownGood<V> {
+    /// Creates a new, empty store of last known good results.
+    pub(crate) fn new() -> Self {
+        Self {
+            results: Mutex::new(SizedCache::with_size(LAST_KNOWN_GOOD_SIZE)),
+        }
+    }
+
+    /// Resolves the result of a provider for the provided position and metric.
+    ///
+    /// If the result is [`Ok`], it is recorded as the last known good result. Otherwise, the last
+    /// known good result is returned marked as stale, or the error if there is none or it was last
+    /// retrieved longer than the maximum staleness ago.
+    pub(crate) fn resolve(
+        &self,
+        position: Position,
+        metric: Metric,
+        result: Result<V>,
+        max_staleness: Duration,
+    ) -> Result<Retrieved<V>> {
+        self.resolve_at(position, metric, result, max_staleness, Utc::now())
+    }
+
+    /// Resolves the result of a provider at the provided time, see [`LastKnownGood::resolve`].
+    fn resolve_at(
+        &self,
+        position: Position,
+        metric: Metric,
+        result: Result<V>,
+        max_staleness: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<Retrieved<V>> {
+        let mut results = self
+            .results
+            .lock()
+            .expect("Last known good results mutex was poisoned");
+        let key = (position, metric);
+
+        match result {
+            Ok(value) => {
+                let retrieved_at = match results.cache_get(&key) {
+                    Some(entry) if entry.value == value => entry.retrieved_at,
+                    _ => now,
+                };
+                let entry = LastKnownGoodEntry {
+                    value: value.clone(),
+                    retrieved_at,
+                    confirmed_at: now,
+                };
+                results.cache_set(key, entry);
+
+                Ok(Retrieved {
+                    value,
+                    retrieved_at,
+                    confirmed_at: now,
+                    stale: false,
+                })
+            }
+            Err(err) => match results.cache_get(&key) {
+                Some(entry) if now - entry.confirmed_at <= max_staleness => {
+                    warn!(
+                        %metric,
+                        error = %err,
+                        retrieved_at = %entry.retrieved_at,
+                        "💥 Encountered error during retrieval, using stale data"
+                    );
+
+                    Ok(Retrieved {
+                        value: entry.value.clone(),
+                        retrieved_at: entry.retrieved_at,
+                        confirmed_at: entry.confirmed_at,
+                        stale: true,
+                    })
+                }
+                _ => Err(err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn cache_stats() {
+        let mut cache = TimedSizedCache::with_size_and_lifespan(2, 60);
+        cache.cache_set(1, "one");
+        cache.cache_get(&1);
+        cache.cache_get(&2);
+
+        // The occupancy is relative to the capacity.
+        let stats = CacheStats::new("test", &cache);
+        assert_eq!((stats.size, stats.capacity), (1, 2));
+        assert_eq!(stats.occupancy, 0.5);
+        assert_eq!(stats.hit_rate, Some(0.5));
+
+        // The least recently used entry is evicted if the cache is full.
+        cache.cache_set(2, "two");
+        cache.cache_set(3, "three");
+        let stats = CacheStats::new("test", &cache);
+        assert_eq!((stats.size, stats.occupancy), (2, 1.0));
+        assert_eq!(cache.cache_get(&1), None);
+
+        // Purging removes the matching entries.
+        assert_eq!(purge(&mut cache, |key| *key > 2), 1);
+        assert_eq!(cache.key_order().collect::<Vec<_>>(), [&2]);
+    }
+
+    #[test]
+    fn last_known_good() {
+        let last_known_good = LastKnownGood::new();
+        let position = Position::new(51.4, 5.5);
+        let max_staleness = Duration::hours(1);
+        let t_0 = Utc::now();
+        let resolve = |metric, result, now| {
+            last_known_good.resolve_at(position, metric, result, max_staleness, now)
+        };
+
+        // Without a last known good result, the error is returned.
+        let result = resolve(Metric::AQI, Err(Error::NoPositionFound), t_0);
+        assert_matches!(result, Err(Error::NoPositionFound));
+
+        // A fresh result is returned and recorded.
+        let retrieved = resolve(Metric::AQI, Ok(1), t_0).unwrap();
+        assert_eq!(retrieved.value, 1);
+        assert!(!retrieved.stale);
+
+        // The last known good result is returned as stale on error, but only for the same
+        // position and metric.
+        let stale = resolve(Metric::AQI, Err(Error::NoPositionFound), t_0).unwrap();
+        assert_eq!(stale.value, 1);
+        assert_eq!(stale.retrieved_at, t_0);
+        assert!(stale.stale);
+        let result = resolve(Metric::NO2, Err(Error::NoPositionFound), t_0);
+        assert_matches!(result, Err(Error::NoPositionFound));
+
+        // Retrieving the same value again keeps the time it was first retrieved, but extends how
+        // long it can be used as stale result.
+        let t_1 = t_0 + Duration::minutes(50);
+        let retrieved = resolve(Metric::AQI, Ok(1), t_1).unwrap();
+        assert_eq!(retrieved.retrieved_at, t_0);
+        assert_eq!(retrieved.confirmed_at, t_1);
+        let t_2 = t_1 + max_staleness;
+        let stale = resolve(Metric::AQI, Err(Error::NoPositionFound), t_2).unwrap();
+        assert_eq!(stale.retrieved_at, t_0);
+        assert_eq!(stale.confirmed_at, t_1);
+        assert!(stale.stale);
+
+        // A last known good result that is too old is not used anymore.
+        let t_3 = t_2 + Duration::seconds(1);
+        let result = resolve(Metric::AQI, Err(Error::NoPositionFound), t_3);
+        assert_matches!(result, Err(Error::NoPositionFound));
+    }
+}
@@ -3,23 +3,159 @@
 //! For more information about Buienradar, see: <https://www.buienradar.nl/overbuienradar/contact>
 //! and <https://www.buienradar.nl/overbuienradar/gratis-weerdata>.
 
+#[cfg(feature = "buienradar")]
+use std::sync::LazyLock;
+#[cfg(feature = "buienradar")]
+use std::time::Instant;
+
+#[cfg(feature = "buienradar")]
 use cached::proc_macro::cached;
+#[cfg(feature = "buienradar")]
+use cached::TimedSizedCache;
 use chrono::serde::ts_seconds;
-use chrono::{DateTime, Datelike, Duration, NaiveTime, ParseError, TimeZone, Utc};
-use chrono_tz::{Europe, Tz};
-use csv::ReaderBuilder;
-use reqwest::Url;
-use rocket::serde::{Deserialize, Serialize};
-
+#[cfg(feature = "buienradar")]
+use chrono::Datelike;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, ParseError, TimeZone, Utc};
+use chrono_tz::Europe;
+#[cfg(feature = "buienradar")]
+use chrono_tz::Tz;
+#[cfg(feature = "buienradar")]
+use csv::{ReaderBuilder, Trim};
+use rocket::serde::{Deserialize, Serialize, Serializer};
+#[cfg(feature = "buienradar")]
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "maps")]
+use crate::maps;
 use crate::maps::MapsHandle;
 use crate::position::Position;
+#[cfg(feature = "buienradar")]
+use crate::providers::circuit_breaker::{CircuitBreaker, CircuitBreakerStatus};
+use crate::providers::mock;
+#[cfg(feature = "buienradar")]
+use crate::providers::single_flight::SingleFlight;
+#[cfg(feature = "buienradar")]
+use crate::providers::CacheStats;
+#[cfg(feature = "buienradar")]
+use crate::{http, providers, shared_cache};
 use crate::{Error, Metric, Result};
 
-/// The base URL for the Buienradar API.
-const BUIENRADAR_BASE_URL: &str = "https://gpsgadget.buienradar.nl/data/raintext";
+/// The default base URL for the Buienradar API.
+pub(crate) const BUIENRADAR_BASE_URL: &str = "https://gpsgadget.buienradar.nl/data/raintext";
+
+/// The default base URL for retrieving the pollen maps from Buienradar.
+pub(crate) const POLLEN_BASE_URL: &str =
+    "https://image.buienradar.nl/2.0/image/sprite/WeatherMapPollenRadarHourlyNL\
+        ?width=820&height=988&extension=png&renderBackground=False&renderBranding=False\
+        &renderText=False&history=0&skip=0";
+
+/// The default base URL for retrieving the precipitation radar maps from Buienradar.
+pub(crate) const PRECIPITATION_BASE_URL: &str =
+    "https://image.buienradar.nl/2.0/image/sprite/RadarMapRainNL\
+        ?width=820&height=988&extension=png&renderBackground=False&renderBranding=False\
+        &renderText=False&history=0&skip=0";
+
+/// The default base URL for retrieving the temperature maps from Buienradar.
+pub(crate) const TEMPERATURE_BASE_URL: &str =
+    "https://image.buienradar.nl/2.0/image/sprite/WeatherMapTemperatureForecastNL\
+        ?width=820&height=988&extension=png&renderBackground=False&renderBranding=False\
+        &renderText=False&history=0&skip=0";
+
+/// The default base URL for retrieving the UV index maps from Buienradar.
+pub(crate) const UVI_BASE_URL: &str =
+    "https://image.buienradar.nl/2.0/image/sprite/WeatherMapUVIndexNL\
+        ?width=820&height=988&extension=png&&renderBackground=False&renderBranding=False\
+        &renderText=False&history=0&skip=0";
+
+/// The circuit breaker for calls to the Buienradar (precipitation) API.
+#[cfg(feature = "buienradar")]
+static CIRCUIT_BREAKER: CircuitBreaker = CircuitBreaker::new("Buienradar");
+
+/// The precipitation lookups in flight.
+#[cfg(feature = "buienradar")]
+static PRECIPITATION_IN_FLIGHT: LazyLock<SingleFlight<Position>> = LazyLock::new(SingleFlight::new);
+
+/// A Buienradar map sample.
+///
+/// This represents a value at a given time.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Sample {
+    /// The time(stamp) of the forecast.
+    #[serde(serialize_with = "ts_seconds::serialize")]
+    pub(crate) time: DateTime<Utc>,
+
+    /// The forecasted score.
+    ///
+    /// A value in the range `1.0..=10.0`, which is only fractional if interpolated.
+    #[serde(rename(serialize = "value"), serialize_with = "serialize_score")]
+    pub(crate) score: f32,
+
+    /// The confidence of the score.
+    ///
+    /// The fraction (in the range `0.0..=1.0`) of the pixels in the sample area that have the
+    /// color of the score. Pixels without a known color, e.g. the background, are included.
+    pub(crate) confidence: f32,
+
+    /// The human-readable classification of the value (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<&'static str>,
+
+    /// Whether the value exceeds the threshold of the metric (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) exceeds: Option<bool>,
+}
+
+impl Sample {
+    #[cfg(test)]
+    pub(crate) fn new(time: DateTime<Utc>, score: u8) -> Self {
+        Self {
+            time,
+            score: f32::from(score),
+            confidence: 1.0,
+            label: None,
+            exceeds: None,
+        }
+    }
+}
+
+/// Serializes a score as an integer, unless it is fractional because it is interpolated.
+fn serialize_score<S: Serializer>(score: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    if score.fract() == 0.0 {
+        serializer.serialize_u8(*score as u8)
+    } else {
+        serializer.serialize_f32(*score)
+    }
+}
+
+/// Linearly interpolates between the provided samples using the provided step.
+///
+/// The maps only provide a sample per map, i.e. per hour for the pollen maps and per day for the
+/// UV index maps. Interpolating the score and confidence between them results in a smoother
+/// series, e.g. for charting. The last sample is kept as is.
+pub(crate) fn interpolate(samples: &[Sample], step: Duration) -> Vec<Sample> {
+    let mut interpolated = Vec::new();
+    for pair in samples.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        let duration = to.time - from.time;
+        let mut offset = Duration::zero();
+        while offset < duration {
+            let fraction = offset.num_seconds() as f32 / duration.num_seconds() as f32;
+            let lerp = |from: f32, to: f32| from + (to - from) * fraction;
+            interpolated.push(Sample {
+                time: from.time + offset,
+                score: lerp(from.score, to.score),
+                confidence: lerp(from.confidence, to.confidence),
+                label: None,
+                exceeds: None,
+            });
+            offset += step;
+        }
+    }
+    interpolated.extend(samples.last().cloned());
 
-/// The Buienradar pollen/UV index map sample.
-pub(crate) type Sample = crate::maps::Sample;
+    interpolated
+}
 
 /// A row in the precipitation text output.
 ///
@@ -27,14 +163,16 @@ pub(crate) type Sample = crate::maps::Sample;
 #[derive(Debug, Deserialize)]
 #[serde(crate = "rocket::serde")]
 struct Row {
-    /// The precipitation value in the range `0..=255`.
-    value: u16,
+    /// The precipitation value in the range `0..=255`; larger values are clamped.
+    value: u32,
 
     /// The time in the `HH:MM` format.
     time: String,
 }
 
-/// The Buienradar API precipitation data item.
+/// A Buienradar data item.
+///
+/// This is either a precipitation item from the API or a temperature item sampled from the maps.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(crate = "rocket::serde", try_from = "Row")]
 pub(crate) struct Item {
@@ -44,41 +182,140 @@ pub(crate) struct Item {
 
     /// The forecasted value.
     ///
-    /// Its unit is mm/h.
+    /// Its unit is mm/h for precipitation and °C for temperature.
     pub(crate) value: f32,
+
+    /// The human-readable classification of the value (when asked for).
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<&'static str>,
+
+    /// Whether the value exceeds the threshold of the metric (when asked for).
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub(crate) exceeds: Option<bool>,
+
+    /// The intensity class of the value (for precipitation only).
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub(crate) intensity: Option<Intensity>,
+
+    /// The total expected precipitation up to and including this item (for precipitation only).
+    ///
+    /// Its unit is mm, or in for imperial units.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub(crate) accumulated: Option<f32>,
 }
 
 impl Item {
-    #[cfg(test)]
+    #[cfg(all(test, feature = "buienradar"))]
     pub(crate) fn new(time: DateTime<Utc>, value: f32) -> Self {
-        Self { time, value }
+        Self {
+            time,
+            value,
+            label: None,
+            exceeds: None,
+            intensity: None,
+            accumulated: None,
+        }
+    }
+}
+
+/// The intensity class of precipitation, following the scale of the KNMI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub(crate) enum Intensity {
+    /// Less than 0.1 mm/h.
+    Dry,
+    /// From 0.1 up to 1 mm/h.
+    Light,
+    /// From 1 up to 5 mm/h.
+    Moderate,
+    /// 5 mm/h or more.
+    Heavy,
+}
+
+impl Intensity {
+    /// Classifies the provided precipitation (in mm/h).
+    fn of(value: f32) -> Self {
+        if value < 0.1 {
+            Intensity::Dry
+        } else if value < 1.0 {
+            Intensity::Light
+        } else if value < 5.0 {
+            Intensity::Moderate
+        } else {
+            Intensity::Heavy
+        }
+    }
+}
+
+/// Derives the intensity class and accumulated precipitation of the provided precipitation items.
+///
+/// The values are expected to be in mm/h. The precipitation is accumulated from the first item on;
+/// each item is assumed to last until the next item, and the last item as long as the one before
+/// it (or the native cadence if there is only one item).
+pub(crate) fn derive_precipitation(items: &mut [Item]) {
+    let mut duration = Metric::Precipitation.cadence();
+    let mut accumulated = 0.0;
+    for index in 0..items.len() {
+        if let Some(next) = items.get(index + 1) {
+            duration = next
+                .time
+                .signed_duration_since(items[index].time)
+                .num_seconds();
+        }
+        let item = &mut items[index];
+        accumulated += item.value * duration as f32 / 3_600.0;
+        item.intensity = Some(Intensity::of(item.value));
+        item.accumulated = Some((accumulated * 100.0).round() / 100.0);
     }
 }
 
+/// The errors that can occur when parsing the time of a row.
+#[derive(Debug, thiserror::Error)]
+enum TimeError {
+    /// The time could not be parsed.
+    #[error("Time parse error: {0}")]
+    Parse(#[from] ParseError),
+
+    /// The time does not exist in the Europe/Amsterdam time zone (during the DST gap).
+    #[error("Nonexistent local time: {0}")]
+    Nonexistent(NaiveDateTime),
+}
+
 impl TryFrom<Row> for Item {
-    type Error = ParseError;
+    type Error = TimeError;
 
     fn try_from(row: Row) -> Result<Self, Self::Error> {
-        let time = parse_time(&row.time)?;
-        let value = convert_value(row.value);
-
-        Ok(Item { time, value })
+        // Interpret the time relative to the current date in the Europe/Amsterdam time zone.
+        let today = Utc::now().with_timezone(&Europe::Amsterdam).date_naive();
+        let time = parse_time(&row.time, today)?;
+        let value = convert_value(row.value.min(255) as u16);
+
+        Ok(Item {
+            time,
+            value,
+            label: None,
+            exceeds: None,
+            intensity: None,
+            accumulated: None,
+        })
     }
 }
 
-/// Parses a time string to date/time in the UTC time zone.
+/// Parses a time string on the provided date to date/time in the UTC time zone.
 ///
 /// The provided time has the format `HH:MM` and is considered to be in the Europe/Amsterdam
-/// time zone.
-fn parse_time(t: &str) -> Result<DateTime<Utc>, ParseError> {
-    // First, get the current date in the Europe/Amsterdam time zone.
-    let today = Utc::now().with_timezone(&Europe::Amsterdam).date_naive();
-    // Then, parse the time and interpret it relative to "today".
+/// time zone. An ambiguous time (during the DST fall-back hour) is interpreted as the earliest
+/// one, a nonexistent time (during the DST spring-forward gap) is an error.
+fn parse_time(t: &str, date: NaiveDate) -> Result<DateTime<Utc>, TimeError> {
+    // First, parse the time and interpret it relative to the date.
     let ntime = NaiveTime::parse_from_str(t, "%H:%M")?;
-    let ndtime = today.and_time(ntime);
-    // Finally, interpret the naive date/time in the Europe/Amsterdam time zone and convert it to
+    let ndtime = date.and_time(ntime);
+    // Then, interpret the naive date/time in the Europe/Amsterdam time zone and convert it to
     // the UTC time zone.
-    let ldtime = Europe::Amsterdam.from_local_datetime(&ndtime).unwrap();
+    let ldtime = Europe::Amsterdam
+        .from_local_datetime(&ndtime)
+        .earliest()
+        .ok_or(TimeError::Nonexistent(ndtime))?;
     let dtime = ldtime.with_timezone(&Utc);
 
     Ok(dtime)
@@ -99,6 +336,7 @@ fn convert_value(v: u16) -> f32 {
 /// If in the Europe/Amsterdam time zone it is still before 0:00, all timestamps after 0:00 need to
 /// be bumped up with a day. If it is already after 0:00, all timestamps before 0:00 need to be
 /// bumped back with a day.
+#[cfg(feature = "buienradar")]
 fn fix_items_day_boundary(items: Vec<Item>, now: DateTime<Tz>) -> Vec<Item> {
     // Use noon on the same day as "now" as a comparison moment.
     let noon = Europe::Amsterdam
@@ -131,25 +369,102 @@ fn fix_items_day_boundary(items: Vec<Item>, now: DateTime<Tz>) -> Vec<Item> {
     }
 }
 
+/// Parses the precipitation text output into items.
+///
+/// Each row has the format `VALUE|HH:MM`. Blank lines and surrounding whitespace are ignored and
+/// values above 255 are clamped. Malformed rows are skipped instead of failing the whole output.
+///
+/// Returns the items and the number of skipped rows.
+#[cfg(feature = "buienradar")]
+fn parse_precipitation(output: &str) -> (Vec<Item>, usize) {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(b'|')
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(output.as_bytes());
+    let mut items = Vec::new();
+    let mut dropped = 0;
+    for record in rdr.records() {
+        let item = record.and_then(|record| {
+            if record.iter().all(str::is_empty) {
+                return Ok(None);
+            }
+
+            record.deserialize::<Item>(None).map(Some)
+        });
+        match item {
+            Ok(Some(item)) => items.push(item),
+            Ok(None) => {}
+            Err(_) => dropped += 1,
+        }
+    }
+
+    (items, dropped)
+}
+
 /// Retrieves the Buienradar forecasted precipitation items for the provided position.
 ///
-/// If the result is [`Ok`] it will be cached for 5 minutes for the the given position.
-#[cached(time = 300, result = true)]
+/// If the result is [`Ok`] it will be cached for the given position for the configured time
+/// (5 minutes by default).
+#[cfg(feature = "buienradar")]
+#[cached(
+    ty = "TimedSizedCache<Position, Vec<Item>>",
+    create = "{
+        let (capacity, ttl) = (providers::cache_capacities().precipitation, providers::cache_ttls().precipitation);
+        TimedSizedCache::with_size_and_lifespan(capacity.max(1), ttl)
+    }",
+    result = true
+)]
 async fn get_precipitation(position: Position) -> Result<Vec<Item>> {
-    let mut url = Url::parse(BUIENRADAR_BASE_URL).unwrap();
+    let mut url = providers::base_urls().buienradar.clone();
     url.query_pairs_mut()
         .append_pair("lat", &position.lat_as_str(2))
         .append_pair("lon", &position.lon_as_str(2));
 
-    println!("▶️  Retrieving Buienradar data from: {url}");
-    let response = reqwest::get(url).await?;
-    let output = response.error_for_status()?.text().await?;
-
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .from_reader(output.as_bytes());
-    let items: Vec<Item> = rdr.deserialize().collect::<Result<_, _>>()?;
+    let key = format!("buienradar:{url}");
+    let ttl = providers::cache_ttls().precipitation;
+    let output = shared_cache::get_or_retrieve(&key, ttl, || {
+        CIRCUIT_BREAKER.call(|| async {
+            info!(provider = "Buienradar", %url, "▶️  Retrieving data");
+            let start = Instant::now();
+            let response = http::client().get(url.clone()).await?;
+            // The data is plain text, but errors are reported using HTML pages.
+            if http::media_type(response.headers()).as_deref() == Some("text/html") {
+                return Err(Error::UnexpectedContentType(
+                    "Buienradar",
+                    url.to_string(),
+                    String::from("text/html"),
+                ));
+            }
+            let output = response.text().await?;
+            debug!(
+                provider = "Buienradar",
+                duration_ms = start.elapsed().as_millis() as u64,
+                "Retrieved data"
+            );
+
+            Ok(output)
+        })
+    })
+    .await?;
+
+    let (items, dropped) = parse_precipitation(&output);
+    if dropped > 0 {
+        warn!(
+            provider = "Buienradar",
+            %url,
+            dropped,
+            "💥 Dropped malformed precipitation rows"
+        );
+    }
+    if items.is_empty() && dropped > 0 {
+        return Err(Error::InvalidResponse(
+            "Buienradar",
+            url.to_string(),
+            format!("all {dropped} rows are malformed"),
+        ));
+    }
 
     // Check if the first item stamp is (timewise) later than the last item stamp.
     // In this case `parse_time` interpreted e.g. 23:00 and later 0:30 in the same day and some
@@ -170,51 +485,141 @@ async fn get_precipitation(position: Position) -> Result<Vec<Item>> {
 
 /// Retrieves the Buienradar forecasted pollen samples for the provided position.
 ///
-/// If the result is [`Ok`] if will be cached for 1 hour for the given position.
+/// If the result is [`Ok`] if will be cached for the given position for the configured time
+/// (1 hour by default).
+#[cfg(feature = "maps")]
 #[cached(
-    time = 3_600,
-    key = "Position",
+    ty = "TimedSizedCache<Position, Vec<Sample>>",
+    create = "{
+        let (capacity, ttl) = (providers::cache_capacities().pollen, providers::cache_ttls().pollen);
+        TimedSizedCache::with_size_and_lifespan(capacity.max(1), ttl)
+    }",
     convert = r#"{ position }"#,
     result = true
 )]
 async fn get_pollen(position: Position, maps_handle: &MapsHandle) -> Result<Vec<Sample>> {
-    maps_handle
-        .lock()
-        .expect("Maps handle mutex was poisoned")
-        .pollen_samples(position)
+    maps::process(maps_handle, move |maps| maps.pollen_samples(position))
+        .await
         .map_err(Into::into)
 }
 
 /// Retrieves the Buienradar forecasted UV index samples for the provided position.
 ///
-/// If the result is [`Ok`] if will be cached for 1 day for the given position.
+/// If the result is [`Ok`] if will be cached for the given position for the configured time
+/// (1 day by default).
+#[cfg(feature = "maps")]
 #[cached(
-    time = 86_400,
-    key = "Position",
+    ty = "TimedSizedCache<Position, Vec<Sample>>",
+    create = "{
+        let (capacity, ttl) = (providers::cache_capacities().uvi, providers::cache_ttls().uvi);
+        TimedSizedCache::with_size_and_lifespan(capacity.max(1), ttl)
+    }",
     convert = r#"{ position }"#,
     result = true
 )]
 async fn get_uvi(position: Position, maps_handle: &MapsHandle) -> Result<Vec<Sample>> {
-    maps_handle
-        .lock()
-        .expect("Maps handle mutex was poisoned")
-        .uvi_samples(position)
+    maps::process(maps_handle, move |maps| maps.uvi_samples(position))
+        .await
+        .map_err(Into::into)
+}
+
+/// Retrieves the Buienradar forecasted temperature items sampled from the maps for the provided
+/// position.
+///
+/// If the result is [`Ok`] if will be cached for the given position for the configured time
+/// (1 hour by default).
+#[cfg(feature = "maps")]
+#[cached(
+    ty = "TimedSizedCache<Position, Vec<Item>>",
+    create = "{
+        let (capacity, ttl) = (providers::cache_capacities().temperature, providers::cache_ttls().temperature);
+        TimedSizedCache::with_size_and_lifespan(capacity.max(1), ttl)
+    }",
+    convert = r#"{ position }"#,
+    result = true
+)]
+async fn get_temperature(position: Position, maps_handle: &MapsHandle) -> Result<Vec<Item>> {
+    maps::process(maps_handle, move |maps| maps.temperature_items(position))
+        .await
         .map_err(Into::into)
 }
 
+/// Returns the status of the Buienradar circuit breaker.
+#[cfg(feature = "buienradar")]
+pub(crate) fn circuit_breaker_status() -> CircuitBreakerStatus {
+    CIRCUIT_BREAKER.status()
+}
+
+/// Returns the statistics of the Buienradar caches.
+#[cfg(feature = "buienradar")]
+#[cfg_attr(not(feature = "maps"), allow(unused_mut))]
+pub(crate) async fn cache_stats() -> Vec<CacheStats> {
+    let mut stats = Vec::from([CacheStats::new(
+        "buienradar_precipitation",
+        &*GET_PRECIPITATION.lock().await,
+    )]);
+    #[cfg(feature = "maps")]
+    stats.extend([
+        CacheStats::new("buienradar_pollen", &*GET_POLLEN.lock().await),
+        CacheStats::new("buienradar_temperature", &*GET_TEMPERATURE.lock().await),
+        CacheStats::new("buienradar_uvi", &*GET_UVI.lock().await),
+    ]);
+
+    stats
+}
+
+/// Purges the cached Buienradar results, optionally only for a metric and/or position.
+///
+/// Returns the number of purged entries.
+#[cfg(feature = "buienradar")]
+pub(crate) async fn purge_cache(metric: Option<Metric>, position: Option<Position>) -> usize {
+    let selected = |m: Metric| metric.is_none_or(|metric| metric == m);
+    let matches = |p: &Position| position.is_none_or(|position| *p == position);
+    let mut count = 0;
+    if selected(Metric::Precipitation) {
+        count += providers::purge(&mut *GET_PRECIPITATION.lock().await, matches);
+    }
+    #[cfg(feature = "maps")]
+    if selected(Metric::Pollen) {
+        count += providers::purge(&mut *GET_POLLEN.lock().await, matches);
+    }
+    #[cfg(feature = "maps")]
+    if selected(Metric::Temperature) {
+        count += providers::purge(&mut *GET_TEMPERATURE.lock().await, matches);
+    }
+    #[cfg(feature = "maps")]
+    if selected(Metric::UVI) {
+        count += providers::purge(&mut *GET_UVI.lock().await, matches);
+    }
+
+    count
+}
+
 /// Retrieves the Buienradar forecasted map samples for the provided position.
 ///
 /// It only supports the following metric:
 /// * [`Metric::Pollen`]
 /// * [`Metric::UVI`]
+///
+/// If the `maps` feature is disabled, this always fails for these metrics. If the mock provider is
+/// enabled, synthetic samples are returned instead.
+#[cfg_attr(not(feature = "maps"), allow(unused_variables))]
 pub(crate) async fn get_samples(
     position: Position,
     metric: Metric,
     maps_handle: &MapsHandle,
 ) -> Result<Vec<Sample>> {
+    if mock::is_enabled() {
+        return mock::buienradar_samples(position, metric);
+    }
+
     match metric {
+        #[cfg(feature = "maps")]
         Metric::Pollen => get_pollen(position, maps_handle).await,
+        #[cfg(feature = "maps")]
         Metric::UVI => get_uvi(position, maps_handle).await,
+        #[cfg(not(feature = "maps"))]
+        Metric::Pollen | Metric::UVI => Err(Error::MetricDisabled(metric)),
         _ => Err(Error::UnsupportedMetric(metric)),
     }
 }
@@ -224,14 +629,55 @@ pub(crate) async fn get_samples(
 /// It only supports the following metric:
 /// * [`Metric::Precipitation`]
 ///
+/// Concurrent lookups for the same position share one upstream request. If the `buienradar`
+/// feature is disabled, this always fails for this metric. If the mock provider is enabled,
+/// synthetic items are returned instead.
+#[cfg_attr(not(feature = "buienradar"), allow(unused_variables))]
 pub(crate) async fn get_items(position: Position, metric: Metric) -> Result<Vec<Item>> {
+    if mock::is_enabled() {
+        return mock::buienradar_items(position, metric);
+    }
+
     match metric {
-        Metric::Precipitation => get_precipitation(position).await,
+        #[cfg(feature = "buienradar")]
+        Metric::Precipitation => {
+            PRECIPITATION_IN_FLIGHT
+                .run(position, || get_precipitation(position))
+                .await
+        }
+        #[cfg(not(feature = "buienradar"))]
+        Metric::Precipitation => Err(Error::MetricDisabled(metric)),
         _ => Err(Error::UnsupportedMetric(metric)),
     }
 }
 
-#[cfg(test)]
+/// Retrieves the Buienradar forecasted items sampled from the maps for the provided position.
+///
+/// It only supports the following metric:
+/// * [`Metric::Temperature`]
+///
+/// If the `maps` feature is disabled, this always fails for this metric. If the mock provider is
+/// enabled, synthetic items are returned instead.
+#[cfg_attr(not(feature = "maps"), allow(unused_variables))]
+pub(crate) async fn get_map_items(
+    position: Position,
+    metric: Metric,
+    maps_handle: &MapsHandle,
+) -> Result<Vec<Item>> {
+    if mock::is_enabled() {
+        return mock::buienradar_items(position, metric);
+    }
+
+    match metric {
+        #[cfg(feature = "maps")]
+        Metric::Temperature => get_temperature(position, maps_handle).await,
+        #[cfg(not(feature = "maps"))]
+        Metric::Temperature => Err(Error::MetricDisabled(metric)),
+        _ => Err(Error::UnsupportedMetric(metric)),
+    }
+}
+
+#[cfg(all(test, feature = "buienradar"))]
 mod tests {
     use super::*;
 
@@ -292,4 +738,108 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn parse_precipitation() {
+        // Blank lines and surrounding whitespace are ignored, large values are clamped and
+        // malformed rows are skipped.
+        let output = "000|10:00\r\n 077 | 10:05 \n\n   \n300|10:10\nabc|10:15\n141|25:61\n\n";
+        let (items, dropped) = super::parse_precipitation(output);
+        let values = items.iter().map(|item| item.value).collect::<Vec<_>>();
+        assert_eq!(values, [0.0, 0.1, convert_value(255)]);
+        assert_eq!(dropped, 2);
+
+        // The well-formed output of the API is parsed completely.
+        let (items, dropped) = super::parse_precipitation("109|10:00\n141|10:05\n");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].value, 10.0);
+        assert_eq!(dropped, 0);
+
+        // Around the DST boundaries, ambiguous times are interpreted as the earliest one and
+        // nonexistent times are errors instead of panics.
+        let fall_back = NaiveDate::from_ymd_opt(2024, 10, 27).unwrap();
+        assert_eq!(
+            parse_time("02:30", fall_back).unwrap(),
+            Utc.with_ymd_and_hms(2024, 10, 27, 0, 30, 0).unwrap()
+        );
+        let spring_forward = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_matches::assert_matches!(
+            parse_time("02:30", spring_forward),
+            Err(TimeError::Nonexistent(_))
+        );
+        assert_eq!(
+            parse_time("03:00", spring_forward).unwrap(),
+            Utc.with_ymd_and_hms(2024, 3, 31, 1, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn interpolate() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let t_1 = t_0 + Duration::hours(1);
+        let samples = [Sample::new(t_0, 2), Sample::new(t_1, 4)];
+
+        // The scores are interpolated linearly between the samples, the last sample is kept.
+        let interpolated = super::interpolate(&samples, Duration::minutes(15));
+        let scores = interpolated
+            .iter()
+            .map(|sample| (sample.time, sample.score))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scores,
+            [
+                (t_0, 2.0),
+                (t_0 + Duration::minutes(15), 2.5),
+                (t_0 + Duration::minutes(30), 3.0),
+                (t_0 + Duration::minutes(45), 3.5),
+                (t_1, 4.0)
+            ]
+        );
+        assert!(super::interpolate(&[], Duration::minutes(15)).is_empty());
+
+        // Whole scores are serialized as integers, fractional ones as floats.
+        let json = rocket::serde::json::serde_json::to_string(&interpolated[..2]).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"[{{"time":{},"value":2,"confidence":1.0}},{{"time":{},"value":2.5,"confidence":1.0}}]"#,
+                t_0.timestamp(),
+                t_0.timestamp() + 900
+            )
+        );
+    }
+
+    #[test]
+    fn derive_precipitation() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let t = |minutes| t_0 + Duration::minutes(minutes);
+        let mut items = [
+            Item::new(t(0), 0.0),
+            Item::new(t(5), 0.6),
+            Item::new(t(10), 2.4),
+            Item::new(t(15), 6.0),
+        ];
+
+        // The items are classified and the precipitation accumulates per 5 minutes; the last item
+        // lasts as long as the one before it.
+        super::derive_precipitation(&mut items);
+        let derived = items
+            .iter()
+            .map(|item| (item.intensity.unwrap(), item.accumulated.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            derived,
+            [
+                (Intensity::Dry, 0.0),
+                (Intensity::Light, 0.05),
+                (Intensity::Moderate, 0.25),
+                (Intensity::Heavy, 0.75)
+            ]
+        );
+
+        // A single item lasts for the native cadence.
+        let mut items = [Item::new(t(0), 1.2)];
+        super::derive_precipitation(&mut items);
+        assert_eq!(items[0].accumulated, Some(0.1));
+    }
 }
@@ -0,0 +1,252 @@
+//! Circuit breakers for calls to the upstream APIs of the providers.
+//!
+//! If calls to the API of a provider keep failing, further calls are short-circuited for a while
+//! so that forecasts fail fast instead of waiting for the API to time out.
+
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use chrono::{DateTime, Duration, Utc};
+use rocket::serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::CircuitBreakerConfig;
+use crate::{http, Error, Result};
+
+/// The configuration of the circuit breakers of the providers.
+static CONFIG: OnceLock<CircuitBreakerConfig> = OnceLock::new();
+
+/// Sets the configuration of the circuit breakers of the providers.
+///
+/// This needs to be done before the first data is retrieved, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_config(config: CircuitBreakerConfig) {
+    let _ = CONFIG.set(config);
+}
+
+/// Returns the configuration of the circuit breakers of the providers.
+///
+/// If it has not been set, the defaults are used.
+fn config() -> &'static CircuitBreakerConfig {
+    CONFIG.get_or_init(CircuitBreakerConfig::default)
+}
+
+/// The state of a circuit breaker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub(crate) enum CircuitState {
+    /// Calls are made as usual.
+    Closed,
+
+    /// Calls are short-circuited, because the provider failed repeatedly.
+    Open,
+
+    /// The cooldown has passed; the next call is a trial call.
+    HalfOpen,
+}
+
+/// The status of a circuit breaker.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct CircuitBreakerStatus {
+    /// The name of the provider.
+    provider: &'static str,
+
+    /// The state of the circuit breaker.
+    state: CircuitState,
+
+    /// The number of consecutive failed calls.
+    failures: u32,
+}
+
+/// The (mutable) state of a circuit breaker.
+#[derive(Debug)]
+struct CircuitBreakerState {
+    /// The number of consecutive failed calls.
+    failures: u32,
+
+    /// The time the circuit breaker was opened or the last trial call was made (if open).
+    opened_at: Option<DateTime<Utc>>,
+}
+
+/// A circuit breaker for calls to the upstream API of a provider.
+///
+/// After a configured number of consecutive failed calls, the circuit breaker opens and calls are
+/// short-circuited for a cooldown period. After that, a single trial call is made: if it succeeds
+/// the circuit breaker closes again, otherwise it remains open for another cooldown period.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    /// The name of the provider.
+    provider: &'static str,
+
+    /// The state of the circuit breaker.
+    state: Mutex<CircuitBreakerState>,
+}
+
+impl CircuitBreaker {
+    /// Creates a new, closed circuit breaker for the provider with the provided name.
+    pub(crate) const fn new(provider: &'static str) -> Self {
+        Self {
+            provider,
+            state: Mutex::new(CircuitBreakerState {
+                failures: 0,
+                opened_at: None,
+            }),
+        }
+    }
+
+    /// Makes the provided call unless the circuit breaker is open.
+    ///
+    /// Returns [`Error::CircuitOpen`] if the call is short-circuited, otherwise the result of the
+    /// call, which is recorded to determine the state of the circuit breaker.
+    pub(crate) async fn call<T, F, Fut>(&self, call: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let config = config();
+        self.acquire(config, Utc::now())?;
+        let result = call().await;
+        let failed = result.as_ref().is_err_and(is_upstream_failure);
+        self.record(config, !failed, Utc::now());
+
+        result
+    }
+
+    /// Returns the status of the circuit breaker.
+    pub(crate) fn status(&self) -> CircuitBreakerStatus {
+        let state = self.state(config(), Utc::now());
+        let failures = self.lock().failures;
+
+        CircuitBreakerStatus {
+            provider: self.provider,
+            state,
+            failures,
+        }
+    }
+
+    /// Returns the state of the circuit breaker at the provided time.
+    fn state(&self, config: &CircuitBreakerConfig, now: DateTime<Utc>) -> CircuitState {
+        let cooldown = Duration::seconds(config.cooldown as i64);
+
+        match self.lock().opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if now < opened_at + cooldown => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Determines whether a call can be made at the provided time.
+    ///
+    /// If the cooldown period has passed, the call is allowed as trial call and the cooldown
+    /// period restarts so that concurrent calls are still short-circuited.
+    fn acquire(&self, config: &CircuitBreakerConfig, now: DateTime<Utc>) -> Result<()> {
+        let cooldown = Duration::seconds(config.cooldown as i64);
+        let mut state = self.lock();
+
+        match state.opened_at {
+            None => Ok(()),
+            Some(opened_at) if now < opened_at + cooldown => Err(Error::CircuitOpen(self.provider)),
+            Some(_) => {
+                info!(provider = self.provider, "🔌 Making a trial call");
+                state.opened_at = Some(now);
+                Ok(())
+            }
+        }
+    }
+
+    /// Records whether a call made at the provided time succeeded.
+    fn record(&self, config: &CircuitBreakerConfig, success: bool, now: DateTime<Utc>) {
+        let mut state = self.lock();
+
+        if success {
+            if state.opened_at.is_some() {
+                info!(provider = self.provider, "🔌 Closing the circuit breaker");
+            }
+            state.failures = 0;
+            state.opened_at = None;
+        } else {
+            state.failures = state.failures.saturating_add(1);
+            if state.failures >= config.failure_threshold {
+                if state.opened_at.is_none() {
+                    warn!(
+                        provider = self.provider,
+                        failures = state.failures,
+                        "🔌 Opening the circuit breaker"
+                    );
+                }
+                state.opened_at = Some(now);
+            }
+        }
+    }
+
+    /// Locks the state of the circuit breaker.
+    fn lock(&self) -> MutexGuard<'_, CircuitBreakerState> {
+        self.state
+            .lock()
+            .expect("Circuit breaker state mutex was poisoned")
+    }
+}
+
+/// Determines whether the error indicates that the upstream API of a provider failed.
+///
+/// Failed requests that would not be retried, e.g. because of a client error status, mean that
+/// the API itself is available.
+fn is_upstream_failure(err: &Error) -> bool {
+    match err {
+        Error::HttpRequest(http::Error::Request(err)) => http::is_retryable(err),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn circuit_breaker() {
+        let circuit_breaker = CircuitBreaker::new("Test");
+        let config = CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown: 60,
+        };
+        let now = Utc::now();
+        let secs = Duration::seconds;
+
+        // The circuit breaker opens after the configured number of consecutive failures.
+        assert_matches!(circuit_breaker.acquire(&config, now), Ok(()));
+        circuit_breaker.record(&config, false, now);
+        assert_eq!(circuit_breaker.state(&config, now), CircuitState::Closed);
+        circuit_breaker.record(&config, false, now);
+        assert_eq!(circuit_breaker.state(&config, now), CircuitState::Open);
+
+        // While open, calls are short-circuited until the cooldown has passed.
+        let result = circuit_breaker.acquire(&config, now + secs(59));
+        assert_matches!(result, Err(Error::CircuitOpen("Test")));
+        assert_eq!(
+            circuit_breaker.state(&config, now + secs(60)),
+            CircuitState::HalfOpen
+        );
+
+        // Only a single trial call is made; if it fails, the circuit breaker opens again.
+        assert_matches!(circuit_breaker.acquire(&config, now + secs(60)), Ok(()));
+        let result = circuit_breaker.acquire(&config, now + secs(61));
+        assert_matches!(result, Err(Error::CircuitOpen("Test")));
+        circuit_breaker.record(&config, false, now + secs(62));
+        assert_eq!(
+            circuit_breaker.state(&config, now + secs(121)),
+            CircuitState::Open
+        );
+
+        // If the trial call succeeds, the circuit breaker closes.
+        assert_matches!(circuit_breaker.acquire(&config, now + secs(122)), Ok(()));
+        circuit_breaker.record(&config, true, now + secs(123));
+        assert_eq!(
+            circuit_breaker.state(&config, now + secs(123)),
+            CircuitState::Closed
+        );
+        assert_eq!(circuit_breaker.status().failures, 0);
+    }
+}
@@ -2,16 +2,42 @@
 //!
 //! This combines and collates data using the other providers.
 
+use std::collections::BTreeMap;
+use std::ops::Not;
+use std::sync::OnceLock;
+
 use cached::proc_macro::cached;
+use cached::{Cached, TimedSizedCache};
 use chrono::serde::ts_seconds;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use rocket::serde::Serialize;
+use tracing::warn;
 
 pub(crate) use super::buienradar::{self, Sample as BuienradarSample};
 pub(crate) use super::luchtmeetnet::{self, Item as LuchtmeetnetItem};
+use crate::config::PaqiMergeConfig;
 use crate::maps::MapsHandle;
 use crate::position::Position;
-use crate::{Error, Metric};
+use crate::providers::CacheStats;
+use crate::{providers, Error, Metric};
+
+/// The tolerances of merging the pollen and AQI data.
+static CONFIG: OnceLock<PaqiMergeConfig> = OnceLock::new();
+
+/// Sets the tolerances of merging the pollen and AQI data.
+///
+/// This needs to be done before the first data is merged, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_config(config: PaqiMergeConfig) {
+    let _ = CONFIG.set(config);
+}
+
+/// Returns the tolerances of merging the pollen and AQI data.
+///
+/// If they have not been set, the defaults are used.
+pub(crate) fn config() -> &'static PaqiMergeConfig {
+    CONFIG.get_or_init(PaqiMergeConfig::default)
+}
 
 /// The possible merge errors that can occur.
 #[allow(clippy::enum_variant_names)]
@@ -25,12 +51,12 @@ pub(crate) enum MergeError {
     #[error("No pollen item found")]
     NoPollenItemFound,
 
-    /// No AQI item found within 30 minutes of first pollen item.
-    #[error("No AQI item found within 30 minutes of first pollen item")]
+    /// No AQI item found within the alignment window of the pollen items.
+    #[error("No AQI item found within the alignment window of the pollen items")]
     NoCloseAqiItemFound,
 
-    /// No pollen item found within 30 minutes of first AQI item.
-    #[error("No pollen item found within 30 minutes of first AQI item")]
+    /// No pollen item found within the alignment window of the AQI items.
+    #[error("No pollen item found within the alignment window of the AQI items")]
     NoClosePollenItemFound,
 }
 
@@ -40,24 +66,49 @@ pub(crate) enum MergeError {
 pub(crate) struct Item {
     /// The time(stamp) of the forecast.
     #[serde(serialize_with = "ts_seconds::serialize")]
-    time: DateTime<Utc>,
+    pub(crate) time: DateTime<Utc>,
 
     /// The forecasted value.
-    value: f32,
+    pub(crate) value: f32,
+
+    /// The human-readable classification of the value (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<&'static str>,
+
+    /// Whether the value exceeds the threshold of the metric (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) exceeds: Option<bool>,
+
+    /// Whether the value is only based on either pollen or AQI, because the other is missing.
+    #[serde(skip_serializing_if = "Not::not")]
+    pub(crate) partial: bool,
+
+    /// The underlying pollutant concentrations (in µg/m³) of the AQI (when asked for).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) concentrations: Option<BTreeMap<Metric, f32>>,
 }
 
 impl Item {
     #[cfg(test)]
     pub(crate) fn new(time: DateTime<Utc>, value: f32) -> Self {
-        Self { time, value }
+        Self {
+            time,
+            value,
+            label: None,
+            exceeds: None,
+            partial: false,
+            concentrations: None,
+        }
     }
 }
 
 /// Merges pollen samples and AQI items into combined items.
 ///
-/// The merging drops items from either the pollen samples or from the AQI items if they are not
-/// stamped within half an hour of the first item of the latest starting series, thus lining them
-/// before they are combined.
+/// The merging aligns the series by linearly interpolating the AQI items onto the times of the
+/// pollen samples, so that no samples are lost if the series are not stamped at the same times.
+/// Pollen samples outside the period of the AQI items are only kept if they are stamped within the
+/// configured alignment window (half an hour by default) of the first or last AQI item, using the
+/// value of that item.
 fn merge(
     pollen_samples: Vec<BuienradarSample>,
     aqi_items: Vec<LuchtmeetnetItem>,
@@ -65,84 +116,280 @@ fn merge(
     let mut pollen_samples = pollen_samples;
     let mut aqi_items = aqi_items;
 
-    // Only retain samples/items that have timestamps that are at least an hour ago.
+    // Only retain samples/items that are not older than the maximum age (an hour by default).
+    let PaqiMergeConfig {
+        alignment_window,
+        max_age,
+    } = *config();
     let now = Utc::now();
-    pollen_samples.retain(|smp| smp.time.signed_duration_since(now).num_seconds() > -3600);
-    aqi_items.retain(|item| item.time.signed_duration_since(now).num_seconds() > -3600);
+    pollen_samples.retain(|smp| smp.time.signed_duration_since(now).num_seconds() > -max_age);
+    aqi_items.retain(|item| item.time.signed_duration_since(now).num_seconds() > -max_age);
 
-    // Align the iterators based on the (hourly) timestamps!
     let pollen_first_time = pollen_samples
         .first()
         .ok_or(MergeError::NoPollenItemFound)?
         .time;
     let aqi_first_time = aqi_items.first().ok_or(MergeError::NoAqiItemFound)?.time;
-    if pollen_first_time < aqi_first_time {
-        // Drain one or more pollen samples to line up.
-        let idx = pollen_samples
-            .iter()
-            .position(|smp| {
-                smp.time
-                    .signed_duration_since(aqi_first_time)
-                    .num_seconds()
-                    .abs()
-                    < 1800
-            })
-            .ok_or(MergeError::NoCloseAqiItemFound)?;
-        pollen_samples.drain(..idx);
-    } else {
-        // Drain one or more AQI items to line up.
-        let idx = aqi_items
-            .iter()
-            .position(|item| {
-                item.time
-                    .signed_duration_since(pollen_first_time)
-                    .num_seconds()
-                    .abs()
-                    < 1800
-            })
-            .ok_or(MergeError::NoClosePollenItemFound)?;
-        aqi_items.drain(..idx);
-    }
 
-    // Combine the samples with items by taking the maximum of pollen sample score and AQI item
-    // value.
+    // Combine the samples with the AQI value at their time by taking the maximum of the pollen
+    // sample score and the AQI value.
     let items = pollen_samples
         .into_iter()
-        .zip(aqi_items)
-        .map(|(pollen_sample, aqi_item)| {
+        .filter_map(|pollen_sample| {
             let time = pollen_sample.time;
-            let value = (pollen_sample.score as f32).max(aqi_item.value);
+            let aqi_value = aqi_value_at(&aqi_items, time, alignment_window)?;
+            let value = pollen_sample.score.max(aqi_value);
 
-            Item { time, value }
+            Some(Item {
+                time,
+                value,
+                label: None,
+                exceeds: None,
+                partial: false,
+                concentrations: None,
+            })
+        })
+        .collect::<Vec<_>>();
+    if items.is_empty() {
+        if pollen_first_time < aqi_first_time {
+            return Err(MergeError::NoCloseAqiItemFound);
+        } else {
+            return Err(MergeError::NoClosePollenItemFound);
+        }
+    }
+
+    Ok(items)
+}
+
+/// Returns the AQI value at the provided time by linearly interpolating between the AQI items.
+///
+/// Before the first and after the last AQI item, the value of that item is used if it is stamped
+/// within the alignment window (in seconds) of the provided time. Otherwise, [`None`] is returned.
+fn aqi_value_at(
+    aqi_items: &[LuchtmeetnetItem],
+    time: DateTime<Utc>,
+    alignment_window: i64,
+) -> Option<f32> {
+    let index = aqi_items.partition_point(|item| item.time <= time);
+    let before = index.checked_sub(1).map(|index| &aqi_items[index]);
+    let after = aqi_items.get(index);
+    let is_close = |item: &LuchtmeetnetItem| {
+        item.time.signed_duration_since(time).num_seconds().abs() < alignment_window
+    };
+
+    match (before, after) {
+        (Some(before), Some(after)) => {
+            let duration = after.time.signed_duration_since(before.time).num_seconds();
+            let offset = time.signed_duration_since(before.time).num_seconds();
+            let fraction = offset as f32 / duration as f32;
+
+            Some(before.value + (after.value - before.value) * fraction)
+        }
+        (Some(item), None) | (None, Some(item)) if is_close(item) => Some(item.value),
+        _ => None,
+    }
+}
+
+/// Returns the daily maxima of the values of the series, keyed by their (UTC) day.
+fn daily_maxima(
+    series: impl IntoIterator<Item = (DateTime<Utc>, f32)>,
+) -> BTreeMap<NaiveDate, f32> {
+    let mut maxima = BTreeMap::new();
+    for (time, value) in series {
+        maxima
+            .entry(time.date_naive())
+            .and_modify(|max: &mut f32| *max = max.max(value))
+            .or_insert(value);
+    }
+
+    maxima
+}
+
+/// Merges pollen samples and AQI items into combined items per day.
+///
+/// The items are stamped at the start of each (UTC) day from today up to the last day of the AQI
+/// forecast, and combine the daily maxima of the pollen score and the AQI. The pollen maps cover a
+/// shorter period than the AQI forecast, so for the days after the last pollen sample the daily
+/// maximum of the last day with pollen samples is carried forward.
+fn merge_daily(
+    pollen_samples: Vec<BuienradarSample>,
+    aqi_items: Vec<LuchtmeetnetItem>,
+) -> Result<Vec<Item>, MergeError> {
+    let today = Utc::now().date_naive();
+    let pollen_maxima = daily_maxima(
+        pollen_samples
+            .into_iter()
+            .map(|sample| (sample.time, sample.score)),
+    );
+    let aqi_maxima = daily_maxima(aqi_items.into_iter().map(|item| (item.time, item.value)));
+    if pollen_maxima.range(today..).next().is_none() {
+        return Err(MergeError::NoPollenItemFound);
+    }
+    if aqi_maxima.range(today..).next().is_none() {
+        return Err(MergeError::NoAqiItemFound);
+    }
+
+    let items = aqi_maxima
+        .range(today..)
+        .filter_map(|(&day, &aqi_max)| {
+            let (_, &pollen_max) = pollen_maxima.range(..=day).next_back()?;
+            let time = day.and_time(NaiveTime::MIN).and_utc();
+            let value = pollen_max.max(aqi_max);
+
+            Some(Item {
+                time,
+                value,
+                label: None,
+                exceeds: None,
+                partial: false,
+                concentrations: None,
+            })
         })
         .collect();
 
     Ok(items)
 }
 
+/// Converts the series of either pollen or AQI into partial combined items for the metric.
+///
+/// This is used if the other series is missing. Like when merging, old items are dropped and for
+/// [`Metric::PAQIDaily`] the daily maxima are used.
+fn partial(metric: Metric, series: Vec<(DateTime<Utc>, f32)>) -> Vec<Item> {
+    let now = Utc::now();
+    let series = match metric {
+        Metric::PAQIDaily => daily_maxima(series)
+            .range(now.date_naive()..)
+            .map(|(day, &max)| (day.and_time(NaiveTime::MIN).and_utc(), max))
+            .collect(),
+        _ => series
+            .into_iter()
+            .filter(|(time, _)| time.signed_duration_since(now).num_seconds() > -config().max_age)
+            .collect::<Vec<_>>(),
+    };
+
+    series
+        .into_iter()
+        .map(|(time, value)| Item {
+            time,
+            value,
+            label: None,
+            exceeds: None,
+            partial: true,
+            concentrations: None,
+        })
+        .collect()
+}
+
 /// Retrieves the combined forecasted items for the provided position and metric.
 ///
-/// It supports the following metric:
+/// It supports the following metrics:
 /// * [`Metric::PAQI`]
+/// * [`Metric::PAQIDaily`]
+///
+/// If either the pollen or the AQI data is missing, the items are only based on the other and
+/// marked as partial. Partial results are not kept in the cache, so that the next lookup tries to
+/// retrieve the complete data again.
+pub(crate) async fn get(
+    position: Position,
+    metric: Metric,
+    maps_handle: &MapsHandle,
+) -> Result<Vec<Item>, Error> {
+    let items = retrieve(position, metric, maps_handle).await?;
+    if items.iter().any(|item| item.partial) {
+        RETRIEVE.lock().await.cache_remove(&(position, metric));
+    }
+
+    Ok(items)
+}
+
+/// Retrieves the pollen and AQI data and combines them for the provided position and metric.
+///
+/// If the result is [`Ok`] it will be cached for the given position and metric for the configured
+/// time (30 minutes by default).
 #[cached(
-    time = 1800,
-    key = "(Position, Metric)",
+    ty = "TimedSizedCache<(Position, Metric), Vec<Item>>",
+    create = "{
+        let (capacity, ttl) = (providers::cache_capacities().combined, providers::cache_ttls().combined);
+        TimedSizedCache::with_size_and_lifespan(capacity.max(1), ttl)
+    }",
     convert = r#"{ (position, metric) }"#,
     result = true
 )]
-pub(crate) async fn get(
+async fn retrieve(
     position: Position,
     metric: Metric,
     maps_handle: &MapsHandle,
 ) -> Result<Vec<Item>, Error> {
-    if metric != Metric::PAQI {
-        return Err(Error::UnsupportedMetric(metric));
+    let merge = match metric {
+        Metric::PAQI => merge,
+        Metric::PAQIDaily => merge_daily,
+        _ => return Err(Error::UnsupportedMetric(metric)),
+    };
+    let pollen_samples = buienradar::get_samples(position, Metric::Pollen, maps_handle).await;
+    let aqi_items = luchtmeetnet::get(position, Metric::AQI).await;
+    let (pollen_samples, aqi_items) = match (pollen_samples, aqi_items) {
+        (Ok(pollen_samples), Ok(aqi_items)) => (pollen_samples, aqi_items),
+        (Ok(pollen_samples), Err(error)) => {
+            warn!(%metric, error = %error, "💥 Missing AQI data, only using pollen");
+            return non_empty(partial(metric, pollen_series(&pollen_samples)), error);
+        }
+        (Err(error), Ok(aqi_items)) => {
+            warn!(%metric, error = %error, "💥 Missing pollen data, only using AQI");
+            return non_empty(partial(metric, aqi_series(&aqi_items)), error);
+        }
+        (Err(error), Err(_)) => return Err(error),
     };
-    let pollen_items = buienradar::get_samples(position, Metric::Pollen, maps_handle).await?;
-    let aqi_items = luchtmeetnet::get(position, Metric::AQI).await?;
-    let items = merge(pollen_items, aqi_items)?;
 
-    Ok(items)
+    let (pollen_series, aqi_series) = (pollen_series(&pollen_samples), aqi_series(&aqi_items));
+    match merge(pollen_samples, aqi_items) {
+        Ok(items) => Ok(items),
+        Err(error @ MergeError::NoAqiItemFound) => {
+            non_empty(partial(metric, pollen_series), error.into())
+        }
+        Err(error @ MergeError::NoPollenItemFound) => {
+            non_empty(partial(metric, aqi_series), error.into())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Returns the time and score of the pollen samples.
+fn pollen_series(pollen_samples: &[BuienradarSample]) -> Vec<(DateTime<Utc>, f32)> {
+    pollen_samples
+        .iter()
+        .map(|sample| (sample.time, sample.score))
+        .collect()
+}
+
+/// Returns the time and value of the AQI items.
+fn aqi_series(aqi_items: &[LuchtmeetnetItem]) -> Vec<(DateTime<Utc>, f32)> {
+    aqi_items
+        .iter()
+        .map(|item| (item.time, item.value))
+        .collect()
+}
+
+/// Returns the partial items, or the provided error if there are none.
+fn non_empty(items: Vec<Item>, error: Error) -> Result<Vec<Item>, Error> {
+    if items.is_empty() {
+        Err(error)
+    } else {
+        Ok(items)
+    }
+}
+
+/// Returns the statistics of the combined cache.
+pub(crate) async fn cache_stats() -> CacheStats {
+    CacheStats::new("combined", &*RETRIEVE.lock().await)
+}
+
+/// Purges the cached combined results, optionally only for a metric and/or position.
+///
+/// Returns the number of purged entries.
+pub(crate) async fn purge_cache(metric: Option<Metric>, position: Option<Position>) -> usize {
+    providers::purge(&mut *RETRIEVE.lock().await, |(p, m)| {
+        metric.is_none_or(|metric| *m == metric) && position.is_none_or(|position| *p == position)
+    })
 }
 
 #[cfg(test)]
@@ -231,6 +478,25 @@ mod tests {
         let paqi = merged.unwrap();
         assert_eq!(paqi, Vec::from([Item::new(t_0, 1.1)]));
 
+        // The pollen samples are stamped half an hour later; the AQI values are interpolated and
+        // the last sample is dropped because it is not within half an hour of the last AQI item.
+        let halfway_pollen_samples = pollen_samples
+            .iter()
+            .cloned()
+            .map(|mut item| {
+                item.time = item.time.checked_add_signed(Duration::minutes(30)).unwrap();
+                item
+            })
+            .collect::<Vec<_>>();
+        let merged = super::merge(halfway_pollen_samples, aqi_items.clone());
+        assert!(merged.is_ok());
+        let paqi = merged.unwrap();
+        let t_half = |t: DateTime<Utc>| t.checked_add_signed(Duration::minutes(30)).unwrap();
+        assert_eq!(
+            paqi,
+            Vec::from([Item::new(t_half(t_0), 2.0), Item::new(t_half(t_1), 3.0),])
+        );
+
         // Merging fails because the samples/items are too far (6 hours) apart.
         let shifted_aqi_items = aqi_items
             .iter()
@@ -266,4 +532,83 @@ mod tests {
         let merged = super::merge(pollen_samples, aqi_items[0..2].to_vec());
         assert_eq!(merged, Err(MergeError::NoAqiItemFound));
     }
+
+    #[test]
+    fn merge_daily() {
+        let today = Utc::now().date_naive().and_time(NaiveTime::MIN).and_utc();
+        let t = |days, hours| today + Duration::days(days) + Duration::hours(hours);
+
+        let pollen_samples = Vec::from([
+            BuienradarSample::new(t(-1, 12), 9),
+            BuienradarSample::new(t(0, 10), 3),
+            BuienradarSample::new(t(0, 14), 5),
+            BuienradarSample::new(t(1, 2), 4),
+        ]);
+        let aqi_items = Vec::from([
+            LuchtmeetnetItem::new(t(-1, 12), 8.0),
+            LuchtmeetnetItem::new(t(0, 12), 4.5),
+            LuchtmeetnetItem::new(t(1, 12), 6.0),
+            LuchtmeetnetItem::new(t(2, 12), 2.0),
+        ]);
+
+        // The days before today are dropped and the last pollen maximum is carried forward.
+        let paqi_daily = super::merge_daily(pollen_samples.clone(), aqi_items.clone());
+        assert_eq!(
+            paqi_daily,
+            Ok(Vec::from([
+                Item::new(t(0, 0), 5.0),
+                Item::new(t(1, 0), 6.0),
+                Item::new(t(2, 0), 4.0),
+            ]))
+        );
+
+        // There must be pollen samples and AQI items from today on.
+        let merged = super::merge_daily(pollen_samples[..1].to_vec(), aqi_items.clone());
+        assert_eq!(merged, Err(MergeError::NoPollenItemFound));
+        let merged = super::merge_daily(pollen_samples, aqi_items[..1].to_vec());
+        assert_eq!(merged, Err(MergeError::NoAqiItemFound));
+    }
+
+    #[test]
+    fn partial() {
+        let now = Utc::now();
+        let today = now.date_naive().and_time(NaiveTime::MIN).and_utc();
+        let series = Vec::from([
+            (now - Duration::hours(2), 5.0),
+            (now, 3.0),
+            (now + Duration::hours(1), 4.0),
+            (today + Duration::days(1), 2.0),
+        ]);
+        let partial_item = |time, value| Item {
+            partial: true,
+            ..Item::new(time, value)
+        };
+
+        // Old items are dropped and the remaining items are marked as partial.
+        let items = super::partial(Metric::PAQI, series);
+        assert_eq!(
+            items,
+            [
+                partial_item(now, 3.0),
+                partial_item(now + Duration::hours(1), 4.0),
+                partial_item(today + Duration::days(1), 2.0),
+            ]
+        );
+
+        // For the daily PAQI, the daily maxima from today on are used.
+        let series = Vec::from([
+            (today - Duration::hours(12), 9.0),
+            (today + Duration::hours(1), 5.0),
+            (today + Duration::hours(3), 6.0),
+            (today + Duration::days(1), 2.0),
+        ]);
+        let items = super::partial(Metric::PAQIDaily, series);
+        assert_eq!(
+            items,
+            [
+                partial_item(today, 6.0),
+                partial_item(today + Duration::days(1), 2.0),
+            ]
+        );
+    }
 }
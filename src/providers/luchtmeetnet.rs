@@ -2,21 +2,56 @@
 //!
 //! For more information about Luchtmeetnet, see: <https://www.luchtmeetnet.nl/contact>.
 
+use std::collections::BTreeMap;
+#[cfg(feature = "luchtmeetnet")]
+use std::sync::LazyLock;
+#[cfg(feature = "luchtmeetnet")]
+use std::time::Instant;
+
+#[cfg(feature = "luchtmeetnet")]
 use cached::proc_macro::cached;
+#[cfg(feature = "luchtmeetnet")]
+use cached::TimedSizedCache;
 use chrono::serde::ts_seconds;
 use chrono::{DateTime, Duration, Utc};
-use reqwest::Url;
+#[cfg(feature = "luchtmeetnet")]
+use rocket::serde::json::serde_json;
 use rocket::serde::{Deserialize, Serialize};
+#[cfg(feature = "luchtmeetnet")]
+use tracing::{debug, info};
 
 use crate::position::Position;
+#[cfg(feature = "luchtmeetnet")]
+use crate::providers::circuit_breaker::{CircuitBreaker, CircuitBreakerStatus};
+#[cfg(feature = "luchtmeetnet")]
+use crate::providers::mock;
+#[cfg(feature = "luchtmeetnet")]
+use crate::providers::single_flight::SingleFlight;
+#[cfg(feature = "luchtmeetnet")]
+use crate::providers::CacheStats;
+#[cfg(feature = "luchtmeetnet")]
+use crate::{http, providers, shared_cache};
 use crate::{Error, Metric, Result};
 
-/// The base URL for the Luchtmeetnet API.
-const LUCHTMEETNET_BASE_URL: &str = "https://api.luchtmeetnet.nl/open_api/concentrations";
+/// The default base URL for the Luchtmeetnet API.
+pub(crate) const LUCHTMEETNET_BASE_URL: &str =
+    "https://api.luchtmeetnet.nl/open_api/concentrations";
+
+/// The pollutants of which Luchtmeetnet provides the concentrations.
+pub(crate) const POLLUTANTS: [Metric; 3] = [Metric::NO2, Metric::O3, Metric::PM10];
+
+/// The circuit breaker for calls to the Luchtmeetnet API.
+#[cfg(feature = "luchtmeetnet")]
+static CIRCUIT_BREAKER: CircuitBreaker = CircuitBreaker::new("Luchtmeetnet");
+
+/// The Luchtmeetnet lookups in flight.
+#[cfg(feature = "luchtmeetnet")]
+static IN_FLIGHT: LazyLock<SingleFlight<(Position, Metric)>> = LazyLock::new(SingleFlight::new);
 
 /// The Luchtmeetnet API data container.
 ///
 /// This is only used temporarily during deserialization.
+#[cfg(feature = "luchtmeetnet")]
 #[derive(Debug, Deserialize)]
 #[serde(crate = "rocket::serde")]
 struct Container {
@@ -38,15 +73,59 @@ pub(crate) struct Item {
     ///
     /// The unit depends on the selected [metric](Metric).
     pub(crate) value: f32,
+
+    /// The human-readable classification of the value (when asked for).
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<&'static str>,
+
+    /// Whether the value exceeds the threshold of the metric (when asked for).
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub(crate) exceeds: Option<bool>,
+
+    /// The underlying pollutant concentrations (in µg/m³) of an index (when asked for).
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub(crate) concentrations: Option<BTreeMap<Metric, f32>>,
 }
 
 impl Item {
     #[cfg(test)]
     pub(crate) fn new(time: DateTime<Utc>, value: f32) -> Self {
-        Self { time, value }
+        Self {
+            time,
+            value,
+            label: None,
+            exceeds: None,
+            concentrations: None,
+        }
     }
 }
 
+/// Returns the highest concentration per pollutant during the provided cadence (in seconds)
+/// starting at the provided time.
+///
+/// Returns [`None`] if there are no concentrations during it.
+pub(crate) fn concentrations_at(
+    concentrations: &[(Metric, Vec<Item>)],
+    time: DateTime<Utc>,
+    cadence: i64,
+) -> Option<BTreeMap<Metric, f32>> {
+    let end = time + Duration::seconds(cadence);
+    let concentrations = concentrations
+        .iter()
+        .filter_map(|(pollutant, items)| {
+            let max = items
+                .iter()
+                .filter(|item| time <= item.time && item.time < end)
+                .map(|item| item.value)
+                .reduce(f32::max)?;
+
+            Some((*pollutant, max))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    (!concentrations.is_empty()).then_some(concentrations)
+}
+
 /// Retrieves the Luchtmeetnet forecasted items for the provided position and metric.
 ///
 /// It supports the following metrics:
@@ -54,8 +133,34 @@ impl Item {
 /// * [`Metric::NO2`]
 /// * [`Metric::O3`]
 /// * [`Metric::PM10`]
-#[cached(time = 1800, result = true)]
+///
+/// Concurrent lookups for the same position and metric share one upstream request. If the mock
+/// provider is enabled, synthetic items are returned instead.
+#[cfg(feature = "luchtmeetnet")]
 pub(crate) async fn get(position: Position, metric: Metric) -> Result<Vec<Item>> {
+    if mock::is_enabled() {
+        return mock::luchtmeetnet_items(position, metric);
+    }
+
+    IN_FLIGHT
+        .run((position, metric), || retrieve(position, metric))
+        .await
+}
+
+/// Retrieves the Luchtmeetnet forecasted items for the provided position and metric from the API.
+///
+/// If the result is [`Ok`] it will be cached for the given position and metric for the configured
+/// time (30 minutes by default).
+#[cfg(feature = "luchtmeetnet")]
+#[cached(
+    ty = "TimedSizedCache<(Position, Metric), Vec<Item>>",
+    create = "{
+        let (capacity, ttl) = (providers::cache_capacities().luchtmeetnet, providers::cache_ttls().luchtmeetnet);
+        TimedSizedCache::with_size_and_lifespan(capacity.max(1), ttl)
+    }",
+    result = true
+)]
+async fn retrieve(position: Position, metric: Metric) -> Result<Vec<Item>> {
     let formula = match metric {
         Metric::AQI => "lki",
         Metric::NO2 => "no2",
@@ -63,15 +168,43 @@ pub(crate) async fn get(position: Position, metric: Metric) -> Result<Vec<Item>>
         Metric::PM10 => "pm10",
         _ => return Err(Error::UnsupportedMetric(metric)),
     };
-    let mut url = Url::parse(LUCHTMEETNET_BASE_URL).unwrap();
+    let mut url = providers::base_urls().luchtmeetnet.clone();
     url.query_pairs_mut()
         .append_pair("formula", formula)
         .append_pair("latitude", &position.lat_as_str(5))
         .append_pair("longitude", &position.lon_as_str(5));
 
-    println!("▶️  Retrieving Luchtmeetnet data from: {url}");
-    let response = reqwest::get(url).await?;
-    let root: Container = response.error_for_status()?.json().await?;
+    let key = format!("luchtmeetnet:{url}");
+    let ttl = providers::cache_ttls().luchtmeetnet;
+    let output = shared_cache::get_or_retrieve(&key, ttl, || {
+        CIRCUIT_BREAKER.call(|| async {
+            info!(provider = "Luchtmeetnet", %metric, %url, "▶️  Retrieving data");
+            let start = Instant::now();
+            let response = http::client().get(url.clone()).await?;
+            match http::media_type(response.headers()) {
+                Some(media_type) if media_type != "application/json" => {
+                    return Err(Error::UnexpectedContentType(
+                        "Luchtmeetnet",
+                        url.to_string(),
+                        media_type,
+                    ));
+                }
+                _ => {}
+            }
+            let output = response.text().await?;
+            debug!(
+                provider = "Luchtmeetnet",
+                %metric,
+                duration_ms = start.elapsed().as_millis() as u64,
+                "Retrieved data"
+            );
+
+            Ok(output)
+        })
+    })
+    .await?;
+    let root: Container = serde_json::from_str(&output)
+        .map_err(|err| Error::InvalidResponse("Luchtmeetnet", url.to_string(), err.to_string()))?;
 
     // Filter items that are older than one hour before now. They seem to occur sometimes?
     let too_old = Utc::now() - Duration::hours(1);
@@ -83,3 +216,71 @@ pub(crate) async fn get(position: Position, metric: Metric) -> Result<Vec<Item>>
 
     Ok(items)
 }
+
+/// Retrieves the Luchtmeetnet forecasted items for the provided position and metric.
+///
+/// The `luchtmeetnet` feature is disabled, so this always fails.
+#[cfg(not(feature = "luchtmeetnet"))]
+pub(crate) async fn get(_position: Position, metric: Metric) -> Result<Vec<Item>> {
+    Err(Error::MetricDisabled(metric))
+}
+
+/// Returns the status of the Luchtmeetnet circuit breaker.
+#[cfg(feature = "luchtmeetnet")]
+pub(crate) fn circuit_breaker_status() -> CircuitBreakerStatus {
+    CIRCUIT_BREAKER.status()
+}
+
+/// Returns the statistics of the Luchtmeetnet cache.
+#[cfg(feature = "luchtmeetnet")]
+pub(crate) async fn cache_stats() -> CacheStats {
+    CacheStats::new("luchtmeetnet", &*RETRIEVE.lock().await)
+}
+
+/// Purges the cached Luchtmeetnet results, optionally only for a metric and/or position.
+///
+/// Returns the number of purged entries.
+#[cfg(feature = "luchtmeetnet")]
+pub(crate) async fn purge_cache(metric: Option<Metric>, position: Option<Position>) -> usize {
+    providers::purge(&mut *RETRIEVE.lock().await, |(p, m)| {
+        metric.is_none_or(|metric| *m == metric) && position.is_none_or(|position| *p == position)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn concentrations_at() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let t_1 = t_0 + Duration::hours(1);
+        let concentrations = [
+            (
+                Metric::NO2,
+                Vec::from([Item::new(t_0, 35.0), Item::new(t_1, 95.0)]),
+            ),
+            (Metric::PM10, Vec::from([Item::new(t_1, 10.0)])),
+        ];
+
+        // Only the pollutants with concentrations during the cadence are included.
+        assert_eq!(
+            super::concentrations_at(&concentrations, t_0, 3_600),
+            Some(BTreeMap::from([(Metric::NO2, 35.0)]))
+        );
+
+        // The highest concentration during the cadence is used.
+        assert_eq!(
+            super::concentrations_at(&concentrations, t_0, 24 * 3_600),
+            Some(BTreeMap::from([(Metric::NO2, 95.0), (Metric::PM10, 10.0)]))
+        );
+
+        // Without concentrations during the cadence, there are none.
+        assert_eq!(
+            super::concentrations_at(&concentrations, t_1 + Duration::hours(1), 3_600),
+            None
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! The mock data provider.
+//!
+//! This provider generates deterministic synthetic data for all metrics instead of retrieving it
+//! from the upstream APIs and maps, so that demos and load tests can run without network access.
+//! The values follow a daily cycle with a phase that depends on the position, the metric and the
+//! configured seed; the same position, metric and time always result in the same value.
+
+use std::f32::consts::TAU;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+
+use crate::config::MockConfig;
+use crate::position::Position;
+use crate::providers::buienradar;
+#[cfg(feature = "luchtmeetnet")]
+use crate::providers::luchtmeetnet;
+use crate::{Error, Metric, Result};
+
+/// The number of seconds in an hour.
+const HOUR: i64 = 3_600;
+
+/// The configuration of the mock provider (if enabled).
+static CONFIG: OnceLock<MockConfig> = OnceLock::new();
+
+/// Sets the configuration of the mock provider, which enables it if set.
+///
+/// This needs to be done before the first data is retrieved, otherwise it has no effect.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) fn set_config(config: Option<MockConfig>) {
+    if let Some(config) = config {
+        let _ = CONFIG.set(config);
+    }
+}
+
+/// Returns whether the mock provider is used instead of the upstream providers.
+pub(crate) fn is_enabled() -> bool {
+    CONFIG.get().is_some()
+}
+
+/// The shape of the synthetic series of a metric.
+struct Series {
+    /// The interval (in seconds) between the values.
+    step: i64,
+
+    /// The number of values.
+    count: i64,
+
+    /// The lowest value of the daily cycle.
+    min: f32,
+
+    /// The highest value of the daily cycle.
+    max: f32,
+
+    /// The number of decimals the values are rounded to.
+    decimals: i32,
+}
+
+impl Series {
+    /// Returns the shape of the series of the provided metric, if it is provided by a single
+    /// provider.
+    fn for_metric(metric: Metric) -> Option<Self> {
+        let series = |step, count, min, max, decimals| Series {
+            step,
+            count,
+            min,
+            max,
+            decimals,
+        };
+        let series = match metric {
+            Metric::AQI => series(HOUR, 24, 1.0, 8.0, 0),
+            Metric::NO2 => series(HOUR, 24, 5.0, 40.0, 1),
+            Metric::O3 => series(HOUR, 24, 20.0, 90.0, 1),
+            Metric::PM10 => series(HOUR, 24, 10.0, 40.0, 1),
+            Metric::Pollen => series(HOUR, 24, 1.0, 6.0, 0),
+            // Below zero there is no precipitation, i.e. it only rains part of the day.
+            Metric::Precipitation => series(300, 24, -4.0, 4.0, 1),
+            Metric::Temperature => series(HOUR, 24, 5.0, 20.0, 1),
+            Metric::UVI => series(24 * HOUR, 5, 1.0, 7.0, 0),
+            Metric::All | Metric::PAQI | Metric::PAQIDaily => return None,
+        };
+
+        Some(series)
+    }
+}
+
+/// Returns the phase (in radians) of the daily cycle for the provided seed, position and metric.
+///
+/// The phase is derived from a (FNV-1a) hash, so that it is stable across runs and builds.
+fn phase(seed: u64, position: Position, metric: Metric) -> f32 {
+    let key = format!(
+        "{seed}:{}:{}:{metric}",
+        position.lat_as_str(2),
+        position.lon_as_str(2)
+    );
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    (hash % 3_600) as f32 / 3_600.0 * TAU
+}
+
+/// Generates the synthetic values for the provided seed, position and metric, starting at the
+/// provided time truncated to the interval of the metric.
+fn generate(
+    seed: u64,
+    position: Position,
+    metric: Metric,
+    now: DateTime<Utc>,
+) -> Result<Vec<(DateTime<Utc>, f32)>> {
+    let series = Series::for_metric(metric).ok_or(Error::UnsupportedMetric(metric))?;
+    let phase = phase(seed, position, metric);
+    let start = now.timestamp() - now.timestamp().rem_euclid(series.step);
+    let factor = 10_f32.powi(series.decimals);
+
+    let values = (0..series.count)
+        .filter_map(|index| {
+            let timestamp = start + index * series.step;
+            let cycle = (timestamp % (24 * HOUR)) as f32 / (24 * HOUR) as f32 * TAU;
+            let value =
+                series.min + (series.max - series.min) * (0.5 + 0.5 * (cycle + phase).sin());
+            let value = (value.max(0.0) * factor).round() / factor;
+
+            DateTime::from_timestamp(timestamp, 0).map(|time| (time, value))
+        })
+        .collect();
+
+    Ok(values)
+}
+
+/// Generates the synthetic values for the provided position and metric using the configured seed.
+fn values(position: Position, metric: Metric) -> Result<Vec<(DateTime<Utc>, f32)>> {
+    let seed = CONFIG.get().map(|config| config.seed).unwrap_or_default();
+
+    generate(seed, position, metric, Utc::now())
+}
+
+/// Returns the synthetic Luchtmeetnet items for the provided position and metric.
+///
+/// It supports the same metrics as the Luchtmeetnet provider.
+#[cfg(feature = "luchtmeetnet")]
+pub(crate) fn luchtmeetnet_items(
+    position: Position,
+    metric: Metric,
+) -> Result<Vec<luchtmeetnet::Item>> {
+    let items = values(position, metric)?
+        .into_iter()
+        .map(|(time, value)| luchtmeetnet::Item {
+            time,
+            value,
+            label: None,
+            exceeds: None,
+            concentrations: None,
+        })
+        .collect();
+
+    Ok(items)
+}
+
+/// Returns the synthetic Buienradar items for the provided position and metric.
+///
+/// It supports the precipitation and temperature metrics.
+pub(crate) fn buienradar_items(
+    position: Position,
+    metric: Metric,
+) -> Result<Vec<buienradar::Item>> {
+    let items = values(position, metric)?
+        .into_iter()
+        .map(|(time, value)| buienradar::Item {
+            time,
+            value,
+            label: None,
+            exceeds: None,
+            intensity: None,
+            accumulated: None,
+        })
+        .collect();
+
+    Ok(items)
+}
+
+/// Returns the synthetic Buienradar map samples for the provided position and metric.
+///
+/// It supports the pollen and UV index metrics.
+pub(crate) fn buienradar_samples(
+    position: Position,
+    metric: Metric,
+) -> Result<Vec<buienradar::Sample>> {
+    let samples = values(position, metric)?
+        .into_iter()
+        .map(|(time, score)| buienradar::Sample {
+            time,
+            score,
+            confidence: 1.0,
+            label: None,
+            exceeds: None,
+        })
+        .collect();
+
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn generate() {
+        let position = Position::new(51.4, 5.5);
+        let now = Utc.with_ymd_and_hms(2024, 1, 10, 12, 34, 56).unwrap();
+
+        // The series start at the current interval and have the configured number of values.
+        let values = super::generate(0, position, Metric::Pollen, now).unwrap();
+        assert_eq!(values.len(), 24);
+        assert_eq!(
+            values[0].0,
+            Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            values[1].0,
+            Utc.with_ymd_and_hms(2024, 1, 10, 13, 0, 0).unwrap()
+        );
+        assert!(values
+            .iter()
+            .all(|(_, score)| (1.0..=6.0).contains(score) && score.fract() == 0.0));
+        let values = super::generate(0, position, Metric::UVI, now).unwrap();
+        assert_eq!(values.len(), 5);
+        assert_eq!(
+            values[0].0,
+            Utc.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap()
+        );
+
+        // Precipitation is never negative.
+        let values = super::generate(0, position, Metric::Precipitation, now).unwrap();
+        assert!(values.iter().all(|(_, value)| *value >= 0.0));
+
+        // The values are deterministic, but depend on the seed.
+        let values = super::generate(0, position, Metric::Temperature, now).unwrap();
+        assert_eq!(
+            values,
+            super::generate(0, position, Metric::Temperature, now).unwrap()
+        );
+        assert_ne!(
+            values,
+            super::generate(1, position, Metric::Temperature, now).unwrap()
+        );
+
+        // Combined metrics are not generated directly.
+        assert!(matches!(
+            super::generate(0, position, Metric::PAQI, now),
+            Err(Error::UnsupportedMetric(Metric::PAQI))
+        ));
+    }
+}
@@ -0,0 +1,247 @@
+//! The Open-Meteo data provider.
+//!
+//! This provider is only used as second opinion on the precipitation of Buienradar, see
+//! [`Consensus`](crate::consensus::Consensus).
+//!
+//! For more information about Open-Meteo, see: <https://open-meteo.com/en/docs>.
+
+#[cfg(feature = "buienradar")]
+use std::sync::LazyLock;
+#[cfg(feature = "buienradar")]
+use std::time::Instant;
+
+#[cfg(feature = "buienradar")]
+use cached::proc_macro::cached;
+#[cfg(feature = "buienradar")]
+use cached::TimedSizedCache;
+use chrono::{DateTime, Utc};
+#[cfg(feature = "buienradar")]
+use rocket::serde::json::serde_json;
+#[cfg(feature = "buienradar")]
+use rocket::serde::Deserialize;
+#[cfg(feature = "buienradar")]
+use tracing::{debug, info};
+
+use crate::position::Position;
+#[cfg(feature = "buienradar")]
+use crate::providers::circuit_breaker::{CircuitBreaker, CircuitBreakerStatus};
+#[cfg(feature = "buienradar")]
+use crate::providers::mock;
+#[cfg(feature = "buienradar")]
+use crate::providers::single_flight::SingleFlight;
+#[cfg(feature = "buienradar")]
+use crate::providers::CacheStats;
+#[cfg(feature = "buienradar")]
+use crate::{http, providers, shared_cache};
+use crate::{Error, Metric, Result};
+
+/// The default base URL for the Open-Meteo API.
+pub(crate) const OPEN_METEO_BASE_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+/// The interval (in seconds) of the precipitation of the Open-Meteo API.
+pub(crate) const PRECIPITATION_INTERVAL: i64 = 15 * 60;
+
+/// The number of upcoming intervals to retrieve, covering the two hours of Buienradar.
+#[cfg(feature = "buienradar")]
+const FORECAST_INTERVALS: u32 = 9;
+
+/// The circuit breaker for calls to the Open-Meteo API.
+#[cfg(feature = "buienradar")]
+static CIRCUIT_BREAKER: CircuitBreaker = CircuitBreaker::new("Open-Meteo");
+
+/// The Open-Meteo lookups in flight.
+#[cfg(feature = "buienradar")]
+static IN_FLIGHT: LazyLock<SingleFlight<Position>> = LazyLock::new(SingleFlight::new);
+
+/// The Open-Meteo API data container.
+///
+/// This is only used temporarily during deserialization.
+#[cfg(feature = "buienradar")]
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Container {
+    minutely_15: Minutely,
+}
+
+/// The Open-Meteo API 15-minutely data.
+#[cfg(feature = "buienradar")]
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Minutely {
+    /// The times (in seconds since the UNIX epoch) at the end of the intervals.
+    time: Vec<i64>,
+
+    /// The total precipitation (in mm) during the intervals, if known.
+    precipitation: Vec<Option<f32>>,
+}
+
+/// Retrieves the Open-Meteo precipitation for the provided position.
+///
+/// The precipitation is returned as a series of the start of each 15-minute interval and the
+/// precipitation (in mm/h) during it. Concurrent lookups for the same position share one upstream
+/// request. If the mock provider is enabled, the synthetic precipitation of Buienradar is returned
+/// instead, i.e. the providers always agree.
+#[cfg(feature = "buienradar")]
+pub(crate) async fn get_precipitation(position: Position) -> Result<Vec<(DateTime<Utc>, f32)>> {
+    if mock::is_enabled() {
+        let items = mock::buienradar_items(position, Metric::Precipitation)?;
+
+        return Ok(items
+            .into_iter()
+            .map(|item| (item.time, item.value))
+            .collect());
+    }
+
+    IN_FLIGHT.run(position, || retrieve(position)).await
+}
+
+/// Retrieves the Open-Meteo precipitation for the provided position from the API.
+///
+/// If the result is [`Ok`] it will be cached for the given position for the configured time (15
+/// minutes by default).
+#[cfg(feature = "buienradar")]
+#[cached(
+    ty = "TimedSizedCache<Position, Vec<(DateTime<Utc>, f32)>>",
+    create = "{
+        let (capacity, ttl) = (providers::cache_capacities().open_meteo, providers::cache_ttls().open_meteo);
+        TimedSizedCache::with_size_and_lifespan(capacity.max(1), ttl)
+    }",
+    result = true
+)]
+async fn retrieve(position: Position) -> Result<Vec<(DateTime<Utc>, f32)>> {
+    let mut url = providers::base_urls().open_meteo.clone();
+    url.query_pairs_mut()
+        .append_pair("latitude", &position.lat_as_str(2))
+        .append_pair("longitude", &position.lon_as_str(2))
+        .append_pair("minutely_15", "precipitation")
+        .append_pair("past_minutely_15", "1")
+        .append_pair("forecast_minutely_15", &FORECAST_INTERVALS.to_string())
+        .append_pair("timeformat", "unixtime");
+
+    let key = format!("open_meteo:{url}");
+    let ttl = providers::cache_ttls().open_meteo;
+    let output = shared_cache::get_or_retrieve(&key, ttl, || {
+        CIRCUIT_BREAKER.call(|| async {
+            let metric = Metric::Precipitation;
+            info!(provider = "Open-Meteo", %metric, %url, "▶️  Retrieving data");
+            let start = Instant::now();
+            let response = http::client().get(url.clone()).await?;
+            match http::media_type(response.headers()) {
+                Some(media_type) if media_type != "application/json" => {
+                    return Err(Error::UnexpectedContentType(
+                        "Open-Meteo",
+                        url.to_string(),
+                        media_type,
+                    ));
+                }
+                _ => {}
+            }
+            let output = response.text().await?;
+            debug!(
+                provider = "Open-Meteo",
+                %metric,
+                duration_ms = start.elapsed().as_millis() as u64,
+                "Retrieved data"
+            );
+
+            Ok(output)
+        })
+    })
+    .await?;
+
+    parse_precipitation(&output)
+        .map_err(|err| Error::InvalidResponse("Open-Meteo", url.to_string(), err))
+}
+
+/// Parses the Open-Meteo API output into a series of precipitation (in mm/h) per interval.
+///
+/// The API provides the total precipitation of the 15 minutes preceding each time, so the times
+/// are moved to the start of the intervals and the totals converted to hourly rates. Intervals
+/// without a known precipitation are skipped.
+#[cfg(feature = "buienradar")]
+fn parse_precipitation(output: &str) -> std::result::Result<Vec<(DateTime<Utc>, f32)>, String> {
+    let root: Container = serde_json::from_str(output).map_err(|err| err.to_string())?;
+    let minutely = root.minutely_15;
+    if minutely.time.len() != minutely.precipitation.len() {
+        return Err(String::from("the number of times and values differ"));
+    }
+
+    let intervals_per_hour = (3_600 / PRECIPITATION_INTERVAL) as f32;
+    let series = minutely
+        .time
+        .into_iter()
+        .zip(minutely.precipitation)
+        .filter_map(|(timestamp, precipitation)| {
+            let time = DateTime::from_timestamp(timestamp - PRECIPITATION_INTERVAL, 0)?;
+
+            Some((time, precipitation? * intervals_per_hour))
+        })
+        .collect();
+
+    Ok(series)
+}
+
+/// Retrieves the Open-Meteo precipitation for the provided position.
+///
+/// The `buienradar` feature is disabled, so this always fails.
+#[cfg(not(feature = "buienradar"))]
+pub(crate) async fn get_precipitation(_position: Position) -> Result<Vec<(DateTime<Utc>, f32)>> {
+    Err(Error::MetricDisabled(Metric::Precipitation))
+}
+
+/// Returns the status of the Open-Meteo circuit breaker.
+#[cfg(feature = "buienradar")]
+pub(crate) fn circuit_breaker_status() -> CircuitBreakerStatus {
+    CIRCUIT_BREAKER.status()
+}
+
+/// Returns the statistics of the Open-Meteo cache.
+#[cfg(feature = "buienradar")]
+pub(crate) async fn cache_stats() -> CacheStats {
+    CacheStats::new("open_meteo", &*RETRIEVE.lock().await)
+}
+
+/// Purges the cached Open-Meteo results, optionally only for a metric and/or position.
+///
+/// Returns the number of purged entries.
+#[cfg(feature = "buienradar")]
+pub(crate) async fn purge_cache(metric: Option<Metric>, position: Option<Position>) -> usize {
+    if metric.is_some_and(|metric| metric != Metric::Precipitation) {
+        return 0;
+    }
+
+    providers::purge(&mut *RETRIEVE.lock().await, |p| {
+        position.is_none_or(|position| *p == position)
+    })
+}
+
+#[cfg(all(test, feature = "buienradar"))]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn parse_precipitation() {
+        // The times are moved to the start of the intervals, the totals converted to mm/h and
+        // unknown values skipped.
+        let output = r#"{
+            "latitude": 52.1,
+            "longitude": 5.1,
+            "minutely_15": {
+                "time": [1717243200, 1717244100, 1717245000],
+                "precipitation": [0.0, 0.3, null]
+            }
+        }"#;
+        let t_0 = Utc.with_ymd_and_hms(2024, 6, 1, 11, 45, 0).unwrap();
+        assert_eq!(
+            super::parse_precipitation(output).unwrap(),
+            [(t_0, 0.0), (t_0 + chrono::Duration::minutes(15), 1.2)]
+        );
+
+        // Missing or mismatched data is invalid.
+        assert!(super::parse_precipitation(r#"{"hourly": {}}"#).is_err());
+        let output = r#"{"minutely_15": {"time": [1717243200], "precipitation": []}}"#;
+        assert!(super::parse_precipitation(output).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Coalescing of concurrent identical provider lookups.
+//!
+//! If many clients ask for the forecast of the same position while the cache is cold, only one of
+//! the lookups calls the upstream API. The others wait for it and then find the result in the
+//! cache.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rocket::tokio::sync::Mutex as AsyncMutex;
+
+/// The locks of the lookups in flight per key.
+type InFlight<K> = HashMap<K, Arc<AsyncMutex<()>>>;
+
+/// Coalesces concurrent lookups with the same key.
+///
+/// Lookups with the same key are performed one after another, so that a lookup of a cached
+/// provider function can use the result cached by the lookup before it. Lookups with different
+/// keys are performed concurrently.
+#[derive(Debug)]
+pub(crate) struct SingleFlight<K> {
+    /// The lookups in flight.
+    in_flight: Mutex<InFlight<K>>,
+}
+
+impl<K: Clone + Eq + Hash> SingleFlight<K> {
+    /// Creates a new coalescer without lookups in flight.
+    pub(crate) fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Performs the lookup with the provided key once no other lookup with the same key is in
+    /// flight anymore.
+    pub(crate) async fn run<T, F, Fut>(&self, key: K, lookup: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let key_lock = Arc::clone(self.lock().entry(key.clone()).or_default());
+        let result = {
+            let _guard = key_lock.lock().await;
+            lookup().await
+        };
+
+        // Forget the key if no other lookups are waiting for it; the map holds the other reference.
+        let mut in_flight = self.lock();
+        if Arc::strong_count(&key_lock) == 2 {
+            in_flight.remove(&key);
+        }
+
+        result
+    }
+
+    /// Returns the number of keys with lookups in flight.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Locks the lookups in flight.
+    fn lock(&self) -> MutexGuard<'_, InFlight<K>> {
+        self.in_flight
+            .lock()
+            .expect("Lookups in flight mutex was poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rocket::tokio::runtime::Builder;
+    use rocket::tokio::time::{sleep, Duration};
+
+    use super::*;
+
+    #[test]
+    fn single_flight() {
+        let single_flight = SingleFlight::new();
+        let cache = Mutex::new(HashMap::new());
+        let calls = AtomicUsize::new(0);
+        let (cache, calls) = (&cache, &calls);
+        let lookup = |key: &'static str| {
+            single_flight.run(key, move || async move {
+                // Only call the "provider" on a cache miss, like a cached provider function.
+                let cached = cache.lock().unwrap().get(key).copied();
+                match cached {
+                    Some(value) => value,
+                    None => {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        sleep(Duration::from_millis(10)).await;
+                        cache.lock().unwrap().insert(key, key.len());
+                        key.len()
+                    }
+                }
+            })
+        };
+
+        // Concurrent lookups with the same key result in one call; the key is forgotten after.
+        let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+        let results = runtime
+            .block_on(async { rocket::tokio::join!(lookup("key"), lookup("key"), lookup("key")) });
+        assert_eq!(results, (3, 3, 3));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(single_flight.len(), 0);
+
+        // Concurrent lookups with different keys do not wait for each other.
+        let results =
+            runtime.block_on(async { rocket::tokio::join!(lookup("other"), lookup("another")) });
+        assert_eq!(results, (5, 7));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(single_flight.len(), 0);
+    }
+}
@@ -0,0 +1,170 @@
+//! Per-client rate limiting.
+//!
+//! This module provides a fairing that keeps a token bucket per client IP address. Every request
+//! takes a token from the bucket of its client; the buckets are refilled at the configured rate up
+//! to the configured burst size. Requests to the data endpoints without a token left are rejected
+//! by the [`ApiKey`](crate::auth::ApiKey) request guard.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::{Data, Request, Response};
+
+use crate::config::Config;
+
+/// The number of buckets above which idle buckets are pruned.
+const MAX_BUCKETS: usize = 10_000;
+
+/// A token bucket of a client.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    /// The number of tokens left.
+    tokens: f64,
+
+    /// The instant the bucket was last refilled.
+    refilled: Instant,
+}
+
+impl Bucket {
+    /// Refills the bucket and tries to take a token from it.
+    ///
+    /// Returns the number of seconds until a token is available if there are none left.
+    fn take(&mut self, now: Instant, rate: f64, burst: u32) -> Result<(), u64> {
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(burst));
+        self.refilled = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - self.tokens) / rate).ceil() as u64)
+        }
+    }
+}
+
+/// The number of seconds after which a rate limited request can be retried.
+///
+/// This is stored in the request-local cache; a value of 0 means that the request is not rate
+/// limited.
+#[derive(Debug, Default)]
+struct RetryAfter(AtomicU64);
+
+/// Marks the request as rate limited, retryable after the provided number of seconds.
+pub(crate) fn set_retry_after(request: &Request<'_>, seconds: u64) {
+    let retry_after = request.local_cache(RetryAfter::default);
+    retry_after.0.store(seconds.max(1), Ordering::Relaxed);
+}
+
+/// Returns the number of seconds after which the request can be retried if it is rate limited.
+pub(crate) fn retry_after(request: &Request<'_>) -> Option<u64> {
+    let retry_after = request.local_cache(RetryAfter::default);
+
+    match retry_after.0.load(Ordering::Relaxed) {
+        0 => None,
+        seconds => Some(seconds),
+    }
+}
+
+/// The fairing that limits the rate of requests per client IP address.
+///
+/// If rate limiting is not configured, it does nothing.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    /// The token buckets per client IP address.
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Takes a token for the client IP address.
+    ///
+    /// Returns the number of seconds until a token is available if there are none left.
+    fn take(&self, ip: IpAddr, rate: f64, burst: u32) -> Result<(), u64> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .expect("Rate limiter buckets mutex was poisoned");
+        let now = Instant::now();
+        if buckets.len() > MAX_BUCKETS {
+            // Buckets that would have been refilled completely by now can be dropped.
+            let full_after = f64::from(burst) / rate;
+            buckets
+                .retain(|_, bucket| now.duration_since(bucket.refilled).as_secs_f64() < full_after);
+        }
+
+        buckets
+            .entry(ip)
+            .or_insert(Bucket {
+                tokens: f64::from(burst),
+                refilled: now,
+            })
+            .take(now, rate, burst)
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for RateLimiter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate limiter",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let config = request
+            .rocket()
+            .state::<Config>()
+            .expect("Config is managed");
+        let (Some(rate_limit), Some(ip)) = (&config.rate_limit, request.client_ip()) else {
+            return;
+        };
+
+        if let Err(seconds) = self.take(ip, rate_limit.rate, rate_limit.burst) {
+            set_retry_after(request, seconds);
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.status() != Status::TooManyRequests {
+            return;
+        }
+
+        if let Some(seconds) = retry_after(request) {
+            response.set_header(Header::new("Retry-After", seconds.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn bucket_take() {
+        let now = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 2.0,
+            refilled: now,
+        };
+
+        // The burst can be used immediately, then it is limited to the rate.
+        assert_eq!(bucket.take(now, 0.5, 2), Ok(()));
+        assert_eq!(bucket.take(now, 0.5, 2), Ok(()));
+        assert_eq!(bucket.take(now, 0.5, 2), Err(2));
+        assert_eq!(bucket.take(now + Duration::from_secs(1), 0.5, 2), Err(1));
+        assert_eq!(bucket.take(now + Duration::from_secs(2), 0.5, 2), Ok(()));
+
+        // The bucket never refills beyond the burst size.
+        assert_eq!(bucket.take(now + Duration::from_secs(60), 0.5, 2), Ok(()));
+        assert_eq!(bucket.take(now + Duration::from_secs(60), 0.5, 2), Ok(()));
+        assert_eq!(bucket.take(now + Duration::from_secs(60), 0.5, 2), Err(2));
+    }
+}
@@ -0,0 +1,209 @@
+//! Home Assistant sensor data.
+//!
+//! This module is used to construct flat JSON that can be used directly by Home Assistant RESTful
+//! sensors: a single value per metric for the current time, and the forecast for the next hours as
+//! attributes per metric.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rocket::serde::Serialize;
+
+use crate::forecast::{Forecast, Metric};
+use crate::{Error, Result};
+
+/// The default number of hours of forecast items included in the attributes.
+pub(crate) const DEFAULT_SENSOR_HOURS: u32 = 6;
+
+/// The maximum number of hours of forecast items included in the attributes (a week, beyond the
+/// range of any provider).
+const MAX_SENSOR_HOURS: u32 = 7 * 24;
+
+/// A forecast item as sensor attribute.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SensorItem {
+    /// The time of the item (RFC 3339).
+    datetime: String,
+
+    /// The forecasted value.
+    value: f32,
+}
+
+/// The sensor attributes of a metric.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SensorAttributes {
+    /// The forecast items of the next hours.
+    forecast: Vec<SensorItem>,
+
+    /// The maximum value of the current and next hours (if any).
+    max: Option<f32>,
+
+    /// The error that occurred for the metric (if any).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The sensor data for a forecast.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SensorData {
+    /// The latitude of the position.
+    lat: f64,
+
+    /// The longitude of the position.
+    lon: f64,
+
+    /// The current time (in seconds since the UNIX epoch).
+    time: i64,
+
+    /// The current value per metric, or `null` if not available.
+    #[serde(flatten)]
+    values: BTreeMap<Metric, Option<f32>>,
+
+    /// The attributes per metric.
+    attributes: BTreeMap<Metric, SensorAttributes>,
+}
+
+impl SensorData {
+    /// Creates the sensor data for the metrics present in the forecast.
+    ///
+    /// The current value of a metric is the value of the most recent item at `now`, or of the first
+    /// item if all items lie in the future. The attributes contain the items after it up to the
+    /// provided number of hours after `now`.
+    ///
+    /// Returns an error if the number of hours exceeds the maximum.
+    pub(crate) fn new(forecast: &Forecast, hours: u32, now: DateTime<Utc>) -> Result<Self> {
+        let until = (hours <= MAX_SENSOR_HOURS)
+            .then(|| now.checked_add_signed(Duration::hours(i64::from(hours))))
+            .flatten()
+            .ok_or(Error::InvalidHours(hours, MAX_SENSOR_HOURS))?;
+        let mut values = BTreeMap::new();
+        let mut attributes = BTreeMap::new();
+
+        for metric in Metric::all() {
+            let error = forecast.error(metric).map(String::from);
+            let Some(series) = forecast.series(metric) else {
+                if error.is_some() {
+                    values.insert(metric, None);
+                    attributes.insert(
+                        metric,
+                        SensorAttributes {
+                            error,
+                            ..Default::default()
+                        },
+                    );
+                }
+                continue;
+            };
+
+            let current = current_index(&series, now);
+            let next = current.map_or(0, |index| index + 1);
+            let items = series[next..]
+                .iter()
+                .take_while(|(time, _)| *time <= until)
+                .map(|(time, value)| SensorItem {
+                    datetime: time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    value: *value,
+                })
+                .collect::<Vec<_>>();
+            let value = current.map(|index| series[index].1);
+            let max = value
+                .into_iter()
+                .chain(items.iter().map(|item| item.value))
+                .reduce(f32::max);
+
+            values.insert(metric, value);
+            attributes.insert(
+                metric,
+                SensorAttributes {
+                    forecast: items,
+                    max,
+                    error,
+                },
+            );
+        }
+
+        let position = forecast.position();
+        Ok(Self {
+            lat: position.lat,
+            lon: position.lon,
+            time: forecast.time(),
+            values,
+            attributes,
+        })
+    }
+}
+
+/// Returns the index of the current item of the series: the most recent item at `now`, or the
+/// first item if all items lie in the future.
+///
+/// Returns [`None`] if the series is empty.
+pub(crate) fn current_index(series: &[(DateTime<Utc>, f32)], now: DateTime<Utc>) -> Option<usize> {
+    series
+        .iter()
+        .rposition(|(time, _)| *time <= now)
+        .or_else(|| (!series.is_empty()).then_some(0))
+}
+
+#[cfg(all(test, feature = "maps"))]
+mod tests {
+    use chrono::TimeZone;
+    use rocket::serde::json::{json, serde_json};
+
+    use super::*;
+    use crate::position::Position;
+    use crate::providers::buienradar::Sample;
+
+    #[test]
+    fn sensor_data() {
+        let t = |hour| Utc.with_ymd_and_hms(2024, 6, 1, hour, 0, 0).unwrap();
+        let samples = Vec::from([
+            Sample::new(t(10), 2),
+            Sample::new(t(12), 4),
+            Sample::new(t(14), 5),
+            Sample::new(t(20), 3),
+        ]);
+        let forecast = Forecast::with_uvi(Position::new(51.4, 5.5), t(13).timestamp(), samples);
+
+        // The current value is of the most recent item, the next hours are attributes.
+        let sensor_data = SensorData::new(&forecast, 6, t(13)).unwrap();
+        assert_eq!(
+            serde_json::to_value(&sensor_data).unwrap(),
+            json!({
+                "lat": 51.4,
+                "lon": 5.5,
+                "time": t(13).timestamp(),
+                "UVI": 4.0,
+                "attributes": {
+                    "UVI": {
+                        "forecast": [{"datetime": "2024-06-01T14:00:00Z", "value": 5.0}],
+                        "max": 5.0
+                    }
+                }
+            })
+        );
+
+        // If all items lie in the future, the first one is the current value.
+        let sensor_data = SensorData::new(&forecast, 1, t(8)).unwrap();
+        assert_eq!(sensor_data.values[&Metric::UVI], Some(2.0));
+        assert_eq!(sensor_data.attributes[&Metric::UVI].forecast, []);
+        assert_eq!(sensor_data.attributes[&Metric::UVI].max, Some(2.0));
+
+        // The number of hours is limited, so that it cannot overflow the time.
+        assert!(SensorData::new(&forecast, 7 * 24, t(13)).is_ok());
+        assert!(matches!(
+            SensorData::new(&forecast, 7 * 24 + 1, t(13)),
+            Err(Error::InvalidHours(169, 168))
+        ));
+        assert!(matches!(
+            SensorData::new(&forecast, u32::MAX, t(13)),
+            Err(Error::InvalidHours(u32::MAX, 168))
+        ));
+        assert!(matches!(
+            SensorData::new(&forecast, 1, DateTime::<Utc>::MAX_UTC),
+            Err(Error::InvalidHours(1, 168))
+        ));
+    }
+}
@@ -0,0 +1,3746 @@
+//! The HTTP server providing the API endpoints.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::Utc;
+use rocket::fairing::AdHoc;
+use rocket::form::{self, DataField, FromForm, ValueField};
+use rocket::fs::NamedFile;
+use rocket::futures::future::join_all;
+use rocket::http::uri::Origin;
+use rocket::http::{Accept, Header, MediaType, Status};
+use rocket::request::Outcome;
+use rocket::response::content::RawHtml;
+use rocket::response::status::{Created, NoContent};
+use rocket::response::stream::{Event, EventStream};
+use rocket::response::Responder;
+use rocket::serde::json::{serde_json, Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::select;
+use rocket::tokio::time::{sleep, Duration};
+use rocket::{
+    catch, catchers, delete, get, post, put, routes, Build, Request, Rocket, Shutdown, State,
+};
+use tracing::{error, info, warn, Instrument};
+
+use crate::advice::{Advice, Profile, ADVICE_METRICS, DEFAULT_ADVICE_HOURS};
+use crate::aqi_scale::AqiScale;
+use crate::auth::{AdminKey, ApiKey, ApiKeyUsage};
+#[cfg(feature = "maps")]
+use crate::badge;
+use crate::binary;
+#[cfg(feature = "maps")]
+use crate::chart::{self, ChartFormat};
+use crate::compression::Compression;
+use crate::conditional::{Conditional, Preconditions};
+use crate::config::Config;
+use crate::exporter::ExporterHandle;
+use crate::fields::FieldSelection;
+use crate::forecast::{forecast, Forecast, Metric, TimeWindow, Units};
+use crate::geocoder::{Candidate, Geocoder};
+use crate::grafana::{self, QueryRequest, SearchRequest, Target, TimeSeries};
+use crate::history::{AccuracyStats, History, HistoryItem};
+use crate::http::HttpClient;
+use crate::i18n::{self, AcceptLanguage, Locale};
+use crate::locations::{self, NamedLocation, NamedLocations, NamedLocationsHandle};
+use crate::logging::{RequestLogger, RequestSpan};
+#[cfg(feature = "maps")]
+use crate::maps;
+#[cfg(feature = "maps")]
+use crate::maps::{
+    animate_map, mark_map, projection, raw_map, Error as MapsError, MapFormat, MapOptions,
+    MapsType, Projection, RefreshOutcome, Validity,
+};
+use crate::maps::{Maps, MapsHandle, RefresherStatus, RefresherStatusHandle};
+use crate::position::{
+    resolve_address, resolve_candidate, resolve_candidates, resolve_place_name, resolve_postcode,
+    resolve_suggestions, BoundingBox, GeocodingCache, Position, MAX_SEARCH_SUGGESTIONS,
+};
+use crate::protobuf;
+use crate::providers::circuit_breaker::{self, CircuitBreakerStatus};
+use crate::providers::{combined, mock, CacheStats, Provider};
+use crate::rate_limit::RateLimiter;
+use crate::sensor::{SensorData, DEFAULT_SENSOR_HOURS};
+use crate::subscriptions::{NewSubscription, Subscription, Subscriptions, SubscriptionsHandle};
+use crate::thresholds::ThresholdParams;
+use crate::{
+    calendar, conditional, consensus, exporter, feed, geocoder, history, http, logging, notifier,
+    nowcast, position, prefetch, providers, shared_cache, subscriptions, Error, Result,
+};
+
+impl Error {
+    /// Returns the request guard outcome for the error.
+    ///
+    /// The error is kept with the request so that the catcher can respond with its details.
+    pub(crate) fn into_outcome<T>(self, request: &Request<'_>) -> Outcome<T, Error> {
+        let body = ErrorBody::from(&self);
+        request.local_cache(|| Some(body));
+
+        Outcome::Error((self.status(), self))
+    }
+}
+
+/// The body of an error response.
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ErrorBody {
+    /// The error message.
+    error: String,
+
+    /// The kind of the error, see [`Error::kind`].
+    kind: String,
+
+    /// The HTTP status code.
+    status: u16,
+
+    /// The supported coverage area (if the position lies outside of it).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coverage: Option<BoundingBox>,
+
+    /// The kind of the error per failed metric (if the forecast is incomplete).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kinds: Option<BTreeMap<Metric, &'static str>>,
+}
+
+impl ErrorBody {
+    /// Creates an error body for the provided status only.
+    ///
+    /// The kind is derived from the reason of the status, e.g. `not_found`.
+    fn from_status(status: Status) -> Self {
+        let reason = status.reason().unwrap_or("Unknown Error");
+
+        Self {
+            error: String::from(reason),
+            kind: reason.to_lowercase().replace([' ', '-'], "_"),
+            status: status.code,
+            coverage: None,
+            error_kinds: None,
+        }
+    }
+}
+
+impl ErrorBody {
+    /// Translates the error message to the provided locale, if there is a translation for its
+    /// kind.
+    fn localize(mut self, locale: Locale) -> Self {
+        if let Some(message) = i18n::error_message(locale, &self.kind) {
+            self.error = String::from(message);
+        }
+
+        self
+    }
+}
+
+impl From<&Error> for ErrorBody {
+    fn from(error: &Error) -> Self {
+        let coverage = match error {
+            #[cfg(feature = "maps")]
+            Error::Maps(MapsError::OutsideCoverage(_, coverage)) => Some(*coverage),
+            _ => None,
+        };
+        let error_kinds = match error {
+            Error::IncompleteForecast(error_kinds) => Some(error_kinds.clone()),
+            _ => None,
+        };
+
+        Self {
+            error: error.to_string(),
+            kind: String::from(error.kind()),
+            status: error.status().code,
+            coverage,
+            error_kinds,
+        }
+    }
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'o> {
+        let status = self.status();
+        warn!(error = %self, status = status.code, "💥 Encountered error during request");
+
+        let AcceptLanguage(locale) = AcceptLanguage::of(request);
+        let body = ErrorBody::from(&self).localize(locale);
+
+        (status, Json(body)).respond_to(request)
+    }
+}
+
+/// Catcher that responds with a JSON error body for all errors not produced by handlers.
+///
+/// If a request guard failed, the details of its error are used.
+#[catch(default)]
+fn default_catcher(status: Status, request: &Request<'_>) -> (Status, Json<ErrorBody>) {
+    let body = request
+        .local_cache(|| None::<ErrorBody>)
+        .clone()
+        .unwrap_or_else(|| ErrorBody::from_status(status));
+    let AcceptLanguage(locale) = AcceptLanguage::of(request);
+
+    (status, Json(body.localize(locale)))
+}
+
+/// The map image data, either a single map or an animation of all maps.
+#[cfg(feature = "maps")]
+#[derive(Responder)]
+enum MapImageData {
+    /// A single map as PNG image.
+    #[response(content_type = "image/png")]
+    Png(Vec<u8>),
+
+    /// A single map as JPEG image.
+    #[response(content_type = "image/jpeg")]
+    Jpeg(Vec<u8>),
+
+    /// A single map as WebP image.
+    #[response(content_type = "image/webp")]
+    Webp(Vec<u8>),
+
+    /// An animation of all maps as animated PNG (APNG) image.
+    #[response(content_type = "image/apng")]
+    Apng(Vec<u8>),
+}
+
+/// The map response, which varies with the `Accept` header as the image format is negotiated.
+#[cfg(feature = "maps")]
+#[derive(Responder)]
+struct MapResponse {
+    /// The map image data.
+    image_data: MapImageData,
+
+    /// The `Vary` header.
+    vary: Header<'static>,
+}
+
+#[cfg(feature = "maps")]
+#[derive(Responder)]
+#[response(content_type = "image/png")]
+struct BadgeData(Vec<u8>);
+
+/// The chart image data in the requested format.
+#[cfg(feature = "maps")]
+#[derive(Responder)]
+enum ChartData {
+    /// A chart as PNG image.
+    #[response(content_type = "image/png")]
+    Png(Vec<u8>),
+
+    /// A chart as SVG image.
+    #[response(content_type = "image/svg+xml")]
+    Svg(String),
+}
+
+#[derive(Responder)]
+#[response(content_type = "text/calendar")]
+struct CalendarData(String);
+
+#[derive(Responder)]
+#[response(content_type = "application/atom+xml")]
+struct FeedData(String);
+
+#[derive(Responder)]
+#[response(content_type = "text/plain")]
+struct MetricsData(String);
+
+#[derive(Responder)]
+#[response(content_type = "text/plain")]
+struct SchemaData(&'static str);
+
+/// The supported forecast response formats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, rocket::FromFormField)]
+enum Format {
+    /// A JSON object with the items per metric.
+    #[default]
+    Json,
+    /// A CSV table with a row per timestamp and a column per metric.
+    Csv,
+    /// The JSON object encoded as MessagePack.
+    Msgpack,
+    /// The JSON object encoded as CBOR.
+    Cbor,
+    /// A Protocol Buffers message, see the published schema.
+    Protobuf,
+}
+
+impl Format {
+    /// Negotiates the format given the media types the client accepts per the `Accept` header.
+    ///
+    /// The supported media type with the highest quality value is selected; media types with a
+    /// quality value of zero are not acceptable. Returns [`None`] if no supported media type is
+    /// accepted explicitly (wildcards are not considered).
+    fn negotiate(accept: &Accept) -> Option<Self> {
+        let mut formats = accept
+            .iter()
+            .filter_map(|media_type| {
+                let format = [
+                    Format::Json,
+                    Format::Csv,
+                    Format::Msgpack,
+                    Format::Cbor,
+                    Format::Protobuf,
+                ]
+                .into_iter()
+                .find(|format| media_type.media_type() == &format.media_type())?;
+                let quality = media_type.weight_or(1.0);
+
+                (quality > 0.0).then_some((format, quality))
+            })
+            .collect::<Vec<_>>();
+        // Sort stably so that the first format listed wins if the quality values are equal.
+        formats.sort_by(|(_, q1), (_, q2)| q2.total_cmp(q1));
+
+        formats.first().map(|(format, _)| *format)
+    }
+
+    /// Returns the media type of the format.
+    fn media_type(self) -> MediaType {
+        match self {
+            Format::Json => MediaType::JSON,
+            Format::Csv => MediaType::CSV,
+            Format::Msgpack => MediaType::MsgPack,
+            Format::Cbor => MediaType::new("application", "cbor"),
+            Format::Protobuf => MediaType::new("application", "x-protobuf"),
+        }
+    }
+}
+
+/// The forecast data in the requested format.
+#[derive(Responder)]
+enum ForecastData {
+    /// The forecast as JSON.
+    Json(Json<Value>),
+
+    /// The forecast as CSV table.
+    #[response(content_type = "text/csv")]
+    Csv(String),
+
+    /// The forecast as MessagePack.
+    #[response(content_type = "application/msgpack")]
+    Msgpack(Vec<u8>),
+
+    /// The forecast as CBOR.
+    #[response(content_type = "application/cbor")]
+    Cbor(Vec<u8>),
+
+    /// The forecast as Protocol Buffers message.
+    #[response(content_type = "application/x-protobuf")]
+    Protobuf(Vec<u8>),
+}
+
+/// The forecast response, which varies with the `Accept` header as the format is negotiated.
+#[derive(Responder)]
+struct ForecastResponse {
+    /// The forecast data.
+    data: ForecastData,
+
+    /// The `Vary` header.
+    vary: Header<'static>,
+}
+
+impl ForecastResponse {
+    /// Creates a forecast response in the provided format.
+    ///
+    /// If a field selection is provided, it is applied to all formats but CSV.
+    fn new(forecast: Forecast, format: Format, fields: Option<&FieldSelection>) -> Result<Self> {
+        let mut value = serde_json::to_value(&forecast)?;
+        if let Some(fields) = fields {
+            fields.apply(&mut value);
+        }
+        let data = match format {
+            Format::Json => ForecastData::Json(Json(value)),
+            Format::Csv => ForecastData::Csv(forecast.to_csv()?),
+            // Without a field selection, the forecast itself is serialized, so that its values
+            // keep their precision instead of becoming JSON numbers.
+            Format::Msgpack if fields.is_none() => {
+                ForecastData::Msgpack(binary::to_msgpack(&forecast)?)
+            }
+            Format::Msgpack => ForecastData::Msgpack(binary::to_msgpack(&value)?),
+            Format::Cbor if fields.is_none() => ForecastData::Cbor(binary::to_cbor(&forecast)?),
+            Format::Cbor => ForecastData::Cbor(binary::to_cbor(&value)?),
+            Format::Protobuf => ForecastData::Protobuf(protobuf::to_protobuf::<
+                protobuf::messages::Forecast,
+            >(&value)?),
+        };
+
+        Ok(Self {
+            data,
+            vary: Header::new("Vary", "Accept"),
+        })
+    }
+
+    /// Creates a conditional forecast response in the provided format.
+    ///
+    /// The entity tag is derived from the data of the forecast, the format and the field
+    /// selection, the last modification time from the time the data was retrieved and the maximum
+    /// age from the time until the cached data expires.
+    fn conditional(
+        forecast: Forecast,
+        format: Format,
+        fields: Option<&FieldSelection>,
+        preconditions: &Preconditions,
+    ) -> Result<Conditional<Self>> {
+        let etag = conditional::etag(&(forecast.data(), format, fields));
+        let last_modified = forecast.last_modified();
+        let max_age = forecast.max_age();
+        let response = Self::new(forecast, format, fields)?;
+
+        Ok(Conditional::new(response, etag, last_modified, preconditions).with_max_age(max_age))
+    }
+}
+
+/// The query parameters shared by the forecast endpoints, other than the personal thresholds.
+#[derive(Debug, rocket::FromForm)]
+struct ForecastOptions {
+    /// The metrics to retrieve the forecast for.
+    metrics: Vec<Metric>,
+
+    /// The start of the time window (in seconds since the UNIX epoch).
+    from: Option<i64>,
+
+    /// The end of the time window (in seconds since the UNIX epoch).
+    to: Option<i64>,
+
+    /// The length of the time window (in hours).
+    hours: Option<u32>,
+
+    /// The number of items to skip per metric.
+    offset: Option<usize>,
+
+    /// The maximum number of items per metric.
+    limit: Option<usize>,
+
+    /// The response format.
+    format: Option<Format>,
+
+    /// The selection of fields to trim the response to.
+    fields: Option<String>,
+
+    /// Whether to fail if the forecast for any of the metrics could not be retrieved.
+    strict: Option<bool>,
+
+    /// Whether to interpolate the items of the metrics sampled from the maps.
+    interpolate: Option<bool>,
+
+    /// Whether to include the metadata per metric.
+    include_meta: Option<bool>,
+
+    /// The system of units to convert the values to.
+    units: Option<Units>,
+
+    /// Whether to label the items with their classification.
+    labels: Option<bool>,
+
+    /// The locale of the labels.
+    locale: Option<Locale>,
+
+    /// Whether to include a summary of the highlights.
+    summary: Option<bool>,
+
+    /// Whether to include the consensus of the precipitation providers.
+    consensus: Option<bool>,
+
+    /// The scale to convert the air quality index to.
+    aqi_scale: Option<AqiScale>,
+
+    /// Whether to include the underlying pollutant concentrations of the indices.
+    raw: Option<bool>,
+}
+
+/// The query parameters shared by the forecast endpoints.
+///
+/// The personal thresholds are parsed from the same (flat) query parameters as the options, e.g.
+/// `metrics=pollen&pollen_max=5`, so each field is pushed to both.
+#[derive(Debug)]
+struct ForecastParams {
+    /// The forecast options.
+    options: ForecastOptions,
+
+    /// The personal thresholds.
+    thresholds: ThresholdParams,
+}
+
+#[rocket::async_trait]
+impl<'v> FromForm<'v> for ForecastParams {
+    type Context = (
+        <ForecastOptions as FromForm<'v>>::Context,
+        <ThresholdParams as FromForm<'v>>::Context,
+    );
+
+    fn init(opts: form::Options) -> Self::Context {
+        (ForecastOptions::init(opts), ThresholdParams::init(opts))
+    }
+
+    fn push_value((options, thresholds): &mut Self::Context, field: ValueField<'v>) {
+        ForecastOptions::push_value(options, field.clone());
+        ThresholdParams::push_value(thresholds, field);
+    }
+
+    async fn push_data((options, _): &mut Self::Context, field: DataField<'v, '_>) {
+        ForecastOptions::push_data(options, field).await;
+    }
+
+    fn finalize((options, thresholds): Self::Context) -> form::Result<'v, Self> {
+        match (
+            ForecastOptions::finalize(options),
+            ThresholdParams::finalize(thresholds),
+        ) {
+            (Ok(options), Ok(thresholds)) => Ok(Self {
+                options,
+                thresholds,
+            }),
+            (Err(mut errors), Err(threshold_errors)) => {
+                errors.extend(threshold_errors);
+                Err(errors)
+            }
+            (Err(errors), _) | (_, Err(errors)) => Err(errors),
+        }
+    }
+}
+
+/// The current version of the API.
+///
+/// Breaking changes to the responses require a new version, mounted under its own path prefix.
+const API_VERSION: &str = "v1";
+
+/// The version information as JSON response.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct VersionInfo {
+    /// The version of the build.
+    version: String,
+
+    /// The timestamp of the build.
+    timestamp: String,
+
+    /// The (most recent) git SHA used for the build.
+    git_sha: String,
+
+    /// The timestamp of the last git commit used for the build.
+    git_timestamp: String,
+
+    /// The current version of the API.
+    api_version: &'static str,
+}
+impl VersionInfo {
+    /// Retrieves the version information from the environment variables.
+    fn new() -> Self {
+        Self {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            timestamp: String::from(env!("VERGEN_BUILD_TIMESTAMP")),
+            git_sha: String::from(&env!("VERGEN_GIT_SHA")[0..7]),
+            git_timestamp: String::from(env!("VERGEN_GIT_COMMIT_TIMESTAMP")),
+            api_version: API_VERSION,
+        }
+    }
+}
+
+/// A location to retrieve a forecast for as part of a batch request, or to store under a name.
+///
+/// It is either an address or a Dutch postal code that still needs to be geocoded, or a geocoded
+/// position.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde", untagged)]
+pub(crate) enum Location {
+    /// An address.
+    Address {
+        /// The address to geocode.
+        address: String,
+    },
+
+    /// A Dutch postal code.
+    Postcode {
+        /// The postal code to geocode.
+        postcode: String,
+    },
+
+    /// A geocoded position.
+    Geo {
+        /// The latitude of the position.
+        lat: f64,
+
+        /// The longitude of the position.
+        lon: f64,
+    },
+}
+
+impl Location {
+    /// Resolves the location to a geocoded position.
+    pub(crate) async fn resolve(self) -> Result<Position> {
+        match self {
+            Location::Address { address } => resolve_address(address).await,
+            Location::Postcode { postcode } => resolve_postcode(postcode).await,
+            Location::Geo { lat, lon } => Ok(Position::new(lat, lon)),
+        }
+    }
+}
+
+/// Returns the path prefix of the API version the request was made with, if any.
+fn api_base(uri: &Origin<'_>) -> String {
+    match uri.path().segments().next() {
+        Some(API_VERSION) => format!("/{API_VERSION}"),
+        _ => String::new(),
+    }
+}
+
+/// Responds with the forecast for the provided position, which all forecast endpoints share.
+///
+/// The items can optionally be restricted to a time window using `from`, `to` and/or `hours`, and
+/// then to a page using `offset` and `limit`. The response is JSON unless another format is
+/// requested using `format` or negotiated using the `Accept` header, and can be trimmed to a
+/// selection of `fields`. If `strict` is set, the request fails if the forecast for any of the
+/// metrics could not be retrieved. If `interpolate` is set, the items of the metrics sampled from
+/// the maps are interpolated. If `include_meta` is set, the metadata per metric is included. The
+/// values are converted to the system of `units`, metric by default. If `labels` is set, the items
+/// are labeled with their classification in the `locale`, or the locale preferred by the
+/// `Accept-Language` header. If `summary` is set, a summary of the highlights is included. If
+/// `consensus` is set, the consensus of the precipitation providers is included. The items are
+/// marked with whether they exceed the `<metric>_max` thresholds and those of the API key. The air
+/// quality index is converted to the `aqi_scale`, the Dutch LKI by default. If `raw` is set, the
+/// underlying pollutant concentrations of the indices are included.
+///
+/// If a place name is provided, it is included as the location of the forecast.
+#[allow(clippy::too_many_arguments)]
+async fn respond_forecast(
+    position: Position,
+    place_name: Option<String>,
+    params: ForecastParams,
+    config: &Config,
+    maps_handle: &MapsHandle,
+    preconditions: &Preconditions,
+    accept: Option<&Accept>,
+    accept_language: AcceptLanguage,
+    uri: &Origin<'_>,
+    api_key: &ApiKey,
+) -> Result<Conditional<ForecastResponse>> {
+    let ForecastParams {
+        options,
+        thresholds,
+    } = params;
+    let window = TimeWindow::new(options.from, options.to, options.hours)?;
+    let thresholds = thresholds.merge(&api_key.thresholds(config))?;
+    let mut forecast = forecast(position, options.metrics, maps_handle).await;
+    history::record(&forecast);
+    if let Some(aqi_scale) = options.aqi_scale {
+        forecast.convert_aqi_scale(aqi_scale).await;
+    }
+    if options.raw.unwrap_or_default() {
+        forecast.include_concentrations().await;
+    }
+    if options.strict.unwrap_or_default() {
+        forecast.check_complete()?;
+    }
+    if options.interpolate.unwrap_or_default() {
+        forecast.interpolate();
+    }
+    forecast.retain_window(window);
+    forecast.retain_page(options.offset.unwrap_or_default(), options.limit);
+    if options.labels.unwrap_or_default() {
+        forecast.include_labels(options.locale.unwrap_or(accept_language.0));
+    }
+    forecast.mark_thresholds(&thresholds);
+    if options.consensus.unwrap_or_default() {
+        forecast.include_consensus().await;
+    }
+    forecast.convert_units(options.units.unwrap_or_default());
+    if options.summary.unwrap_or_default() {
+        forecast.include_summary();
+    }
+    if options.include_meta.unwrap_or_default() {
+        forecast.include_meta();
+    }
+    forecast.include_links(uri.to_string(), &api_base(uri));
+    if let Some(place_name) = place_name {
+        forecast.set_location(place_name);
+    }
+
+    let format = options
+        .format
+        .or_else(|| accept.and_then(Format::negotiate))
+        .unwrap_or_default();
+    let fields = options.fields.as_deref().map(FieldSelection::parse);
+    ForecastResponse::conditional(forecast, format, fields.as_ref(), preconditions)
+}
+
+/// Handler for retrieving the forecast for an address.
+///
+/// If `candidate` is set, the position of the geocoding candidate with that index is used instead
+/// of the first one. See [`respond_forecast`] for the other parameters.
+#[get("/forecast?<address>&<candidate>&<params..>")]
+#[allow(clippy::too_many_arguments)]
+async fn forecast_address(
+    address: String,
+    candidate: Option<usize>,
+    params: ForecastParams,
+    config: &State<Config>,
+    maps_handle: &State<MapsHandle>,
+    preconditions: Preconditions,
+    accept: Option<&Accept>,
+    accept_language: AcceptLanguage,
+    uri: &Origin<'_>,
+    api_key: ApiKey,
+    span: RequestSpan,
+) -> Result<Conditional<ForecastResponse>> {
+    async {
+        let position = match candidate {
+            Some(index) => resolve_candidate(address, index).await?,
+            None => resolve_address(address).await?,
+        };
+
+        respond_forecast(
+            position,
+            None,
+            params,
+            config,
+            maps_handle,
+            &preconditions,
+            accept,
+            accept_language,
+            uri,
+            &api_key,
+        )
+        .await
+    }
+    .instrument(span.0)
+    .await
+}
+
+/// Handler for retrieving the geocoding candidates for an address.
+///
+/// The candidates are ordered from most to least plausible; their index can be used to select
+/// one when retrieving a forecast.
+#[get("/geocode?<address>")]
+async fn geocode(address: String, _api_key: ApiKey) -> Result<Json<Vec<Candidate>>> {
+    let candidates = resolve_candidates(address).await?;
+
+    Ok(Json(candidates))
+}
+
+/// Handler for retrieving place suggestions for a (partial) search query.
+///
+/// This is meant for autocompletion, e.g. `eind` suggests Eindhoven. At most `limit` suggestions
+/// are returned, 5 by default. The names of the places are preferably in the `locale` language,
+/// or the language preferred by the client.
+#[get("/search?<q>&<limit>&<locale>")]
+async fn search(
+    q: String,
+    limit: Option<usize>,
+    locale: Option<Locale>,
+    accept_language: AcceptLanguage,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<Candidate>>> {
+    let locale = locale.unwrap_or(accept_language.0);
+    let mut suggestions = resolve_suggestions(&q, locale).await?;
+    suggestions.truncate(limit.unwrap_or(5).min(MAX_SEARCH_SUGGESTIONS));
+
+    Ok(Json(suggestions))
+}
+
+/// Handler for retrieving the forecast for a geocoded position.
+///
+/// If `resolve_name` is set, the position is reverse geocoded and the resulting place name is
+/// included (if found). See [`respond_forecast`] for the other parameters.
+#[get("/forecast?<lat>&<lon>&<resolve_name>&<params..>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn forecast_geo(
+    lat: f64,
+    lon: f64,
+    resolve_name: Option<bool>,
+    params: ForecastParams,
+    config: &State<Config>,
+    maps_handle: &State<MapsHandle>,
+    preconditions: Preconditions,
+    accept: Option<&Accept>,
+    accept_language: AcceptLanguage,
+    uri: &Origin<'_>,
+    api_key: ApiKey,
+    span: RequestSpan,
+) -> Result<Conditional<ForecastResponse>> {
+    async {
+        let position = Position::new(lat, lon);
+        let place_name = if resolve_name.unwrap_or_default() {
+            resolve_place_name(position)
+                .await
+                .inspect_err(|e| warn!(error = %e, "💥 Encountered error during reverse geocoding"))
+                .ok()
+        } else {
+            None
+        };
+
+        respond_forecast(
+            position,
+            place_name,
+            params,
+            config,
+            maps_handle,
+            &preconditions,
+            accept,
+            accept_language,
+            uri,
+            &api_key,
+        )
+        .await
+    }
+    .instrument(span.0)
+    .await
+}
+
+/// Handler for retrieving the forecast for a Dutch postal code.
+///
+/// See [`respond_forecast`] for the parameters.
+#[get("/forecast?<postcode>&<params..>", rank = 3)]
+#[allow(clippy::too_many_arguments)]
+async fn forecast_postcode(
+    postcode: String,
+    params: ForecastParams,
+    config: &State<Config>,
+    maps_handle: &State<MapsHandle>,
+    preconditions: Preconditions,
+    accept: Option<&Accept>,
+    accept_language: AcceptLanguage,
+    uri: &Origin<'_>,
+    api_key: ApiKey,
+    span: RequestSpan,
+) -> Result<Conditional<ForecastResponse>> {
+    async {
+        let position = resolve_postcode(postcode).await?;
+
+        respond_forecast(
+            position,
+            None,
+            params,
+            config,
+            maps_handle,
+            &preconditions,
+            accept,
+            accept_language,
+            uri,
+            &api_key,
+        )
+        .await
+    }
+    .instrument(span.0)
+    .await
+}
+
+/// Handler for retrieving the forecast for a named location, e.g. `home`.
+///
+/// See [`respond_forecast`] for the parameters.
+#[get("/forecast?<location>&<params..>", rank = 4)]
+#[allow(clippy::too_many_arguments)]
+async fn forecast_location(
+    location: String,
+    params: ForecastParams,
+    config: &State<Config>,
+    maps_handle: &State<MapsHandle>,
+    named_locations_handle: &State<NamedLocationsHandle>,
+    preconditions: Preconditions,
+    accept: Option<&Accept>,
+    accept_language: AcceptLanguage,
+    uri: &Origin<'_>,
+    api_key: ApiKey,
+    span: RequestSpan,
+) -> Result<Conditional<ForecastResponse>> {
+    async {
+        let position = named_locations_handle
+            .lock()
+            .expect("Named locations handle mutex was poisoned")
+            .get(&location)?
+            .position();
+
+        respond_forecast(
+            position,
+            None,
+            params,
+            config,
+            maps_handle,
+            &preconditions,
+            accept,
+            accept_language,
+            uri,
+            &api_key,
+        )
+        .await
+    }
+    .instrument(span.0)
+    .await
+}
+
+/// Handler for streaming the forecast for a geocoded position as server-sent events.
+///
+/// A forecast event is sent immediately and then whenever the forecast data changes, i.e. when the
+/// maps have been refreshed or cached provider data has expired and been retrieved again. Changes
+/// are checked for at the configured check interval of the maps.
+#[get("/forecast/stream?<lat>&<lon>&<metrics>")]
+fn forecast_stream(
+    lat: f64,
+    lon: f64,
+    metrics: Vec<Metric>,
+    maps_handle: &State<MapsHandle>,
+    config: &State<Config>,
+    _api_key: ApiKey,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let position = Position::new(lat, lon);
+    let maps_handle = Arc::clone(maps_handle);
+    let check_interval = Duration::from_secs(config.maps.check_interval);
+
+    EventStream! {
+        let mut last_forecast: Option<Forecast> = None;
+        loop {
+            let forecast = forecast(position, metrics.clone(), &maps_handle).await;
+            if last_forecast
+                .as_ref()
+                .is_none_or(|last_forecast| !last_forecast.has_same_data(&forecast))
+            {
+                yield Event::json(&forecast).event("forecast");
+                last_forecast = Some(forecast);
+            }
+
+            select! {
+                _ = sleep(check_interval) => {},
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+}
+
+/// Handler for retrieving the forecasts for a batch of locations.
+///
+/// The locations are provided as a JSON array of addresses and/or geocoded positions. The same
+/// metrics, time window, page and field selection are used for all forecasts, which are returned
+/// in the same order. The locations are resolved and their forecasts retrieved concurrently. If a
+/// location cannot be resolved, an error body is returned in its place. Batches with more
+/// locations than configured are rejected.
+#[post(
+    "/forecasts?<metrics>&<from>&<to>&<hours>&<offset>&<limit>&<fields>",
+    format = "json",
+    data = "<locations>"
+)]
+#[allow(clippy::too_many_arguments)]
+async fn forecasts(
+    metrics: Vec<Metric>,
+    from: Option<i64>,
+    to: Option<i64>,
+    hours: Option<u32>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    fields: Option<String>,
+    locations: Json<Vec<Location>>,
+    config: &State<Config>,
+    maps_handle: &State<MapsHandle>,
+    accept_language: AcceptLanguage,
+    _api_key: ApiKey,
+    span: RequestSpan,
+) -> Result<Json<Vec<Value>>> {
+    async {
+        if locations.len() > config.max_batch_locations {
+            return Err(Error::TooManyLocations(
+                locations.len(),
+                config.max_batch_locations,
+            ));
+        }
+        let window = TimeWindow::new(from, to, hours)?;
+        let fields = fields.as_deref().map(FieldSelection::parse);
+        let (metrics, fields) = (&metrics, &fields);
+        let forecasts = locations
+            .into_inner()
+            .into_iter()
+            .map(|location| async move {
+                let position = location.resolve().await?;
+                // Concurrent retrievals for the same position are de-duplicated by the providers.
+                let mut forecast = forecast(position, metrics.clone(), maps_handle).await;
+                history::record(&forecast);
+                forecast.retain_window(window);
+                forecast.retain_page(offset.unwrap_or_default(), limit);
+                let mut forecast = serde_json::to_value(&forecast)?;
+                if let Some(fields) = fields {
+                    fields.apply(&mut forecast);
+                }
+
+                Ok(forecast)
+            });
+        let forecasts = join_all(forecasts)
+            .await
+            .into_iter()
+            .map(|result: Result<Value>| {
+                result.or_else(|e| {
+                    warn!(error = %e, "💥 Encountered error during batch forecast");
+                    let body = ErrorBody::from(&e).localize(accept_language.0);
+
+                    Ok(serde_json::to_value(body)?)
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Json(forecasts))
+    }
+    .instrument(span.0)
+    .await
+}
+
+/// Returns the calendar feed for the given position, metric and threshold.
+async fn calendar_data(
+    position: Position,
+    metric: Metric,
+    threshold: f32,
+    maps_handle: &MapsHandle,
+) -> Result<CalendarData> {
+    if metric == Metric::All {
+        return Err(Error::UnsupportedMetric(metric));
+    }
+    let forecast = forecast(position, Vec::from([metric]), maps_handle).await;
+    let series = forecast.series(metric).ok_or_else(|| {
+        let error = forecast.error(metric).unwrap_or_default();
+        Error::MetricFailed(metric, error.to_owned())
+    })?;
+
+    Ok(CalendarData(calendar::calendar(
+        position, metric, threshold, &series,
+    )))
+}
+
+/// Handler for retrieving a calendar feed with events for when the forecast of a metric for an
+/// address exceeds a threshold.
+#[get("/calendar?<address>&<metric>&<threshold>")]
+async fn calendar_address(
+    address: String,
+    metric: Metric,
+    threshold: f32,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<CalendarData> {
+    let position = resolve_address(address).await?;
+
+    calendar_data(position, metric, threshold, maps_handle).await
+}
+
+/// Handler for retrieving a calendar feed with events for when the forecast of a metric for a
+/// geocoded position exceeds a threshold.
+#[get("/calendar?<lat>&<lon>&<metric>&<threshold>", rank = 2)]
+async fn calendar_geo(
+    lat: f64,
+    lon: f64,
+    metric: Metric,
+    threshold: f32,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<CalendarData> {
+    let position = Position::new(lat, lon);
+
+    calendar_data(position, metric, threshold, maps_handle).await
+}
+
+/// Returns the warnings feed for the given position.
+async fn feed_data(position: Position, config: &Config, maps_handle: &MapsHandle) -> FeedData {
+    let thresholds = &config.warning_thresholds;
+    let metrics = thresholds.keys().copied().collect();
+    let forecast = forecast(position, metrics, maps_handle).await;
+
+    FeedData(feed::feed(position, thresholds, &forecast))
+}
+
+/// Handler for retrieving an Atom feed with warnings for when the forecast of a metric for an
+/// address exceeds its configured threshold.
+#[get("/feed?<address>")]
+async fn feed_address(
+    address: String,
+    config: &State<Config>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<FeedData> {
+    let position = resolve_address(address).await?;
+
+    Ok(feed_data(position, config, maps_handle).await)
+}
+
+/// Handler for retrieving an Atom feed with warnings for when the forecast of a metric for a
+/// geocoded position exceeds its configured threshold.
+#[get("/feed?<lat>&<lon>", rank = 2)]
+async fn feed_geo(
+    lat: f64,
+    lon: f64,
+    config: &State<Config>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> FeedData {
+    let position = Position::new(lat, lon);
+
+    feed_data(position, config, maps_handle).await
+}
+
+/// Returns the outdoor advice for the given position and profile.
+async fn advice_data(
+    position: Position,
+    profile: Option<Profile>,
+    hours: Option<u32>,
+    maps_handle: &MapsHandle,
+) -> Result<Json<Advice>> {
+    let forecast = forecast(position, Vec::from(ADVICE_METRICS), maps_handle).await;
+    let profile = profile.unwrap_or_default();
+    let hours = hours.unwrap_or(DEFAULT_ADVICE_HOURS);
+
+    Ok(Json(Advice::new(&forecast, profile, hours, Utc::now())?))
+}
+
+/// Handler for retrieving outdoor advice for an address.
+///
+/// The advice is given for the next `hours` for the `profile`.
+#[get("/advice?<address>&<profile>&<hours>")]
+async fn advice_address(
+    address: String,
+    profile: Option<Profile>,
+    hours: Option<u32>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<Json<Advice>> {
+    let position = resolve_address(address).await?;
+
+    advice_data(position, profile, hours, maps_handle).await
+}
+
+/// Handler for retrieving outdoor advice for a geocoded position.
+///
+/// The advice is given for the next `hours` for the `profile`.
+#[get("/advice?<lat>&<lon>&<profile>&<hours>", rank = 2)]
+async fn advice_geo(
+    lat: f64,
+    lon: f64,
+    profile: Option<Profile>,
+    hours: Option<u32>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<Json<Advice>> {
+    let position = Position::new(lat, lon);
+
+    advice_data(position, profile, hours, maps_handle).await
+}
+
+/// Returns the Home Assistant sensor data for the given position and metrics.
+async fn sensor_data(
+    position: Position,
+    metrics: Vec<Metric>,
+    hours: Option<u32>,
+    maps_handle: &MapsHandle,
+) -> Result<Json<SensorData>> {
+    let forecast = forecast(position, metrics, maps_handle).await;
+    let hours = hours.unwrap_or(DEFAULT_SENSOR_HOURS);
+
+    Ok(Json(SensorData::new(&forecast, hours, Utc::now())?))
+}
+
+/// Handler for retrieving Home Assistant sensor data for an address.
+///
+/// The attributes contain the forecast items of the next `hours`.
+#[get("/sensor?<address>&<metrics>&<hours>")]
+async fn sensor_address(
+    address: String,
+    metrics: Vec<Metric>,
+    hours: Option<u32>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<Json<SensorData>> {
+    let position = resolve_address(address).await?;
+
+    sensor_data(position, metrics, hours, maps_handle).await
+}
+
+/// Handler for retrieving Home Assistant sensor data for a geocoded position.
+///
+/// The attributes contain the forecast items of the next `hours`.
+#[get("/sensor?<lat>&<lon>&<metrics>&<hours>", rank = 2)]
+async fn sensor_geo(
+    lat: f64,
+    lon: f64,
+    metrics: Vec<Metric>,
+    hours: Option<u32>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<Json<SensorData>> {
+    let position = Position::new(lat, lon);
+
+    sensor_data(position, metrics, hours, maps_handle).await
+}
+
+/// Renders the badge for the provided position and metric.
+#[cfg(feature = "maps")]
+async fn badge_data(
+    position: Position,
+    metric: Metric,
+    maps_handle: &MapsHandle,
+) -> Result<BadgeData> {
+    let forecast = forecast(position, Vec::from([metric]), maps_handle).await;
+    let image_data = badge::render(&forecast, metric, Utc::now())?;
+
+    Ok(BadgeData(image_data))
+}
+
+/// Handler for retrieving the badge with the current value of a metric for an address.
+#[cfg(feature = "maps")]
+#[get("/badge?<address>&<metric>")]
+async fn badge_address(
+    address: String,
+    metric: Metric,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<BadgeData> {
+    let position = resolve_address(address).await?;
+
+    badge_data(position, metric, maps_handle).await
+}
+
+/// Handler for retrieving the badge with the current value of a metric for a geocoded position.
+#[cfg(feature = "maps")]
+#[get("/badge?<lat>&<lon>&<metric>", rank = 2)]
+async fn badge_geo(
+    lat: f64,
+    lon: f64,
+    metric: Metric,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<BadgeData> {
+    let position = Position::new(lat, lon);
+
+    badge_data(position, metric, maps_handle).await
+}
+
+/// Renders the chart of the forecast for the provided position and metric in the provided format.
+#[cfg(feature = "maps")]
+async fn chart_data(
+    position: Position,
+    metric: Metric,
+    format: Option<ChartFormat>,
+    maps_handle: &MapsHandle,
+) -> Result<ChartData> {
+    if metric == Metric::All {
+        return Err(Error::UnsupportedMetric(metric));
+    }
+    let forecast = forecast(position, Vec::from([metric]), maps_handle).await;
+    let series = forecast.series(metric).ok_or_else(|| {
+        let error = forecast.error(metric).unwrap_or_default();
+        Error::MetricFailed(metric, error.to_owned())
+    })?;
+
+    match format.unwrap_or_default() {
+        ChartFormat::Png => Ok(ChartData::Png(chart::render_png(metric, &series)?)),
+        ChartFormat::Svg => Ok(ChartData::Svg(chart::render_svg(metric, &series))),
+    }
+}
+
+/// Handler for retrieving the chart of the forecast of a metric for an address.
+#[cfg(feature = "maps")]
+#[get("/chart?<address>&<metric>&<format>")]
+async fn chart_address(
+    address: String,
+    metric: Metric,
+    format: Option<ChartFormat>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<ChartData> {
+    let position = resolve_address(address).await?;
+
+    chart_data(position, metric, format, maps_handle).await
+}
+
+/// Handler for retrieving the chart of the forecast of a metric for a geocoded position.
+#[cfg(feature = "maps")]
+#[get("/chart?<lat>&<lon>&<metric>&<format>", rank = 2)]
+async fn chart_geo(
+    lat: f64,
+    lon: f64,
+    metric: Metric,
+    format: Option<ChartFormat>,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<ChartData> {
+    let position = Position::new(lat, lon);
+
+    chart_data(position, metric, format, maps_handle).await
+}
+
+/// Creates a conditional map response for the provided position and metric.
+///
+/// If `animate` is set, an animation of all maps is returned instead of only the current map. The
+/// map(s) are rendered using the provided options. The map is encoded in the provided format, or
+/// otherwise in the format negotiated using the `Accept` header, defaulting to PNG; animations are
+/// always APNG images. The maximum age is the time until the map(s) expire.
+#[cfg(feature = "maps")]
+#[allow(clippy::too_many_arguments)]
+async fn map_response(
+    position: Position,
+    metric: Metric,
+    animate: Option<bool>,
+    format: Option<MapFormat>,
+    accept: Option<&Accept>,
+    options: MapOptions,
+    maps_handle: &MapsHandle,
+    preconditions: &Preconditions,
+) -> Result<Conditional<MapResponse>> {
+    let animate = animate.unwrap_or_default();
+    if animate && format.is_some_and(|format| format != MapFormat::Png) {
+        return Err(Error::from(MapsError::InvalidOptions(String::from(
+            "animations are only available as APNG images",
+        ))));
+    }
+    let format = format
+        .or_else(|| accept.and_then(MapFormat::negotiate))
+        .unwrap_or_default();
+    let response = if animate {
+        let (image_data, validity) = animate_map(position, metric, options, maps_handle).await?;
+        conditional_map(image_data, None, validity, preconditions)
+    } else {
+        let (image_data, validity) =
+            mark_map(position, metric, options, format, maps_handle).await?;
+        conditional_map(image_data, Some(format), validity, preconditions)
+    };
+
+    Ok(response)
+}
+
+/// Creates a conditional map response for the provided image data in the provided format.
+///
+/// If no format is provided, the image data is an animation (APNG image). The maximum age is the
+/// time until the map(s) expire.
+#[cfg(feature = "maps")]
+fn conditional_map(
+    image_data: Vec<u8>,
+    format: Option<MapFormat>,
+    validity: Validity,
+    preconditions: &Preconditions,
+) -> Conditional<MapResponse> {
+    let etag = conditional::etag(&image_data);
+    let last_modified = Some(validity.last_modified);
+    let max_age = validity
+        .expires_at
+        .signed_duration_since(Utc::now())
+        .num_seconds()
+        .max(0) as u64;
+    let image_data = match format {
+        None => MapImageData::Apng(image_data),
+        Some(MapFormat::Png) => MapImageData::Png(image_data),
+        Some(MapFormat::Jpeg) => MapImageData::Jpeg(image_data),
+        Some(MapFormat::Webp) => MapImageData::Webp(image_data),
+    };
+    let image_data = MapResponse {
+        image_data,
+        vary: Header::new("Vary", "Accept"),
+    };
+
+    Conditional::new(image_data, etag, last_modified, preconditions).with_max_age(max_age)
+}
+
+/// Handler for showing the current map with the geocoded position of an address for a specific
+/// metric.
+///
+/// If `animate` is set, an animation of all maps is shown instead. The image format can be set
+/// using `format` or negotiated using the `Accept` header.
+///
+/// Note: This handler is mosly used for debugging purposes!
+#[cfg(feature = "maps")]
+#[get("/map?<address>&<metric>&<animate>&<format>&<options..>")]
+#[allow(clippy::too_many_arguments)]
+async fn map_address(
+    address: String,
+    metric: Metric,
+    animate: Option<bool>,
+    format: Option<MapFormat>,
+    options: MapOptions,
+    accept: Option<&Accept>,
+    maps_handle: &State<MapsHandle>,
+    preconditions: Preconditions,
+    _api_key: ApiKey,
+) -> Result<Conditional<MapResponse>> {
+    let position = resolve_address(address).await?;
+    map_response(
+        position,
+        metric,
+        animate,
+        format,
+        accept,
+        options,
+        maps_handle,
+        &preconditions,
+    )
+    .await
+}
+
+/// Handler for showing the current map with the geocoded position of a Dutch postal code for a
+/// specific metric.
+///
+/// If `animate` is set, an animation of all maps is shown instead. The image format can be set
+/// using `format` or negotiated using the `Accept` header.
+///
+/// Note: This handler is mosly used for debugging purposes!
+#[cfg(feature = "maps")]
+#[get("/map?<postcode>&<metric>&<animate>&<format>&<options..>", rank = 3)]
+#[allow(clippy::too_many_arguments)]
+async fn map_postcode(
+    postcode: String,
+    metric: Metric,
+    animate: Option<bool>,
+    format: Option<MapFormat>,
+    options: MapOptions,
+    accept: Option<&Accept>,
+    maps_handle: &State<MapsHandle>,
+    preconditions: Preconditions,
+    _api_key: ApiKey,
+) -> Result<Conditional<MapResponse>> {
+    let position = resolve_postcode(postcode).await?;
+    map_response(
+        position,
+        metric,
+        animate,
+        format,
+        accept,
+        options,
+        maps_handle,
+        &preconditions,
+    )
+    .await
+}
+
+/// The default size (in seconds) of the lead time buckets of the accuracy statistics.
+const DEFAULT_ACCURACY_BUCKET: u32 = 3_600;
+
+/// Handler for retrieving the forecast accuracy statistics of a metric per lead time.
+///
+/// The statistics are determined for all positions, or only for the geocoded position if both
+/// `lat` and `lon` are provided. The size of the lead time buckets (in seconds) can be set using
+/// `bucket`.
+#[get("/accuracy?<metric>&<lat>&<lon>&<bucket>")]
+async fn get_accuracy(
+    metric: Metric,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    bucket: Option<u32>,
+    history: &State<Option<History>>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<AccuracyStats>>> {
+    let history = history.as_ref().cloned().ok_or(Error::HistoryDisabled)?;
+    if metric == Metric::All {
+        return Err(Error::UnsupportedMetric(metric));
+    }
+    let position = lat.zip(lon).map(|(lat, lon)| Position::new(lat, lon));
+    let bucket = i64::from(bucket.unwrap_or(DEFAULT_ACCURACY_BUCKET).max(1));
+    let stats =
+        rocket::tokio::task::spawn_blocking(move || history.accuracy(position, metric, bucket))
+            .await??;
+
+    Ok(Json(stats))
+}
+
+/// Handler for retrieving the historical items of a metric for a geocoded position.
+///
+/// The items can optionally be restricted to a time window using the `from` and `to` (UNIX)
+/// timestamps.
+#[get("/history?<lat>&<lon>&<metric>&<from>&<to>")]
+async fn get_history(
+    lat: f64,
+    lon: f64,
+    metric: Metric,
+    from: Option<i64>,
+    to: Option<i64>,
+    history: &State<Option<History>>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<HistoryItem>>> {
+    let history = history.as_ref().cloned().ok_or(Error::HistoryDisabled)?;
+    if metric == Metric::All {
+        return Err(Error::UnsupportedMetric(metric));
+    }
+    TimeWindow::new(from, to, None)?;
+    let position = Position::new(lat, lon);
+    let items =
+        rocket::tokio::task::spawn_blocking(move || history.query(position, metric, from, to))
+            .await??;
+
+    Ok(Json(items))
+}
+
+/// Handler for testing the connection of the Grafana JSON datasource.
+#[get("/grafana")]
+fn grafana_health(_api_key: ApiKey) -> Status {
+    Status::Ok
+}
+
+/// Handler for searching the targets of the Grafana JSON datasource.
+#[post("/grafana/search", format = "json", data = "<request>")]
+fn grafana_search(request: Json<SearchRequest>, _api_key: ApiKey) -> Json<Vec<String>> {
+    Json(grafana::search(&request))
+}
+
+/// Handler for querying the time series of targets of the Grafana JSON datasource.
+///
+/// The time series combine the stored history (if enabled) with the current forecast.
+#[post("/grafana/query", format = "json", data = "<request>")]
+async fn grafana_query(
+    request: Json<QueryRequest>,
+    maps_handle: &State<MapsHandle>,
+    history: &State<Option<History>>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<TimeSeries>>> {
+    let QueryRequest { range, targets } = request.into_inner();
+    let mut time_series = Vec::with_capacity(targets.len());
+    for target in targets {
+        let Target { metric, position } = target.target.parse()?;
+        let forecast = forecast(position, Vec::from([metric]), maps_handle).await;
+        let series = forecast.series(metric).ok_or_else(|| {
+            let error = forecast.error(metric).unwrap_or_default();
+            Error::MetricFailed(metric, error.to_owned())
+        })?;
+        let history_items = match history.as_ref().cloned() {
+            Some(history) => {
+                let (from, to) = (range.from.timestamp(), range.to.timestamp());
+                rocket::tokio::task::spawn_blocking(move || {
+                    history.query(position, metric, Some(from), Some(to))
+                })
+                .await??
+            }
+            None => Vec::new(),
+        };
+
+        time_series.push(grafana::time_series(
+            target.target,
+            range,
+            history_items,
+            series,
+        ));
+    }
+
+    Ok(Json(time_series))
+}
+
+/// Handler for showing the current map with the geocoded position for a specific metric.
+///
+/// If `animate` is set, an animation of all maps is shown instead. The image format can be set
+/// using `format` or negotiated using the `Accept` header.
+///
+/// Note: This handler is mosly used for debugging purposes!
+#[cfg(feature = "maps")]
+#[get("/map?<lat>&<lon>&<metric>&<animate>&<format>&<options..>", rank = 2)]
+#[allow(clippy::too_many_arguments)]
+async fn map_geo(
+    lat: f64,
+    lon: f64,
+    metric: Metric,
+    animate: Option<bool>,
+    format: Option<MapFormat>,
+    options: MapOptions,
+    accept: Option<&Accept>,
+    maps_handle: &State<MapsHandle>,
+    preconditions: Preconditions,
+    _api_key: ApiKey,
+) -> Result<Conditional<MapResponse>> {
+    let position = Position::new(lat, lon);
+    map_response(
+        position,
+        metric,
+        animate,
+        format,
+        accept,
+        options,
+        maps_handle,
+        &preconditions,
+    )
+    .await
+}
+
+/// Handler for retrieving an unmodified map for a specific metric.
+///
+/// The current map is retrieved, unless another map is selected using `time` or `offset`. No
+/// position is marked, so clients can draw their own overlays. The image format can be set using
+/// `format` or negotiated using the `Accept` header.
+#[cfg(feature = "maps")]
+#[get("/map/raw?<metric>&<time>&<offset>&<format>")]
+#[allow(clippy::too_many_arguments)]
+async fn map_raw(
+    metric: Metric,
+    time: Option<i64>,
+    offset: Option<u32>,
+    format: Option<MapFormat>,
+    accept: Option<&Accept>,
+    maps_handle: &State<MapsHandle>,
+    preconditions: Preconditions,
+    _api_key: ApiKey,
+) -> Result<Conditional<MapResponse>> {
+    let options = MapOptions {
+        time,
+        offset,
+        ..Default::default()
+    };
+    let format = format
+        .or_else(|| accept.and_then(MapFormat::negotiate))
+        .unwrap_or_default();
+    let (image_data, validity) = raw_map(metric, options, format, maps_handle).await?;
+
+    Ok(conditional_map(
+        image_data,
+        Some(format),
+        validity,
+        &preconditions,
+    ))
+}
+
+/// Handler for projecting a geocoded position on the current map for a specific metric.
+///
+/// Returns the pixel coordinates of the position on the map and whether they lie within it. This
+/// helps debugging sampled values and allows overlaying positions on the maps.
+#[cfg(feature = "maps")]
+#[get("/project?<lat>&<lon>&<metric>")]
+fn project(
+    lat: f64,
+    lon: f64,
+    metric: Metric,
+    maps_handle: &State<MapsHandle>,
+    _api_key: ApiKey,
+) -> Result<Json<Projection>> {
+    let position = Position::new(lat, lon);
+
+    Ok(Json(projection(position, metric, maps_handle)?))
+}
+
+/// Handler for retrieving the current values of the exported forecasts as Prometheus gauges.
+#[get("/metrics")]
+fn metrics(
+    exporter_handle: &State<Option<ExporterHandle>>,
+    _api_key: ApiKey,
+) -> Result<MetricsData> {
+    let exporter_handle = exporter_handle.as_ref().ok_or(Error::ExporterDisabled)?;
+    let exposition = exporter_handle
+        .read()
+        .expect("Exporter handle lock was poisoned");
+
+    Ok(MetricsData(exposition.render(Utc::now())))
+}
+
+/// Handler for listing all webhook subscriptions.
+///
+/// Only the subscriptions created with the API key are listed, unless it is an admin key.
+#[get("/subscriptions")]
+fn list_subscriptions(
+    config: &State<Config>,
+    subscriptions_handle: &State<SubscriptionsHandle>,
+    api_key: ApiKey,
+) -> Json<Vec<Subscription>> {
+    let subscriptions = subscriptions_handle
+        .lock()
+        .expect("Subscriptions handle mutex was poisoned");
+
+    Json(subscriptions.accessible(api_key.restriction(config)))
+}
+
+/// Handler for retrieving a webhook subscription.
+///
+/// Only a subscription created with the API key can be retrieved, unless it is an admin key.
+#[get("/subscriptions/<id>")]
+fn get_subscription(
+    id: u64,
+    config: &State<Config>,
+    subscriptions_handle: &State<SubscriptionsHandle>,
+    api_key: ApiKey,
+) -> Result<Json<Subscription>> {
+    let subscriptions = subscriptions_handle
+        .lock()
+        .expect("Subscriptions handle mutex was poisoned");
+
+    subscriptions.get(id, api_key.restriction(config)).map(Json)
+}
+
+/// Handler for adding a webhook subscription.
+///
+/// The callback URL is rejected if its host is not publicly reachable, unless it is allowed by
+/// the configuration. The subscription records the API key it is created with.
+#[post("/subscriptions", format = "json", data = "<new_subscription>")]
+async fn add_subscription(
+    new_subscription: Json<NewSubscription>,
+    config: &State<Config>,
+    subscriptions_handle: &State<SubscriptionsHandle>,
+    api_key: ApiKey,
+) -> Result<Created<Json<Subscription>>> {
+    new_subscription
+        .check_callback_url(&config.subscriptions_allowed_hosts)
+        .await?;
+    let (subscription, snapshot) = subscriptions_handle
+        .lock()
+        .expect("Subscriptions handle mutex was poisoned")
+        .add(new_subscription.into_inner(), api_key.key())?;
+    snapshot.write().await?;
+    let location = format!("/subscriptions/{}", subscription.id);
+
+    Ok(Created::new(location).body(Json(subscription)))
+}
+
+/// Handler for removing a webhook subscription.
+///
+/// Only a subscription created with the API key can be removed, unless it is an admin key.
+#[delete("/subscriptions/<id>")]
+async fn remove_subscription(
+    id: u64,
+    config: &State<Config>,
+    subscriptions_handle: &State<SubscriptionsHandle>,
+    api_key: ApiKey,
+) -> Result<NoContent> {
+    let snapshot = subscriptions_handle
+        .lock()
+        .expect("Subscriptions handle mutex was poisoned")
+        .remove(id, api_key.restriction(config))?;
+    snapshot.write().await?;
+
+    Ok(NoContent)
+}
+
+/// Handler for listing all named locations.
+#[get("/locations")]
+fn list_locations(
+    named_locations_handle: &State<NamedLocationsHandle>,
+    _api_key: ApiKey,
+) -> Json<Vec<NamedLocation>> {
+    let named_locations = named_locations_handle
+        .lock()
+        .expect("Named locations handle mutex was poisoned");
+
+    Json(named_locations.all())
+}
+
+/// Handler for retrieving a named location.
+#[get("/locations/<name>")]
+fn get_location(
+    name: &str,
+    named_locations_handle: &State<NamedLocationsHandle>,
+    _api_key: ApiKey,
+) -> Result<Json<NamedLocation>> {
+    let named_locations = named_locations_handle
+        .lock()
+        .expect("Named locations handle mutex was poisoned");
+
+    named_locations.get(name).map(Json)
+}
+
+/// Handler for storing a named location.
+///
+/// The location is an address, a Dutch postal code or a geocoded position; the former two are
+/// geocoded once when storing it. A location with the same name is replaced, but only if it was
+/// stored with the same API key, unless it is an admin key.
+#[put("/locations/<name>", format = "json", data = "<location>")]
+async fn set_location(
+    name: &str,
+    location: Json<Location>,
+    config: &State<Config>,
+    named_locations_handle: &State<NamedLocationsHandle>,
+    api_key: ApiKey,
+) -> Result<Json<NamedLocation>> {
+    // Check the name and owner first, so that these do not cost a geocoding request.
+    let restriction = api_key.restriction(config);
+    locations::validate_name(name)?;
+    named_locations_handle
+        .lock()
+        .expect("Named locations handle mutex was poisoned")
+        .check_changeable(name, restriction)?;
+    let position = location.into_inner().resolve().await?;
+    let (named_location, snapshot) = named_locations_handle
+        .lock()
+        .expect("Named locations handle mutex was poisoned")
+        .set(name, position, api_key.key(), restriction)?;
+    snapshot.write().await?;
+
+    Ok(Json(named_location))
+}
+
+/// Handler for removing a named location.
+///
+/// Only a location stored with the API key can be removed, unless it is an admin key.
+#[delete("/locations/<name>")]
+async fn remove_location(
+    name: &str,
+    config: &State<Config>,
+    named_locations_handle: &State<NamedLocationsHandle>,
+    api_key: ApiKey,
+) -> Result<NoContent> {
+    let snapshot = named_locations_handle
+        .lock()
+        .expect("Named locations handle mutex was poisoned")
+        .remove(name, api_key.restriction(config))?;
+    snapshot.write().await?;
+
+    Ok(NoContent)
+}
+
+/// The result of purging the provider caches as JSON response.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct CachePurgeInfo {
+    /// The number of purged cache entries.
+    purged: usize,
+}
+
+/// Handler for retrieving the statistics of the provider caches.
+#[get("/admin/cache/stats")]
+async fn cache_stats(_admin_key: AdminKey) -> Json<Vec<CacheStats>> {
+    Json(providers::cache_stats().await)
+}
+
+/// Handler for purging the provider caches.
+///
+/// The purge can optionally be restricted to a provider, metric and/or geocoded position.
+#[post("/admin/cache/purge?<provider>&<metric>&<lat>&<lon>")]
+async fn purge_cache(
+    provider: Option<Provider>,
+    metric: Option<Metric>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    _admin_key: AdminKey,
+) -> Json<CachePurgeInfo> {
+    let position = lat.zip(lon).map(|(lat, lon)| Position::new(lat, lon));
+    let purged = providers::purge_caches(provider, metric, position).await;
+
+    Json(CachePurgeInfo { purged })
+}
+
+/// The query parameters for forcing a refresh of the maps.
+#[cfg(feature = "maps")]
+#[derive(Debug, rocket::FromForm)]
+struct MapsRefreshQuery {
+    /// The type of the maps to refresh.
+    #[field(name = "type", default = MapsType::All)]
+    maps_type: MapsType,
+}
+
+/// Handler for forcing a refresh of the maps of the provided type.
+///
+/// After a successful refresh, the cached samples of the refreshed maps are purged.
+#[cfg(feature = "maps")]
+#[post("/admin/maps/refresh?<query..>")]
+async fn refresh_maps(
+    query: MapsRefreshQuery,
+    maps_handle: &State<MapsHandle>,
+    _admin_key: AdminKey,
+) -> Json<Vec<RefreshOutcome>> {
+    let outcomes = maps::refresh(maps_handle, query.maps_type).await;
+    if outcomes.iter().any(RefreshOutcome::is_ok) {
+        providers::purge_caches(Some(Provider::Buienradar), Some(Metric::Pollen), None).await;
+        providers::purge_caches(Some(Provider::Buienradar), Some(Metric::Temperature), None).await;
+        providers::purge_caches(Some(Provider::Buienradar), Some(Metric::UVI), None).await;
+        providers::purge_caches(Some(Provider::Combined), None, None).await;
+    }
+
+    Json(outcomes)
+}
+
+/// The health of the service.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Health<'a> {
+    /// The overall status: `ok`, `starting` or `degraded`.
+    status: &'static str,
+
+    /// The status of the maps refresher (if the maps are enabled).
+    #[cfg_attr(not(feature = "maps"), allow(dead_code), serde(skip))]
+    maps_refresher: &'a RefresherStatus,
+
+    /// The status of the circuit breakers of the providers.
+    circuit_breakers: Vec<CircuitBreakerStatus>,
+}
+
+/// Returns the health of the service.
+///
+/// If a background task is not running, the service is degraded and a service unavailable status
+/// is returned. This status is also returned while the service is starting, i.e. when waiting for
+/// the first maps (if configured). Open circuit breakers of providers are reported, but do not
+/// degrade the service.
+#[get("/health")]
+fn health<'a>(
+    maps_handle: &State<MapsHandle>,
+    refresher_status: &'a State<RefresherStatusHandle>,
+) -> (Status, Json<Health<'a>>) {
+    let maps = maps_handle.read().expect("Maps handle lock was poisoned");
+    let (status, health_status) = if !refresher_status.is_healthy() {
+        (Status::ServiceUnavailable, "degraded")
+    } else if refresher_status.is_starting(&maps, Utc::now()) {
+        (Status::ServiceUnavailable, "starting")
+    } else {
+        (Status::Ok, "ok")
+    };
+    let health = Health {
+        status: health_status,
+        maps_refresher: refresher_status,
+        circuit_breakers: providers::circuit_breakers(),
+    };
+
+    (status, Json(health))
+}
+
+/// The page of the embedded web UI.
+const INDEX_HTML: &str = include_str!("../assets/index.html");
+
+/// Handler for the embedded web UI.
+///
+/// The page lets a user enter an address and select metrics to chart the forecast and show the
+/// marked maps, using the API endpoints.
+#[get("/")]
+fn index() -> RawHtml<&'static str> {
+    RawHtml(INDEX_HTML)
+}
+
+/// The embedded default favicon.
+const FAVICON: &[u8] = include_bytes!("../assets/favicon.ico");
+
+/// The favicon, either from the static files directory or the embedded default one.
+#[derive(Responder)]
+enum Favicon {
+    /// The favicon file in the static files directory.
+    File(NamedFile),
+
+    /// The embedded default favicon.
+    #[response(content_type = "image/x-icon")]
+    Default(&'static [u8]),
+}
+
+/// Handler for the favicon.
+///
+/// The favicon in the configured static files directory is used if present, otherwise the
+/// embedded default one.
+#[get("/favicon.ico")]
+async fn favicon(config: &State<Config>) -> Favicon {
+    let file = match &config.static_dir {
+        Some(static_dir) => NamedFile::open(static_dir.join("favicon.ico")).await.ok(),
+        None => None,
+    };
+
+    file.map_or(Favicon::Default(FAVICON), Favicon::File)
+}
+
+/// Handler for the static files in the configured static files directory.
+///
+/// Paths with `..` segments or hidden files are rejected by the path guard.
+#[get("/static/<path..>")]
+async fn static_file(path: PathBuf, config: &State<Config>) -> Option<NamedFile> {
+    let path = config.static_dir.as_ref()?.join(path);
+    if !rocket::tokio::fs::metadata(&path).await.ok()?.is_file() {
+        return None;
+    }
+
+    NamedFile::open(path).await.ok()
+}
+
+/// Handler for retrieving the Protocol Buffers schema of the forecast responses.
+///
+/// Clients can generate their models from it to decode the forecasts requested with
+/// `format=protobuf`.
+#[get("/sinoptik.proto")]
+fn protobuf_schema() -> SchemaData {
+    SchemaData(protobuf::SCHEMA)
+}
+
+/// Returns the version information.
+#[get("/version", format = "application/json")]
+async fn version() -> Result<Json<VersionInfo>> {
+    Ok(Json(VersionInfo::new()))
+}
+
+/// Sets up Rocket without fairings.
+///
+/// The routes are mounted under the prefix of the current API version, and also without a prefix
+/// as aliases for clients of the unversioned API.
+fn rocket_core(maps_handle: MapsHandle) -> Rocket<Build> {
+    let mut rocket = rocket::build().mount("/", routes![favicon, index, static_file]);
+    for base in [String::from("/"), format!("/{API_VERSION}")] {
+        rocket = rocket.mount(
+            base.as_str(),
+            routes![
+                add_subscription,
+                advice_address,
+                advice_geo,
+                cache_stats,
+                calendar_address,
+                calendar_geo,
+                feed_address,
+                feed_geo,
+                forecast_address,
+                forecast_geo,
+                forecast_location,
+                forecast_postcode,
+                forecast_stream,
+                forecasts,
+                geocode,
+                get_accuracy,
+                get_history,
+                get_location,
+                get_subscription,
+                grafana_health,
+                grafana_query,
+                grafana_search,
+                health,
+                list_locations,
+                list_subscriptions,
+                metrics,
+                protobuf_schema,
+                purge_cache,
+                remove_location,
+                remove_subscription,
+                search,
+                sensor_address,
+                sensor_geo,
+                set_location,
+                version
+            ],
+        );
+        #[cfg(feature = "maps")]
+        {
+            rocket = rocket.mount(
+                base.as_str(),
+                routes![
+                    badge_address,
+                    badge_geo,
+                    chart_address,
+                    chart_geo,
+                    map_address,
+                    map_geo,
+                    map_postcode,
+                    map_raw,
+                    project,
+                    refresh_maps
+                ],
+            );
+        }
+    }
+
+    rocket
+        .register("/", catchers![default_catcher])
+        .manage(maps_handle)
+        .manage(RefresherStatusHandle::default())
+        .manage(ApiKeyUsage::default())
+        .attach(AdHoc::config::<Config>())
+        .attach(AdHoc::try_on_ignite("Rate limit", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                let Some(rate_limit) = &config.rate_limit else {
+                    return Ok(rocket);
+                };
+
+                match rate_limit.validate() {
+                    Ok(()) => Ok(rocket),
+                    Err(e) => {
+                        error!(error = %e, "💥 Invalid rate limit configuration");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+        .attach(RateLimiter::default())
+        .attach(RequestLogger)
+        .attach(Compression)
+        .attach(AdHoc::try_on_ignite("HTTP client", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                match HttpClient::new(&config.http, &config.subscriptions_allowed_hosts) {
+                    Ok(client) => {
+                        http::set_client(client);
+                        Ok(rocket)
+                    }
+                    Err(e) => {
+                        error!(error = %e, "💥 Failed to set up the HTTP client");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+        .attach(AdHoc::try_on_ignite("Shared cache", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                match shared_cache::connect(config).await {
+                    Ok(()) => Ok(rocket),
+                    Err(e) => {
+                        error!(error = %e, "💥 Failed to connect to the shared cache");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+        .attach(AdHoc::on_ignite("Geocoding cache", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                position::set_geocoding_cache(GeocodingCache::load(&config.geocoding_cache));
+
+                rocket
+            })
+        }))
+        .attach(AdHoc::on_ignite("Exporter", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                let exporter_handle = config.exporter.as_ref().map(|_| ExporterHandle::default());
+
+                rocket.manage(exporter_handle)
+            })
+        }))
+        .attach(AdHoc::try_on_ignite("History", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                let Some(path) = config.history_file.clone() else {
+                    return Ok(rocket.manage(None::<History>));
+                };
+
+                match History::open(&path) {
+                    Ok(history) => {
+                        let receiver = history::enable();
+                        let _writer = rocket::tokio::spawn(history::run(history.clone(), receiver));
+                        Ok(rocket.manage(Some(history)))
+                    }
+                    Err(e) => {
+                        error!(error = %e, "💥 Failed to open the history database");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+        .attach(AdHoc::try_on_ignite("Subscriptions", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                match Subscriptions::load(config.subscriptions_file.clone()) {
+                    Ok(subscriptions) => {
+                        let subscriptions_handle = Arc::new(Mutex::new(subscriptions));
+                        Ok(rocket.manage(subscriptions_handle))
+                    }
+                    Err(e) => {
+                        error!(error = %e, "💥 Failed to load the subscriptions");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+        .attach(AdHoc::try_on_ignite("Named locations", |rocket| {
+            Box::pin(async move {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                match NamedLocations::load(config.locations_file.clone()) {
+                    Ok(named_locations) => {
+                        let named_locations_handle = Arc::new(Mutex::new(named_locations));
+                        Ok(rocket.manage(named_locations_handle))
+                    }
+                    Err(e) => {
+                        error!(error = %e, "💥 Failed to load the named locations");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+}
+
+/// Sets up Rocket.
+fn rocket(maps_handle: MapsHandle) -> Rocket<Build> {
+    let rocket = rocket_core(Arc::clone(&maps_handle));
+    #[cfg(feature = "maps")]
+    let rocket = {
+        let maps_handle = Arc::clone(&maps_handle);
+
+        rocket.attach(AdHoc::on_liftoff("Maps refresher", |rocket| {
+            let refresher_status = rocket
+                .state::<RefresherStatusHandle>()
+                .map(Arc::clone)
+                .expect("Refresher status handle is managed");
+            let maps_supervisor = maps::supervise(maps_handle, refresher_status);
+
+            Box::pin(async move {
+                // The supervisor restarts the refresher if it terminates, so it never finishes.
+                let _supervisor = rocket::tokio::spawn(maps_supervisor);
+            })
+        }))
+    };
+
+    rocket
+        .attach(AdHoc::on_liftoff("Chat notifier", {
+            let maps_handle = Arc::clone(&maps_handle);
+
+            |rocket| {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                let chat_notifier = config
+                    .notifier
+                    .clone()
+                    .map(|config| notifier::run(config, maps_handle));
+
+                Box::pin(async move {
+                    if let Some(chat_notifier) = chat_notifier {
+                        let _notifier = rocket::tokio::spawn(chat_notifier);
+                    }
+                })
+            }
+        }))
+        .attach(AdHoc::on_liftoff("Exporter", {
+            let maps_handle = Arc::clone(&maps_handle);
+
+            |rocket| {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                let exporter_handle = rocket
+                    .state::<Option<ExporterHandle>>()
+                    .cloned()
+                    .expect("Exporter handle is managed");
+                let exporter = config.exporter.clone().zip(exporter_handle).map(
+                    |(config, exporter_handle)| exporter::run(config, exporter_handle, maps_handle),
+                );
+
+                Box::pin(async move {
+                    if let Some(exporter) = exporter {
+                        let _exporter = rocket::tokio::spawn(exporter);
+                    }
+                })
+            }
+        }))
+        .attach(AdHoc::on_liftoff("Prefetcher", {
+            let maps_handle = Arc::clone(&maps_handle);
+
+            |rocket| {
+                let config = rocket.state::<Config>().expect("Config is managed");
+                let prefetcher = (!config.prefetch_positions.is_empty())
+                    .then(|| prefetch::run(config.prefetch_positions.clone(), maps_handle));
+
+                Box::pin(async move {
+                    if let Some(prefetcher) = prefetcher {
+                        let _prefetcher = rocket::tokio::spawn(prefetcher);
+                    }
+                })
+            }
+        }))
+        .attach(AdHoc::on_liftoff("Subscriptions evaluator", |rocket| {
+            let subscriptions_handle = rocket
+                .state::<SubscriptionsHandle>()
+                .map(Arc::clone)
+                .expect("Subscriptions handle is managed");
+            let config = rocket.state::<Config>().expect("Config is managed");
+            let subscriptions_evaluator = subscriptions::run(
+                subscriptions_handle,
+                maps_handle,
+                config.subscriptions_allowed_hosts.clone(),
+            );
+
+            Box::pin(async move {
+                // We don't care about the join handle nor error results?
+                let _evaluator = rocket::tokio::spawn(subscriptions_evaluator);
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Version", |_| {
+            Box::pin(async move {
+                let name = env!("CARGO_PKG_NAME");
+                let version = env!("CARGO_PKG_VERSION");
+                let git_sha = &env!("VERGEN_GIT_SHA")[0..7];
+
+                info!(
+                    name,
+                    version, git_sha, "🌁 Started {name} v{version} (git @{git_sha})"
+                );
+            })
+        }))
+}
+
+/// Sets up Rocket and the maps cache refresher task.
+pub fn setup() -> Rocket<Build> {
+    // Set up logging as early as possible; configuration errors are reported by Rocket later.
+    let config = rocket::Config::figment()
+        .extract::<Config>()
+        .unwrap_or_default();
+    logging::init(&config);
+    providers::set_base_urls(config.base_urls);
+    providers::set_cache_capacities(config.cache_capacities);
+    providers::set_cache_ttls(config.cache_ttls);
+    providers::set_position_grid(config.position_grid);
+    mock::set_config(config.mock);
+    nowcast::set_rain_threshold(config.rain_threshold);
+    consensus::set_divergence_threshold(config.consensus_divergence);
+    circuit_breaker::set_config(config.circuit_breaker);
+    combined::set_config(config.paqi_merge);
+    geocoder::set_geocoder(Geocoder::new(&config.geocoder));
+
+    let maps = Maps::new(config.maps);
+    let maps_handle = Arc::new(RwLock::new(maps));
+
+    rocket(maps_handle)
+}
+
+// The tests use stub maps and rely on all metrics being enabled.
+#[cfg(all(test, feature = "luchtmeetnet", feature = "maps"))]
+mod tests {
+    use assert_float_eq::*;
+    use assert_matches::assert_matches;
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Value as JsonValue};
+
+    use super::*;
+    use crate::maps::RetrievedMaps;
+
+    fn maps_stub(map_count: u32) -> RetrievedMaps {
+        let map_color = Rgba::from([73, 218, 33, 255]); // First color from map key.
+        let image =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(820 * map_count, 988, map_color));
+
+        RetrievedMaps::new(image, map_count)
+    }
+
+    fn temperature_maps_stub(map_count: u32) -> RetrievedMaps {
+        let map_color = Rgba::from([0x96, 0xDC, 0x00, 0xFF]); // Color of 15 °C from map key.
+        let image =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(820 * map_count, 988, map_color));
+
+        RetrievedMaps::new_temperature(image, map_count)
+    }
+
+    fn maps_handle_stub() -> MapsHandle {
+        let mut maps = Maps::default();
+        maps.pollen = Some(maps_stub(24));
+        maps.precipitation = Some(maps_stub(24));
+        maps.temperature = Some(temperature_maps_stub(24));
+        maps.uvi = Some(maps_stub(5));
+
+        Arc::new(RwLock::new(maps))
+    }
+
+    #[test]
+    fn api_keys() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let api_keys = json!([{"key": "secret1"}, {"key": "secret2", "rate_limit": 1}]);
+        let rocket = rocket_core(maps_handle)
+            .configure(rocket::Config::figment().merge(("api_keys", api_keys)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+
+        // No or an invalid API key is not authorized.
+        let response = client.get("/subscriptions").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .get("/subscriptions")
+            .header(Header::new("X-API-Key", "secret3"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "unauthorized");
+        assert_eq!(json["status"], 401);
+
+        // A valid API key is authorized, either via a header or query parameter.
+        let response = client
+            .get("/subscriptions")
+            .header(Header::new("X-API-Key", "secret1"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/subscriptions?api_key=secret1").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // The API key with a rate limit can only be used once per minute.
+        let response = client.get("/subscriptions?api_key=secret2").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/subscriptions?api_key=secret2").dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "rate_limited");
+
+        // The version endpoint does not require an API key.
+        let response = client.get("/version").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // Unknown endpoints also get a JSON error body.
+        let response = client.get("/unknown").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "not_found");
+        assert_eq!(json["status"], 404);
+    }
+
+    #[test]
+    fn admin_cache() {
+        let maps_handle = maps_handle_stub();
+        let api_keys = json!([{"key": "secret1"}, {"key": "admin", "admin": true}]);
+        let rocket = rocket_core(Arc::clone(&maps_handle))
+            .configure(rocket::Config::figment().merge(("api_keys", api_keys)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+
+        // Only admin API keys can access the administration endpoints.
+        let response = client.get("/admin/cache/stats").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client.get("/admin/cache/stats?api_key=secret1").dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        // Fill a cache and inspect it.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=UVI&api_key=secret1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/admin/cache/stats?api_key=admin").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        let stats = json.as_array().unwrap();
+        assert_eq!(stats.len(), 7);
+        let uvi_stats = stats
+            .iter()
+            .find(|s| s["name"] == "buienradar_uvi")
+            .unwrap();
+        assert!(uvi_stats["size"].as_u64().unwrap() >= 1);
+        assert_eq!(uvi_stats["capacity"], 10_000);
+        assert!(uvi_stats["occupancy"].as_f64().unwrap() > 0.0);
+
+        // Purge the cache for another position, and then for the position.
+        let response = client
+            .post("/admin/cache/purge?provider=buienradar&metric=UVI&lat=0.0&lon=0.0")
+            .header(Header::new("X-API-Key", "admin"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<JsonValue>().unwrap()["purged"], 0);
+        let response = client
+            .post("/admin/cache/purge?provider=buienradar&metric=UVI&lat=51.4&lon=5.5")
+            .header(Header::new("X-API-Key", "admin"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<JsonValue>().unwrap()["purged"], 1);
+
+        // Without API keys, there are no admin keys.
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+        let response = client.get("/admin/cache/stats").dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn admin_maps_refresh() {
+        let maps_handle = maps_handle_stub();
+        let api_keys = json!([{"key": "secret1"}, {"key": "admin", "admin": true}]);
+        let rocket = rocket_core(maps_handle)
+            .configure(rocket::Config::figment().merge(("api_keys", api_keys)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+
+        // Only admin API keys can force a maps refresh.
+        let response = client
+            .post("/admin/maps/refresh?type=pollen&api_key=secret1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        // The type of maps must be valid.
+        let response = client
+            .post("/admin/maps/refresh?type=radar&api_key=admin")
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // The outcome is reported per type of maps, whether the retrieval succeeded or not.
+        let response = client
+            .post("/admin/maps/refresh?type=pollen&api_key=admin")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        let outcomes = json.as_array().unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0]["maps"], "pollen");
+        assert!(outcomes[0].get("mtime").is_some() != outcomes[0].get("error").is_some());
+    }
+
+    #[test]
+    fn health() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Without the maps refresher running, the service is degraded.
+        let response = client.get("/health").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["status"], "degraded");
+        assert_eq!(
+            json["maps_refresher"],
+            json!({"running": false, "restarts": 0})
+        );
+
+        // The state of the circuit breaker is reported per provider.
+        let circuit_breakers = json["circuit_breakers"].as_array().unwrap();
+        let providers = circuit_breakers
+            .iter()
+            .map(|circuit_breaker| circuit_breaker["provider"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(providers, ["Buienradar", "Luchtmeetnet", "Open-Meteo"]);
+    }
+
+    #[test]
+    fn versioned_routes() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+
+        // The routes are available under the current version and without a version.
+        for uri in ["/v1/version", "/version"] {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+            assert_eq!(json["api_version"], "v1");
+        }
+
+        // Unknown versions are not found.
+        let response = client.get("/v2/version").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn index() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+
+        // The web UI is served without a version prefix and uses the versioned API.
+        let response = client.get("/").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::HTML));
+        let body = response.into_string().expect("Not a valid body");
+        assert!(body.contains("<title>Sinoptik</title>"));
+        assert!(body.contains("/v1/forecast?"));
+        let response = client.get("/v1/").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn static_files() {
+        // Without a static files directory, only the embedded favicon is served.
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+        let response = client.get("/favicon.ico").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::Icon));
+        assert_eq!(response.into_bytes().as_deref(), Some(FAVICON));
+        let response = client.get("/static/dashboard.html").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // Serve the files in the static files directory, including its favicon.
+        let path =
+            std::env::temp_dir().join(format!("sinoptik-test-static-{}", std::process::id()));
+        std::fs::create_dir_all(path.join("css")).unwrap();
+        std::fs::write(path.join("dashboard.html"), "<h1>Dashboard</h1>").unwrap();
+        std::fs::write(path.join("css/style.css"), "h1 { color: red; }").unwrap();
+        std::fs::write(path.join("favicon.ico"), "icon").unwrap();
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let rocket = rocket_core(maps_handle)
+            .configure(rocket::Config::figment().merge(("static_dir", &path)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+        let response = client.get("/static/dashboard.html").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::HTML));
+        let response = client.get("/static/css/style.css").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::CSS));
+        let response = client.get("/favicon.ico").dispatch();
+        assert_eq!(response.into_string().as_deref(), Some("icon"));
+
+        // Directories, missing files and paths outside the directory are not found.
+        for uri in [
+            "/static/css",
+            "/static/missing.html",
+            "/static/../etc/passwd",
+        ] {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+            let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+            assert_eq!(json["kind"], "not_found");
+        }
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn localized_errors() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+
+        // The error message is translated to the preferred language; the kind stays the same.
+        let response = client
+            .get("/unknown")
+            .header(Header::new("Accept-Language", "nl-NL, en;q=0.8"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["error"], "Niet gevonden");
+        assert_eq!(json["kind"], "not_found");
+
+        // Errors of handlers are translated too.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=UVI&from=1000&to=0")
+            .header(Header::new("Accept-Language", "nl"))
+            .dispatch();
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["error"], "Ongeldig tijdvenster");
+        assert_eq!(json["kind"], "invalid_time_window");
+    }
+
+    #[test]
+    fn rate_limit() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let rate_limit = json!({"rate": 0.01, "burst": 2});
+        let rocket = rocket_core(maps_handle)
+            .configure(rocket::Config::figment().merge(("rate_limit", rate_limit)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+        let remote = "192.0.2.1:8000".parse().unwrap();
+        let other_remote = "192.0.2.2:8000".parse().unwrap();
+
+        // The burst is allowed, then the client is rate limited.
+        for _ in 0..2 {
+            let response = client.get("/subscriptions").remote(remote).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        let response = client.get("/subscriptions").remote(remote).dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("100"));
+
+        // Other clients are not affected.
+        let response = client.get("/subscriptions").remote(other_remote).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn rate_limit_invalid() {
+        // A rate limit that would never refill or never allow a request fails to ignite.
+        for rate_limit in [
+            json!({"rate": 0.0, "burst": 2}),
+            json!({"rate": -1.0, "burst": 2}),
+            json!({"rate": 1.0, "burst": 0}),
+        ] {
+            let maps_handle = Arc::new(RwLock::new(Maps::default()));
+            let rocket = rocket_core(maps_handle)
+                .configure(rocket::Config::figment().merge(("rate_limit", rate_limit)));
+            let Err(error) = Client::tracked(rocket) else {
+                panic!("Rocket ignited with an invalid rate limit");
+            };
+            assert_matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_));
+        }
+    }
+
+    #[test]
+    fn compression() {
+        use std::io::Read;
+
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Large enough responses are compressed if the client accepts it.
+        let uri = "/forecast?lat=51.4&lon=5.5&metrics=pollen&metrics=UVI";
+        let response = client
+            .get(uri)
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+        assert!(response
+            .headers()
+            .get("Vary")
+            .any(|vary| vary == "Accept-Encoding"));
+        let body = response.into_bytes().unwrap();
+        let mut data = String::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_string(&mut data)
+            .unwrap();
+        let json = rocket::serde::json::from_str::<JsonValue>(&data).expect("Not valid JSON");
+        assert_matches!(json["pollen"], JsonValue::Array(_));
+
+        // ... but not otherwise.
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+
+        // Images are never compressed.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch();
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    }
+
+    #[test]
+    fn calendar_geo() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // The pollen stub maps have score 1 everywhere, so there is one long event.
+        let response = client
+            .get("/calendar?lat=51.4&lon=5.5&metric=pollen&threshold=0")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::Calendar));
+        let ical = response.into_string().unwrap();
+        assert_eq!(ical.matches("BEGIN:VEVENT").count(), 1);
+
+        // ... and no events if the threshold is not exceeded.
+        let response = client
+            .get("/calendar?lat=51.4&lon=5.5&metric=pollen&threshold=1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let ical = response.into_string().unwrap();
+        assert_eq!(ical.matches("BEGIN:VEVENT").count(), 0);
+
+        // No threshold passed, don't know when to add events?
+        let response = client
+            .get("/calendar?lat=51.4&lon=5.5&metric=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn feed_geo() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // The UV index stub maps have score 1 everywhere, so there are no warnings.
+        let response = client.get("/feed?lat=51.4&lon=5.5").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "atom+xml"))
+        );
+        let xml = response.into_string().unwrap();
+        assert!(xml.contains("<id>urn:sinoptik:warnings:51.40000:5.50000</id>"));
+        assert!(!xml.contains("<entry>"));
+    }
+
+    #[test]
+    fn forecast_address() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Get an empty forecast for the provided address.
+        let response = client.get("/forecast?address=eindhoven").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_float_absolute_eq!(json["lat"].as_f64().unwrap(), 51.448557, 1e-1);
+        assert_float_absolute_eq!(json["lon"].as_f64().unwrap(), 5.450123, 1e-1);
+        assert_matches!(json["time"], JsonValue::Number(_));
+        assert_matches!(json.get("AQI"), None);
+        assert_matches!(json.get("NO2"), None);
+        assert_matches!(json.get("O3"), None);
+        assert_matches!(json.get("PAQI"), None);
+        assert_matches!(json.get("PM10"), None);
+        assert_matches!(json.get("pollen"), None);
+        assert_matches!(json.get("temperature"), None);
+        assert_matches!(json.get("precipitation"), None);
+        assert_matches!(json.get("UVI"), None);
+
+        // Get a forecast with all metrics for the provided address.
+        let response = client
+            .get("/forecast?address=eindhoven&metrics=all")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_float_absolute_eq!(json["lat"].as_f64().unwrap(), 51.448557, 1e-1);
+        assert_float_absolute_eq!(json["lon"].as_f64().unwrap(), 5.450123, 1e-1);
+        assert_matches!(json["time"], JsonValue::Number(_));
+        assert_matches!(json.get("AQI"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("NO2"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("O3"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("PAQI"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("PM10"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("pollen"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("temperature"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("precipitation"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("UVI"), Some(JsonValue::Array(_)));
+    }
+
+    #[test]
+    fn forecast_postcode() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Invalid postal codes are rejected before geocoding.
+        let response = client.get("/forecast?postcode=0611AB").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.get("/map?postcode=5611&metric=pollen").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn search() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Queries that are too short are rejected before geocoding, also after trimming.
+        let response = client.get("/search?q=ei").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "query_too_short");
+        let response = client
+            .get("/v1/search?q=%20ei%20")
+            .header(Header::new("Accept-Language", "nl"))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["error"], "De zoekopdracht is te kort");
+
+        // The query is required.
+        let response = client.get("/search").dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn forecast_strict() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Without maps, the pollen forecast fails, which is reported with its kind...
+        let response = client
+            .get("/forecast?lat=53.2&lon=6.6&metrics=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["pollen"], JsonValue::Null);
+        assert_matches!(json["errors"]["pollen"], JsonValue::String(_));
+        assert_eq!(json["error_kinds"]["pollen"], "no_maps_yet");
+
+        // ... or fails the request in strict mode.
+        let response = client
+            .get("/forecast?lat=53.2&lon=6.6&metrics=pollen&strict=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "incomplete_forecast");
+        assert_eq!(json["error_kinds"]["pollen"], "no_maps_yet");
+    }
+
+    #[test]
+    fn forecast_thresholds() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let api_keys = json!([{"key": "secret1", "thresholds": {"pollen": 3.0}}]);
+        let rocket =
+            rocket(maps_handle).configure(rocket::Config::figment().merge(("api_keys", api_keys)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+
+        // Valid thresholds can be combined with the API key query parameter...
+        let response = client
+            .get("/forecast?lat=53.2&lon=6.6&metrics=pollen&pollen_max=5&api_key=secret1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // ... but invalid ones are rejected.
+        let response = client
+            .get("/forecast?lat=53.2&lon=6.6&metrics=pollen&uvi_max=-1&api_key=secret1")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_threshold");
+    }
+
+    #[test]
+    fn sensor() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Without maps, the pollen sensor has no value and its attributes contain the error.
+        let response = client
+            .get("/sensor?lat=53.2&lon=6.6&metrics=pollen&hours=3")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_f64_near!(json["lat"].as_f64().unwrap(), 53.2);
+        assert_f64_near!(json["lon"].as_f64().unwrap(), 6.6);
+        assert_matches!(json["time"], JsonValue::Number(_));
+        assert_eq!(json["pollen"], JsonValue::Null);
+        assert_eq!(json["attributes"]["pollen"]["forecast"], json!([]));
+        assert_matches!(json["attributes"]["pollen"]["error"], JsonValue::String(_));
+        assert_matches!(json.get("UVI"), None);
+
+        // The number of hours is limited.
+        let response = client
+            .get("/sensor?lat=53.2&lon=6.6&metrics=pollen&hours=4294967295")
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_hours");
+    }
+
+    #[test]
+    fn badge() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Without maps, the pollen badge has no value and is gray.
+        let response = client
+            .get("/badge?lat=53.2&lon=6.6&metric=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        let image_data = response.into_bytes().unwrap();
+        let image = image::load_from_memory(&image_data).unwrap();
+        let (width, height) = image.dimensions();
+        assert_eq!(height, 18);
+        assert_eq!(
+            image.get_pixel(width - 1, 0),
+            Rgba::from([0x9f, 0x9f, 0x9f, 0xff])
+        );
+
+        // A metric is required.
+        let response = client.get("/badge?lat=53.2&lon=6.6").dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn chart() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // The chart is a PNG image by default.
+        let response = client
+            .get("/chart?lat=51.4&lon=5.5&metric=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        let image_data = response.into_bytes().unwrap();
+        assert!(image::load_from_memory(&image_data).is_ok());
+
+        // ... or an SVG image.
+        let response = client
+            .get("/chart?lat=51.4&lon=5.5&metric=pollen&format=svg")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::SVG));
+        let svg = response.into_string().unwrap();
+        assert!(svg.contains("<polyline points="));
+
+        // The chart of all metrics is not supported.
+        let response = client.get("/chart?lat=51.4&lon=5.5&metric=all").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn forecast_geo() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("valid Rocket instance");
+
+        // Get an empty forecast for the geocoded location.
+        let response = client.get("/forecast?lat=51.4&lon=5.5").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_f64_near!(json["lat"].as_f64().unwrap(), 51.4);
+        assert_f64_near!(json["lon"].as_f64().unwrap(), 5.5);
+        assert_matches!(json["time"], JsonValue::Number(_));
+        assert_matches!(json.get("AQI"), None);
+        assert_matches!(json.get("NO2"), None);
+        assert_matches!(json.get("O3"), None);
+        assert_matches!(json.get("PAQI"), None);
+        assert_matches!(json.get("PM10"), None);
+        assert_matches!(json.get("pollen"), None);
+        assert_matches!(json.get("temperature"), None);
+        assert_matches!(json.get("precipitation"), None);
+        assert_matches!(json.get("UVI"), None);
+
+        // Get an empty forecast for the geocoded location as CSV.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&format=csv")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::CSV));
+        assert_eq!(response.into_string().unwrap(), "time\n");
+
+        // Get a forecast with all metrics for the geocoded location.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=all")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_f64_near!(json["lat"].as_f64().unwrap(), 51.4);
+        assert_f64_near!(json["lon"].as_f64().unwrap(), 5.5);
+        assert_matches!(json["time"], JsonValue::Number(_));
+        assert_matches!(json.get("AQI"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("NO2"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("O3"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("PAQI"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("PM10"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("pollen"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("temperature"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("precipitation"), Some(JsonValue::Array(_)));
+        assert_matches!(json.get("UVI"), Some(JsonValue::Array(_)));
+        assert_matches!(json["freshness"]["UVI"]["stale"], JsonValue::Bool(false));
+    }
+
+    #[test]
+    fn forecast_binary() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Get an empty forecast for the geocoded location as MessagePack.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5")
+            .header(Header::new("Accept", "application/msgpack"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::MsgPack));
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept"));
+        let data = response.into_bytes().unwrap();
+        assert_matches!(data[0], 0x80..=0x8f);
+        assert!(data
+            .windows(4)
+            .any(|window| window == [0xa3, b'l', b'a', b't']));
+
+        // ... and as CBOR, negotiated by quality value.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5")
+            .header(Header::new(
+                "Accept",
+                "application/json;q=0.5, application/cbor",
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "cbor"))
+        );
+        let data = response.into_bytes().unwrap();
+        assert_matches!(data[0], 0xa0..=0xb7);
+        assert!(data
+            .windows(4)
+            .any(|window| window == [0x63, b'l', b'a', b't']));
+
+        // The format parameter takes precedence over the `Accept` header.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&format=json")
+            .header(Header::new("Accept", "application/cbor"))
+            .dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+        // Without an explicitly accepted format, JSON is the default.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5")
+            .header(Header::new("Accept", "*/*"))
+            .dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+    }
+
+    #[test]
+    fn forecast_fields() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Only the selected fields are kept.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=pollen&fields=lat,errors")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let etag = response.headers().get_one("ETag").map(String::from);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_f64_near!(json["lat"].as_f64().unwrap(), 51.4);
+        assert_matches!(json.get("lon"), None);
+        assert_matches!(json.get("time"), None);
+        assert_matches!(json.get("freshness"), None);
+        assert_matches!(json.get("_links"), None);
+
+        // The field selection is part of the entity tag.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=pollen")
+            .dispatch();
+        assert_ne!(response.headers().get_one("ETag").map(String::from), etag);
+
+        // The field selection also applies to batches.
+        let response = client
+            .post("/forecasts?fields=lon")
+            .header(ContentType::JSON)
+            .body(r#"[{"lat": 51.4, "lon": 5.5}]"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json, json!([{"lon": 5.5}]));
+    }
+
+    #[test]
+    fn forecast_links() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // The forecast links to itself and the maps of the metrics asked for.
+        let response = client
+            .get("/v1/forecast?lat=51.4&lon=5.5&metrics=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(
+            json["_links"],
+            json!({
+                "self": { "href": "/v1/forecast?lat=51.4&lon=5.5&metrics=pollen" },
+                "map": [{ "href": "/v1/map?lat=51.4&lon=5.5&metric=pollen", "name": "pollen" }]
+            })
+        );
+
+        // The links of unversioned requests are unversioned too.
+        let response = client.get("/forecast?lat=51.4&lon=5.5").dispatch();
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(
+            json["_links"],
+            json!({ "self": { "href": "/forecast?lat=51.4&lon=5.5" } })
+        );
+    }
+
+    #[test]
+    fn forecast_protobuf() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Get an empty forecast for the geocoded location as Protocol Buffers message.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&format=protobuf")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "x-protobuf"))
+        );
+        let data = response.into_bytes().unwrap();
+        // The message starts with the latitude (field 1) as double.
+        assert_eq!(data[0], 0x09);
+        assert_eq!(data[1..9], 51.4f64.to_le_bytes());
+
+        // Get the published schema.
+        let response = client.get("/v1/sinoptik.proto").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::Plain));
+        let schema = response.into_string().unwrap();
+        assert!(schema.contains("message Forecast {"));
+    }
+
+    #[test]
+    fn forecasts() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Get empty forecasts for the geocoded locations in the same order.
+        let response = client
+            .post("/forecasts")
+            .header(ContentType::JSON)
+            .body(r#"[{"lat": 51.4, "lon": 5.5}, {"lat": 52.1, "lon": 5.1}]"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        let forecasts = json.as_array().expect("Not a JSON array");
+        assert_eq!(forecasts.len(), 2);
+        assert_f64_near!(forecasts[0]["lat"].as_f64().unwrap(), 51.4);
+        assert_f64_near!(forecasts[1]["lat"].as_f64().unwrap(), 52.1);
+        assert_matches!(forecasts[0].get("UVI"), None);
+
+        // A location that cannot be resolved gets an error in its place.
+        let response = client
+            .post("/forecasts")
+            .header(ContentType::JSON)
+            .body(r#"[{"postcode": "0611AB"}, {"lat": 51.4, "lon": 5.5}]"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        let forecasts = json.as_array().expect("Not a JSON array");
+        assert_eq!(forecasts.len(), 2);
+        assert_eq!(forecasts[0]["kind"], "invalid_postcode");
+        assert_eq!(forecasts[0]["status"], 400);
+        assert_f64_near!(forecasts[1]["lat"].as_f64().unwrap(), 51.4);
+
+        // Locations that are neither an address nor a geocoded position are rejected.
+        let response = client
+            .post("/forecasts")
+            .header(ContentType::JSON)
+            .body(r#"[{"town": "Eindhoven"}]"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // Batches with more locations than the maximum are rejected.
+        let locations = vec![json!({"lat": 51.4, "lon": 5.5}); 101];
+        let response = client
+            .post("/forecasts")
+            .header(ContentType::JSON)
+            .body(json!(locations).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "too_many_locations");
+    }
+
+    #[test]
+    fn subscriptions() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+
+        // There are no subscriptions yet.
+        let response = client.get("/subscriptions").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), "[]");
+
+        // Add a subscription.
+        let response = client
+            .post("/subscriptions")
+            .header(ContentType::JSON)
+            .body(
+                r#"{"url": "https://93.184.215.14/hook", "lat": 51.4, "lon": 5.5,
+                    "metric": "pollen", "threshold": 7}"#,
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some("/subscriptions/1")
+        );
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["id"], 1);
+        assert_eq!(json["metric"], "pollen");
+
+        // A subscription with an invalid callback URL is rejected.
+        let response = client
+            .post("/subscriptions")
+            .header(ContentType::JSON)
+            .body(r#"{"url": "nope", "lat": 51.4, "lon": 5.5, "metric": "UVI", "threshold": 7}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // ... and so is one that is not publicly reachable, e.g. the cloud metadata service.
+        let response = client
+            .post("/subscriptions")
+            .header(ContentType::JSON)
+            .body(
+                r#"{"url": "http://169.254.169.254/latest/meta-data", "lat": 51.4, "lon": 5.5,
+                    "metric": "UVI", "threshold": 7}"#,
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_callback_url");
+
+        // Retrieve and remove the subscription.
+        let response = client.get("/subscriptions/1").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.delete("/subscriptions/1").dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client.get("/subscriptions/1").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn subscriptions_api_keys() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let api_keys =
+            json!([{"key": "secret1"}, {"key": "secret2"}, {"key": "admin", "admin": true}]);
+        let rocket = rocket_core(maps_handle)
+            .configure(rocket::Config::figment().merge(("api_keys", api_keys)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+
+        // Add a subscription; the API key is not exposed.
+        let response = client
+            .post("/subscriptions?api_key=secret1")
+            .header(ContentType::JSON)
+            .body(
+                r#"{"url": "https://93.184.215.14/hook", "lat": 51.4, "lon": 5.5,
+                    "metric": "pollen", "threshold": 7}"#,
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert!(json.get("owner").is_none());
+
+        // Another API key cannot list, retrieve or remove it.
+        let response = client.get("/subscriptions?api_key=secret2").dispatch();
+        assert_eq!(response.into_string().unwrap(), "[]");
+        let response = client.get("/subscriptions/1?api_key=secret2").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.delete("/subscriptions/1?api_key=secret2").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // The API key it was created with and an admin key can.
+        let response = client.get("/subscriptions?api_key=secret1").dispatch();
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        let response = client.get("/subscriptions/1?api_key=admin").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.delete("/subscriptions/1?api_key=secret1").dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+    }
+
+    #[test]
+    fn locations() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // There are no named locations yet.
+        let response = client.get("/locations").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), "[]");
+
+        // Store a named location.
+        let response = client
+            .put("/locations/home")
+            .header(ContentType::JSON)
+            .body(r#"{"lat": 51.4, "lon": 5.5}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json, json!({"name": "home", "lat": 51.4, "lon": 5.5}));
+
+        // A location with an invalid name is rejected.
+        let response = client
+            .put("/locations/my%20home")
+            .header(ContentType::JSON)
+            .body(r#"{"lat": 51.4, "lon": 5.5}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // The name is checked before an address is geocoded.
+        let response = client
+            .put("/locations/my%20home")
+            .header(ContentType::JSON)
+            .body(r#"{"address": "Stationsplein, Utrecht"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_location_name");
+
+        // Retrieve the forecast for the named location.
+        let response = client
+            .get("/forecast?location=home&metrics=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_f64_near!(json["lat"].as_f64().unwrap(), 51.4);
+        assert_f64_near!(json["lon"].as_f64().unwrap(), 5.5);
+
+        // Retrieve and remove the named location.
+        let response = client.get("/locations/home").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.delete("/locations/home").dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client.get("/locations/home").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/forecast?location=home").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "location_not_found");
+    }
+
+    #[test]
+    fn history() {
+        // History is not enabled by default.
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+        let response = client
+            .get("/history?lat=51.4&lon=5.5&metric=UVI")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // Get the (empty) history if enabled.
+        let path = std::env::temp_dir().join(format!(
+            "sinoptik-test-history-api-{}.db",
+            std::process::id()
+        ));
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let rocket = rocket_core(maps_handle)
+            .configure(rocket::Config::figment().merge(("history_file", &path)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+        let response = client
+            .get("/history?lat=51.4&lon=5.5&metric=UVI&from=100&to=200")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<JsonValue>(), Some(json!([])));
+
+        // An invalid time window is rejected.
+        let response = client
+            .get("/history?lat=51.4&lon=5.5&metric=UVI&from=200&to=100")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // Get the (empty) accuracy statistics.
+        let response = client.get("/accuracy?metric=UVI&bucket=600").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<JsonValue>(), Some(json!([])));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn grafana() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Test the connection and search for targets.
+        let response = client.get("/grafana").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .post("/grafana/search")
+            .header(ContentType::JSON)
+            .body(r#"{"target": "paqi"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_json::<JsonValue>(),
+            Some(json!(["PAQI", "PAQI_daily"]))
+        );
+
+        // Query the pollen forecast for the current day.
+        let now = Utc::now();
+        let body = json!({
+            "range": {
+                "from": (now - chrono::Duration::hours(12)).to_rfc3339(),
+                "to": (now + chrono::Duration::hours(12)).to_rfc3339()
+            },
+            "targets": [{"target": "pollen@51.4,5.5", "refId": "A"}]
+        });
+        let response = client
+            .post("/grafana/query")
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json[0]["target"], "pollen@51.4,5.5");
+        assert_matches!(json[0]["datapoints"][0][0], JsonValue::Number(_));
+
+        // An invalid target is rejected.
+        let body = body.to_string().replace("pollen@51.4,5.5", "pollen");
+        let response = client
+            .post("/grafana/query")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn forecast_page() {
+        let maps_handle = maps_handle_stub();
+        let client = Client::tracked(rocket(maps_handle)).expect("Not a valid Rocket instance");
+
+        // Only the requested page of the pollen items is included.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=pollen")
+            .dispatch();
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        let items = json["pollen"].as_array().unwrap().clone();
+        assert!(items.len() > 3);
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=pollen&offset=1&limit=2")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["pollen"], json!(items[1..3]));
+    }
+
+    #[test]
+    fn metrics() {
+        // The exporter is not enabled by default.
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "exporter_disabled");
+
+        // Get the (empty) exposition if enabled.
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let rocket = rocket_core(maps_handle)
+            .configure(rocket::Config::figment().merge(("exporter.interval", 60)));
+        let client = Client::tracked(rocket).expect("Not a valid Rocket instance");
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::Plain));
+        assert_eq!(response.into_string(), Some(String::new()));
+    }
+
+    #[test]
+    fn map_address() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let maps_handle_clone = Arc::clone(&maps_handle);
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+
+        // No maps available yet.
+        let response = client
+            .get("/map?address=eindhoven&metric=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+
+        // Load some dummy map.
+        let mut maps = maps_handle_clone
+            .write()
+            .expect("Maps handle lock was poisoned");
+        maps.pollen = Some(maps_stub(24));
+        drop(maps);
+
+        // There should be a map now.
+        let response = client
+            .get("/map?address=eindhoven&metric=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+
+        // ... but not if it is out of bounds.
+        let response = client.get("/map?address=berlin&metric=pollen").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // No metric selected, don't know which map to show?
+        let response = client.get("/map?address=eindhoven").dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn map_geo() {
+        let maps_handle = Arc::new(RwLock::new(Maps::default()));
+        let maps_handle_clone = Arc::clone(&maps_handle);
+        let client =
+            Client::tracked(rocket_core(maps_handle)).expect("Not a valid Rocket instance");
+
+        // No maps available yet.
+        let response = client.get("/map?lat=51.4&lon=5.5&metric=pollen").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "no_maps_yet");
+        assert_eq!(json["status"], 503);
+
+        // There are no maps for some metrics.
+        let response = client.get("/map?lat=51.4&lon=5.5&metric=AQI").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "unsupported_metric");
+        assert_eq!(json["status"], 400);
+
+        // Load some dummy map.
+        let mut maps = maps_handle_clone
+            .write()
+            .expect("Maps handle lock was poisoned");
+        maps.pollen = Some(maps_stub(24));
+        drop(maps);
+
+        // There should be a map now.
+        let response = client.get("/map?lat=51.4&lon=5.5&metric=pollen").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        let etag = response
+            .headers()
+            .get_one("ETag")
+            .map(String::from)
+            .unwrap();
+        let last_modified = response
+            .headers()
+            .get_one("Last-Modified")
+            .map(String::from);
+        assert!(last_modified.is_some());
+        let cache_control = response.headers().get_one("Cache-Control").unwrap();
+        let max_age = cache_control.strip_prefix("max-age=").unwrap();
+        assert!(max_age.parse::<u64>().unwrap() <= 3_600);
+
+        // ... which is not sent again if it has not changed.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen")
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen")
+            .header(Header::new("If-Modified-Since", last_modified.unwrap()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen")
+            .header(Header::new("If-None-Match", "W/\"other\""))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // The map can be cropped around the position, but only using valid options.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&radius_km=25")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&zoom=0.5")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_map_options");
+
+        // The map can be requested in another image format, or negotiate it.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&format=jpeg")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JPEG));
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept"));
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen")
+            .header(Header::new("Accept", "image/webp,image/*;q=0.8"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::WEBP));
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&animate=true&format=webp")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // The style of the marker can be customized.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&marker=circle&marker_color=%23ff0000")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&marker=pin&marker_size=1000")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // The legend of the map key can be overlaid.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&legend=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+
+        // A later map can be selected, but only if it is available.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&offset=6")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&offset=24")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_map_options");
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&offset=4294967295")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&time=0")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // An animation of all maps can be requested as well.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=pollen&animate=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("image", "apng"))
+        );
+
+        // ... but not if it is out of bounds, which reports the supported coverage area.
+        let response = client.get("/map?lat=0.0&lon=0.0&metric=pollen").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_matches!(json["error"], JsonValue::String(_));
+        assert_eq!(json["kind"], "outside_coverage");
+        let coverage = &json["coverage"];
+        assert!(coverage["min_lat"].as_f64().unwrap() < 51.4);
+        assert!(coverage["max_lat"].as_f64().unwrap() > 51.4);
+        assert!(coverage["min_lon"].as_f64().unwrap() < 5.5);
+        assert!(coverage["max_lon"].as_f64().unwrap() > 5.5);
+
+        // No metric passed, don't know which map to show?
+        let response = client.get("/map?lat=51.4&lon=5.5").dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn map_raw() {
+        let client =
+            Client::tracked(rocket_core(maps_handle_stub())).expect("Not a valid Rocket instance");
+
+        // The unmodified current map is returned.
+        let response = client.get("/map/raw?metric=pollen").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        assert!(response.headers().get_one("ETag").is_some());
+        let image_data = response.into_bytes().unwrap();
+        let image = image::load_from_memory(&image_data).unwrap();
+        assert_eq!(image.dimensions(), (820, 988));
+        assert!(image
+            .pixels()
+            .all(|(_, _, pixel)| pixel == Rgba::from([73, 218, 33, 255])));
+
+        // A later map can be selected, but only if it is available, in another format.
+        let response = client
+            .get("/map/raw?metric=uvi&offset=4&format=webp")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::WEBP));
+        let response = client.get("/map/raw?metric=uvi&offset=5").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_map_options");
+    }
+
+    #[test]
+    fn map_precipitation() {
+        let client =
+            Client::tracked(rocket_core(maps_handle_stub())).expect("Not a valid Rocket instance");
+
+        // The precipitation radar map can be shown, marked or not.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=precipitation")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        let response = client.get("/map/raw?metric=precipitation").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // ... but there is no legend for it.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=precipitation&legend=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["kind"], "invalid_map_options");
+    }
+
+    #[test]
+    fn forecast_interpolate() {
+        let client =
+            Client::tracked(rocket_core(maps_handle_stub())).expect("Not a valid Rocket instance");
+
+        // The daily UV index samples are interpolated to an item every 15 minutes.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=UVI&interpolate=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        let items = json["UVI"].as_array().unwrap();
+        assert_eq!(items.len(), 4 * 96 + 1);
+        let time = |index: usize| items[index]["time"].as_i64().unwrap();
+        assert_eq!(time(1) - time(0), 900);
+    }
+
+    #[test]
+    fn project() {
+        let client =
+            Client::tracked(rocket_core(maps_handle_stub())).expect("Not a valid Rocket instance");
+
+        // Positions are projected to pixel coordinates on the map.
+        let response = client
+            .get("/project?lat=51.44&lon=3.57&metric=pollen")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(
+            json,
+            json!({"x": 84, "y": 745, "width": 820, "height": 988, "in_bounds": true})
+        );
+
+        // ... also if they lie outside of the map.
+        let response = client
+            .get("/project?lat=48.86&lon=2.35&metric=UVI")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        assert_eq!(json["in_bounds"], false);
+    }
+
+    #[test]
+    fn map_temperature() {
+        let client =
+            Client::tracked(rocket_core(maps_handle_stub())).expect("Not a valid Rocket instance");
+
+        // The temperature map can be shown with its legend.
+        let response = client
+            .get("/map?lat=51.4&lon=5.5&metric=temperature&legend=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+
+        // The temperature forecast is sampled from the maps.
+        let response = client
+            .get("/forecast?lat=51.4&lon=5.5&metrics=temperature")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let json = response.into_json::<JsonValue>().expect("Not valid JSON");
+        let items = json["temperature"].as_array().unwrap();
+        assert_eq!(items.len(), 24);
+        assert_f64_near!(items[0]["value"].as_f64().unwrap(), 15.0);
+    }
+}
@@ -0,0 +1,116 @@
+//! Shared cache for multi-instance deployments.
+//!
+//! If the Redis cache backend is configured, the responses of the upstream provider APIs and the
+//! geocoded positions of addresses are shared between instances via Redis. This is a second cache
+//! layer; each instance still caches results in memory as well.
+//!
+//! Failures to access Redis are logged and otherwise ignored, i.e. the data is then retrieved from
+//! the upstream APIs directly.
+
+use std::future::Future;
+use std::sync::OnceLock;
+
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use rocket::serde::Deserialize;
+use rocket::tokio;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::{Error, Result};
+
+/// The prefix of all keys stored in Redis.
+const KEY_PREFIX: &str = "sinoptik:";
+
+/// The connection to Redis (if the Redis cache backend is used).
+static CONNECTION: OnceLock<ConnectionManager> = OnceLock::new();
+
+tokio::task_local! {
+    /// Whether lookups in the shared cache are bypassed for the current task, see [`bypass`].
+    static BYPASS: bool;
+}
+
+/// The supported cache backends.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub(crate) enum CacheBackend {
+    /// Only cache in memory, per instance.
+    #[default]
+    Memory,
+    /// Additionally cache in Redis, shared between instances.
+    Redis,
+}
+
+/// Connects to Redis if the Redis cache backend is configured.
+pub(crate) async fn connect(config: &Config) -> Result<()> {
+    if config.cache_backend != CacheBackend::Redis {
+        return Ok(());
+    }
+
+    let redis_url = config.redis_url.as_deref().ok_or(Error::MissingRedisUrl)?;
+    let client = redis::Client::open(redis_url)?;
+    let connection = client.get_connection_manager().await?;
+    let _ = CONNECTION.set(connection);
+
+    Ok(())
+}
+
+/// Returns the value for the provided key from the shared cache, if any.
+async fn get(key: &str) -> Option<String> {
+    let mut connection = CONNECTION.get()?.clone();
+
+    connection
+        .get(format!("{KEY_PREFIX}{key}"))
+        .await
+        .map_err(|e| warn!(%key, error = %e, "💥 Encountered error during shared cache lookup"))
+        .ok()
+        .flatten()
+}
+
+/// Sets the value for the provided key in the shared cache, expiring after the provided number of
+/// seconds.
+async fn set(key: &str, value: &str, ttl: u64) {
+    let Some(connection) = CONNECTION.get() else {
+        return;
+    };
+    let mut connection = connection.clone();
+
+    let result: redis::RedisResult<()> = connection
+        .set_ex(format!("{KEY_PREFIX}{key}"), value, ttl)
+        .await;
+    if let Err(e) = result {
+        warn!(%key, error = %e, "💥 Encountered error during shared cache update");
+    }
+}
+
+/// Runs the future with the lookups in the shared cache bypassed.
+///
+/// The values are then always retrieved from the upstream APIs, e.g. to refresh them before they
+/// expire. They are still stored in the shared cache, so that the other instances use them too.
+pub(crate) async fn bypass<Fut: Future>(future: Fut) -> Fut::Output {
+    BYPASS.scope(true, future).await
+}
+
+/// Returns the value for the provided key from the shared cache, or retrieves it and stores it
+/// in the shared cache for the provided number of seconds.
+///
+/// If no shared cache is used or the lookups are bypassed (see [`bypass`]), the value is always
+/// retrieved.
+pub(crate) async fn get_or_retrieve<F, Fut>(key: &str, ttl: u64, retrieve: F) -> Result<String>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let bypassed = BYPASS.try_with(|bypassed| *bypassed).unwrap_or_default();
+    if !bypassed {
+        if let Some(value) = get(key).await {
+            debug!(%key, "Found value in shared cache");
+            return Ok(value);
+        }
+    }
+
+    let value = retrieve().await?;
+    set(key, &value, ttl).await;
+
+    Ok(value)
+}
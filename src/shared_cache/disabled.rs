@@ -0,0 +1,30 @@
+//! Stand-in for the shared cache if the `server` feature is disabled.
+//!
+//! Only the in-memory caches are used, so the values are always retrieved from the upstream APIs
+//! directly.
+
+use std::future::Future;
+
+use rocket::serde::Deserialize;
+
+use crate::Result;
+
+/// The supported cache backends.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub(crate) enum CacheBackend {
+    /// Only cache in memory, per instance.
+    #[default]
+    Memory,
+    /// Additionally cache in Redis, which is not supported without the `server` feature.
+    Redis,
+}
+
+/// Retrieves the value, because there is no shared cache.
+pub(crate) async fn get_or_retrieve<F, Fut>(_key: &str, _ttl: u64, retrieve: F) -> Result<String>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    retrieve().await
+}
@@ -0,0 +1,84 @@
+//! Snapshots of collections that are persisted to a file.
+//!
+//! Collections like the webhook subscriptions, the named locations and the geocoding cache are
+//! changed while they are locked. Writing them to a file at that time would block other requests,
+//! so instead a snapshot is taken under the lock and written after the lock has been released.
+//! Because concurrent changes can write their snapshots in any order, every snapshot has a version
+//! and a snapshot is only written if no later one has been written already.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rocket::serde::json::serde_json;
+use rocket::serde::Serialize;
+use rocket::tokio;
+use rocket::tokio::sync::Mutex as AsyncMutex;
+
+use crate::Result;
+
+/// The versions of the snapshots of a persisted collection.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotVersions {
+    /// The version of the collection, which increases with every change.
+    version: u64,
+
+    /// The version of the snapshot that was written last.
+    written: Arc<AsyncMutex<u64>>,
+}
+
+impl SnapshotVersions {
+    /// Returns a snapshot of the changed collection to persist to the file at the path, if any.
+    pub(crate) fn snapshot<T>(&mut self, path: Option<&Path>, collection: &T) -> Result<Snapshot>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.version += 1;
+        let file = match path {
+            Some(path) => {
+                let data = serde_json::to_string_pretty(collection)?;
+
+                Some((path.to_path_buf(), data, self.version))
+            }
+            None => None,
+        };
+
+        Ok(Snapshot {
+            file,
+            written: Arc::clone(&self.written),
+        })
+    }
+}
+
+/// A snapshot of a collection after a change that still needs to be persisted.
+#[derive(Debug)]
+#[must_use = "the snapshot needs to be written to persist the change"]
+pub(crate) struct Snapshot {
+    /// The path of the file, the serialized collection and the version of the snapshot (if the
+    /// collection is persisted).
+    file: Option<(PathBuf, String, u64)>,
+
+    /// The version of the snapshot that was written last.
+    written: Arc<AsyncMutex<u64>>,
+}
+
+impl Snapshot {
+    /// Writes the snapshot to the file, if any.
+    ///
+    /// If a later snapshot has been written already by a concurrent change, this snapshot is
+    /// skipped. The snapshot is written to a temporary file first that then replaces the file, so
+    /// that the file is never left partially written, e.g. after a crash.
+    pub(crate) async fn write(self) -> Result<()> {
+        let Some((path, data, version)) = self.file else {
+            return Ok(());
+        };
+        let mut written = self.written.lock().await;
+        if version > *written {
+            let temp_path = path.with_extension("tmp");
+            tokio::fs::write(&temp_path, data).await?;
+            tokio::fs::rename(temp_path, path).await?;
+            *written = version;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,636 @@
+//! Webhook subscriptions and notification.
+//!
+//! This module keeps track of webhook subscriptions and provides a task that periodically
+//! evaluates the forecasts for all subscriptions. Whenever the forecast of the subscribed metric
+//! exceeds the threshold, the callback URL is notified once per period using a JSON payload.
+//!
+//! Callback URLs need to point to publicly reachable hosts, unless their host is explicitly
+//! allowed, so that subscriptions cannot be used to reach internal services.
+//!
+//! Subscriptions record the API key they were created with (if any), so that they can only be
+//! accessed with that API key or an admin key.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use reqwest::Url;
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio;
+use rocket::tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+use crate::forecast::{forecast, Metric};
+use crate::http;
+use crate::maps::MapsHandle;
+use crate::position::Position;
+use crate::snapshot::{Snapshot, SnapshotVersions};
+use crate::thresholds::periods;
+use crate::{Error, Result};
+
+/// The interval between evaluations of the subscriptions.
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(300);
+
+/// A handle to access the subscriptions.
+pub(crate) type SubscriptionsHandle = Arc<Mutex<Subscriptions>>;
+
+/// A request for a new subscription.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct NewSubscription {
+    /// The callback URL that is notified.
+    url: String,
+
+    /// The latitude of the position.
+    lat: f64,
+
+    /// The longitude of the position.
+    lon: f64,
+
+    /// The metric to evaluate.
+    metric: Metric,
+
+    /// The threshold the metric needs to exceed for a notification.
+    threshold: f32,
+}
+
+impl NewSubscription {
+    /// Checks whether the callback URL is allowed, see [`check_callback_url`].
+    pub(crate) async fn check_callback_url(&self, allowed_hosts: &[String]) -> Result<()> {
+        check_callback_url(&self.url, allowed_hosts).await
+    }
+}
+
+/// A webhook subscription.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Subscription {
+    /// The unique ID of the subscription.
+    pub(crate) id: u64,
+
+    /// The callback URL that is notified.
+    url: String,
+
+    /// The latitude of the position.
+    lat: f64,
+
+    /// The longitude of the position.
+    lon: f64,
+
+    /// The metric to evaluate.
+    metric: Metric,
+
+    /// The threshold the metric needs to exceed for a notification.
+    threshold: f32,
+
+    /// The (UNIX) timestamps of the starts of the periods that have been notified.
+    #[serde(default)]
+    notified_periods: BTreeSet<i64>,
+
+    /// The API key the subscription was created with (if authentication is enabled).
+    ///
+    /// It is only persisted, see [`StoredSubscription`], and never exposed.
+    #[serde(skip)]
+    owner: Option<String>,
+}
+
+impl Subscription {
+    /// Returns whether the subscription is accessible with the API key that access is restricted
+    /// to, see [`ApiKey::restriction`](crate::auth::ApiKey::restriction).
+    fn is_accessible(&self, restriction: Option<&str>) -> bool {
+        restriction.is_none_or(|key| self.owner.as_deref() == Some(key))
+    }
+}
+
+/// The payload that is posted to the callback URL.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Notification {
+    /// The ID of the subscription.
+    subscription: u64,
+
+    /// The latitude of the position.
+    lat: f64,
+
+    /// The longitude of the position.
+    lon: f64,
+
+    /// The metric that exceeds the threshold.
+    metric: Metric,
+
+    /// The threshold that is exceeded.
+    threshold: f32,
+
+    /// The start of the period (in seconds since the UNIX epoch).
+    start: i64,
+
+    /// The end of the period (in seconds since the UNIX epoch).
+    end: i64,
+
+    /// The maximum value during the period.
+    max_value: f32,
+}
+
+/// A subscription as it is persisted, including the API key it was created with.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct StoredSubscription<S> {
+    /// The subscription.
+    #[serde(flatten)]
+    subscription: S,
+
+    /// The API key the subscription was created with (if authentication is enabled).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+}
+
+/// The contents of the file the subscriptions are persisted to.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SubscriptionsFile<S> {
+    /// The ID of the next subscription.
+    next_id: u64,
+
+    /// The subscriptions.
+    subscriptions: Vec<StoredSubscription<S>>,
+}
+
+/// The collection of all subscriptions.
+///
+/// If a file path is set, the subscriptions are persisted to it after every change, see
+/// [`Snapshot`].
+#[derive(Debug)]
+pub(crate) struct Subscriptions {
+    /// The path of the file the subscriptions are persisted to (if any).
+    path: Option<PathBuf>,
+
+    /// The versions of the snapshots of the subscriptions.
+    versions: SnapshotVersions,
+
+    /// The ID of the next subscription.
+    ///
+    /// IDs only ever increase, so that the ID of a removed subscription is never reused.
+    next_id: u64,
+
+    /// The subscriptions by ID.
+    subscriptions: BTreeMap<u64, Subscription>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self {
+            path: None,
+            versions: SnapshotVersions::default(),
+            next_id: 1,
+            subscriptions: BTreeMap::new(),
+        }
+    }
+}
+
+impl Subscriptions {
+    /// Loads the subscriptions from the file at the provided path, if any.
+    ///
+    /// If the file does not exist yet, there are no subscriptions.
+    pub(crate) fn load(path: Option<PathBuf>) -> Result<Self> {
+        let path = match path {
+            Some(path) if path.exists() => path,
+            path => {
+                return Ok(Self {
+                    path,
+                    ..Default::default()
+                })
+            }
+        };
+        let data = std::fs::read_to_string(&path)?;
+        let file: SubscriptionsFile<Subscription> = serde_json::from_str(&data)?;
+        let subscriptions = file
+            .subscriptions
+            .into_iter()
+            .map(|stored| {
+                let subscription = Subscription {
+                    owner: stored.owner,
+                    ..stored.subscription
+                };
+                (subscription.id, subscription)
+            })
+            .collect::<BTreeMap<_, _>>();
+        // Never hand out an ID that is still in use, even if the file was edited by hand.
+        let next_id = subscriptions
+            .keys()
+            .last()
+            .map_or(file.next_id, |id| file.next_id.max(id + 1));
+
+        Ok(Self {
+            path: Some(path),
+            next_id,
+            subscriptions,
+            ..Default::default()
+        })
+    }
+
+    /// Returns a snapshot of the changed subscriptions to persist to the file, if any.
+    fn snapshot(&mut self) -> Result<Snapshot> {
+        let file = SubscriptionsFile {
+            next_id: self.next_id,
+            subscriptions: self
+                .subscriptions
+                .values()
+                .map(|subscription| StoredSubscription {
+                    subscription,
+                    owner: subscription.owner.clone(),
+                })
+                .collect(),
+        };
+
+        self.versions.snapshot(self.path.as_deref(), &file)
+    }
+
+    /// Returns all subscriptions.
+    pub(crate) fn all(&self) -> Vec<Subscription> {
+        self.subscriptions.values().cloned().collect()
+    }
+
+    /// Returns the subscriptions that are accessible with the API key that access is restricted
+    /// to, if any.
+    pub(crate) fn accessible(&self, restriction: Option<&str>) -> Vec<Subscription> {
+        self.subscriptions
+            .values()
+            .filter(|subscription| subscription.is_accessible(restriction))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the subscription with the provided ID.
+    ///
+    /// A subscription that is not accessible with the API key that access is restricted to, if
+    /// any, is not found.
+    pub(crate) fn get(&self, id: u64, restriction: Option<&str>) -> Result<Subscription> {
+        self.subscriptions
+            .get(&id)
+            .filter(|subscription| subscription.is_accessible(restriction))
+            .cloned()
+            .ok_or(Error::SubscriptionNotFound(id))
+    }
+
+    /// Adds a new subscription created with the provided API key (if any) and returns it,
+    /// together with the snapshot to persist.
+    pub(crate) fn add(
+        &mut self,
+        new_subscription: NewSubscription,
+        owner: Option<&str>,
+    ) -> Result<(Subscription, Snapshot)> {
+        let NewSubscription {
+            url,
+            lat,
+            lon,
+            metric,
+            threshold,
+        } = new_subscription;
+        if metric == Metric::All {
+            return Err(Error::UnsupportedMetric(metric));
+        }
+        parse_callback_url(&url)?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let subscription = Subscription {
+            id,
+            url,
+            lat,
+            lon,
+            metric,
+            threshold,
+            notified_periods: BTreeSet::new(),
+            owner: owner.map(String::from),
+        };
+        self.subscriptions.insert(id, subscription.clone());
+
+        Ok((subscription, self.snapshot()?))
+    }
+
+    /// Removes the subscription with the provided ID and returns the snapshot to persist.
+    ///
+    /// A subscription that is not accessible with the API key that access is restricted to, if
+    /// any, is not found.
+    pub(crate) fn remove(&mut self, id: u64, restriction: Option<&str>) -> Result<Snapshot> {
+        self.get(id, restriction)?;
+        self.subscriptions.remove(&id);
+
+        self.snapshot()
+    }
+
+    /// Marks the period starting at the provided timestamp as notified for a subscription.
+    ///
+    /// Notified periods that have started more than a day ago are forgotten. Returns the snapshot
+    /// to persist.
+    fn mark_notified(&mut self, id: u64, start: i64) -> Result<Snapshot> {
+        // The subscription could have been removed in the meantime.
+        if let Some(subscription) = self.subscriptions.get_mut(&id) {
+            let too_old = Utc::now().timestamp() - 24 * 3_600;
+            subscription
+                .notified_periods
+                .retain(|&start| start > too_old);
+            subscription.notified_periods.insert(start);
+        }
+
+        self.snapshot()
+    }
+}
+
+/// Parses the callback URL, which needs to be an HTTP(S) URL.
+fn parse_callback_url(url: &str) -> Result<Url> {
+    match Url::parse(url) {
+        Ok(parsed_url) if ["http", "https"].contains(&parsed_url.scheme()) => Ok(parsed_url),
+        _ => Err(Error::InvalidCallbackUrl(String::from(url))),
+    }
+}
+
+/// Checks whether the callback URL is allowed.
+///
+/// The URL needs to be an HTTP(S) URL and its host needs to resolve to global addresses only, so
+/// that it cannot be used to reach e.g. the loopback interface, the private network or the
+/// (link-local) metadata service of a cloud provider. Hosts that are in the allowed hosts are not
+/// resolved and always allowed.
+pub(crate) async fn check_callback_url(url: &str, allowed_hosts: &[String]) -> Result<()> {
+    let parsed_url = parse_callback_url(url)?;
+    let invalid = || Error::InvalidCallbackUrl(String::from(url));
+    // IPv6 addresses are enclosed in brackets in URLs.
+    let host = parsed_url
+        .host_str()
+        .ok_or_else(invalid)?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if allowed_hosts
+        .iter()
+        .any(|allowed_host| allowed_host.eq_ignore_ascii_case(host))
+    {
+        return Ok(());
+    }
+
+    let addresses = match host.parse::<IpAddr>() {
+        Ok(address) => Vec::from([address]),
+        Err(_) => {
+            let port = parsed_url.port_or_known_default().ok_or_else(invalid)?;
+            tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|_| invalid())?
+                .map(|address| address.ip())
+                .collect()
+        }
+    };
+    if addresses.is_empty() || !addresses.into_iter().all(http::is_global) {
+        return Err(invalid());
+    }
+
+    Ok(())
+}
+
+/// Posts the notification to the callback URL.
+///
+/// It is not retried if posting fails or the callback URL does not respond with a success status,
+/// see [`HttpClient`](crate::http::HttpClient), but it is at the next evaluation.
+async fn notify(url: &str, notification: &Notification) -> Result<()> {
+    http::client().post_json(url, notification).await?;
+
+    Ok(())
+}
+
+/// Evaluates the forecast for a subscription and notifies it of new periods that exceed the
+/// threshold.
+///
+/// The callback URL is checked again before notifying, because the addresses its host resolves to
+/// could have changed since it was added.
+async fn evaluate(
+    subscription: Subscription,
+    subscriptions_handle: &SubscriptionsHandle,
+    maps_handle: &MapsHandle,
+    allowed_hosts: &[String],
+) -> Result<()> {
+    let Subscription {
+        id,
+        url,
+        lat,
+        lon,
+        metric,
+        threshold,
+        notified_periods,
+        ..
+    } = subscription;
+    let position = Position::new(lat, lon);
+    let forecast = forecast(position, Vec::from([metric]), maps_handle).await;
+    let series = forecast.series(metric).ok_or_else(|| {
+        let error = forecast.error(metric).unwrap_or_default();
+        Error::MetricFailed(metric, error.to_owned())
+    })?;
+
+    for period in periods(&series, threshold) {
+        let start = period.start.timestamp();
+        if notified_periods.contains(&start) {
+            continue;
+        }
+
+        let notification = Notification {
+            subscription: id,
+            lat,
+            lon,
+            metric,
+            threshold,
+            start,
+            end: period.end.timestamp(),
+            max_value: period.max_value,
+        };
+        info!(subscription = id, %url, %metric, "🔔 Notifying subscription");
+        check_callback_url(&url, allowed_hosts).await?;
+        notify(&url, &notification).await?;
+        let snapshot = subscriptions_handle
+            .lock()
+            .expect("Subscriptions handle mutex was poisoned")
+            .mark_notified(id, start)?;
+        snapshot.write().await?;
+    }
+
+    Ok(())
+}
+
+/// Runs a loop that keeps evaluating the subscriptions and notifies them when necessary.
+///
+/// Callback URLs with a host in the allowed hosts are notified even if the host is not publicly
+/// reachable.
+pub(crate) async fn run(
+    subscriptions_handle: SubscriptionsHandle,
+    maps_handle: MapsHandle,
+    allowed_hosts: Vec<String>,
+) {
+    loop {
+        let subscriptions = subscriptions_handle
+            .lock()
+            .expect("Subscriptions handle mutex was poisoned")
+            .all();
+        if !subscriptions.is_empty() {
+            info!(count = subscriptions.len(), "🔔 Evaluating subscriptions");
+        }
+
+        for subscription in subscriptions {
+            let id = subscription.id;
+            let result = evaluate(
+                subscription,
+                &subscriptions_handle,
+                &maps_handle,
+                &allowed_hosts,
+            )
+            .await;
+            if let Err(e) = result {
+                error!(subscription = id, error = %e, "💥 Encountered error during evaluation");
+            }
+        }
+
+        sleep(EVALUATION_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::tokio::runtime::Builder;
+
+    use super::*;
+
+    fn new_subscription(url: &str, metric: Metric) -> NewSubscription {
+        NewSubscription {
+            url: String::from(url),
+            lat: 51.4,
+            lon: 5.5,
+            metric,
+            threshold: 7.0,
+        }
+    }
+
+    #[test]
+    fn subscriptions() {
+        let path = std::env::temp_dir().join(format!("sinoptik-test-{}.json", std::process::id()));
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let mut subscriptions = Subscriptions::load(Some(path.clone())).unwrap();
+        assert!(subscriptions.all().is_empty());
+
+        // Add subscriptions; IDs are assigned incrementally.
+        let (subscription, snapshot) = subscriptions
+            .add(
+                new_subscription("https://example.com/hook", Metric::Pollen),
+                Some("secret1"),
+            )
+            .unwrap();
+        assert_eq!(subscription.id, 1);
+        runtime.block_on(snapshot.write()).unwrap();
+        // The snapshot is written to a temporary file that replaces the file.
+        assert!(path.exists());
+        assert!(!path.with_extension("tmp").exists());
+        let (subscription, snapshot) = subscriptions
+            .add(
+                new_subscription("http://example.com/hook", Metric::UVI),
+                Some("secret2"),
+            )
+            .unwrap();
+        assert_eq!(subscription.id, 2);
+        runtime.block_on(snapshot.write()).unwrap();
+
+        // Invalid callback URLs and the all metric are rejected.
+        assert!(matches!(
+            subscriptions.add(new_subscription("ftp://example.com", Metric::UVI), None),
+            Err(Error::InvalidCallbackUrl(_))
+        ));
+        assert!(matches!(
+            subscriptions.add(new_subscription("https://example.com", Metric::All), None),
+            Err(Error::UnsupportedMetric(Metric::All))
+        ));
+
+        // Subscriptions are only accessible with the API key they were created with, unless access
+        // is not restricted.
+        let accessible = subscriptions.accessible(Some("secret1"));
+        assert_eq!(accessible.len(), 1);
+        assert_eq!(accessible[0].id, 1);
+        assert_eq!(subscriptions.accessible(None).len(), 2);
+        assert!(subscriptions.get(1, Some("secret1")).is_ok());
+        assert!(matches!(
+            subscriptions.get(2, Some("secret1")),
+            Err(Error::SubscriptionNotFound(2))
+        ));
+        assert!(matches!(
+            subscriptions.remove(2, Some("secret1")),
+            Err(Error::SubscriptionNotFound(2))
+        ));
+
+        // Notified periods and removals are persisted, even if the snapshots are written out of
+        // order.
+        let notified_snapshot = subscriptions
+            .mark_notified(1, Utc::now().timestamp())
+            .unwrap();
+        let removed_snapshot = subscriptions.remove(2, Some("secret2")).unwrap();
+        runtime.block_on(removed_snapshot.write()).unwrap();
+        runtime.block_on(notified_snapshot.write()).unwrap();
+        assert!(matches!(
+            subscriptions.remove(2, None),
+            Err(Error::SubscriptionNotFound(2))
+        ));
+        let mut loaded_subscriptions = Subscriptions::load(Some(path.clone())).unwrap();
+        assert_eq!(loaded_subscriptions.all(), subscriptions.all());
+        assert_eq!(
+            loaded_subscriptions
+                .get(1, Some("secret1"))
+                .unwrap()
+                .notified_periods
+                .len(),
+            1
+        );
+
+        // The ID of a removed subscription is not reused, also not after loading.
+        let (subscription, _snapshot) = loaded_subscriptions
+            .add(
+                new_subscription("https://example.com/hook", Metric::UVI),
+                None,
+            )
+            .unwrap();
+        assert_eq!(subscription.id, 3);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn callback_url() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let check = |url: &str, allowed_hosts: &[String]| {
+            runtime.block_on(check_callback_url(url, allowed_hosts))
+        };
+
+        // Callback URLs of publicly reachable hosts are allowed.
+        assert!(check("https://93.184.215.14/hook", &[]).is_ok());
+        assert!(check("http://[2606:2800:21f:cb07:6820:80da:af6b:8b2c]/hook", &[]).is_ok());
+
+        // Other schemes, and internal and reserved addresses are not.
+        for url in [
+            "ftp://93.184.215.14/hook",
+            "http://127.0.0.1:8080/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1/hook",
+            "http://192.168.1.10:8123/hook",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+        ] {
+            assert!(
+                matches!(check(url, &[]), Err(Error::InvalidCallbackUrl(_))),
+                "{url} should be rejected"
+            );
+        }
+
+        // Unless their host is explicitly allowed.
+        let allowed_hosts = [String::from("192.168.1.10"), String::from("::1")];
+        assert!(check("http://192.168.1.10:8123/hook", &allowed_hosts).is_ok());
+        assert!(check("http://[::1]/hook", &allowed_hosts).is_ok());
+        assert!(check("http://10.0.0.1/hook", &allowed_hosts).is_err());
+    }
+}
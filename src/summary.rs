@@ -0,0 +1,129 @@
+//! Forecast summaries.
+//!
+//! This module is used to derive the highlights of a forecast, such as when the UV index peaks and
+//! how much precipitation is expected, so that clients building notification texts do not have to
+//! recompute them from the items.
+
+use chrono::{DateTime, Utc};
+use rocket::serde::Serialize;
+
+use crate::forecast::{Forecast, Metric};
+
+/// The highest value of a metric and when it occurs.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Peak {
+    /// The time of the first item with the highest value (in seconds since the UNIX epoch).
+    time: i64,
+
+    /// The highest value.
+    value: f32,
+}
+
+impl Peak {
+    /// Determines the peak of the series, or returns [`None`] if the series is empty.
+    fn of(series: &[(DateTime<Utc>, f32)]) -> Option<Self> {
+        series
+            .iter()
+            .fold(None, |peak: Option<Self>, &(time, value)| match peak {
+                Some(peak) if peak.value >= value => Some(peak),
+                _ => Some(Peak {
+                    time: time.timestamp(),
+                    value,
+                }),
+            })
+    }
+}
+
+/// The highlights of a forecast.
+///
+/// A highlight is only present if the forecast contains items for its metric.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Summary {
+    /// The peak of the air quality index, i.e. the worst hour.
+    #[serde(rename = "AQI_peak", skip_serializing_if = "Option::is_none")]
+    aqi_peak: Option<Peak>,
+
+    /// The peak of the pollen score.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pollen_peak: Option<Peak>,
+
+    /// The total expected precipitation (in mm, or in for imperial units).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    precipitation_total: Option<f32>,
+
+    /// The peak of the UV index.
+    #[serde(rename = "UVI_peak", skip_serializing_if = "Option::is_none")]
+    uvi_peak: Option<Peak>,
+}
+
+impl Summary {
+    /// Derives the summary of the items in the forecast.
+    pub(crate) fn new(forecast: &Forecast) -> Self {
+        let peak = |metric| forecast.series(metric).and_then(|series| Peak::of(&series));
+
+        Self {
+            aqi_peak: peak(Metric::AQI),
+            pollen_peak: peak(Metric::Pollen),
+            precipitation_total: forecast
+                .series(Metric::Precipitation)
+                .filter(|series| !series.is_empty())
+                .map(|series| total(&series, Metric::Precipitation.cadence())),
+            uvi_peak: peak(Metric::UVI),
+        }
+    }
+}
+
+/// Determines the total amount of the series of hourly rates.
+///
+/// Each item is assumed to last until the next item; the last item is assumed to last as long as
+/// the one before it, or the provided cadence (in seconds) if there is only one item.
+fn total(series: &[(DateTime<Utc>, f32)], cadence: i64) -> f32 {
+    let mut duration = cadence;
+    let mut total = 0.0;
+    for (index, &(time, rate)) in series.iter().enumerate() {
+        if let Some(&(next_time, _)) = series.get(index + 1) {
+            duration = next_time.signed_duration_since(time).num_seconds();
+        }
+        total += rate * duration as f32 / 3_600.0;
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn peak() {
+        let t = |hour| Utc.with_ymd_and_hms(2024, 4, 10, hour, 0, 0).unwrap();
+
+        // The first item with the highest value is the peak.
+        let series = Vec::from([(t(10), 3.0), (t(11), 6.0), (t(12), 6.0), (t(13), 2.0)]);
+        assert_eq!(
+            Peak::of(&series),
+            Some(Peak {
+                time: t(11).timestamp(),
+                value: 6.0
+            })
+        );
+        assert_eq!(Peak::of(&[]), None);
+    }
+
+    #[test]
+    fn total() {
+        let t_0 = Utc.with_ymd_and_hms(2024, 4, 10, 12, 0, 0).unwrap();
+        let t = |minutes| t_0 + Duration::minutes(minutes);
+
+        // Three items of 5 minutes at 12 mm/h result in 3 mm.
+        let series = Vec::from([(t(0), 12.0), (t(5), 12.0), (t(10), 12.0)]);
+        assert_eq!(super::total(&series, 300), 3.0);
+
+        // A single item lasts for the cadence.
+        assert_eq!(super::total(&[(t(0), 6.0)], 600), 1.0);
+    }
+}
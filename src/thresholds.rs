@@ -0,0 +1,215 @@
+//! Threshold evaluation.
+//!
+//! This module is used to determine when the forecast of a metric exceeds a threshold.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::forecast::Metric;
+use crate::{Error, Result};
+
+/// The duration of an item if it cannot be derived from the items themselves.
+const DEFAULT_ITEM_DURATION: i64 = 3_600;
+
+/// A period during which the forecast exceeds the threshold.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Period {
+    /// The start of the period.
+    pub(crate) start: DateTime<Utc>,
+
+    /// The end of the period.
+    pub(crate) end: DateTime<Utc>,
+
+    /// The maximum value during the period.
+    pub(crate) max_value: f32,
+}
+
+/// The personal thresholds per metric provided with a request.
+///
+/// The thresholds are in metric units, e.g. `precipitation_max=2.5` for 2.5 mm/h.
+#[derive(Clone, Copy, Debug, Default, PartialEq, rocket::FromForm)]
+pub(crate) struct ThresholdParams {
+    /// The maximum air quality index.
+    aqi_max: Option<f32>,
+
+    /// The maximum NO₂ concentration.
+    no2_max: Option<f32>,
+
+    /// The maximum O₃ concentration.
+    o3_max: Option<f32>,
+
+    /// The maximum combination of pollen + air quality index.
+    paqi_max: Option<f32>,
+
+    /// The maximum daily combination of pollen + air quality index.
+    paqi_daily_max: Option<f32>,
+
+    /// The maximum particulate matter concentration.
+    pm10_max: Option<f32>,
+
+    /// The maximum pollen score.
+    pollen_max: Option<f32>,
+
+    /// The maximum precipitation.
+    precipitation_max: Option<f32>,
+
+    /// The maximum temperature.
+    temperature_max: Option<f32>,
+
+    /// The maximum UV index.
+    uvi_max: Option<f32>,
+}
+
+impl ThresholdParams {
+    /// Validates the thresholds and merges them over the provided (e.g. per API key) thresholds.
+    ///
+    /// Returns [`Error::InvalidThreshold`] if a threshold is not finite or, for metrics other than
+    /// the temperature, negative.
+    pub(crate) fn merge(
+        &self,
+        thresholds: &BTreeMap<Metric, f32>,
+    ) -> Result<BTreeMap<Metric, f32>> {
+        let mut merged = thresholds.clone();
+        for (metric, threshold) in [
+            (Metric::AQI, self.aqi_max),
+            (Metric::NO2, self.no2_max),
+            (Metric::O3, self.o3_max),
+            (Metric::PAQI, self.paqi_max),
+            (Metric::PAQIDaily, self.paqi_daily_max),
+            (Metric::PM10, self.pm10_max),
+            (Metric::Pollen, self.pollen_max),
+            (Metric::Precipitation, self.precipitation_max),
+            (Metric::Temperature, self.temperature_max),
+            (Metric::UVI, self.uvi_max),
+        ] {
+            let Some(threshold) = threshold else {
+                continue;
+            };
+            if !threshold.is_finite() || (threshold < 0.0 && metric != Metric::Temperature) {
+                return Err(Error::InvalidThreshold(metric, threshold));
+            }
+            merged.insert(metric, threshold);
+        }
+
+        Ok(merged)
+    }
+}
+
+/// Determines the periods during which the values of the series exceed the threshold.
+///
+/// Consecutive items that exceed the threshold are merged into one period. Each item is assumed to
+/// last until the next item; the duration of the last item is taken to be the same as the one
+/// before it.
+pub(crate) fn periods(series: &[(DateTime<Utc>, f32)], threshold: f32) -> Vec<Period> {
+    let item_duration = series
+        .windows(2)
+        .next()
+        .map(|items| items[1].0 - items[0].0)
+        .unwrap_or_else(|| Duration::seconds(DEFAULT_ITEM_DURATION));
+    let mut periods: Vec<Period> = Vec::new();
+    let mut in_period = false;
+
+    for (idx, &(time, value)) in series.iter().enumerate() {
+        if value <= threshold {
+            in_period = false;
+            continue;
+        }
+
+        let end = series
+            .get(idx + 1)
+            .map(|&(next_time, _)| next_time)
+            .unwrap_or(time + item_duration);
+        match periods.last_mut() {
+            Some(period) if in_period => {
+                period.end = end;
+                period.max_value = period.max_value.max(value);
+            }
+            _ => periods.push(Period {
+                start: time,
+                end,
+                max_value: value,
+            }),
+        }
+        in_period = true;
+    }
+
+    periods
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn threshold_params_merge() {
+        let thresholds = BTreeMap::from([(Metric::Pollen, 3.0), (Metric::UVI, 5.0)]);
+
+        // The provided thresholds take precedence.
+        let params = ThresholdParams {
+            pollen_max: Some(5.0),
+            temperature_max: Some(-2.5),
+            ..Default::default()
+        };
+        assert_eq!(
+            params.merge(&thresholds).unwrap(),
+            BTreeMap::from([
+                (Metric::Pollen, 5.0),
+                (Metric::Temperature, -2.5),
+                (Metric::UVI, 5.0)
+            ])
+        );
+
+        // Thresholds must be finite and only the temperature can be negative.
+        let params = ThresholdParams {
+            uvi_max: Some(-1.0),
+            ..Default::default()
+        };
+        assert_matches::assert_matches!(
+            params.merge(&thresholds),
+            Err(Error::InvalidThreshold(Metric::UVI, _))
+        );
+        let params = ThresholdParams {
+            aqi_max: Some(f32::NAN),
+            ..Default::default()
+        };
+        assert_matches::assert_matches!(
+            params.merge(&thresholds),
+            Err(Error::InvalidThreshold(Metric::AQI, _))
+        );
+    }
+
+    #[test]
+    fn periods() {
+        let t = |hour| Utc.with_ymd_and_hms(2024, 4, 10, hour, 0, 0).unwrap();
+        let series = Vec::from([
+            (t(10), 8.0),
+            (t(11), 9.0),
+            (t(12), 3.0),
+            (t(13), 7.0),
+            (t(14), 7.5),
+        ]);
+
+        assert_eq!(
+            super::periods(&series, 7.0),
+            Vec::from([
+                Period {
+                    start: t(10),
+                    end: t(12),
+                    max_value: 9.0
+                },
+                Period {
+                    start: t(14),
+                    end: t(15),
+                    max_value: 7.5
+                }
+            ])
+        );
+
+        // Nothing exceeds the threshold.
+        assert_eq!(super::periods(&series, 9.0), Vec::new());
+        assert_eq!(super::periods(&[], 0.0), Vec::new());
+    }
+}